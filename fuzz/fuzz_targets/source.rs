@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use verve_lang::fuzz::{self, Target};
+
+fuzz_target!(|data: &[u8]| {
+    if let Some((source, opt_level)) = Target::Source.input(data) {
+        fuzz::compile(&source, opt_level);
+    }
+});
@@ -0,0 +1,44 @@
+//! Shrinks an input a fuzz target crashed on to the source that still
+//! panics the same way, and prints it:
+//!
+//! ```text
+//! cargo run --manifest-path fuzz/Cargo.toml --bin reduce -- programs fuzz/artifacts/programs/crash-...
+//! ```
+//!
+//! Only panics can be reduced; a stack overflow aborts the process.
+
+use std::process::ExitCode;
+use verve_lang::fuzz::{self, Target};
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().collect();
+    let [_, target, path] = args.as_slice() else {
+        eprintln!("Usage: reduce <source|tokens|programs> <input>");
+        return ExitCode::FAILURE;
+    };
+    let Some(target) = Target::from_name(target) else {
+        eprintln!("Unknown fuzz target: {}", target);
+        return ExitCode::FAILURE;
+    };
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let Some((source, opt_level)) = target.input(&data) else {
+        eprintln!("{}: the {} target ignores this input", path, target.name());
+        return ExitCode::FAILURE;
+    };
+
+    std::panic::set_hook(Box::new(|_| {}));
+    let Some(message) = fuzz::panic_message(&source, opt_level) else {
+        eprintln!("{}: compiles without panicking", path);
+        return ExitCode::FAILURE;
+    };
+    let reduced = fuzz::reduce(&source, |candidate| fuzz::panic_message(candidate, opt_level).as_ref() == Some(&message));
+    eprintln!("panic at -O{}: {}", opt_level, message);
+    println!("{}", reduced);
+    ExitCode::SUCCESS
+}
@@ -7,12 +7,19 @@ pub enum Type {
     Bool,
     String,
     Void,
-    Function(Vec<Type>, Box<Type>),
+    /// A pointer to a function taking the given parameters, as written
+    /// `fn(A, B) -> R` or `extern "stdcall" fn(A, B) -> R`.
+    Function(Vec<Type>, Box<Type>, CallConv),
     Unknown,
 
     Arena,
     Pointer(Box<Type>),
     RawPtr,
+    Named(String),
+    /// Builtin `Option<T>`, constructed with `Some(x)` and `None`.
+    Option(Box<Type>),
+    /// `(A, B, ...)`; elements are read with `.0`, `.1`, ...
+    Tuple(Vec<Type>),
 }
 
 impl Type {
@@ -26,30 +33,445 @@ impl Type {
 pub struct Function {
     pub name: String,
     pub params: Vec<(String, Type)>,
+    /// The span of each parameter's name; empty for generated functions.
+    pub param_spans: Vec<Span>,
     pub return_type: Type,
     pub body: Vec<Stmt>,
+    pub attrs: Vec<Attribute>,
+    /// Set by `extern "stdcall" fn`; plain functions use the C convention.
+    pub call_conv: CallConv,
     pub span: Span,
 }
 
-#[derive(Debug)]
+/// How a function receives its arguments, as named after `extern`. Only
+/// 32-bit x86 tells the two apart; Win32 API functions and the callbacks
+/// they take use `stdcall` there.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum CallConv {
+    #[default]
+    C,
+    Stdcall,
+}
+
+impl CallConv {
+    pub const NAMES: &[&str] = &["C", "stdcall"];
+
+    pub fn from_name(name: &str) -> Option<CallConv> {
+        match name {
+            "C" => Some(CallConv::C),
+            "stdcall" => Some(CallConv::Stdcall),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CallConv::C => "C",
+            CallConv::Stdcall => "stdcall",
+        }
+    }
+}
+
+impl Function {
+    pub fn attr(&self, name: &str) -> Option<&Attribute> {
+        self.attrs.iter().find(|a| a.name == name)
+    }
+
+    /// Whether the function is visible to other C translation units. Only
+    /// `main`, `#[export]`, `#[extern]` and functions with a fixed
+    /// [`symbol`](Function::symbol) are; the rest get internal linkage.
+    pub fn is_exported(&self) -> bool {
+        self.name == "main" || self.attr("export").is_some() || self.is_extern() || self.symbol().is_some()
+    }
+
+    /// The C symbol set by `#[export_name("symbol")]`, or the Verve name
+    /// itself under `#[no_mangle]`, which is then emitted unescaped.
+    pub fn symbol(&self) -> Option<&str> {
+        if let Some(AttrArg::Str(symbol)) = self.attr("export_name").and_then(|attr| attr.args.first()) {
+            return Some(symbol);
+        }
+        self.attr("no_mangle").map(|_| self.name.as_str())
+    }
+
+    pub fn is_inline(&self) -> bool {
+        self.attr("inline").is_some()
+    }
+
+    /// Whether the function is only declared here and defined in another
+    /// C translation unit, as the functions of a loaded interface are.
+    pub fn is_extern(&self) -> bool {
+        self.attr("extern").is_some()
+    }
+}
+
+/// An item attribute such as `#[packed]` or `#[align(16)]`.
+#[derive(Debug, Clone)]
+pub struct Attribute {
+    pub name: String,
+    pub args: Vec<AttrArg>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttrArg {
+    Ident(String),
+    Int(i64),
+    Str(String),
+    /// `key = "value"`, as in `#[cfg(feature = "json")]`.
+    KeyValue(String, String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: String,
+    pub ty: Type,
+    /// Width in bits for bitfield members (`flags: i32 : 3`).
+    pub bits: Option<u32>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct StructDef {
+    pub name: String,
+    pub fields: Vec<Field>,
+    /// Untagged union: all fields share the same storage.
+    pub is_union: bool,
+    pub attrs: Vec<Attribute>,
+    pub span: Span,
+}
+
+impl StructDef {
+    pub fn attr(&self, name: &str) -> Option<&Attribute> {
+        self.attrs.iter().find(|a| a.name == name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub name: String,
+    pub fields: Vec<Type>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct EnumDef {
+    pub name: String,
+    pub variants: Vec<Variant>,
+    pub attrs: Vec<Attribute>,
+    pub span: Span,
+}
+
+impl EnumDef {
+    pub fn attr(&self, name: &str) -> Option<&Attribute> {
+        self.attrs.iter().find(|a| a.name == name)
+    }
+
+    /// An enum whose variants carry no payload lowers to a plain C enum.
+    pub fn is_plain(&self) -> bool {
+        self.variants.iter().all(|v| v.fields.is_empty())
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct Program {
     pub stmts: Vec<Stmt>,
     pub functions: Vec<Function>,
+    pub structs: Vec<StructDef>,
+    pub enums: Vec<EnumDef>,
 }
 
+impl Program {
+    /// Appends the items and top-level statements of `other`, as when a
+    /// program is built from several files.
+    pub fn merge(&mut self, other: Program) {
+        self.stmts.extend(other.stmts);
+        self.functions.extend(other.functions);
+        self.structs.extend(other.structs);
+        self.enums.extend(other.enums);
+    }
+}
 
-#[derive(Debug)]
+
+#[derive(Debug, Clone)]
 pub enum Stmt {
     Let(String, Option<Type>, Expr, Span),
+    /// Function-local variable initialized once and kept across calls.
+    Static(String, Option<Type>, Expr, Span),
     Expr(Expr, Span),
     If(Expr, Vec<Stmt>, Option<Vec<Stmt>>, Span),
     Return(Expr, Span),
     Defer(Expr, Span),
     While(Expr, Vec<Stmt>, Span),
     For(String, Expr, Vec<Stmt>, Span),
+    /// Leaves the innermost enclosing loop.
+    Break(Span),
+    /// Goes on to the next step of the innermost enclosing loop.
+    Continue(Span),
+    /// Destructuring `let`; expanded into plain lets by [`crate::lower`].
+    LetPattern(Pattern, Expr, Span),
+    /// `if let pattern = value { ... } else { ... }`; lowered to a `match`.
+    IfLet(Pattern, Expr, Vec<Stmt>, Option<Vec<Stmt>>, Span),
+    /// `while let pattern = value { ... }`; lowered to a loop over a `match`.
+    WhileLet(Pattern, Expr, Vec<Stmt>, Span),
+    /// `for (i, x) in __enumerate(list) { ... }`; lowered to an index loop.
+    ForPattern(Pattern, Expr, Vec<Stmt>, Span),
+    /// The type slot holds the scrutinee's type once checked. Compiled into
+    /// plain conditionals by [`crate::lower::lower_matches`].
+    Match(Expr, Vec<MatchArm>, Span, Type),
+    /// A nested scope; only produced by lowering.
+    Block(Vec<Stmt>, Span),
+    /// Branches on which variant of the named enum a value holds: one case
+    /// per variant listed, and a default for the others unless every
+    /// variant is listed. Only produced by lowering matches.
+    Switch(Expr, String, Vec<(String, Vec<Stmt>)>, Option<Vec<Stmt>>, Span),
+    /// `return f(args)` inside `f`, re-entering the function with new
+    /// arguments; produced by [`crate::tailcall`].
+    TailCall(Vec<Expr>, Span),
 }
 
-#[derive(Debug)]
+/// `case pattern if guard: body`
+#[derive(Debug, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expr>,
+    pub body: Vec<Stmt>,
+    pub span: Span,
+}
+
+/// Left-hand side of a destructuring `let` or a `match` case.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Wildcard(Span),
+    Bind(String, Span),
+    Int(i64, Span),
+    Bool(bool, Span),
+    /// A string literal, matched by content.
+    Str(String, Span),
+    Tuple(Vec<Pattern>, Span),
+    Struct(String, Vec<(String, Pattern)>, Span),
+    /// `Enum::Variant(p, ...)`, or `Variant(p, ...)` with the enum left for
+    /// the typechecker to fill in. `Some(p)` and `None` use the enum `Option`.
+    Variant(Option<String>, String, Vec<Pattern>, Span),
+}
+
+impl Pattern {
+    pub fn span(&self) -> Span {
+        match self {
+            Pattern::Wildcard(span) => *span,
+            Pattern::Bind(_, span) => *span,
+            Pattern::Int(_, span) => *span,
+            Pattern::Bool(_, span) => *span,
+            Pattern::Str(_, span) => *span,
+            Pattern::Tuple(_, span) => *span,
+            Pattern::Struct(_, _, span) => *span,
+            Pattern::Variant(_, _, _, span) => *span,
+        }
+    }
+
+    /// Whether the pattern matches every value of its type.
+    pub fn is_irrefutable(&self) -> bool {
+        match self {
+            Pattern::Wildcard(_) | Pattern::Bind(..) => true,
+            Pattern::Int(..) | Pattern::Bool(..) | Pattern::Str(..) | Pattern::Variant(..) => false,
+            Pattern::Tuple(items, _) => items.iter().all(Pattern::is_irrefutable),
+            Pattern::Struct(_, fields, _) => fields.iter().all(|(_, p)| p.is_irrefutable()),
+        }
+    }
+}
+
+/// Whether control can never reach the end of `stmts`; see
+/// [`Stmt::always_returns`].
+pub fn always_returns(stmts: &[Stmt]) -> bool {
+    stmts.iter().any(Stmt::always_returns)
+}
+
+/// Whether a `break` in the loop body `stmts` leaves the loop; those in
+/// nested loops leave only those.
+pub fn breaks_out(stmts: &[Stmt]) -> bool {
+    jumps(stmts, &|stmt| matches!(stmt, Stmt::Break(_)))
+}
+
+/// Whether a `continue` in the loop body `stmts` skips the rest of it.
+pub fn continues(stmts: &[Stmt]) -> bool {
+    jumps(stmts, &|stmt| matches!(stmt, Stmt::Continue(_)))
+}
+
+/// Whether one of `stmts`, or a statement nested in them outside of inner
+/// loops, is `jump`. The type checker keeps jumps out of loop conditions.
+fn jumps(stmts: &[Stmt], jump: &dyn Fn(&Stmt) -> bool) -> bool {
+    fn in_expr(expr: &Expr, jump: &dyn Fn(&Stmt) -> bool) -> bool {
+        match expr {
+            Expr::SafeBlock(stmts, _, _) | Expr::Block(stmts, _, _, _) if jumps(stmts, jump) => true,
+            _ => expr.children().into_iter().any(|child| in_expr(child, jump)),
+        }
+    }
+
+    stmts.iter().any(|stmt| {
+        jump(stmt)
+            || match stmt {
+                Stmt::While(..) | Stmt::For(..) | Stmt::WhileLet(..) | Stmt::ForPattern(..) => false,
+                Stmt::Let(_, _, expr, _)
+                | Stmt::Static(_, _, expr, _)
+                | Stmt::Expr(expr, _)
+                | Stmt::Return(expr, _)
+                | Stmt::Defer(expr, _)
+                | Stmt::LetPattern(_, expr, _) => in_expr(expr, jump),
+                Stmt::If(cond, then_branch, else_branch, _) | Stmt::IfLet(_, cond, then_branch, else_branch, _) => {
+                    in_expr(cond, jump)
+                        || jumps(then_branch, jump)
+                        || else_branch.as_deref().is_some_and(|branch| jumps(branch, jump))
+                }
+                Stmt::Match(scrutinee, arms, _, _) => {
+                    in_expr(scrutinee, jump)
+                        || arms.iter().any(|arm| {
+                            arm.guard.as_ref().is_some_and(|guard| in_expr(guard, jump)) || jumps(&arm.body, jump)
+                        })
+                }
+                Stmt::Block(stmts, _) => jumps(stmts, jump),
+                Stmt::Switch(value, _, cases, default, _) => {
+                    in_expr(value, jump)
+                        || cases.iter().any(|(_, body)| jumps(body, jump))
+                        || default.as_deref().is_some_and(|body| jumps(body, jump))
+                }
+                Stmt::TailCall(args, _) => args.iter().any(|arg| in_expr(arg, jump)),
+                Stmt::Break(_) | Stmt::Continue(_) => false,
+            }
+    })
+}
+
+impl Stmt {
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Let(_, _, _, span) => *span,
+            Stmt::Static(_, _, _, span) => *span,
+            Stmt::Expr(_, span) => *span,
+            Stmt::If(_, _, _, span) => *span,
+            Stmt::Return(_, span) => *span,
+            Stmt::Defer(_, span) => *span,
+            Stmt::While(_, _, span) => *span,
+            Stmt::For(_, _, _, span) => *span,
+            Stmt::Break(span) | Stmt::Continue(span) => *span,
+            Stmt::LetPattern(_, _, span) => *span,
+            Stmt::IfLet(_, _, _, _, span) => *span,
+            Stmt::WhileLet(_, _, _, span) => *span,
+            Stmt::ForPattern(_, _, _, span) => *span,
+            Stmt::Match(_, _, span, _) => *span,
+            Stmt::Block(_, span) => *span,
+            Stmt::Switch(_, _, _, _, span) => *span,
+            Stmt::TailCall(_, span) => *span,
+        }
+    }
+
+    /// Whether every path through this statement leaves the function, by
+    /// returning or by a loop that never ends. A `while true` loop exits
+    /// only through a return unless its body breaks out of it.
+    pub fn always_returns(&self) -> bool {
+        match self {
+            Stmt::Return(..) | Stmt::TailCall(..) => true,
+            Stmt::If(_, then_branch, Some(else_branch), _) => {
+                always_returns(then_branch) && always_returns(else_branch)
+            }
+            Stmt::While(Expr::Bool(true, _, _), body, _) => !breaks_out(body),
+            Stmt::While(Expr::Var(name, _, _), body, _) => name == "true" && !breaks_out(body),
+            // Matches are checked for exhaustiveness when they are lowered.
+            Stmt::Match(_, arms, _, _) => !arms.is_empty() && arms.iter().all(|arm| always_returns(&arm.body)),
+            Stmt::Block(stmts, _) | Stmt::Expr(Expr::SafeBlock(stmts, _, _), _) => always_returns(stmts),
+            Stmt::Switch(_, _, cases, default, _) => {
+                cases.iter().all(|(_, body)| always_returns(body)) && default.as_deref().is_none_or(always_returns)
+            }
+            _ => false,
+        }
+    }
+
+    /// The expressions directly in this statement, not those in nested
+    /// blocks. The guards of a match count as its own.
+    pub(crate) fn exprs_mut(&mut self) -> Vec<&mut Expr> {
+        match self {
+            Stmt::Let(_, _, expr, _)
+            | Stmt::Static(_, _, expr, _)
+            | Stmt::Expr(expr, _)
+            | Stmt::Return(expr, _)
+            | Stmt::Defer(expr, _)
+            | Stmt::If(expr, _, _, _)
+            | Stmt::While(expr, _, _)
+            | Stmt::For(_, expr, _, _)
+            | Stmt::LetPattern(_, expr, _)
+            | Stmt::IfLet(_, expr, _, _, _)
+            | Stmt::WhileLet(_, expr, _, _)
+            | Stmt::ForPattern(_, expr, _, _)
+            | Stmt::Switch(expr, _, _, _, _) => vec![expr],
+            Stmt::Match(scrutinee, arms, _, _) => {
+                std::iter::once(scrutinee).chain(arms.iter_mut().filter_map(|arm| arm.guard.as_mut())).collect()
+            }
+            Stmt::TailCall(args, _) => args.iter_mut().collect(),
+            Stmt::Block(..) | Stmt::Break(_) | Stmt::Continue(_) => Vec::new(),
+        }
+    }
+
+    /// Calls `f` on every statement block directly nested in this statement,
+    /// including blocks inside safe-block and block expressions.
+    pub fn for_each_block(&mut self, f: &mut dyn FnMut(&mut Vec<Stmt>)) {
+        fn in_expr(expr: &mut Expr, f: &mut dyn FnMut(&mut Vec<Stmt>)) {
+            if let Expr::SafeBlock(stmts, _, _) | Expr::Block(stmts, _, _, _) = expr {
+                f(stmts);
+            }
+            for child in expr.children_mut() {
+                in_expr(child, f);
+            }
+        }
+
+        match self {
+            Stmt::Let(_, _, expr, _)
+            | Stmt::Static(_, _, expr, _)
+            | Stmt::Expr(expr, _)
+            | Stmt::Return(expr, _)
+            | Stmt::Defer(expr, _)
+            | Stmt::LetPattern(_, expr, _) => in_expr(expr, f),
+            Stmt::If(cond, then_branch, else_branch, _) | Stmt::IfLet(_, cond, then_branch, else_branch, _) => {
+                in_expr(cond, f);
+                f(then_branch);
+                if let Some(else_branch) = else_branch {
+                    f(else_branch);
+                }
+            }
+            Stmt::While(cond, body, _) | Stmt::WhileLet(_, cond, body, _) => {
+                in_expr(cond, f);
+                f(body);
+            }
+            Stmt::For(_, range, body, _) | Stmt::ForPattern(_, range, body, _) => {
+                in_expr(range, f);
+                f(body);
+            }
+            Stmt::Match(scrutinee, arms, _, _) => {
+                in_expr(scrutinee, f);
+                for arm in arms {
+                    if let Some(guard) = &mut arm.guard {
+                        in_expr(guard, f);
+                    }
+                    f(&mut arm.body);
+                }
+            }
+            Stmt::Block(stmts, _) => f(stmts),
+            Stmt::Switch(value, _, cases, default, _) => {
+                in_expr(value, f);
+                for (_, body) in cases {
+                    f(body);
+                }
+                if let Some(default) = default {
+                    f(default);
+                }
+            }
+            Stmt::TailCall(args, _) => {
+                for arg in args {
+                    in_expr(arg, f);
+                }
+            }
+            Stmt::Break(_) | Stmt::Continue(_) => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub enum Expr {
     Int(i64, Span, Type),
     Bool(bool, Span, Type),
@@ -58,12 +480,31 @@ pub enum Expr {
     Var(String, Span, Type),
     Call(String, Vec<Expr>, Span, Type),
     SafeBlock(Vec<Stmt>, Span, Type),
+    /// `{ stmts value }`: runs the statements, then yields the value.
+    Block(Vec<Stmt>, Box<Expr>, Span, Type),
+    /// `if cond { a } else { b }`: the value of the arm the condition picks.
+    /// The arms are block expressions, or another `If` for `else if`.
+    If(Box<Expr>, Box<Expr>, Box<Expr>, Span, Type),
     IntrinsicCall(String, Vec<Expr>, Span, Type),
     Cast(Box<Expr>, Type, Span, Type),
     Deref(Box<Expr>, Span, Type),
     Assign(Box<Expr>, Box<Expr>, Span, Type),
     Print(Box<Expr>, Span, Type),
     Range(Box<Expr>, Box<Expr>, Span, Type),
+    /// `Name { field: value, ..base }`. The typechecker expands `..base` into
+    /// explicit field reads, so the base is always `None` by codegen.
+    StructLit(String, Vec<(String, Expr)>, Option<Box<Expr>>, Span, Type),
+    Field(Box<Expr>, String, Span, Type),
+    EnumVariant(String, String, Vec<Expr>, Span, Type),
+    Tuple(Vec<Expr>, Span, Type),
+    /// Tests which variant of `enum` a value holds; produced by match lowering.
+    IsVariant(Box<Expr>, String, String, Span, Type),
+    /// Reads payload field `n` of a variant known to be held; produced by
+    /// match lowering.
+    VariantField(Box<Expr>, String, String, usize, Span, Type),
+    /// `cfg(key = "value")`: whether the compilation target has `value` for
+    /// `key`. Folded to a boolean before codegen.
+    Cfg(String, String, Span, Type),
 }
 
 impl Expr {
@@ -76,12 +517,21 @@ impl Expr {
             Expr::Var(_, span, _) => *span,
             Expr::Call(_, _, span, _) => *span,
             Expr::SafeBlock(_, span, _) => *span,
+            Expr::Block(_, _, span, _) => *span,
+            Expr::If(_, _, _, span, _) => *span,
             Expr::IntrinsicCall(_, _, span, _) => *span,
             Expr::Cast(_, _, span, _) => *span,
             Expr::Deref(_, span, _) => *span,
             Expr::Assign(_, _, span, _) => *span,
             Expr::Print(_, span, _) => *span,
             Expr::Range(_, _, span, _) => *span,
+            Expr::StructLit(_, _, _, span, _) => *span,
+            Expr::Field(_, _, span, _) => *span,
+            Expr::EnumVariant(_, _, _, span, _) => *span,
+            Expr::Tuple(_, span, _) => *span,
+            Expr::IsVariant(_, _, _, span, _) => *span,
+            Expr::VariantField(_, _, _, _, span, _) => *span,
+            Expr::Cfg(_, _, span, _) => *span,
         }
     }
 
@@ -94,15 +544,125 @@ impl Expr {
             Expr::Var(_, _, ty) => ty.clone(),
             Expr::Call(_, _, _, ty) => ty.clone(),
             Expr::SafeBlock(_, _, ty) => ty.clone(),
+            Expr::Block(_, _, _, ty) => ty.clone(),
+            Expr::If(_, _, _, _, ty) => ty.clone(),
             Expr::IntrinsicCall(_, _, _, ty) => ty.clone(),
             Expr::Cast(_, target_ty, _, _) => target_ty.clone(),
             Expr::Deref(_, _, ty) => ty.clone(),
             Expr::Assign(_, _, _, ty) => ty.clone(),
             Expr::Print(_, _, ty) => ty.clone(),
             Expr::Range(_, _, _, ty) => ty.clone(),
+            Expr::StructLit(_, _, _, _, ty) => ty.clone(),
+            Expr::Field(_, _, _, ty) => ty.clone(),
+            Expr::EnumVariant(_, _, _, _, ty) => ty.clone(),
+            Expr::Tuple(_, _, ty) => ty.clone(),
+            Expr::IsVariant(_, _, _, _, ty) => ty.clone(),
+            Expr::VariantField(_, _, _, _, _, ty) => ty.clone(),
+            Expr::Cfg(_, _, _, ty) => ty.clone(),
+        }
+    }
+
+    /// The slot [`Expr::get_type`] reads, which the type checker fills in.
+    /// Casts have none; their type is the target written in the source.
+    pub(crate) fn type_slot(&mut self) -> Option<&mut Type> {
+        match self {
+            Expr::Cast(..) => None,
+            Expr::Int(_, _, ty)
+            | Expr::Bool(_, _, ty)
+            | Expr::Str(_, _, ty)
+            | Expr::BinOp(_, _, _, _, ty)
+            | Expr::Var(_, _, ty)
+            | Expr::Call(_, _, _, ty)
+            | Expr::SafeBlock(_, _, ty)
+            | Expr::Block(_, _, _, ty)
+            | Expr::If(_, _, _, _, ty)
+            | Expr::IntrinsicCall(_, _, _, ty)
+            | Expr::Deref(_, _, ty)
+            | Expr::Assign(_, _, _, ty)
+            | Expr::Print(_, _, ty)
+            | Expr::Range(_, _, _, ty)
+            | Expr::StructLit(_, _, _, _, ty)
+            | Expr::Field(_, _, _, ty)
+            | Expr::EnumVariant(_, _, _, _, ty)
+            | Expr::Tuple(_, _, ty)
+            | Expr::IsVariant(_, _, _, _, ty)
+            | Expr::VariantField(_, _, _, _, _, ty)
+            | Expr::Cfg(_, _, _, ty) => Some(ty),
         }
     }
 
+    /// Direct sub-expressions, not descending into statements of safe blocks
+    /// or block expressions.
+    pub fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Int(..)
+            | Expr::Bool(..)
+            | Expr::Str(..)
+            | Expr::Var(..)
+            | Expr::SafeBlock(..)
+            | Expr::Cfg(..) => Vec::new(),
+            Expr::Block(_, value, _, _) => vec![value],
+            Expr::If(cond, then_value, else_value, _, _) => vec![cond, then_value, else_value],
+            Expr::BinOp(left, _, right, _, _) => vec![left, right],
+            Expr::Call(_, args, _, _) => args.iter().collect(),
+            Expr::IntrinsicCall(_, args, _, _) => args.iter().collect(),
+            Expr::Cast(inner, _, _, _) => vec![inner],
+            Expr::Deref(inner, _, _) => vec![inner],
+            Expr::Assign(target, value, _, _) => vec![target, value],
+            Expr::Print(inner, _, _) => vec![inner],
+            Expr::Range(start, end, _, _) => vec![start, end],
+            Expr::StructLit(_, fields, base, _, _) => {
+                fields.iter().map(|(_, e)| e).chain(base.as_deref()).collect()
+            }
+            Expr::Field(base, _, _, _) => vec![base],
+            Expr::EnumVariant(_, _, args, _, _) => args.iter().collect(),
+            Expr::Tuple(items, _, _) => items.iter().collect(),
+            Expr::IsVariant(inner, ..) | Expr::VariantField(inner, ..) => vec![inner],
+        }
+    }
+
+    /// Mutable counterpart of [`Expr::children`].
+    pub fn children_mut(&mut self) -> Vec<&mut Expr> {
+        match self {
+            Expr::Int(..)
+            | Expr::Bool(..)
+            | Expr::Str(..)
+            | Expr::Var(..)
+            | Expr::SafeBlock(..)
+            | Expr::Cfg(..) => Vec::new(),
+            Expr::Block(_, value, _, _) => vec![value],
+            Expr::If(cond, then_value, else_value, _, _) => vec![cond, then_value, else_value],
+            Expr::BinOp(left, _, right, _, _) => vec![left, right],
+            Expr::Call(_, args, _, _) => args.iter_mut().collect(),
+            Expr::IntrinsicCall(_, args, _, _) => args.iter_mut().collect(),
+            Expr::Cast(inner, _, _, _) => vec![inner],
+            Expr::Deref(inner, _, _) => vec![inner],
+            Expr::Assign(target, value, _, _) => vec![target, value],
+            Expr::Print(inner, _, _) => vec![inner],
+            Expr::Range(start, end, _, _) => vec![start, end],
+            Expr::StructLit(_, fields, base, _, _) => {
+                fields.iter_mut().map(|(_, e)| e).chain(base.as_deref_mut()).collect()
+            }
+            Expr::Field(base, _, _, _) => vec![base],
+            Expr::EnumVariant(_, _, args, _, _) => args.iter_mut().collect(),
+            Expr::Tuple(items, _, _) => items.iter_mut().collect(),
+            Expr::IsVariant(inner, ..) | Expr::VariantField(inner, ..) => vec![inner],
+        }
+    }
+
+    /// Names of all variables referenced anywhere in this expression.
+    pub fn referenced_vars(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        let mut pending = vec![self];
+        while let Some(expr) = pending.pop() {
+            if let Expr::Var(name, _, _) = expr {
+                names.push(name.as_str());
+            }
+            pending.extend(expr.children().into_iter().rev());
+        }
+        names
+    }
+
     pub(crate) fn is_pointer_cast(&self) -> bool {
         if let Expr::Cast(inner, target_ty, _, _) = self {
             inner.get_type().is_pointer() && *target_ty == Type::I32
@@ -112,7 +672,7 @@ impl Expr {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum BinOp {
     Add,
     Sub,
@@ -132,18 +692,31 @@ impl fmt::Display for Type {
             Type::Bool => write!(f, "bool"),
             Type::String => write!(f, "string"),
             Type::Void => write!(f, "void"),
-            Type::Function(params, ret) => {
+            Type::Function(params, ret, call_conv) => {
+                if *call_conv != CallConv::C {
+                    write!(f, "extern \"{}\" ", call_conv.name())?;
+                }
                 write!(f, "fn(")?;
                 for (i, param) in params.iter().enumerate() {
                     if i > 0 { write!(f, ", ")?; }
                     write!(f, "{}", param)?;
                 }
-                write!(f, ") -> {}", ret)
+                write!(f, ")")?;
+                if **ret != Type::Void {
+                    write!(f, " -> {}", ret)?;
+                }
+                Ok(())
             }
             Type::Unknown => write!(f, "<?>"),
             Type::Arena => write!(f, "arena"),
             Type::Pointer(ty) => write!(f, "*{}", ty),
             Type::RawPtr => write!(f, "rawptr"),
+            Type::Named(name) => write!(f, "{}", name),
+            Type::Option(inner) => write!(f, "Option<{}>", inner),
+            Type::Tuple(items) => {
+                let items: Vec<String> = items.iter().map(|t| t.to_string()).collect();
+                write!(f, "({})", items.join(", "))
+            }
         }
     }
 }
\ No newline at end of file
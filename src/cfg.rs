@@ -0,0 +1,160 @@
+//! Compile-time configuration. `cfg(key = "value")` is true when the target
+//! triple has `value` for `key`, or for `feature`, when that feature of the
+//! manifest is enabled; the evaluator folds it, and comparisons of constant
+//! booleans built from it, into `true` or `false`, then drops the `if` and
+//! `while` branches those constants rule out. That happens before type
+//! checking, so a branch for other targets may use what only exists there,
+//! and it never reaches the backend.
+//!
+//! Items marked `#[cfg(key = "value")]` are removed before anything else
+//! looks at them when the condition does not hold, so they may use what
+//! only exists where it does.
+
+use crate::ast::{AttrArg, Attribute, BinOp, Expr, Program, Stmt, Type};
+use std::collections::BTreeSet;
+
+/// The keys `cfg` accepts.
+pub const KEYS: &[&str] = &["target_os", "target_arch", "target_family", "feature"];
+
+/// What `cfg` conditions are evaluated against.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub target_triple: String,
+    pub features: BTreeSet<String>,
+}
+
+impl Config {
+    /// Whether `cfg(key = "expected")` holds.
+    pub fn holds(&self, key: &str, expected: &str) -> bool {
+        match key {
+            "feature" => self.features.contains(expected),
+            _ => value(key, &self.target_triple) == Some(expected),
+        }
+    }
+
+    /// Whether the `#[cfg]` attributes among `attrs` all hold. Malformed
+    /// ones are left for the type checker to report.
+    fn enables(&self, attrs: &[Attribute]) -> bool {
+        attrs.iter().all(|attr| match parse_attr(attr) {
+            Some((key, expected)) => self.holds(key, expected),
+            None => true,
+        })
+    }
+}
+
+/// The key and value of a well-formed `#[cfg(key = "value")]`.
+pub fn parse_attr(attr: &Attribute) -> Option<(&str, &str)> {
+    match (attr.name.as_str(), attr.args.as_slice()) {
+        ("cfg", [AttrArg::KeyValue(key, value)]) if KEYS.contains(&key.as_str()) => Some((key, value)),
+        _ => None,
+    }
+}
+
+/// Removes the items whose `#[cfg]` does not hold, and the `#[cfg]`
+/// attributes of those that stay.
+pub fn strip_items(program: &mut Program, config: &Config) {
+    program.functions.retain(|function| config.enables(&function.attrs));
+    program.structs.retain(|def| config.enables(&def.attrs));
+    program.enums.retain(|def| config.enables(&def.attrs));
+    let holds = |attr: &Attribute| parse_attr(attr).is_none();
+    for function in &mut program.functions {
+        function.attrs.retain(holds);
+    }
+    for def in &mut program.structs {
+        def.attrs.retain(holds);
+    }
+    for def in &mut program.enums {
+        def.attrs.retain(holds);
+    }
+}
+
+/// The value `target_triple` has for `key`, one of [`KEYS`] other than
+/// `feature`.
+pub fn value(key: &str, target_triple: &str) -> Option<&'static str> {
+    let has = |part: &str| target_triple.split('-').any(|p| p.starts_with(part));
+    let os = if has("windows") {
+        "windows"
+    } else if has("linux") {
+        "linux"
+    } else if has("darwin") || has("macos") {
+        "macos"
+    } else if has("freebsd") {
+        "freebsd"
+    } else if has("wasi") {
+        "wasi"
+    } else {
+        "none"
+    };
+    match key {
+        "target_os" => Some(os),
+        "target_arch" => Some(match target_triple.split('-').next().unwrap_or("") {
+            "i386" | "i586" | "i686" => "x86",
+            "arm64" | "aarch64" => "aarch64",
+            "x86_64" => "x86_64",
+            "wasm32" => "wasm32",
+            arch if arch.starts_with("arm") => "arm",
+            arch if arch.starts_with("riscv64") => "riscv64",
+            _ => "unknown",
+        }),
+        "target_family" => Some(match os {
+            "windows" => "windows",
+            "linux" | "macos" | "freebsd" => "unix",
+            _ if target_triple.starts_with("wasm") => "wasm",
+            _ => "none",
+        }),
+        _ => None,
+    }
+}
+
+/// Folds every `cfg` in the program and removes the branches it makes dead.
+/// A `cfg` with an unknown key stays for the type checker to report.
+pub fn fold_program(program: &mut Program, config: &Config) {
+    for function in &mut program.functions {
+        fold_block(&mut function.body, config);
+    }
+    fold_block(&mut program.stmts, config);
+}
+
+fn fold_block(stmts: &mut Vec<Stmt>, config: &Config) {
+    for stmt in std::mem::take(stmts) {
+        let mut stmt = stmt;
+        stmt.for_each_block(&mut |block| fold_block(block, config));
+        for expr in stmt.exprs_mut() {
+            fold_expr(expr, config);
+        }
+        match stmt {
+            Stmt::If(cond, then_branch, else_branch, span) => match constant(&cond) {
+                Some(true) => stmts.push(Stmt::Block(then_branch, span)),
+                Some(false) => stmts.extend(else_branch.map(|branch| Stmt::Block(branch, span))),
+                None => stmts.push(Stmt::If(cond, then_branch, else_branch, span)),
+            },
+            Stmt::While(cond, _, _) if constant(&cond) == Some(false) => {}
+            stmt => stmts.push(stmt),
+        }
+    }
+}
+
+fn fold_expr(expr: &mut Expr, config: &Config) {
+    for child in expr.children_mut() {
+        fold_expr(child, config);
+    }
+    let folded = match expr {
+        Expr::Cfg(key, expected, _, _) if KEYS.contains(&key.as_str()) => config.holds(key, expected),
+        Expr::BinOp(left, BinOp::Eq, right, _, _) => match (constant(left), constant(right)) {
+            (Some(left), Some(right)) => left == right,
+            _ => return,
+        },
+        _ => return,
+    };
+    *expr = Expr::Bool(folded, expr.span(), Type::Bool);
+}
+
+/// The value of a boolean literal.
+fn constant(expr: &Expr) -> Option<bool> {
+    match expr {
+        Expr::Bool(value, _, _) => Some(*value),
+        Expr::Var(name, _, _) if name == "true" => Some(true),
+        Expr::Var(name, _, _) if name == "false" => Some(false),
+        _ => None,
+    }
+}
@@ -1,5 +1,7 @@
+use crate::lint::Lint;
+use crate::log::Filter;
 use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
 #[command(
@@ -12,48 +14,294 @@ pub struct Args {
     #[command(subcommand)]
     pub command: Option<Command>,
 
-    /// Input file to compile (shorthand syntax)
-    #[arg(required = true)]
-    pub input: Option<PathBuf>,
+    /// Input files to compile into one program, `-` for stdin (shorthand syntax)
+    #[arg(required_unless_present = "print")]
+    pub inputs: Vec<PathBuf>,
 
-    /// Output executable path
-    #[arg(short, long, default_value = "program.exe")]
-    pub output: PathBuf,
+    /// Print information about the compiler instead of compiling
+    #[arg(long, value_enum, value_name = "WHAT")]
+    pub print: Option<PrintRequest>,
+
+    #[command(flatten)]
+    pub options: CompileOptions,
+}
+
+/// What `--print` shows.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrintRequest {
+    /// The target triples known to work, one per line
+    TargetList,
+    /// The properties of the --target triple and the C compiler used for it
+    TargetInfo,
+}
+
+/// Options shared by the shorthand syntax and every subcommand.
+#[derive(clap::Args, Clone)]
+pub struct CompileOptions {
+    /// Output executable path [default: program.exe, or program.wasm for
+    /// wasm32-wasi, in the bin directory of the build under target/]
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
 
     /// Disable optimizations
     #[arg(long, action = clap::ArgAction::SetFalse)]
     pub optimize: bool,
 
-    /// Target triple for code generation
-    #[arg(long, default_value = "x86_64-pc-windows-msvc")]
+    /// Optimization level; 2 adds loop unrolling and strength reduction
+    #[arg(short = 'O', long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(0..=2))]
+    pub opt_level: u8,
+
+    /// Code generation backend
+    #[arg(long, default_value = "c")]
+    pub backend: String,
+
+    /// Target triple for code generation, the host's by default; wasm32-wasi
+    /// builds a WebAssembly command with wasi-sdk (found through WASI_SDK_PATH)
+    #[arg(long, alias = "target", default_value = crate::host::native_triple())]
     pub target_triple: String,
 
-    /// Show verbose output
+    /// Features of verve.toml to enable (comma-separated)
+    #[arg(long, value_delimiter = ',')]
+    pub features: Vec<String>,
+
+    /// Do not enable the features verve.toml lists as default
+    #[arg(long)]
+    pub no_default_features: bool,
+
+    /// Show verbose output, and log compiler events at info level unless
+    /// --log says otherwise
     #[arg(short, long)]
     pub verbose: bool,
+
+    /// Log compiler events to stderr: a level (error, warn, info, debug,
+    /// trace) for every module, or module=level, comma-separated, as in
+    /// `info,codegen=debug` [default: $VERVE_LOG]
+    #[arg(long, value_name = "FILTER", value_parser = Filter::parse)]
+    pub log: Option<Filter>,
+
+    /// Lower bitfields to explicit mask/shift code instead of C bitfields
+    #[arg(long)]
+    pub portable_bitfields: bool,
+
+    /// Track allocations at runtime and report leaks and double frees
+    #[arg(long)]
+    pub debug_alloc: bool,
+
+    /// Build with coverage instrumentation mapped back to Verve source lines
+    #[arg(long)]
+    pub coverage: bool,
+
+    /// Record per-function call counts and timings to verve.prof
+    #[arg(long)]
+    pub instrument_functions: bool,
+
+    /// Abort with the function's name when calls nest deeper than DEPTH
+    #[arg(long, value_name = "DEPTH", num_args = 0..=1, require_equals = true, default_missing_value = "10000")]
+    pub stack_guard: Option<u32>,
+
+    /// Emit C for the MSVC toolchain (the default on Windows hosts)
+    #[arg(long)]
+    pub msvc: bool,
+
+    /// Use GNU C extensions in the generated code (requires gcc or clang)
+    #[arg(long, conflicts_with = "msvc")]
+    pub gnu_extensions: bool,
+
+    /// Build the output as the whole program: every symbol but main is internal
+    #[arg(long)]
+    pub whole_program: bool,
+
+    /// Let the C compiler break strict IEEE floating-point semantics for speed
+    #[arg(long)]
+    pub fast_math: bool,
+
+    /// Build with C compiler sanitizers (comma-separated: address, undefined)
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub sanitize: Vec<Sanitizer>,
+
+    /// Spaces per nesting level in output.c; 0 turns indentation off
+    #[arg(long, default_value_t = 4)]
+    pub indent_width: usize,
+
+    /// Run clang-format over output.c, with this style (file, LLVM, Google, ...)
+    #[arg(long, value_name = "STYLE", num_args = 0..=1, require_equals = true, default_missing_value = "file")]
+    pub clang_format: Option<String>,
+
+    /// Emit the C runtime into output.c, or link the parts that do not
+    /// depend on the program from libverve_rt.a, built once per target
+    #[arg(long, value_enum, default_value_t = RuntimeMode::Bundled)]
+    pub runtime: RuntimeMode,
+
+    /// Do not report a lint (deprecated, dead_fields, unused_parameters, or
+    /// warnings for all of them); may be repeated
+    #[arg(short = 'A', long, value_name = "LINT", value_parser = parse_lint)]
+    pub allow: Vec<&'static [Lint]>,
+
+    /// Report a lint as a warning, overriding --allow
+    #[arg(short = 'W', long, value_name = "LINT", value_parser = parse_lint)]
+    pub warn: Vec<&'static [Lint]>,
+
+    /// Report a lint as an error, overriding --allow and --warn
+    #[arg(short = 'D', long, value_name = "LINT", value_parser = parse_lint)]
+    pub deny: Vec<&'static [Lint]>,
+
+    /// Also write the interface of the program (its exported functions and
+    /// types) here; other programs load it by passing the .vervei file as an input
+    #[arg(long, value_name = "PATH")]
+    pub emit_interface: Option<PathBuf>,
+}
+
+fn parse_lint(name: &str) -> Result<&'static [Lint], String> {
+    Lint::select(name).ok_or_else(|| {
+        let names: Vec<&str> = Lint::ALL.iter().map(|lint| lint.name()).collect();
+        format!("unknown lint (expected warnings, {})", names.join(", "))
+    })
+}
+
+/// How a program gets the C runtime.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuntimeMode {
+    /// Emitted into output.c with the program
+    Bundled,
+    /// Linked from libverve_rt.a under target/<triple>/runtime
+    Linked,
+}
+
+/// Sanitizers the C compiler can instrument the generated code with.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sanitizer {
+    Address,
+    Undefined,
+}
+
+impl Sanitizer {
+    pub fn name(self) -> &'static str {
+        match self {
+            Sanitizer::Address => "address",
+            Sanitizer::Undefined => "undefined",
+        }
+    }
+}
+
+/// C compiler flags enabling `sanitizers`, with debug info and frame
+/// pointers so reports point at useful locations.
+pub fn sanitizer_flags(sanitizers: &[Sanitizer]) -> Vec<String> {
+    if sanitizers.is_empty() {
+        return Vec::new();
+    }
+    let names = sanitizers.iter().map(|s| s.name()).collect::<Vec<_>>().join(",");
+    vec![
+        format!("-fsanitize={}", names),
+        "-fno-omit-frame-pointer".to_string(),
+        "-g".to_string(),
+    ]
+}
+
+/// C compiler optimization flags for a build at Verve `opt_level`: `-O0`,
+/// `-O2`, or `-O3` with link-time optimization. Sanitized builds stay at
+/// `-O1` so reports are accurate. `fast_math` is never implied by a level.
+pub fn optimization_flags(opt_level: u8, sanitizers: &[Sanitizer], fast_math: bool) -> Vec<String> {
+    let mut flags = match (opt_level, sanitizers.is_empty()) {
+        (0, _) => vec!["-O0"],
+        (_, false) => vec!["-O1"],
+        (1, true) => vec!["-O2"],
+        (_, true) => vec!["-O3", "-flto"],
+    };
+    if fast_math {
+        flags.push("-ffast-math");
+    }
+    flags.into_iter().map(String::from).collect()
+}
+
+/// Where wasi-sdk is looked for when `WASI_SDK_PATH` is not set.
+pub const DEFAULT_WASI_SDK: &str = "/opt/wasi-sdk";
+
+/// Whether `target_triple` names WebAssembly with WASI.
+pub fn is_wasi(target_triple: &str) -> bool {
+    target_triple.starts_with("wasm32-wasi")
+}
+
+/// The name of the executable built when `--output` is not given.
+pub fn default_output(target_triple: &str) -> PathBuf {
+    PathBuf::from(if is_wasi(target_triple) { "program.wasm" } else { "program.exe" })
+}
+
+/// The wasi-sdk clang under `sdk` and its arguments for building `output`
+/// from the generated C at `c_source`. wasi-libc implements stdio, files and `main`'s arguments
+/// on WASI imports, so the result runs under any WASI runtime (`wasmtime
+/// program.wasm`).
+pub fn wasi_clang_command(sdk: &Path, target_triple: &str, c_flags: &[String], c_source: &Path, output: &Path) -> (PathBuf, Vec<String>) {
+    let mut args = vec![
+        format!("--target={}", target_triple),
+        format!("--sysroot={}", sdk.join("share").join("wasi-sysroot").display()),
+    ];
+    args.extend_from_slice(c_flags);
+    args.extend([c_source.display().to_string(), "-o".to_string(), output.display().to_string()]);
+    (sdk.join("bin").join("clang"), args)
+}
+
+/// The C compiler and archiver that build the linked runtime for
+/// `target_triple`: the wasi-sdk's for WASI, clang and llvm-ar on Windows,
+/// and `$CC` and `$AR`, or cc and ar, elsewhere.
+pub fn runtime_tools(sdk: &Path, target_triple: &str) -> (PathBuf, PathBuf) {
+    if is_wasi(target_triple) {
+        return (sdk.join("bin").join("clang"), sdk.join("bin").join("llvm-ar"));
+    }
+    if cfg!(target_os = "windows") {
+        return ("clang".into(), "llvm-ar".into());
+    }
+    let tool = |var: &str, default: &str| std::env::var_os(var).map(PathBuf::from).unwrap_or_else(|| default.into());
+    (tool("CC", "cc"), tool("AR", "ar"))
 }
 
 #[derive(Subcommand)]
 pub enum Command {
     /// Compile and run a Verve program
     Run {
-        /// Input file to compile
-        input: PathBuf,
+        /// Input files to compile into one program, `-` for stdin
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+
+        /// Kill the program if it runs longer than this many seconds
+        #[arg(long, value_name = "SECONDS")]
+        timeout: Option<u64>,
 
-        /// Output executable path
-        #[arg(short, long, default_value = "program.exe")]
-        output: PathBuf,
+        /// Cap the program's address space (Linux only), in bytes or with a
+        /// K, M or G suffix
+        #[arg(long, value_name = "SIZE", value_parser = crate::sandbox::parse_size)]
+        memory_limit: Option<u64>,
 
-        /// Disable optimizations
-        #[arg(long, action = clap::ArgAction::SetFalse)]
-        optimize: bool,
+        #[command(flatten)]
+        options: CompileOptions,
+    },
+    /// Apply the fixes the compiler suggests to the source files
+    Fix {
+        /// Input files to compile into one program
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
 
-        /// Target triple for code generation
-        #[arg(long, default_value = "x86_64-pc-windows-msvc")]
-        target_triple: String,
+        /// Print the changes as a unified diff instead of writing them
+        #[arg(long)]
+        dry_run: bool,
 
-        /// Show verbose output
-        #[arg(short, long)]
-        verbose: bool,
+        #[command(flatten)]
+        options: CompileOptions,
+    },
+    /// Remove the target directory of the project
+    Clean,
+    /// Work with profiles written by --instrument-functions builds
+    Profile {
+        #[command(subcommand)]
+        command: ProfileCommand,
     },
-}
\ No newline at end of file
+}
+
+#[derive(Subcommand)]
+pub enum ProfileCommand {
+    /// Print a flat profile by Verve function, summed over the given runs
+    Report {
+        /// Profile files to aggregate
+        #[arg(default_value = "verve.prof")]
+        files: Vec<PathBuf>,
+    },
+}
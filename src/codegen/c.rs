@@ -1,17 +1,244 @@
-use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use codespan::{FileId, Span};
-use crate::{ast, codegen::{CodegenConfig, CompileError}};
+use crate::{ast, cfg, deps, log, tailcall, codegen::{mangle, mangle_variant, runtime, Backend, CodegenConfig, CompileError}};
+use crate::intrinsics::{self, Runtime};
 use crate::ast::Type;
 
+/// C precedence levels of the operators Verve emits, loosest first. An
+/// operand emitted at `ASSIGN_PREC` is never parenthesized; one emitted at
+/// `EQUALITY_PREC` is unless it is an assignment.
+const ASSIGN_PREC: u8 = 0;
+const EQUALITY_PREC: u8 = 1;
+const RELATIONAL_PREC: u8 = 2;
+const ADDITIVE_PREC: u8 = 3;
+const MULTIPLICATIVE_PREC: u8 = 4;
+
+/// Emits a call to a builtin intrinsic from its arguments, which the type
+/// checker has matched against the registry.
+type Lowering = fn(&mut CBackend, &[ast::Expr], Span) -> Result<String, CompileError>;
+
+/// How each builtin intrinsic compiles; intrinsics with runtime support
+/// call their runtime function instead. `__chars` and `__enumerate` are
+/// lowered away before code generation.
+const BUILTINS: &[(&str, Lowering)] = &[
+    ("__alloc", CBackend::lower_alloc),
+    ("__stack_alloc", CBackend::lower_stack_alloc),
+    ("__dealloc", CBackend::lower_dealloc),
+    ("__is_some", CBackend::lower_is_some),
+    ("__unwrap", CBackend::lower_unwrap),
+    ("__hash", CBackend::lower_hash),
+    ("__signal_on", CBackend::lower_signal_on),
+    ("__file", CBackend::lower_file),
+    ("__line", CBackend::lower_line),
+    ("__function", CBackend::lower_function),
+    ("__build_version", CBackend::lower_build_version),
+    ("__build_target", CBackend::lower_build_target),
+    ("__build_timestamp", CBackend::lower_build_timestamp),
+    ("__include_str", CBackend::lower_include_str),
+    ("__include_bytes", CBackend::lower_include_bytes),
+];
+
 pub struct CBackend {
     config: CodegenConfig,
     header: String,
+    types: String,
     body: String,
     file_id: FileId,
-    includes: RefCell<HashSet<&'static str>>,
-    variables: RefCell<HashMap<String, Type>>,
+    /// Ordered so the generated header is the same on every run.
+    includes: RefCell<BTreeSet<&'static str>>,
+    /// Types of the variables in scope, one map per enclosing block with the
+    /// globals at the bottom.
+    scopes: RefCell<Vec<HashMap<String, Type>>>,
     functions_map: HashMap<String, Type>,
+    /// C symbols fixed by `#[export_name]` and `#[no_mangle]`, by function.
+    symbols: HashMap<String, String>,
+    plain_enums: HashSet<String>,
+    /// Struct and enum definitions by name, for generating formatters and
+    /// comparisons.
+    struct_defs: HashMap<String, ast::StructDef>,
+    enum_defs: HashMap<String, ast::EnumDef>,
+    /// Names of the per-type `verve_debug_*`, `verve_eq_*`, `verve_cmp_*`
+    /// `verve_hash_*` and `verve_assert_*` functions and the `verve_build_*` constants
+    /// emitted so far.
+    type_functions: HashSet<String>,
+    bitfields: HashMap<String, HashMap<String, BitSlot>>,
+    /// `Option<T>` and tuple instantiations referenced but not yet defined.
+    pending_generics: RefCell<Vec<Type>>,
+    emitted_generics: HashSet<String>,
+    /// C return type of the function being emitted.
+    return_type: String,
+    /// Verve name of the function being emitted, for `__function`.
+    function: String,
+    /// Statements every exit from the current function runs first: the
+    /// profiler's exit hook and the call depth guard's decrement.
+    exit_hooks: String,
+    /// Cleanup labels of the enclosing safe blocks that defer work,
+    /// innermost last.
+    cleanups: Vec<Cleanup>,
+    /// The loops around the statement being emitted, innermost last.
+    loops: Vec<LoopExit>,
+    /// Entry label and parameters of the function being emitted, when it
+    /// re-enters itself through tail calls.
+    tail_call: Option<(String, Vec<(String, Type)>)>,
+    /// Definitions of the C handlers that call Verve signal handlers, by
+    /// name. They are prototyped with the types and defined last.
+    signal_trampolines: BTreeMap<String, String>,
+    /// Signal handlers can only share state through statics, which the C
+    /// compiler must then reload wherever they are read.
+    volatile_statics: bool,
+    /// The parts of the runtime in [`runtime::LINKABLE`] emitted so far.
+    linkable_runtimes: HashSet<Runtime>,
+    /// Whether the JSON runtime has been emitted.
+    json_runtime: bool,
+    /// Whether the UTF-8 string runtime has been emitted.
+    string_runtime: bool,
+    /// Whether the file runtime has been emitted.
+    files_runtime: bool,
+    /// The C arrays holding the files embedded by `include_str` and
+    /// `include_bytes`, by path.
+    assets: HashMap<std::path::PathBuf, String>,
+    /// Whether the program reads its command-line arguments, which `main`
+    /// then has to take.
+    uses_args: bool,
+    /// Code hoisted out of the block expressions of the statement being
+    /// emitted, which has to run before it.
+    prelude: String,
+    temps: TempGen,
+}
+
+/// The exit path of a safe block with deferred statements: every return,
+/// and every `break` or `continue` out of the block, records itself and
+/// jumps to `label`, where the deferred statements run before it continues
+/// outward.
+struct Cleanup {
+    label: String,
+    /// The flag a return sets and the variable holding its value; void
+    /// functions have no `return` to route through here.
+    returning: Option<(String, String)>,
+    /// The variable holding the [`Jump`] on its way out, once one is.
+    jump: Option<String>,
+    /// The kinds of jump that leave through the cleanup.
+    jumps: Vec<Jump>,
+    /// How many loops were around the block; jumps to the loops in it do
+    /// not leave it.
+    loops: usize,
+}
+
+/// `break` or `continue`, stored in [`Cleanup::jump`] as its value.
+#[derive(Clone, Copy, PartialEq)]
+enum Jump {
+    Break = 1,
+    Continue = 2,
+}
+
+/// Where a `break` in a loop body goes. Inside a C `switch` that is in the
+/// body, `break` would only leave the switch, so it jumps to `label`, placed
+/// right after the loop, instead.
+#[derive(Default)]
+struct LoopExit {
+    switches: usize,
+    label: Option<String>,
+}
+
+/// Names for compiler-generated C temporaries and labels. Numbering restarts
+/// in every function and follows emission order, so output is stable across
+/// runs. The `__` prefix keeps them clear of (mangled) Verve identifiers.
+#[derive(Default)]
+struct TempGen {
+    next: Cell<usize>,
+}
+
+impl TempGen {
+    fn bump(&self) -> usize {
+        let n = self.next.get();
+        self.next.set(n + 1);
+        n
+    }
+
+    fn temp(&self, hint: &str) -> String {
+        format!("__tmp_{}{}", hint, self.bump())
+    }
+
+    fn label(&self, hint: &str) -> String {
+        format!("__label_{}{}", hint, self.bump())
+    }
+
+    fn reset(&self) {
+        self.next.set(0);
+    }
+}
+
+/// Location of a bitfield member inside its struct's integer storage units,
+/// used when bitfields are lowered to mask/shift code.
+#[derive(Clone)]
+struct BitSlot {
+    unit: String,
+    offset: u32,
+    width: u32,
+    ty: Type,
+}
+
+impl BitSlot {
+    fn mask(&self) -> String {
+        format!("{:#x}u", (u64::from(1u32) << self.width) - 1)
+    }
+
+    /// Converts the raw bits of this slot back into a Verve value.
+    fn read(&self, unit_code: &str) -> String {
+        let bits = format!("(({} >> {}) & {})", unit_code, self.offset, self.mask());
+        match self.ty {
+            Type::Bool => format!("({} != 0)", bits),
+            _ if self.width == 32 => format!("(int){}", bits),
+            _ => {
+                let sign = 1u32 << (self.width - 1);
+                format!("((int)({} ^ {:#x}u) - {:#x})", bits, sign, sign)
+            }
+        }
+    }
+
+    /// The bits contributed to the storage unit by `value`.
+    fn pack(&self, value: &str) -> String {
+        format!("(((unsigned int)({}) & {}) << {})", value, self.mask(), self.offset)
+    }
+}
+
+/// A user type definition that lowers to a C struct or union.
+enum AggregateDef<'a> {
+    Struct(&'a ast::StructDef),
+    Enum(&'a ast::EnumDef),
+}
+
+impl AggregateDef<'_> {
+    fn name(&self) -> &str {
+        match self {
+            AggregateDef::Struct(def) => &def.name,
+            AggregateDef::Enum(def) => &def.name,
+        }
+    }
+
+    fn keyword(&self) -> &'static str {
+        match self {
+            AggregateDef::Struct(def) if def.is_union => "union",
+            _ => "struct",
+        }
+    }
+
+    fn span(&self) -> Span {
+        match self {
+            AggregateDef::Struct(def) => def.span,
+            AggregateDef::Enum(def) => def.span,
+        }
+    }
+
+    /// Types stored by value, which must be complete before this definition.
+    fn field_types(&self) -> Vec<&Type> {
+        match self {
+            AggregateDef::Struct(def) => def.fields.iter().map(|f| &f.ty).collect(),
+            AggregateDef::Enum(def) => def.variants.iter().flat_map(|v| v.fields.iter()).collect(),
+        }
+    }
 }
 
 impl CBackend {
@@ -19,171 +246,1944 @@ impl CBackend {
         Self {
             config,
             header: String::new(),
+            types: String::new(),
             body: String::new(),
             file_id,
-            includes: RefCell::new(HashSet::new()),
-            variables: RefCell::new(HashMap::new()),
+            includes: RefCell::new(BTreeSet::new()),
+            scopes: RefCell::new(vec![HashMap::new()]),
             functions_map: HashMap::new(),
+            symbols: HashMap::new(),
+            plain_enums: HashSet::new(),
+            struct_defs: HashMap::new(),
+            enum_defs: HashMap::new(),
+            type_functions: HashSet::new(),
+            bitfields: HashMap::new(),
+            pending_generics: RefCell::new(Vec::new()),
+            emitted_generics: HashSet::new(),
+            return_type: "int".to_string(),
+            function: "main".to_string(),
+            exit_hooks: String::new(),
+            cleanups: Vec::new(),
+            loops: Vec::new(),
+            tail_call: None,
+            signal_trampolines: BTreeMap::new(),
+            volatile_statics: false,
+            linkable_runtimes: HashSet::new(),
+            json_runtime: false,
+            string_runtime: false,
+            assets: HashMap::new(),
+            files_runtime: false,
+            uses_args: false,
+            prelude: String::new(),
+            temps: TempGen::default(),
         }
     }
+}
 
-    pub fn compile(&mut self, program: &ast::Program) -> Result<(), CompileError> {
+impl Backend for CBackend {
+    fn compile(&mut self, program: &ast::Program) -> Result<String, CompileError> {
         self.functions_map = program.functions.iter()
             .map(|f| (f.name.clone(), f.return_type.clone()))
             .collect();
+        self.symbols = program.functions.iter()
+            .filter_map(|f| Some((f.name.clone(), f.symbol()?.to_string())))
+            .collect();
+        self.plain_enums = program.enums.iter()
+            .filter(|e| e.is_plain())
+            .map(|e| e.name.clone())
+            .collect();
+        self.struct_defs = program.structs.iter().map(|def| (def.name.clone(), def.clone())).collect();
+        self.enum_defs = program.enums.iter().map(|def| (def.name.clone(), def.clone())).collect();
+        self.volatile_statics = program.functions.iter()
+            .any(|f| Self::calls_intrinsic(&f.body, "__signal_on"))
+            || Self::calls_intrinsic(&program.stmts, "__signal_on");
+        self.uses_args = ["__arg_count", "__arg"].iter().any(|name| {
+            program.functions.iter().any(|f| Self::calls_intrinsic(&f.body, name))
+                || Self::calls_intrinsic(&program.stmts, name)
+        });
+        if self.uses_args {
+            self.emit_args_runtime();
+        }
+        if self.config.portable_bitfields {
+            self.bitfields = program.structs.iter()
+                .map(|def| (def.name.clone(), Self::layout_bitfields(def)))
+                .collect();
+        }
+        if self.config.debug_alloc {
+            self.emit_alloc_tracker();
+        }
+        if self.config.instrument_functions {
+            self.emit_profiler(program);
+        }
+        if let Some(depth) = self.config.max_call_depth {
+            self.emit_call_depth_guard(depth);
+        }
+        self.emit_types(program)?;
         self.emit_globals(program)?;
         self.emit_functions(program)?;
         self.emit_main_if_missing(program)?;
+        self.flush_generics();
+        for trampoline in self.signal_trampolines.values() {
+            self.body.push_str(trampoline);
+        }
+
+        self.emit_header();
+        let code = format!("{}{}{}", self.header, self.types, self.body);
+        Ok(super::indent::indent(&code, self.config.indent_width))
+    }
+}
+
+impl CBackend {
+    fn emit_header(&mut self) {
+        self.header.push_str(&format!(
+            "// Generated by Verve Compiler (target: {})\n",
+            self.config.target_triple
+        ));
+        self.header.push_str("#include <stdio.h>\n#include <stdlib.h>\n");
+
+        for include in self.includes.borrow().iter() {
+            self.header.push_str(&format!("#include {}\n", include));
+        }
+
+        self.header.push('\n');
+        if self.config.linked_runtime && !self.linkable_runtimes.is_empty() {
+            self.header.push_str(&runtime::abi_check());
+        }
+    }
+
+    /// Allocation tracking for `--debug-alloc`: live allocations are kept in
+    /// a list with their size and Verve source location, frees are checked
+    /// against it, and whatever is left at exit is reported as leaked.
+    fn emit_alloc_tracker(&mut self) {
+        self.types.push_str(r#"typedef struct VerveAllocation {
+    void *ptr;
+    size_t size;
+    const char *site;
+    const char *freed_at;
+    struct VerveAllocation *next;
+} VerveAllocation;
+
+static VerveAllocation *verve_live_allocations = NULL;
+static VerveAllocation *verve_freed_allocations = NULL;
+
+static void verve_alloc_report(void) {
+    size_t count = 0, bytes = 0;
+    for (VerveAllocation *a = verve_live_allocations; a; a = a->next) {
+        fprintf(stderr, "leak: %zu bytes allocated at %s\n", a->size, a->site);
+        count++;
+        bytes += a->size;
+    }
+    if (count > 0) {
+        fprintf(stderr, "leak report: %zu allocation(s), %zu bytes never freed\n", count, bytes);
+    }
+}
+
+static void *verve_debug_alloc(size_t size, const char *site) {
+    static int report_installed = 0;
+    if (!report_installed) {
+        atexit(verve_alloc_report);
+        report_installed = 1;
+    }
+    VerveAllocation *a = malloc(sizeof *a);
+    a->ptr = malloc(size);
+    a->size = size;
+    a->site = site;
+    a->freed_at = NULL;
+    a->next = verve_live_allocations;
+    verve_live_allocations = a;
+    return a->ptr;
+}
+
+static void verve_debug_free(void *ptr, const char *site) {
+    for (VerveAllocation **link = &verve_live_allocations; *link; link = &(*link)->next) {
+        VerveAllocation *a = *link;
+        if (a->ptr == ptr) {
+            *link = a->next;
+            a->freed_at = site;
+            a->next = verve_freed_allocations;
+            verve_freed_allocations = a;
+            free(ptr);
+            return;
+        }
+    }
+    for (VerveAllocation *a = verve_freed_allocations; a; a = a->next) {
+        if (a->ptr == ptr) {
+            fprintf(stderr, "double free at %s: allocated at %s, already freed at %s\n", site, a->site, a->freed_at);
+            abort();
+        }
+    }
+    fprintf(stderr, "invalid free at %s: pointer was not allocated by __alloc\n", site);
+    abort();
+}
+
+"#);
+    }
+
+    /// Profiling runtime for `--instrument-functions`. Each function has a
+    /// slot indexed by its position in the program; a shadow call stack
+    /// tracks time spent in callees so self time can be separated out.
+    /// Runtime for `--stack-guard`: every function entry bumps a call depth
+    /// counter and aborts with the function's name past `depth`, rather than
+    /// letting a runaway recursion overflow the C stack silently.
+    fn emit_call_depth_guard(&mut self, depth: u32) {
+        self.types.push_str(&format!(
+            r#"#define VERVE_MAX_CALL_DEPTH {depth}
+static int verve_call_depth = 0;
+
+static void verve_stack_overflow(const char *function) {{
+    fflush(stdout);
+    fprintf(stderr, "stack overflow in function %s (call depth over %d)\n", function, VERVE_MAX_CALL_DEPTH);
+    abort();
+}}
+
+"#
+        ));
+    }
+
+    fn emit_runtime(&mut self, runtime: Runtime) {
+        match runtime {
+            Runtime::Builtin => {}
+            Runtime::Net => self.emit_net_runtime(),
+            Runtime::Strings => self.emit_string_runtime(),
+            Runtime::Json => self.emit_json_runtime(),
+            Runtime::Panic => self.emit_panic_runtime(),
+            Runtime::List => self.emit_list_runtime(),
+            // Emitted up front, since `main` refers to it.
+            Runtime::Args => {}
+            Runtime::Files => self.emit_files_runtime(),
+        }
+    }
+
+    /// `main` stores its arguments here for `__arg_count` and `__arg`.
+    fn emit_args_runtime(&mut self) {
+        self.types.push_str(r#"static int verve_argc;
+static char **verve_argv;
+
+static int verve_arg_count(void) {
+    return verve_argc;
+}
+
+static const char *verve_arg(int i) {
+    return i >= 0 && i < verve_argc ? verve_argv[i] : "";
+}
+
+"#);
+    }
+
+    /// The parameters of the C `main`.
+    fn main_params(&self) -> &'static str {
+        if self.uses_args { "int argc, char **argv" } else { "" }
+    }
+
+    fn emit_main_prologue(&mut self) {
+        if self.uses_args {
+            self.body.push_str("    verve_argc = argc;\n    verve_argv = argv;\n");
+        }
+    }
+
+    /// Whole-file I/O behind `__read_file` and `__write_file`. Files are
+    /// read as bytes, so contents with a NUL end early.
+    fn emit_files_runtime(&mut self) {
+        if std::mem::replace(&mut self.files_runtime, true) {
+            return;
+        }
+        self.includes.borrow_mut().extend(["<stdbool.h>", "<string.h>"]);
+        let option = self.type_to_c(&Type::Option(Box::new(Type::String)));
+        self.flush_generics();
+        self.types.push_str(&r#"static OPTION verve_read_file(const char *path) {
+    FILE *f = fopen(path, "rb");
+    if (!f) return (OPTION){ .is_some = false };
+    size_t len = 0, cap = 256;
+    char *data = malloc(cap);
+    for (;;) {
+        if (len + 1 == cap) data = realloc(data, cap *= 2);
+        size_t n = fread(data + len, 1, cap - len - 1, f);
+        if (n == 0) break;
+        len += n;
+    }
+    bool ok = !ferror(f);
+    fclose(f);
+    data[len] = '\0';
+    if (!ok) {
+        free(data);
+        return (OPTION){ .is_some = false };
+    }
+    return (OPTION){ .is_some = true, .value = data };
+}
+
+static bool verve_write_file(const char *path, const char *contents) {
+    FILE *f = fopen(path, "wb");
+    if (!f) return false;
+    size_t len = strlen(contents);
+    bool ok = fwrite(contents, 1, len, f) == len;
+    return fclose(f) == 0 && ok;
+}
+
+"#.replace("OPTION", &option));
+    }
+
+    fn emit_panic_runtime(&mut self) {
+        self.emit_linkable_runtime(Runtime::Panic);
+    }
+
+    fn emit_list_runtime(&mut self) {
+        self.emit_linkable_runtime(Runtime::List);
+    }
+
+    fn emit_net_runtime(&mut self) {
+        self.emit_linkable_runtime(Runtime::Net);
+    }
+
+    /// Emits a part of the runtime that does not depend on the program
+    /// once. When the runtime is linked, `verve_rt.h` declares every part
+    /// and the header includes it.
+    fn emit_linkable_runtime(&mut self, runtime: Runtime) {
+        if !self.linkable_runtimes.insert(runtime) {
+            return;
+        }
+        log::event!(log::Level::Trace, "codegen", "{} runtime {:?}", if self.config.linked_runtime { "linking" } else { "emitting" }, runtime);
+        if !self.config.linked_runtime {
+            let part = runtime::part(runtime, &self.config.target_triple).expect("part of the linkable runtime");
+            self.includes.borrow_mut().extend(part.includes);
+            self.types.push_str(&part.code);
+        }
+    }
+
+    /// Strings are UTF-8. Bytes that do not start a well-formed sequence
+    /// read as U+FFFD one at a time, so counting and iterating never fail;
+    /// `__str_slice` refuses malformed strings instead.
+    fn emit_string_runtime(&mut self) {
+        if std::mem::replace(&mut self.string_runtime, true) {
+            return;
+        }
+        self.includes.borrow_mut().extend(["<stdbool.h>", "<string.h>"]);
+        let option = self.type_to_c(&Type::Option(Box::new(Type::String)));
+        self.flush_generics();
+        self.types.push_str(&r#"static int verve_utf8_char(const unsigned char *s, int *width) {
+    static const int min[] = { 0, 0, 0x80, 0x800, 0x10000 };
+    unsigned c = s[0];
+    int n = c < 0x80 ? 1 : c >= 0xC2 && c < 0xE0 ? 2 : c >= 0xE0 && c < 0xF0 ? 3 : c >= 0xF0 && c < 0xF5 ? 4 : 0;
+    *width = 1;
+    if (n == 1) return (int)c;
+    if (n == 0) return 0xFFFD;
+    int code = (int)(c & (0x3Fu >> (n - 1)));
+    for (int i = 1; i < n; i++) {
+        if ((s[i] & 0xC0) != 0x80) return 0xFFFD;
+        code = (code << 6) | (s[i] & 0x3F);
+    }
+    if (code < min[n] || code > 0x10FFFF || (code >= 0xD800 && code < 0xE000)) return 0xFFFD;
+    *width = n;
+    return code;
+}
+
+static int verve_utf8_decode(const char *s, int offset) {
+    int width;
+    return offset < 0 ? 0 : verve_utf8_char((const unsigned char *)s + offset, &width);
+}
+
+static int verve_utf8_next(const char *s, int offset) {
+    int width;
+    if (offset < 0 || s[offset] == '\0') return offset;
+    verve_utf8_char((const unsigned char *)s + offset, &width);
+    return offset + width;
+}
+
+static bool verve_str_eq(const char *a, const char *b) {
+    return strcmp(a, b) == 0;
+}
+
+static bool verve_utf8_valid(const char *s) {
+    int width;
+    for (const unsigned char *p = (const unsigned char *)s; *p; p += width) {
+        verve_utf8_char(p, &width);
+        if (width == 1 && *p >= 0x80) return false;
+    }
+    return true;
+}
+
+/* Byte offset of code point `index`, or -1 past the end. */
+static int verve_utf8_offset(const char *s, int index) {
+    int offset = 0;
+    if (index < 0) return -1;
+    for (; index > 0; index--) {
+        if (s[offset] == '\0') return -1;
+        offset = verve_utf8_next(s, offset);
+    }
+    return offset;
+}
+
+static int verve_char_len(const char *s) {
+    int n = 0;
+    for (int offset = 0; s[offset]; offset = verve_utf8_next(s, offset)) n++;
+    return n;
+}
+
+static int verve_char_at(const char *s, int index) {
+    int offset = verve_utf8_offset(s, index);
+    return offset < 0 || s[offset] == '\0' ? -1 : verve_utf8_decode(s, offset);
+}
+
+static OPTION verve_str_slice(const char *s, int start, int end) {
+    int from = verve_utf8_offset(s, start);
+    int to = verve_utf8_offset(s, end);
+    if (from < 0 || to < from || !verve_utf8_valid(s)) return (OPTION){ .is_some = false };
+    char *slice = malloc((size_t)(to - from) + 1);
+    memcpy(slice, s + from, (size_t)(to - from));
+    slice[to - from] = '\0';
+    return (OPTION){ .is_some = true, .value = slice };
+}
+
+"#.replace("OPTION", &option));
+    }
+
+    /// Parsing into and serializing out of the builtin `JsonValue` enum.
+    /// Arrays and objects point to a `VerveJsonList` of items, with keys
+    /// for objects. Parsed strings and lists are never freed.
+    fn emit_json_runtime(&mut self) {
+        if std::mem::replace(&mut self.json_runtime, true) {
+            return;
+        }
+        self.includes.borrow_mut().extend(["<stdbool.h>", "<string.h>"]);
+        let option = self.type_to_c(&Type::Option(Box::new(Type::Named(crate::json::JSON_VALUE.to_string()))));
+        self.flush_generics();
+        self.types.push_str(&r#"typedef struct VerveJsonList {
+    int len, cap;
+    const char **keys;
+    JsonValue *items;
+} VerveJsonList;
+
+static const JsonValue verve_json_null = { .tag = JsonValue_Null };
+
+static JsonValue verve_json_array(void) {
+    return (JsonValue){ .tag = JsonValue_Array, .as.Array = { calloc(1, sizeof(VerveJsonList)) } };
+}
+
+static JsonValue verve_json_object(void) {
+    return (JsonValue){ .tag = JsonValue_Object, .as.Object = { calloc(1, sizeof(VerveJsonList)) } };
+}
+
+static VerveJsonList *verve_json_list(JsonValue v) {
+    if (v.tag == JsonValue_Array) return v.as.Array._0;
+    if (v.tag == JsonValue_Object) return v.as.Object._0;
+    return NULL;
+}
+
+static void verve_json_add(VerveJsonList *list, const char *key, JsonValue item) {
+    if (list->len == list->cap) {
+        list->cap = list->cap ? list->cap * 2 : 4;
+        list->keys = realloc(list->keys, list->cap * sizeof *list->keys);
+        list->items = realloc(list->items, list->cap * sizeof *list->items);
+    }
+    list->keys[list->len] = key;
+    list->items[list->len++] = item;
+}
+
+static int verve_json_len(JsonValue v) {
+    VerveJsonList *list = verve_json_list(v);
+    return list ? list->len : 0;
+}
+
+static JsonValue verve_json_at(JsonValue v, int i) {
+    VerveJsonList *list = verve_json_list(v);
+    return list && i >= 0 && i < list->len ? list->items[i] : verve_json_null;
+}
+
+static const char *verve_json_key(JsonValue v, int i) {
+    VerveJsonList *list = v.tag == JsonValue_Object ? v.as.Object._0 : NULL;
+    return list && i >= 0 && i < list->len ? list->keys[i] : "";
+}
+
+static OPTION verve_json_get(JsonValue v, const char *key) {
+    VerveJsonList *list = v.tag == JsonValue_Object ? v.as.Object._0 : NULL;
+    for (int i = 0; list && i < list->len; i++) {
+        if (strcmp(list->keys[i], key) == 0) return (OPTION){ .is_some = true, .value = list->items[i] };
+    }
+    return (OPTION){ .is_some = false };
+}
+
+static void verve_json_push(JsonValue array, JsonValue item) {
+    if (array.tag == JsonValue_Array) verve_json_add(array.as.Array._0, NULL, item);
+}
+
+static void verve_json_set(JsonValue object, const char *key, JsonValue item) {
+    VerveJsonList *list = object.tag == JsonValue_Object ? object.as.Object._0 : NULL;
+    if (!list) return;
+    for (int i = 0; i < list->len; i++) {
+        if (strcmp(list->keys[i], key) == 0) {
+            list->items[i] = item;
+            return;
+        }
+    }
+    verve_json_add(list, key, item);
+}
+
+typedef struct VerveJsonBuffer {
+    char *data;
+    size_t len, cap;
+} VerveJsonBuffer;
+
+static void verve_json_put(VerveJsonBuffer *b, const char *s, size_t n) {
+    if (b->len + n + 1 > b->cap) {
+        while (b->len + n + 1 > b->cap) b->cap = b->cap ? b->cap * 2 : 64;
+        b->data = realloc(b->data, b->cap);
+    }
+    memcpy(b->data + b->len, s, n);
+    b->len += n;
+    b->data[b->len] = '\0';
+}
+
+static void verve_json_put_utf8(VerveJsonBuffer *b, unsigned long code) {
+    char bytes[4];
+    size_t n;
+    if (code < 0x80) {
+        bytes[0] = (char)code;
+        n = 1;
+    } else if (code < 0x800) {
+        bytes[0] = (char)(0xC0 | (code >> 6));
+        bytes[1] = (char)(0x80 | (code & 0x3F));
+        n = 2;
+    } else if (code < 0x10000) {
+        bytes[0] = (char)(0xE0 | (code >> 12));
+        bytes[1] = (char)(0x80 | ((code >> 6) & 0x3F));
+        bytes[2] = (char)(0x80 | (code & 0x3F));
+        n = 3;
+    } else {
+        bytes[0] = (char)(0xF0 | (code >> 18));
+        bytes[1] = (char)(0x80 | ((code >> 12) & 0x3F));
+        bytes[2] = (char)(0x80 | ((code >> 6) & 0x3F));
+        bytes[3] = (char)(0x80 | (code & 0x3F));
+        n = 4;
+    }
+    verve_json_put(b, bytes, n);
+}
+
+static void verve_json_put_string(VerveJsonBuffer *b, const char *s) {
+    verve_json_put(b, "\"", 1);
+    for (; *s; s++) {
+        unsigned char c = (unsigned char)*s;
+        char escaped[8];
+        switch (c) {
+        case '"': verve_json_put(b, "\\\"", 2); break;
+        case '\\': verve_json_put(b, "\\\\", 2); break;
+        case '\n': verve_json_put(b, "\\n", 2); break;
+        case '\r': verve_json_put(b, "\\r", 2); break;
+        case '\t': verve_json_put(b, "\\t", 2); break;
+        default:
+            if (c < 0x20) {
+                snprintf(escaped, sizeof escaped, "\\u%04x", c);
+                verve_json_put(b, escaped, 6);
+            } else {
+                verve_json_put(b, s, 1);
+            }
+        }
+    }
+    verve_json_put(b, "\"", 1);
+}
+
+static void verve_json_write(VerveJsonBuffer *b, JsonValue v) {
+    char number[16];
+    VerveJsonList *list = verve_json_list(v);
+    switch (v.tag) {
+    case JsonValue_Null: verve_json_put(b, "null", 4); break;
+    case JsonValue_Bool: v.as.Bool._0 ? verve_json_put(b, "true", 4) : verve_json_put(b, "false", 5); break;
+    case JsonValue_Number: verve_json_put(b, number, (size_t)snprintf(number, sizeof number, "%d", v.as.Number._0)); break;
+    case JsonValue_String: verve_json_put_string(b, v.as.String._0); break;
+    case JsonValue_Array:
+    case JsonValue_Object:
+        verve_json_put(b, v.tag == JsonValue_Array ? "[" : "{", 1);
+        for (int i = 0; i < list->len; i++) {
+            if (i > 0) verve_json_put(b, ",", 1);
+            if (v.tag == JsonValue_Object) {
+                verve_json_put_string(b, list->keys[i]);
+                verve_json_put(b, ":", 1);
+            }
+            verve_json_write(b, list->items[i]);
+        }
+        verve_json_put(b, v.tag == JsonValue_Array ? "]" : "}", 1);
+        break;
+    }
+}
+
+static const char *verve_json_stringify(JsonValue v) {
+    VerveJsonBuffer b = { 0 };
+    verve_json_put(&b, "", 0);
+    verve_json_write(&b, v);
+    return b.data;
+}
+
+#define VERVE_JSON_MAX_DEPTH 512
+
+static void verve_json_skip_space(const char **at) {
+    while (**at == ' ' || **at == '\t' || **at == '\n' || **at == '\r') (*at)++;
+}
+
+static int verve_json_hex4(const char *p, unsigned long *code) {
+    *code = 0;
+    for (int i = 0; i < 4; i++) {
+        char c = p[i];
+        int digit = c >= '0' && c <= '9' ? c - '0'
+            : c >= 'a' && c <= 'f' ? c - 'a' + 10
+            : c >= 'A' && c <= 'F' ? c - 'A' + 10 : -1;
+        if (digit < 0) return 0;
+        *code = *code * 16 + (unsigned long)digit;
+    }
+    return 1;
+}
+
+/* Reads a string whose opening quote is just before *at. */
+static const char *verve_json_parse_string(const char **at) {
+    VerveJsonBuffer b = { 0 };
+    verve_json_put(&b, "", 0);
+    const char *p = *at;
+    while (*p != '"') {
+        if ((unsigned char)*p < 0x20) {
+            free(b.data);
+            return NULL;
+        }
+        if (*p != '\\') {
+            verve_json_put(&b, p++, 1);
+            continue;
+        }
+        p++;
+        unsigned long code;
+        switch (*p++) {
+        case '"': verve_json_put(&b, "\"", 1); break;
+        case '\\': verve_json_put(&b, "\\", 1); break;
+        case '/': verve_json_put(&b, "/", 1); break;
+        case 'b': verve_json_put(&b, "\b", 1); break;
+        case 'f': verve_json_put(&b, "\f", 1); break;
+        case 'n': verve_json_put(&b, "\n", 1); break;
+        case 'r': verve_json_put(&b, "\r", 1); break;
+        case 't': verve_json_put(&b, "\t", 1); break;
+        case 'u':
+            if (!verve_json_hex4(p, &code)) {
+                free(b.data);
+                return NULL;
+            }
+            p += 4;
+            unsigned long low;
+            if (code >= 0xD800 && code < 0xDC00 && p[0] == '\\' && p[1] == 'u'
+                && verve_json_hex4(p + 2, &low) && low >= 0xDC00 && low < 0xE000) {
+                code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                p += 6;
+            }
+            verve_json_put_utf8(&b, code);
+            break;
+        default:
+            free(b.data);
+            return NULL;
+        }
+    }
+    *at = p + 1;
+    return b.data;
+}
+
+static int verve_json_parse_value(const char **at, JsonValue *out, int depth) {
+    verve_json_skip_space(at);
+    const char *p = *at;
+    if (depth > VERVE_JSON_MAX_DEPTH) return 0;
+    if (strncmp(p, "null", 4) == 0) {
+        *out = verve_json_null;
+        *at = p + 4;
+        return 1;
+    }
+    if (strncmp(p, "true", 4) == 0 || strncmp(p, "false", 5) == 0) {
+        *out = (JsonValue){ .tag = JsonValue_Bool, .as.Bool = { *p == 't' } };
+        *at = p + (*p == 't' ? 4 : 5);
+        return 1;
+    }
+    if (*p == '"') {
+        *at = p + 1;
+        const char *s = verve_json_parse_string(at);
+        *out = (JsonValue){ .tag = JsonValue_String, .as.String = { s } };
+        return s != NULL;
+    }
+    if (*p == '[' || *p == '{') {
+        int is_object = *p == '{';
+        char close = is_object ? '}' : ']';
+        *out = is_object ? verve_json_object() : verve_json_array();
+        *at = p + 1;
+        verve_json_skip_space(at);
+        if (**at == close) {
+            (*at)++;
+            return 1;
+        }
+        for (;;) {
+            const char *key = NULL;
+            if (is_object) {
+                verve_json_skip_space(at);
+                if (**at != '"') return 0;
+                (*at)++;
+                if (!(key = verve_json_parse_string(at))) return 0;
+                verve_json_skip_space(at);
+                if (**at != ':') return 0;
+                (*at)++;
+            }
+            JsonValue item;
+            if (!verve_json_parse_value(at, &item, depth + 1)) return 0;
+            verve_json_add(verve_json_list(*out), key, item);
+            verve_json_skip_space(at);
+            if (**at == ',') {
+                (*at)++;
+            } else if (**at == close) {
+                (*at)++;
+                return 1;
+            } else {
+                return 0;
+            }
+        }
+    }
+    const char *digits = p + (*p == '-');
+    if (*digits >= '0' && *digits <= '9' && !(digits[0] == '0' && (digits[1] == 'x' || digits[1] == 'X'))) {
+        char *end;
+        double n = strtod(p, &end);
+        if (end == p) return 0;
+        n = n > 2147483647.0 ? 2147483647.0 : n < -2147483648.0 ? -2147483648.0 : n;
+        *out = (JsonValue){ .tag = JsonValue_Number, .as.Number = { (int)n } };
+        *at = end;
+        return 1;
+    }
+    return 0;
+}
+
+static OPTION verve_json_parse(const char *text) {
+    OPTION result = { .is_some = false };
+    const char *at = text;
+    if (verve_json_parse_value(&at, &result.value, 0)) {
+        verve_json_skip_space(&at);
+        result.is_some = *at == '\0';
+    }
+    return result;
+}
+
+"#.replace("OPTION", &option));
+    }
+
+    fn emit_profiler(&mut self, program: &ast::Program) {
+        self.includes.borrow_mut().insert("<time.h>");
+        let names = program.functions.iter()
+            .map(|f| format!("    {{ .name = \"{}\" }},\n", f.name))
+            .collect::<String>();
+        self.types.push_str(&format!("typedef struct VerveProfileEntry {{
+    const char *name;
+    unsigned long long calls, total_ns, self_ns;
+    int active;
+}} VerveProfileEntry;
+
+static VerveProfileEntry verve_profile_entries[] = {{
+{}    {{ .name = NULL }}
+}};
+", names));
+        self.types.push_str(&format!(r#"
+#define VERVE_PROFILE_MAX_DEPTH 4096
+
+typedef struct VerveProfileFrame {{
+    int id;
+    unsigned long long start, children_ns;
+}} VerveProfileFrame;
+
+static VerveProfileFrame verve_profile_stack[VERVE_PROFILE_MAX_DEPTH];
+static int verve_profile_depth = 0;
+
+static unsigned long long verve_profile_now(void) {{
+    struct timespec ts;
+    timespec_get(&ts, TIME_UTC);
+    return (unsigned long long)ts.tv_sec * 1000000000ull + (unsigned long long)ts.tv_nsec;
+}}
+
+static void verve_profile_write(void) {{
+    FILE *out = fopen("{}", "w");
+    if (!out) return;
+    fprintf(out, "{}\n");
+    for (VerveProfileEntry *e = verve_profile_entries; e->name; e++) {{
+        if (e->calls > 0) {{
+            fprintf(out, "%s\t%llu\t%llu\t%llu\n", e->name, e->calls, e->total_ns, e->self_ns);
+        }}
+    }}
+    fclose(out);
+}}
+
+static void verve_profile_enter(int id) {{
+    static int write_installed = 0;
+    if (!write_installed) {{
+        atexit(verve_profile_write);
+        write_installed = 1;
+    }}
+    if (verve_profile_depth < VERVE_PROFILE_MAX_DEPTH) {{
+        VerveProfileFrame *frame = &verve_profile_stack[verve_profile_depth];
+        frame->id = id;
+        frame->children_ns = 0;
+        frame->start = verve_profile_now();
+        verve_profile_entries[id].active++;
+    }}
+    verve_profile_depth++;
+}}
+
+static void verve_profile_exit(void) {{
+    verve_profile_depth--;
+    if (verve_profile_depth >= VERVE_PROFILE_MAX_DEPTH) return;
+    VerveProfileFrame *frame = &verve_profile_stack[verve_profile_depth];
+    unsigned long long elapsed = verve_profile_now() - frame->start;
+    VerveProfileEntry *e = &verve_profile_entries[frame->id];
+    e->calls++;
+    // Only the outermost activation of a recursive function counts toward
+    // its total, so nested calls are not counted twice.
+    if (--e->active == 0) e->total_ns += elapsed;
+    e->self_ns += elapsed - frame->children_ns;
+    if (verve_profile_depth > 0) {{
+        verve_profile_stack[verve_profile_depth - 1].children_ns += elapsed;
+    }}
+}}
+
+"#, crate::profile::PROFILE_FILE, crate::profile::PROFILE_HEADER));
+    }
+
+    fn emit_types(&mut self, program: &ast::Program) -> Result<(), CompileError> {
+        if program.structs.is_empty() && program.enums.is_empty() {
+            return Ok(());
+        }
+
+        for def in program.enums.iter().filter(|e| e.is_plain()) {
+            let variants = def.variants.iter()
+                .map(|v| mangle_variant(&def.name, &v.name))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let name = mangle(&def.name);
+            self.types.push_str(&format!("typedef enum {} {{ {} }} {};\n", name, variants, name));
+        }
+
+        let mut aggregates: Vec<AggregateDef> = program.structs.iter().map(AggregateDef::Struct)
+            .chain(program.enums.iter().filter(|e| !e.is_plain()).map(AggregateDef::Enum))
+            .collect();
+        aggregates.sort_by_key(|def| def.span().start());
+
+        // Forward declarations let definitions refer to each other through pointers.
+        for def in &aggregates {
+            let name = mangle(def.name());
+            self.types.push_str(&format!("typedef {} {} {};\n", def.keyword(), name, name));
+        }
+        for def in &aggregates {
+            if let AggregateDef::Enum(def) = def {
+                let tags = def.variants.iter()
+                    .map(|v| mangle_variant(&def.name, &v.name))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                self.types.push_str(&format!("typedef enum {}_Tag {{ {} }} {}_Tag;\n", def.name, tags, def.name));
+            }
+        }
+        self.types.push('\n');
+
+        for def in self.order_aggregates(&aggregates)? {
+            let code = self.emit_aggregate(def);
+            self.flush_generics();
+            self.types.push_str(&code);
+        }
+
+        for def in program.enums.iter().filter(|e| e.is_plain()) {
+            self.emit_enum_from(def);
+            self.emit_enum_to_string(def);
+        }
+        Ok(())
+    }
+
+    /// Emits `Enum_from(int) -> Option<Enum>`, which checks the integer
+    /// against the enum's discriminants.
+    fn emit_enum_from(&mut self, def: &ast::EnumDef) {
+        let option_ty = self.type_to_c(&Type::Option(Box::new(Type::Named(def.name.clone()))));
+        self.flush_generics();
+
+        let mut code = format!("static inline {} {}_from(int value) {{\n", option_ty, def.name);
+        code.push_str("    switch (value) {\n");
+        for variant in &def.variants {
+            code.push_str(&format!("    case {}:\n", mangle_variant(&def.name, &variant.name)));
+        }
+        if !def.variants.is_empty() {
+            code.push_str(&format!(
+                "        return ({}){{ .is_some = true, .value = ({})value }};\n",
+                option_ty, mangle(&def.name)
+            ));
+        }
+        code.push_str("    default:\n");
+        code.push_str(&format!("        return ({}){{ .is_some = false }};\n", option_ty));
+        code.push_str("    }\n}\n\n");
+        self.types.push_str(&code);
+    }
+
+    /// Emits `Enum_to_string(Enum) -> const char*`, which names the
+    /// variant a value holds.
+    fn emit_enum_to_string(&mut self, def: &ast::EnumDef) {
+        let mut code = format!("static inline const char* {}_to_string({} value) {{\n", def.name, mangle(&def.name));
+        code.push_str("    switch (value) {\n");
+        for variant in &def.variants {
+            code.push_str(&format!("    case {}: return \"{}\";\n", mangle_variant(&def.name, &variant.name), variant.name));
+        }
+        code.push_str("    default: return \"\";\n");
+        code.push_str("    }\n}\n\n");
+        self.types.push_str(&code);
+    }
+
+    /// Emits `verve_debug_T`, which prints a value of `ty` to `out` as it
+    /// would be written in Verve, with no newline, and returns its name. Formatters
+    /// for the types the value holds are emitted first; pointers are
+    /// printed as addresses, so the recursion ends.
+    fn emit_debug_formatter(&mut self, ty: &Type) -> String {
+        let name = format!("verve_debug_{}", Self::mangle_type(ty));
+        if !self.type_functions.insert(name.clone()) {
+            return name;
+        }
+        let c_ty = self.type_to_c(ty);
+        self.flush_generics();
+        let mut code = format!("static void {}(FILE *out, {} v) {{\n", name, c_ty);
+        match ty {
+            Type::Option(inner) => {
+                code.push_str("if (v.is_some) {\nfprintf(out, \"Some(\");\n");
+                code.push_str(&self.debug_value(inner, "v.value"));
+                code.push_str("fprintf(out, \")\");\n} else {\nfprintf(out, \"None\");\n}\n");
+            }
+            Type::Tuple(items) => {
+                code.push_str("fprintf(out, \"(\");\n");
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        code.push_str("fprintf(out, \", \");\n");
+                    }
+                    code.push_str(&self.debug_value(item, &format!("v._{}", i)));
+                }
+                code.push_str(if items.len() == 1 { "fprintf(out, \",)\");\n" } else { "fprintf(out, \")\");\n" });
+            }
+            Type::Named(type_name) => {
+                if let Some(def) = self.struct_defs.get(type_name).cloned() {
+                    code.push_str(&format!("fprintf(out, \"{} {{\");\n", type_name));
+                    for (i, field) in def.fields.iter().enumerate() {
+                        let separator = if i > 0 { ", " } else { " " };
+                        code.push_str(&format!("fprintf(out, \"{}{}: \");\n", separator, field.name));
+                        let value = self.field_value(type_name, field, "v");
+                        code.push_str(&self.debug_value(&field.ty, &value));
+                    }
+                    code.push_str(if def.fields.is_empty() { "(void)v;\nfprintf(out, \"}\");\n" } else { "fprintf(out, \" }\");\n" });
+                } else if let Some(def) = self.enum_defs.get(type_name).cloned() {
+                    let tag = if def.is_plain() { "v" } else { "v.tag" };
+                    code.push_str(&format!("switch ({}) {{\n", tag));
+                    for variant in &def.variants {
+                        code.push_str(&format!(
+                            "case {}:\nfprintf(out, \"{}::{}\");\n",
+                            mangle_variant(&def.name, &variant.name), def.name, variant.name
+                        ));
+                        if !variant.fields.is_empty() {
+                            code.push_str("fprintf(out, \"(\");\n");
+                            for (i, field) in variant.fields.iter().enumerate() {
+                                if i > 0 {
+                                    code.push_str("fprintf(out, \", \");\n");
+                                }
+                                code.push_str(&self.debug_value(field, &format!("v.as.{}._{}", variant.name, i)));
+                            }
+                            code.push_str("fprintf(out, \")\");\n");
+                        }
+                        code.push_str("break;\n");
+                    }
+                    code.push_str("}\n");
+                }
+            }
+            _ => {}
+        }
+        code.push_str("}\n\n");
+        self.types.push_str(&code);
+        name
+    }
+
+    /// A statement printing `value` to `out`, a part of type `ty` of a value
+    /// being formatted. Strings are quoted, as in a Verve literal.
+    fn debug_value(&mut self, ty: &Type, value: &str) -> String {
+        match ty {
+            Type::I32 => format!("fprintf(out, \"%d\", {});\n", value),
+            Type::Bool => format!("fprintf(out, \"%s\", ({}) ? \"true\" : \"false\");\n", value),
+            Type::String => format!("fprintf(out, \"\\\"%s\\\"\", {});\n", value),
+            Type::Pointer(_) | Type::RawPtr => {
+                self.includes.borrow_mut().insert("<inttypes.h>");
+                format!("fprintf(out, \"%\"PRIuPTR, (uintptr_t)({}));\n", value)
+            }
+            _ => format!("{}(out, {});\n", self.emit_debug_formatter(ty), value),
+        }
+    }
+
+    /// Reads `field` of the struct `type_name` from the value `base`, through
+    /// its storage unit when bitfields are lowered.
+    fn field_value(&self, type_name: &str, field: &ast::Field, base: &str) -> String {
+        match self.bitfields.get(type_name).and_then(|slots| slots.get(&field.name)) {
+            Some(slot) => slot.read(&format!("{}.{}", base, slot.unit)),
+            None => format!("{}.{}", base, mangle(&field.name)),
+        }
+    }
+
+    /// Whether comparing and hashing `ty` go through derived functions:
+    /// structs and payload-carrying enums. Plain enums compare and hash as
+    /// integers, in declaration order.
+    fn has_derived_functions(&self, ty: &Type) -> bool {
+        match ty {
+            Type::Named(name) => {
+                self.struct_defs.contains_key(name) || self.enum_defs.get(name).is_some_and(|def| !def.is_plain())
+            }
+            _ => false,
+        }
+    }
+
+    /// `left op right` for operands of a type that derives `Eq` or `Ord`,
+    /// or `None` when C can compare them directly.
+    fn emit_derived_comparison(
+        &mut self,
+        left: &ast::Expr,
+        op: &ast::BinOp,
+        right: &ast::Expr,
+    ) -> Result<Option<String>, CompileError> {
+        let ty = self.expr_type(left);
+        if !self.has_derived_functions(&ty) {
+            return Ok(None);
+        }
+        let left_code = self.emit_operand(left, EQUALITY_PREC)?;
+        let right_code = self.emit_operand(right, EQUALITY_PREC)?;
+        Ok(match op {
+            ast::BinOp::Eq => Some(format!("{}({}, {})", self.emit_eq_function(&ty), left_code, right_code)),
+            ast::BinOp::Lt => Some(format!("{}({}, {}) < 0", self.emit_cmp_function(&ty), left_code, right_code)),
+            ast::BinOp::Gt => Some(format!("{}({}, {}) > 0", self.emit_cmp_function(&ty), left_code, right_code)),
+            _ => None,
+        })
+    }
+
+    /// Emits `verve_eq_T` for `#[derive(Eq)]`, which compares two values of
+    /// `ty` field by field, and returns its name. Strings compare by content.
+    fn emit_eq_function(&mut self, ty: &Type) -> String {
+        let name = format!("verve_eq_{}", Self::mangle_type(ty));
+        if !self.type_functions.insert(name.clone()) {
+            return name;
+        }
+        self.includes.borrow_mut().insert("<stdbool.h>");
+        let c_ty = self.type_to_c(ty);
+        let mut code = format!("static bool {}({} a, {} b) {{\n", name, c_ty, c_ty);
+        let type_name = Self::mangle_type(ty);
+        if let Some(def) = self.struct_defs.get(&type_name).cloned() {
+            let tests = def.fields.iter()
+                .map(|field| {
+                    let (a, b) = (self.field_value(&type_name, field, "a"), self.field_value(&type_name, field, "b"));
+                    self.eq_value(&field.ty, &a, &b)
+                })
+                .collect::<Vec<_>>();
+            if tests.is_empty() {
+                code.push_str("(void)a;\n(void)b;\nreturn true;\n");
+            } else {
+                code.push_str(&format!("return {};\n", tests.join(" && ")));
+            }
+        } else if let Some(def) = self.enum_defs.get(&type_name).cloned() {
+            code.push_str("if (a.tag != b.tag) return false;\nswitch (a.tag) {\n");
+            for variant in def.variants.iter().filter(|v| !v.fields.is_empty()) {
+                let tests = variant.fields.iter().enumerate()
+                    .map(|(i, field)| {
+                        let (a, b) = (format!("a.as.{}._{}", variant.name, i), format!("b.as.{}._{}", variant.name, i));
+                        self.eq_value(field, &a, &b)
+                    })
+                    .collect::<Vec<_>>();
+                code.push_str(&format!("case {}:\nreturn {};\n", mangle_variant(&def.name, &variant.name), tests.join(" && ")));
+            }
+            code.push_str("default:\nreturn true;\n}\n");
+        }
+        code.push_str("}\n\n");
+        self.types.push_str(&code);
+        name
+    }
+
+    /// A C condition that holds when the parts `a` and `b` of type `ty` are equal.
+    fn eq_value(&mut self, ty: &Type, a: &str, b: &str) -> String {
+        match ty {
+            Type::String => {
+                self.includes.borrow_mut().insert("<string.h>");
+                format!("strcmp({}, {}) == 0", a, b)
+            }
+            _ if self.has_derived_functions(ty) => format!("{}({}, {})", self.emit_eq_function(ty), a, b),
+            _ => format!("{} == {}", a, b),
+        }
+    }
+
+    /// Emits `verve_assert_T`, behind `assert_eq` and `assert_ne`, and
+    /// returns its name. When two values of `ty` are not equal, or are, as
+    /// `equal` expects, it shows both and where the assertion is on stderr
+    /// with the `print` formatting, and exits with status 101 like a panic.
+    fn emit_assert_function(&mut self, ty: &Type) -> String {
+        let name = format!("verve_assert_{}", Self::mangle_type(ty));
+        if !self.type_functions.insert(name.clone()) {
+            return name;
+        }
+        self.includes.borrow_mut().insert("<stdbool.h>");
+        let c_ty = self.type_to_c(ty);
+        let same = self.eq_value(ty, "left", "right");
+        let show_left = self.debug_value(ty, "left");
+        let show_right = self.debug_value(ty, "right");
+        self.types.push_str(&format!(
+            r#"static void {name}({c_ty} left, {c_ty} right, bool equal, const char *site) {{
+if (({same}) == equal) return;
+FILE *out = stderr;
+fflush(stdout);
+fprintf(out, "assertion failed: left %s right at %s\n  left: ", equal ? "==" : "!=", site);
+{show_left}fprintf(out, "\n right: ");
+{show_right}fprintf(out, "\n  type: {ty}\n");
+exit(101);
+}}
+
+"#
+        ));
+        name
+    }
+
+    /// Emits `verve_cmp_T` for `#[derive(Ord)]`, which returns -1, 0 or 1 as
+    /// the first value of `ty` orders before, with or after the second, and
+    /// returns its name. Structs order by their fields in declaration order,
+    /// enums by variant and then payload.
+    fn emit_cmp_function(&mut self, ty: &Type) -> String {
+        let name = format!("verve_cmp_{}", Self::mangle_type(ty));
+        if !self.type_functions.insert(name.clone()) {
+            return name;
+        }
+        let c_ty = self.type_to_c(ty);
+        let mut code = format!("static int {}({} a, {} b) {{\n", name, c_ty, c_ty);
+        let type_name = Self::mangle_type(ty);
+        if let Some(def) = self.struct_defs.get(&type_name).cloned() {
+            if def.fields.is_empty() {
+                code.push_str("(void)a;\n(void)b;\n");
+            }
+            for field in &def.fields {
+                let (a, b) = (self.field_value(&type_name, field, "a"), self.field_value(&type_name, field, "b"));
+                code.push_str(&self.cmp_value(&field.ty, &a, &b));
+            }
+        } else if let Some(def) = self.enum_defs.get(&type_name).cloned() {
+            code.push_str("if (a.tag != b.tag) return a.tag < b.tag ? -1 : 1;\nswitch (a.tag) {\n");
+            for variant in def.variants.iter().filter(|v| !v.fields.is_empty()) {
+                code.push_str(&format!("case {}:\n", mangle_variant(&def.name, &variant.name)));
+                for (i, field) in variant.fields.iter().enumerate() {
+                    let (a, b) = (format!("a.as.{}._{}", variant.name, i), format!("b.as.{}._{}", variant.name, i));
+                    code.push_str(&self.cmp_value(field, &a, &b));
+                }
+                code.push_str("break;\n");
+            }
+            code.push_str("default:\nbreak;\n}\n");
+        }
+        code.push_str("return 0;\n}\n\n");
+        self.types.push_str(&code);
+        name
+    }
+
+    /// Statements returning from a `verve_cmp_*` function when the parts
+    /// `a` and `b` of type `ty` differ.
+    fn cmp_value(&mut self, ty: &Type, a: &str, b: &str) -> String {
+        match ty {
+            Type::String => {
+                self.includes.borrow_mut().insert("<string.h>");
+                format!("{{\nint c = strcmp({}, {});\nif (c != 0) return c < 0 ? -1 : 1;\n}}\n", a, b)
+            }
+            _ if self.has_derived_functions(ty) => {
+                format!("{{\nint c = {}({}, {});\nif (c != 0) return c;\n}}\n", self.emit_cmp_function(ty), a, b)
+            }
+            _ => format!("if ({} != {}) return {} < {} ? -1 : 1;\n", a, b, a, b),
+        }
+    }
+
+    /// A C `uint32_t` hash of `value`, of type `ty`, through the
+    /// `verve_hash_*` function of a type that derives `Hash`. Strings hash
+    /// by content, pointers by address.
+    fn hash_value(&mut self, ty: &Type, value: &str) -> String {
+        self.includes.borrow_mut().insert("<stdint.h>");
+        match ty {
+            Type::String => format!("{}({})", self.emit_hash_function(ty), value),
+            Type::Pointer(_) | Type::RawPtr => format!("(uint32_t)(uintptr_t)({})", value),
+            _ if self.has_derived_functions(ty) => format!("{}({})", self.emit_hash_function(ty), value),
+            _ => format!("(uint32_t)({})", value),
+        }
+    }
+
+    /// Emits `verve_hash_T`, which folds the hashes of a value's parts (the
+    /// tag first for enums) together with FNV-1a, and returns its name.
+    fn emit_hash_function(&mut self, ty: &Type) -> String {
+        let name = format!("verve_hash_{}", Self::mangle_type(ty));
+        if !self.type_functions.insert(name.clone()) {
+            return name;
+        }
+        let c_ty = self.type_to_c(ty);
+        let mut code = format!("static uint32_t {}({} v) {{\nuint32_t h = 2166136261u;\n", name, c_ty);
+        let type_name = Self::mangle_type(ty);
+        if *ty == Type::String {
+            code.push_str("for (const unsigned char *c = (const unsigned char *)v; *c; c++) h = (h ^ *c) * 16777619u;\n");
+        } else if let Some(def) = self.struct_defs.get(&type_name).cloned() {
+            if def.fields.is_empty() {
+                code.push_str("(void)v;\n");
+            }
+            for field in &def.fields {
+                let value = self.field_value(&type_name, field, "v");
+                code.push_str(&format!("h = (h ^ {}) * 16777619u;\n", self.hash_value(&field.ty, &value)));
+            }
+        } else if let Some(def) = self.enum_defs.get(&type_name).cloned() {
+            code.push_str("h = (h ^ (uint32_t)v.tag) * 16777619u;\nswitch (v.tag) {\n");
+            for variant in def.variants.iter().filter(|v| !v.fields.is_empty()) {
+                code.push_str(&format!("case {}:\n", mangle_variant(&def.name, &variant.name)));
+                for (i, field) in variant.fields.iter().enumerate() {
+                    let hash = self.hash_value(field, &format!("v.as.{}._{}", variant.name, i));
+                    code.push_str(&format!("h = (h ^ {}) * 16777619u;\n", hash));
+                }
+                code.push_str("break;\n");
+            }
+            code.push_str("default:\nbreak;\n}\n");
+        }
+        code.push_str("return h;\n}\n\n");
+        self.types.push_str(&code);
+        name
+    }
+
+    /// Spells a type as a C identifier fragment, for naming instantiations.
+    fn mangle_type(ty: &Type) -> String {
+        match ty {
+            Type::I32 => "i32".to_string(),
+            Type::Bool => "bool".to_string(),
+            Type::String => "string".to_string(),
+            Type::Void => "void".to_string(),
+            Type::RawPtr => "rawptr".to_string(),
+            Type::Pointer(inner) => format!("ptr_{}", Self::mangle_type(inner)),
+            Type::Named(name) => name.clone(),
+            Type::Option(inner) => format!("Option_{}", Self::mangle_type(inner)),
+            Type::Tuple(items) => {
+                let items: Vec<String> = items.iter().map(Self::mangle_type).collect();
+                format!("Tuple{}_{}", items.len(), items.join("_"))
+            }
+            Type::Function(params, ret, call_conv) => {
+                let items: Vec<String> = params.iter().chain([&**ret]).map(Self::mangle_type).collect();
+                let suffix = if *call_conv == ast::CallConv::C { String::new() } else { format!("_{}", call_conv.name()) };
+                format!("Fn{}_{}{}", params.len(), items.join("_"), suffix)
+            }
+            Type::Unknown | Type::Arena => "unknown".to_string(),
+        }
+    }
+
+    /// Emits definitions for the `Option<T>` and tuple instantiations requested
+    /// so far, each after the instantiations its element types depend on.
+    fn flush_generics(&mut self) {
+        loop {
+            let next = self.pending_generics.borrow_mut().pop();
+            let Some(ty) = next else { break };
+            let name = Self::mangle_type(&ty);
+            if self.emitted_generics.contains(&name) {
+                continue;
+            }
+            let code = match &ty {
+                Type::Option(inner) => {
+                    let inner_c = self.type_to_c(inner);
+                    let none = self.expect("!o.is_some", false);
+                    self.includes.borrow_mut().insert("<stdbool.h>");
+                    format!(
+                        "typedef struct {name} {{ bool is_some; {inner_c} value; }} {name};\n\
+                         static inline {inner_c} {name}_unwrap({name} o) {{\n    if ({none}) {{\n        fprintf(stderr, \"called unwrap on None\\n\");\n        abort();\n    }}\n    return o.value;\n}}\n\n"
+                    )
+                }
+                Type::Tuple(items) => {
+                    let fields: Vec<String> = items.iter().enumerate()
+                        .map(|(i, item)| format!("{} _{};", self.type_to_c(item), i))
+                        .collect();
+                    format!("typedef struct {} {{ {} }} {};\n\n", name, fields.join(" "), name)
+                }
+                Type::Function(params, ret, call_conv) => {
+                    let mut params: Vec<String> = params.iter().map(|param| self.type_to_c(param)).collect();
+                    if params.is_empty() {
+                        params.push("void".to_string());
+                    }
+                    let ret = self.type_to_c(ret);
+                    format!("typedef {} ({}*{})({});\n\n", ret, self.call_conv(*call_conv), name, params.join(", "))
+                }
+                _ => continue,
+            };
+            self.flush_generics();
+            if self.emitted_generics.insert(name) {
+                self.types.push_str(&code);
+            }
+        }
+    }
+
+    /// Named types stored inline in a value of `ty`, including inside
+    /// `Option` and tuple instantiations but not behind pointers.
+    fn by_value_names(ty: &Type) -> Vec<&str> {
+        match ty {
+            Type::Named(name) => vec![name.as_str()],
+            Type::Option(inner) => Self::by_value_names(inner),
+            Type::Tuple(items) => items.iter().flat_map(Self::by_value_names).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Orders definitions so every by-value field type is complete before use.
+    fn order_aggregates<'a>(
+        &self,
+        aggregates: &'a [AggregateDef<'a>],
+    ) -> Result<Vec<&'a AggregateDef<'a>>, CompileError> {
+        let index: HashMap<&str, usize> = aggregates.iter().enumerate()
+            .map(|(i, def)| (def.name(), i))
+            .collect();
+        let order = deps::topo_sort(aggregates.len(), |i| {
+            aggregates[i].field_types().into_iter()
+                .flat_map(Self::by_value_names)
+                .filter_map(|name| index.get(name).copied())
+                .collect()
+        });
+
+        match order {
+            Ok(order) => Ok(order.into_iter().map(|i| &aggregates[i]).collect()),
+            Err(cycle) => {
+                let names = cycle.iter()
+                    .map(|&i| aggregates[i].name())
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+                Err(CompileError::CodegenError {
+                    message: format!(
+                        "Recursive type '{}' has infinite size ({}); store a field behind a pointer to break the cycle",
+                        aggregates[cycle[0]].name(), names
+                    ),
+                    span: Some(aggregates[cycle[0]].span()),
+                    file_id: self.file_id,
+                })
+            }
+        }
+    }
+
+    fn emit_aggregate(&self, def: &AggregateDef) -> String {
+        let mut code = String::new();
+        let packed = matches!(def, AggregateDef::Struct(s) if s.attr("packed").is_some());
+        if packed {
+            code.push_str("#pragma pack(push, 1)\n");
+        }
+        code.push_str(&format!("{} {} {{\n", def.keyword(), mangle(def.name())));
+        match def {
+            AggregateDef::Struct(def) => {
+                let slots = self.bitfields.get(&def.name).cloned().unwrap_or_default();
+                // Over-aligning the first member raises the alignment of the whole struct.
+                let mut align = def.attr("align").and_then(|a| match a.args.first() {
+                    Some(ast::AttrArg::Int(n)) if self.config.msvc => Some(format!("__declspec(align({})) ", n)),
+                    Some(ast::AttrArg::Int(n)) => Some(format!("_Alignas({}) ", n)),
+                    _ => None,
+                });
+                let mut last_unit = None;
+                for field in &def.fields {
+                    if let Some(slot) = slots.get(&field.name) {
+                        if last_unit != Some(&slot.unit) {
+                            let prefix = align.take().unwrap_or_default();
+                            code.push_str(&format!("    {}unsigned int {};\n", prefix, slot.unit));
+                            last_unit = Some(&slot.unit);
+                        }
+                        continue;
+                    }
+                    last_unit = None;
+                    let prefix = align.take().unwrap_or_default();
+                    let c_ty = match (&field.ty, field.bits) {
+                        (Type::I32, Some(_)) => "signed int".to_string(),
+                        _ => self.type_to_c(&field.ty),
+                    };
+                    match field.bits {
+                        Some(bits) => code.push_str(&format!("    {} {} : {};\n", c_ty, mangle(&field.name), bits)),
+                        None => code.push_str(&format!("    {}{} {};\n", prefix, c_ty, mangle(&field.name))),
+                    }
+                }
+            }
+            AggregateDef::Enum(def) => {
+                code.push_str(&format!("    {}_Tag tag;\n", def.name));
+                code.push_str("    union {\n");
+                for variant in def.variants.iter().filter(|v| !v.fields.is_empty()) {
+                    let fields = variant.fields.iter().enumerate()
+                        .map(|(i, ty)| format!("{} _{};", self.type_to_c(ty), i))
+                        .collect::<Vec<_>>()
+                        .join(" ");
+                    code.push_str(&format!("        struct {{ {} }} {};\n", fields, variant.name));
+                }
+                code.push_str("    } as;\n");
+            }
+        }
+        code.push_str("};\n");
+        if packed {
+            code.push_str("#pragma pack(pop)\n");
+        }
+        code.push('\n');
+        code
+    }
+
+    /// Packs consecutive bitfields into 32-bit storage units, starting a new
+    /// unit when a field does not fit or a regular field intervenes.
+    fn layout_bitfields(def: &ast::StructDef) -> HashMap<String, BitSlot> {
+        let mut slots = HashMap::new();
+        let mut units = 0;
+        let mut offset = None;
+        for field in &def.fields {
+            let Some(width) = field.bits else {
+                offset = None;
+                continue;
+            };
+            let start = match offset {
+                Some(used) if used + width <= 32 => used,
+                _ => {
+                    units += 1;
+                    0
+                }
+            };
+            slots.insert(field.name.clone(), BitSlot {
+                unit: format!("__bits{}", units - 1),
+                offset: start,
+                width,
+                ty: field.ty.clone(),
+            });
+            offset = Some(start + width);
+        }
+        slots
+    }
+
+    fn bitfield_slot(&self, base: &ast::Expr, field: &str) -> Option<BitSlot> {
+        let struct_name = match self.expr_type(base) {
+            Type::Named(name) => name,
+            Type::Pointer(inner) => match *inner {
+                Type::Named(name) => name,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        self.bitfields.get(&struct_name)?.get(field).cloned()
+    }
+
+    fn emit_globals(&mut self, program: &ast::Program) -> Result<(), CompileError> {
+        let order = deps::global_order(&program.stmts).map_err(|cycle| {
+            let names = cycle.iter()
+                .filter_map(|&i| match &program.stmts[i] {
+                    ast::Stmt::Let(name, ..) => Some(name.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            CompileError::CodegenError {
+                message: format!("Global initializers form a cycle: {}", names),
+                span: Some(program.stmts[cycle[0]].span()),
+                file_id: self.file_id,
+            }
+        })?;
+
+        let mut inits = HashMap::new();
+        for i in order {
+            let ast::Stmt::Let(name, ty, expr, _) = &program.stmts[i] else { continue };
+            let Some(value) = self.emit_const_expr(expr, &inits) else {
+                return Err(CompileError::CodegenError {
+                    message: format!("Non-constant initializer for global '{}'", name),
+                    span: Some(expr.span()),
+                    file_id: self.file_id,
+                });
+            };
+            let var_type = ty.clone().unwrap_or_else(|| self.expr_type(expr));
+            // Globals are private to the program; nothing can export them yet.
+            self.body.push_str(&format!("static {} {} = {};\n", self.type_to_c(&var_type), mangle(name), value));
+            self.declare(name, var_type);
+            inits.insert(name.clone(), value);
+        }
+        Ok(())
+    }
+
+    /// Lowers a global initializer to a C constant expression. Reads of other
+    /// globals are replaced by their (already emitted) initializers, since C
+    /// does not accept them in static initializers.
+    fn emit_const_expr(&self, expr: &ast::Expr, inits: &HashMap<String, String>) -> Option<String> {
+        match expr {
+            ast::Expr::Int(n, _, _) => Some(n.to_string()),
+            ast::Expr::Str(s, _, _) => Some(format!("\"{}\"", s)),
+            ast::Expr::Var(name, _, _) if name == "true" || name == "false" => {
+                self.includes.borrow_mut().insert("<stdbool.h>");
+                Some(name.clone())
+            }
+            // Folded `cfg` expressions.
+            ast::Expr::Bool(value, _, _) => {
+                self.includes.borrow_mut().insert("<stdbool.h>");
+                Some(value.to_string())
+            }
+            ast::Expr::Var(name, _, _) => inits.get(name).cloned(),
+            ast::Expr::BinOp(left, op, right, _, _) => Some(format!(
+                "({} {} {})",
+                self.emit_const_expr(left, inits)?,
+                Self::binop_to_c(op),
+                self.emit_const_expr(right, inits)?
+            )),
+            _ => None,
+        }
+    }
+
+    fn emit_main_if_missing(&mut self, program: &ast::Program) -> Result<(), CompileError> {
+        // A program of nothing but globals and exported functions is a
+        // library; the program linking against it brings its own `main`.
+        let is_library = program.stmts.iter().all(|stmt| matches!(stmt, ast::Stmt::Let(..)))
+            && program.functions.iter().any(|f| f.attr("export").is_some());
+        if !is_library && !program.functions.iter().any(|f| f.name == "main") {
+            self.body.push_str(&format!("\nint main({}) {{\n", self.main_params()));
+            self.emit_main_prologue();
+
+            for stmt in &program.stmts {
+                if !matches!(stmt, ast::Stmt::Let(..)) {
+                    self.emit_stmt(stmt)?;
+                }
+            }
+
+            self.emit_pause();
+
+            self.body.push_str("    return 0;\n}\n");
+        }
+        Ok(())
+    }
+
+    /// `cond` with a hint that it is usually `expected`; without GNU
+    /// extensions the hint is dropped.
+    fn expect(&self, cond: &str, expected: bool) -> String {
+        if self.config.gnu_extensions {
+            format!("__builtin_expect(!!({}), {})", cond, expected as i32)
+        } else {
+            cond.to_string()
+        }
+    }
+
+    /// A statement the C compiler may assume is never reached.
+    fn unreachable(&self) -> &'static str {
+        if self.config.gnu_extensions {
+            "__builtin_unreachable()"
+        } else if self.config.msvc {
+            "__assume(0)"
+        } else {
+            self.includes.borrow_mut().insert("<stdlib.h>");
+            "abort()"
+        }
+    }
+
+    /// Keeps the console open at the end of `main`. WASI programs are run
+    /// by a runtime such as wasmtime from a shell, and have no console to
+    /// hold.
+    fn emit_pause(&mut self) {
+        if self.config.is_wasi() {
+            return;
+        }
+        if self.config.msvc {
+            self.body.push_str("    system(\"pause\");\n");
+        } else {
+            self.body.push_str("    getchar();\n");
+        }
+    }
+
+    fn emit_functions(&mut self, program: &ast::Program) -> Result<(), CompileError> {
+        for func in &program.functions {
+            let return_type = if func.name == "main" {
+                "int".to_string()
+            } else {
+                self.type_to_c(&func.return_type)
+            };
+            let params = if func.name == "main" {
+                self.main_params().to_string()
+            } else {
+                func.params.iter()
+                    .map(|(name, ty)| format!("{} {}", self.type_to_c(ty), mangle(name)))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            self.body.push_str(&format!(
+                "{}{} {}{}({});\n",
+                self.linkage(func), return_type, self.call_conv(func.call_conv), self.symbol(&func.name), params
+            ));
+        }
+        self.body.push('\n');
+
+        // Inline definitions come first, right after the prototypes, so
+        // every call site below sees the body.
+        let (inline, outline): (Vec<_>, Vec<_>) = program.functions.iter().enumerate()
+            .partition(|(_, func)| func.is_inline());
+        // Extern functions are defined in another translation unit.
+        for (id, func) in inline.into_iter().chain(outline).filter(|(_, func)| !func.is_extern()) {
+            self.emit_function(id, func)?;
+        }
+        Ok(())
+    }
+
+    fn calls_intrinsic(stmts: &[ast::Stmt], name: &str) -> bool {
+        fn in_expr(expr: &ast::Expr, name: &str) -> bool {
+            match expr {
+                ast::Expr::IntrinsicCall(callee, ..) if callee == name => true,
+                ast::Expr::SafeBlock(stmts, _, _) => CBackend::calls_intrinsic(stmts, name),
+                ast::Expr::Block(stmts, value, _, _) => CBackend::calls_intrinsic(stmts, name) || in_expr(value, name),
+                _ => expr.children().into_iter().any(|child| in_expr(child, name)),
+            }
+        }
+
+        stmts.iter().any(|stmt| match stmt {
+            ast::Stmt::Let(_, _, expr, _)
+            | ast::Stmt::Static(_, _, expr, _)
+            | ast::Stmt::Expr(expr, _)
+            | ast::Stmt::Return(expr, _)
+            | ast::Stmt::Defer(expr, _) => in_expr(expr, name),
+            ast::Stmt::If(cond, then_branch, else_branch, _) => {
+                in_expr(cond, name)
+                    || Self::calls_intrinsic(then_branch, name)
+                    || else_branch.as_deref().is_some_and(|b| Self::calls_intrinsic(b, name))
+            }
+            ast::Stmt::While(expr, body, _) | ast::Stmt::For(_, expr, body, _) => {
+                in_expr(expr, name) || Self::calls_intrinsic(body, name)
+            }
+            ast::Stmt::Block(body, _) => Self::calls_intrinsic(body, name),
+            ast::Stmt::Switch(value, _, cases, default, _) => {
+                in_expr(value, name)
+                    || cases.iter().any(|(_, body)| Self::calls_intrinsic(body, name))
+                    || default.as_deref().is_some_and(|b| Self::calls_intrinsic(b, name))
+            }
+            ast::Stmt::TailCall(args, _) => args.iter().any(|arg| in_expr(arg, name)),
+            ast::Stmt::Break(_) | ast::Stmt::Continue(_) => false,
+            // Rejected by `emit_stmt`.
+            ast::Stmt::LetPattern(..) | ast::Stmt::IfLet(..) | ast::Stmt::WhileLet(..) | ast::Stmt::ForPattern(..) | ast::Stmt::Match(..) => false,
+        })
+    }
+
+    fn lower_alloc(&mut self, args: &[ast::Expr], span: Span) -> Result<String, CompileError> {
+        let size = self.emit_expr(&args[0])?;
+        if self.config.debug_alloc {
+            let offset = span.start().to_usize();
+            let site = self.config.source_at(offset).site_literal(offset);
+            return Ok(format!("verve_debug_alloc({}, {})", size, site));
+        }
+        Ok(format!("malloc({})", size))
+    }
+
+    /// A compound literal lives until the end of the enclosing block, which
+    /// is also the end of the pointer's scope. MSVC has no max_align_t in
+    /// C; nothing there is more strictly aligned than double.
+    fn lower_stack_alloc(&mut self, args: &[ast::Expr], _: Span) -> Result<String, CompileError> {
+        let unit = if self.config.msvc {
+            "double"
+        } else {
+            self.includes.borrow_mut().insert("<stddef.h>");
+            "max_align_t"
+        };
+        let size = self.emit_expr(&args[0])?;
+        Ok(format!("(void *)({unit}[({} + sizeof({unit}) - 1) / sizeof({unit})]){{0}}", size))
+    }
+
+    fn lower_dealloc(&mut self, args: &[ast::Expr], span: Span) -> Result<String, CompileError> {
+        let ptr = self.emit_expr(&args[0])?;
+        if self.config.debug_alloc {
+            let offset = span.start().to_usize();
+            let site = self.config.source_at(offset).site_literal(offset);
+            return Ok(format!("verve_debug_free({}, {})", ptr, site));
+        }
+        Ok(format!("free({})", ptr))
+    }
+
+    fn lower_is_some(&mut self, args: &[ast::Expr], _: Span) -> Result<String, CompileError> {
+        let value = self.emit_expr(&args[0])?;
+        Ok(format!("({}).is_some", value))
+    }
+
+    fn lower_unwrap(&mut self, args: &[ast::Expr], _: Span) -> Result<String, CompileError> {
+        let value = self.emit_expr(&args[0])?;
+        let option_ty = self.type_to_c(&self.expr_type(&args[0]));
+        Ok(format!("{}_unwrap({})", option_ty, value))
+    }
+
+    fn lower_hash(&mut self, args: &[ast::Expr], _: Span) -> Result<String, CompileError> {
+        let value = self.emit_operand(&args[0], EQUALITY_PREC)?;
+        let hash = self.hash_value(&self.expr_type(&args[0]), &value);
+        Ok(format!("(int)({} & 0x7fffffffu)", hash))
+    }
+
+    fn lower_file(&mut self, _: &[ast::Expr], span: Span) -> Result<String, CompileError> {
+        Ok(self.config.source_at(span.start().to_usize()).name_literal())
+    }
+
+    fn lower_line(&mut self, _: &[ast::Expr], span: Span) -> Result<String, CompileError> {
+        let offset = span.start().to_usize();
+        Ok(self.config.source_at(offset).location(offset).0.to_string())
+    }
+
+    fn lower_function(&mut self, _: &[ast::Expr], _: Span) -> Result<String, CompileError> {
+        Ok(format!("\"{}\"", self.function))
+    }
+
+    fn lower_build_version(&mut self, _: &[ast::Expr], _: Span) -> Result<String, CompileError> {
+        let version = self.config.build.version.clone();
+        Ok(self.build_constant("version", &version))
+    }
 
-        self.emit_header();
-        self.write_output()?;
-        Ok(())
+    fn lower_build_target(&mut self, _: &[ast::Expr], _: Span) -> Result<String, CompileError> {
+        let target = self.config.target_triple.clone();
+        Ok(self.build_constant("target", &target))
     }
 
-    fn emit_header(&mut self) {
-        self.header.push_str(&format!(
-            "// Generated by Verve Compiler (target: {})\n",
-            self.config.target_triple
-        ));
-        self.header.push_str("#include <stdio.h>\n#include <stdlib.h>\n");
+    fn lower_build_timestamp(&mut self, _: &[ast::Expr], _: Span) -> Result<String, CompileError> {
+        let timestamp = self.config.build.timestamp_utc();
+        Ok(self.build_constant("timestamp", &timestamp))
+    }
 
-        for include in self.includes.borrow().iter() {
-            self.header.push_str(&format!("#include {}\n", include));
+    /// Defines `verve_build_<name>` once, as a global the binary carries
+    /// for tools like `strings` to find, and returns its name.
+    fn build_constant(&mut self, name: &str, value: &str) -> String {
+        let global = format!("verve_build_{}", name);
+        if self.type_functions.insert(global.clone()) {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            self.types.push_str(&format!("static const char {}[] = \"{}\";\n\n", global, value));
         }
-
-        self.header.push('\n');
+        global
     }
 
-    fn emit_globals(&mut self, program: &ast::Program) -> Result<(), CompileError> {
-        for stmt in &program.stmts {
-            if let ast::Stmt::Let(name, ty, expr, _) = stmt {
-                if self.is_constant_expr(expr) {
-                    let c_ty = self.type_to_c(ty.as_ref().unwrap_or(&Type::I32));
-                    let value = self.emit_expr(expr)?;
-                    self.body.push_str(&format!("{} {} = {};\n", c_ty, name, value));
-                } else {
-                    return Err(CompileError::CodegenError {
-                        message: format!("Non-constant initializer for global '{}'", name),
-                        span: Some(expr.span()),
-                        file_id: self.file_id,
-                    });
-                }
-            }
+    fn lower_include_str(&mut self, args: &[ast::Expr], span: Span) -> Result<String, CompileError> {
+        let (array, bytes) = self.embed(args, span)?;
+        if std::str::from_utf8(&bytes).is_err() || bytes.contains(&0) {
+            return Err(CompileError::CodegenError {
+                message: "include_str needs a UTF-8 text file without NUL bytes; use include_bytes".to_string(),
+                span: Some(span),
+                file_id: self.file_id,
+            });
         }
-        Ok(())
+        Ok(format!("(const char*){}", array))
     }
 
-    fn is_constant_expr(&self, expr: &ast::Expr) -> bool {
-        matches!(expr, ast::Expr::Int(..) | ast::Expr::Str(..))
+    fn lower_include_bytes(&mut self, args: &[ast::Expr], span: Span) -> Result<String, CompileError> {
+        self.emit_list_runtime();
+        let (array, bytes) = self.embed(args, span)?;
+        Ok(format!("verve_list_from_bytes({}, {})", array, bytes.len()))
     }
 
-    fn emit_main_if_missing(&mut self, program: &ast::Program) -> Result<(), CompileError> {
-        if !program.functions.iter().any(|f| f.name == "main") {
-            self.body.push_str("\nint main() {\n");
+    /// Reads the file an `include_*` call names, relative to the source
+    /// file of the call, and defines a C array of its bytes once per file.
+    /// The array is NUL-terminated so it doubles as a string. Returns the
+    /// array's name and the bytes.
+    fn embed(&mut self, args: &[ast::Expr], span: Span) -> Result<(String, Vec<u8>), CompileError> {
+        let error = |message: String| CompileError::CodegenError { message, span: Some(span), file_id: self.file_id };
+        let [ast::Expr::Str(path, _, _)] = args else {
+            return Err(error("include path must be a string literal".to_string()));
+        };
+        let source = match self.config.sources.is_empty() {
+            true => None,
+            false => Some(self.config.source_at(span.start().to_usize()).name.as_str()),
+        };
+        // Sources with no file behind them, such as stdin, resolve paths
+        // against the working directory.
+        let dir = source.map(std::path::Path::new).and_then(std::path::Path::parent).unwrap_or(std::path::Path::new(""));
+        let file = dir.join(path);
+        let bytes = self.config.file_system.read(&file).map_err(|e| error(format!("Cannot read '{}': {}", file.display(), e)))?;
+        if let Some(array) = self.assets.get(&file) {
+            return Ok((array.clone(), bytes));
+        }
+        let array = format!("verve_asset_{}", self.assets.len());
+        // A byte list rather than a string literal, which MSVC limits in length.
+        let mut definition = format!("static const unsigned char {}[{}] = {{", array, bytes.len() + 1);
+        for (i, byte) in bytes.iter().chain([&0]).enumerate() {
+            definition.push_str(if i % 16 == 0 { "\n    " } else { " " });
+            definition.push_str(&format!("{},", byte));
+        }
+        definition.push_str("\n};\n\n");
+        self.types.push_str(&definition);
+        self.assets.insert(file, array.clone());
+        Ok((array, bytes))
+    }
 
-            for stmt in &program.stmts {
-                if !matches!(stmt, ast::Stmt::Let(..)) {
-                    self.emit_stmt(stmt)?;
+    fn lower_signal_on(&mut self, args: &[ast::Expr], span: Span) -> Result<String, CompileError> {
+        let message = match args {
+            _ if self.config.is_wasi() => format!("Signal handlers are not available on {}", self.config.target_triple),
+            [ast::Expr::Var(signal, _, _), ast::Expr::Var(handler, _, _)] => {
+                return Ok(self.install_signal_handler(signal, handler));
+            }
+            _ => "signal.on expects a signal name and a function name".to_string(),
+        };
+        Err(CompileError::CodegenError { message, span: Some(span), file_id: self.file_id })
+    }
+
+    /// Installs the Verve function `handler` for `signal`. Handlers run as C
+    /// signal handlers, so execution resumes where it was interrupted once
+    /// they return. MSVC runs Ctrl-C handlers on a thread of their own via
+    /// the console API; declaring that directly keeps `<windows.h>` and its
+    /// macros out of the generated code.
+    fn install_signal_handler(&mut self, signal: &str, handler: &str) -> String {
+        let handler = self.symbol(handler).into_owned();
+        if self.config.msvc && signal == "SIGINT" {
+            let name = format!("verve_console_{}", handler);
+            if !self.signal_trampolines.contains_key(&name) {
+                if self.signal_trampolines.keys().all(|n| !n.starts_with("verve_console_")) {
+                    self.types.push_str(
+                        "__declspec(dllimport) int __stdcall SetConsoleCtrlHandler(int (__stdcall *)(unsigned long), int);\n",
+                    );
                 }
+                self.types.push_str(&format!("static int __stdcall {}(unsigned long event);\n", name));
+                // Returning 0 passes other events (Ctrl-Break, closing the
+                // console) on to the default handler.
+                let definition = format!(
+                    "static int __stdcall {name}(unsigned long event) {{\n    if (event != 0) return 0;\n    {handler}();\n    return 1;\n}}\n\n"
+                );
+                self.signal_trampolines.insert(name.clone(), definition);
             }
+            return format!("SetConsoleCtrlHandler({}, 1)", name);
+        }
 
-            #[cfg(target_os = "windows")]
-            self.body.push_str("    system(\"pause\");\n");
-            #[cfg(not(target_os = "windows"))]
-            self.body.push_str("    getchar();\n");
+        self.includes.borrow_mut().insert("<signal.h>");
+        let name = format!("verve_signal_{}", handler);
+        if !self.signal_trampolines.contains_key(&name) {
+            self.types.push_str(&format!("static void {}(int signo);\n", name));
+            let definition = format!(
+                "static void {name}(int signo) {{\n    (void)signo;\n    {handler}();\n}}\n\n"
+            );
+            self.signal_trampolines.insert(name.clone(), definition);
+        }
+        format!("signal({}, {})", signal, name)
+    }
 
-            self.body.push_str("    return 0;\n}\n");
+    /// The C name of the function `name`.
+    fn symbol<'a>(&'a self, name: &'a str) -> Cow<'a, str> {
+        match self.symbols.get(name) {
+            Some(symbol) => Cow::Borrowed(symbol),
+            None => mangle(name),
         }
-        Ok(())
     }
 
-    fn emit_functions(&mut self, program: &ast::Program) -> Result<(), CompileError> {
-        for func in &program.functions {
-            let return_type = if func.name == "main" {
-                "int".to_string()
-            } else {
-                self.type_to_c(&func.return_type)
-            };
-            let params = func.params.iter()
-                .map(|(name, ty)| format!("{} {}", self.type_to_c(ty), name))
-                .collect::<Vec<_>>()
-                .join(", ");
-            self.body.push_str(&format!("{} {}({});\n", return_type, func.name, params));
+    /// The C attribute for `call_conv`, which only 32-bit x86 has more than
+    /// one of; elsewhere every convention is the platform's own.
+    fn call_conv(&self, call_conv: ast::CallConv) -> &'static str {
+        if call_conv == ast::CallConv::C || cfg::value("target_arch", &self.config.target_triple) != Some("x86") {
+            ""
+        } else if self.config.msvc {
+            "__stdcall "
+        } else {
+            "__attribute__((stdcall)) "
         }
-        self.body.push('\n');
+    }
 
-        for func in &program.functions {
-            self.emit_function(func)?;
+    fn linkage(&self, func: &ast::Function) -> &'static str {
+        // Nothing links against a whole-program build, so only `main` has
+        // to stay visible, besides the functions it links against and those
+        // whose symbol something outside expects.
+        let exported = if self.config.whole_program {
+            func.name == "main" || func.is_extern() || func.symbol().is_some()
+        } else {
+            func.is_exported()
+        };
+        if exported {
+            ""
+        } else if func.is_inline() {
+            "static inline "
+        } else {
+            "static "
         }
-        Ok(())
     }
 
-    fn emit_function(&mut self, func: &ast::Function) -> Result<(), CompileError> {
+    fn emit_function(&mut self, id: usize, func: &ast::Function) -> Result<(), CompileError> {
+        log::event!(log::Level::Debug, "codegen", "emitting function '{}'", func.name);
         let return_type = if func.name == "main" {
             "int".to_string()
         } else {
             self.type_to_c(&func.return_type)
         };
 
-        let mut param_strings = Vec::new();
-        for (name, ty) in &func.params {
-            let c_ty = self.type_to_c(ty);
-            param_strings.push(format!("{} {}", c_ty, name));
-            self.variables.borrow_mut().insert(name.clone(), ty.clone());
-        }
-        let params = param_strings.join(", ");
-
-        self.body.push_str(&format!("{} {}({}) {{\n", return_type, func.name, params));
+        let params = if func.name == "main" {
+            self.main_params().to_string()
+        } else {
+            func.params.iter()
+                .map(|(name, ty)| format!("{} {}", self.type_to_c(ty), mangle(name)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
 
-        for stmt in &func.body {
-            self.emit_stmt(stmt)?;
+        self.body.push_str(&format!(
+            "{}{} {}{}({}) {{\n",
+            self.linkage(func), return_type, self.call_conv(func.call_conv), self.symbol(&func.name), params
+        ));
+        if func.name == "main" {
+            self.emit_main_prologue();
         }
+        self.temps.reset();
+        self.return_type = return_type;
+        self.function = func.name.clone();
+        if self.config.instrument_functions {
+            self.body.push_str(&format!("    verve_profile_enter({});\n", id));
+            self.exit_hooks.insert_str(0, "verve_profile_exit(); ");
+        }
+        if self.config.max_call_depth.is_some() {
+            self.body.push_str(&format!(
+                "    if (++verve_call_depth > VERVE_MAX_CALL_DEPTH) verve_stack_overflow(\"{}\");\n",
+                func.name
+            ));
+            self.exit_hooks.insert_str(0, "verve_call_depth--; ");
+        }
+        self.tail_call = tailcall::has_tail_call(&func.body).then(|| {
+            let label = self.temps.label("tail");
+            self.body.push_str(&format!("{}:;\n", label));
+            (label, func.params.clone())
+        });
 
-        if func.name == "main" {
-            #[cfg(target_os = "windows")]
-            self.body.push_str("    system(\"pause\");\n");
-            #[cfg(not(target_os = "windows"))]
-            self.body.push_str("    getchar();\n");
+        let body = self.nested(|this| {
+            for (name, ty) in &func.params {
+                this.declare(name, ty.clone());
+            }
+            func.body.iter().try_for_each(|stmt| this.emit_stmt(stmt))
+        })?;
+        self.body.push_str(&body);
 
-            let last_is_return = func.body.last().is_some_and(|s| matches!(s, ast::Stmt::Return(..)));
+        let falls_through = !ast::always_returns(&func.body);
+        if func.name == "main" {
+            self.emit_pause();
 
-            if !last_is_return {
-                self.body.push_str("    return 0;\n");
+            // Reaching the end of `main` exits successfully.
+            if falls_through {
+                self.emit_return("0");
+            }
+        } else {
+            if !self.exit_hooks.is_empty() && falls_through {
+                self.body.push_str(&format!("    {}\n", self.exit_hooks.trim_end()));
+            }
+            if func.return_type == Type::Void {
+                self.body.push_str("    return;\n");
             }
-        } else if func.return_type == Type::Void {
-            self.body.push_str("    return;\n");
         }
+        self.exit_hooks.clear();
+        self.tail_call = None;
 
         self.body.push_str("}\n\n");
         Ok(())
     }
 
+    /// Every statement kind has its own arm, with no wildcard, so adding one
+    /// to the AST does not build until it is emitted here or reported.
     fn emit_stmt(&mut self, stmt: &ast::Stmt) -> Result<(), CompileError> {
+        if self.config.line_directives {
+            let offset = stmt.span().start().to_usize();
+            let source = self.config.source_at(offset);
+            let (line, _) = source.location(offset);
+            self.body.push_str(&format!("#line {} {}\n", line, source.name_literal()));
+        }
+        let start = self.body.len();
+        let outer = std::mem::take(&mut self.prelude);
         match stmt {
             ast::Stmt::Let(name, ty, expr, _) => {
                 let var_type = if let Some(ty) = ty {
                     ty.clone()
                 } else {
-                    match expr {
-                        ast::Expr::Call(func_name, _, _, _) => {
-                            self.functions_map.get(func_name)
-                                .cloned()
-                                .unwrap_or(Type::Unknown)
-                        }
-                        _ => expr.get_type()
-                    }
+                    self.expr_type(expr)
                 };
                 let c_ty = self.type_to_c(&var_type);
-                let expr_code = self.emit_expr(expr)?;
-                self.body.push_str(&format!("{} {} = {};\n", c_ty, name, expr_code));
-                self.variables.borrow_mut().insert(name.clone(), var_type);
+                let expr_code = self.emit_operand(expr, ASSIGN_PREC)?;
+                self.body.push_str(&format!("{} {} = {};\n", c_ty, mangle(name), expr_code));
+                self.declare(name, var_type);
+            }
+            ast::Stmt::Static(name, ty, expr, _) => {
+                let Some(value) = self.emit_const_expr(expr, &HashMap::new()) else {
+                    return Err(CompileError::CodegenError {
+                        message: format!("Non-constant initializer for static '{}'", name),
+                        span: Some(expr.span()),
+                        file_id: self.file_id,
+                    });
+                };
+                let var_type = ty.clone().unwrap_or_else(|| self.expr_type(expr));
+                let qualifier = if self.volatile_statics { "volatile " } else { "" };
+                self.body.push_str(&format!(
+                    "static {}{} {} = {};\n",
+                    qualifier, self.type_to_c(&var_type), mangle(name), value
+                ));
+                self.declare(name, var_type);
             }
             ast::Stmt::Return(expr, _) => {
-                let expr_code = self.emit_expr(expr)?;
-                self.body.push_str(&format!("return {};\n", expr_code));
+                let expr_code = self.emit_operand(expr, ASSIGN_PREC)?;
+                self.emit_return(&expr_code);
             },
             ast::Stmt::Expr(expr, _) => {
-                let expr_code = self.emit_expr(expr)?;
+                let expr_code = self.emit_operand(expr, ASSIGN_PREC)?;
                 if expr_code.starts_with('{') {
                     self.body.push_str(&expr_code);
                 } else if !expr_code.ends_with(';') {
@@ -193,86 +2193,268 @@ impl CBackend {
                 }
             },
             ast::Stmt::While(cond, body, _) => {
-                let cond_code = self.emit_expr(cond)?;
-                self.body.push_str(&format!("while ({}) {{\n", cond_code));
-                for stmt in body {
-                    self.emit_stmt(stmt)?;
+                // C compilers warn about assignments used as conditions.
+                let (prelude, cond_code) = self.hoisting(|this| this.emit_operand(cond, EQUALITY_PREC))?;
+                let (body_code, exit) = self.in_loop(|this| this.emit_block(body))?;
+                if prelude.is_empty() {
+                    self.body.push_str(&format!("while ({}) {{\n{}}}\n", cond_code, body_code));
+                } else {
+                    // The hoisted code has to run again before every test,
+                    // which `continue` also goes back to.
+                    self.body.push_str(&format!(
+                        "while (1) {{\n{}if (!({})) break;\n{}}}\n",
+                        prelude, cond_code, body_code
+                    ));
                 }
-                self.body.push_str("}\n");
+                self.emit_loop_exit(exit);
             },
             ast::Stmt::For(var_name, range, body, _) => {
                 let range_code = self.emit_expr(range)?;
-                self.body.push_str(&format!("for (int {} = 0; {} < {}; {}++) {{\n", var_name, var_name, range_code, var_name));
-                for stmt in body {
-                    self.emit_stmt(stmt)?;
-                }
-                self.body.push_str("}\n");
+                let (body_code, exit) = self.in_loop(|this| this.nested(|this| {
+                    this.declare(var_name, Type::I32);
+                    body.iter().try_for_each(|stmt| this.emit_stmt(stmt))
+                }))?;
+                let var_name = mangle(var_name);
+                self.body.push_str(&format!(
+                    "for (int {} = 0; {} < {}; {}++) {{\n{}}}\n",
+                    var_name, var_name, range_code, var_name, body_code
+                ));
+                self.emit_loop_exit(exit);
             },
+            ast::Stmt::Break(span) => {
+                let code = self.jump_code(Jump::Break, *span)?;
+                self.body.push_str(&code);
+            }
+            ast::Stmt::Continue(span) => {
+                let code = self.jump_code(Jump::Continue, *span)?;
+                self.body.push_str(&code);
+            }
             ast::Stmt::If(cond, then_branch, else_branch, _) => {
-                let cond_code = self.emit_expr(cond)?;
-                self.body.push_str(&format!("if ({}) {{\n", cond_code));
-
-                for stmt in then_branch {
-                    self.emit_stmt(stmt)?;
-                }
-                self.body.push('}');
+                let cond_code = self.emit_operand(cond, EQUALITY_PREC)?;
+                let then_code = self.emit_block(then_branch)?;
+                self.body.push_str(&format!("if ({}) {{\n{}}}", cond_code, then_code));
 
                 if let Some(else_body) = else_branch {
-                    self.body.push_str(" else {\n");
-                    for stmt in else_body {
-                        self.emit_stmt(stmt)?;
-                    }
-                    self.body.push('}');
+                    let else_code = self.emit_block(else_body)?;
+                    self.body.push_str(&format!(" else {{\n{}}}", else_code));
                 }
 
                 self.body.push('\n');
             }
-            _ => unimplemented!(),
+            ast::Stmt::Block(stmts, _) => {
+                let code = self.emit_block(stmts)?;
+                self.body.push_str(&format!("{{\n{}}}\n", code));
+            }
+            // With every variant listed there is no default, so the C
+            // compiler's -Wswitch checks the cases against the enum too.
+            ast::Stmt::Switch(value, enum_name, cases, default, _) => {
+                let value_code = self.emit_operand(value, EQUALITY_PREC)?;
+                let tag = if self.plain_enums.contains(enum_name) {
+                    value_code
+                } else {
+                    format!("{}.tag", value_code)
+                };
+                let mut code = format!("switch ({}) {{\n", tag);
+                if let Some(exit) = self.loops.last_mut() {
+                    exit.switches += 1;
+                }
+                let mut case = |label: String, body: &[ast::Stmt]| -> Result<(), CompileError> {
+                    let body_code = self.emit_block(body)?;
+                    let jumps = matches!(body.last(), Some(ast::Stmt::Break(_) | ast::Stmt::Continue(_)));
+                    let exit = if jumps || ast::always_returns(body) { "" } else { "break;\n" };
+                    code.push_str(&format!("{}: {{\n{}{}}}\n", label, body_code, exit));
+                    Ok(())
+                };
+                let emitted = cases
+                    .iter()
+                    .map(|(variant, body)| (format!("case {}", mangle_variant(enum_name, variant)), body.as_slice()))
+                    .chain(default.as_deref().map(|default| ("default".to_string(), default)))
+                    .try_for_each(|(label, body)| case(label, body));
+                if let Some(exit) = self.loops.last_mut() {
+                    exit.switches -= 1;
+                }
+                emitted?;
+                code.push_str("}\n");
+                // Only a corrupted tag gets past a switch whose cases all
+                // return; saying so keeps -Wreturn-type quiet.
+                if default.is_none() && stmt.always_returns() {
+                    code.push_str(&format!("{};\n", self.unreachable()));
+                }
+                self.body.push_str(&code);
+            }
+            ast::Stmt::TailCall(args, _) => {
+                let Some((label, params)) = self.tail_call.clone() else {
+                    return Err(CompileError::CodegenError {
+                        message: "Tail call outside the function it jumps back into".to_string(),
+                        span: Some(stmt.span()),
+                        file_id: self.file_id,
+                    });
+                };
+                // A parameter that a later argument reads is only updated
+                // once every argument has been evaluated; the others can be
+                // assigned right away.
+                let mut code = String::from("{\n");
+                let mut assigns = String::new();
+                for (i, ((name, ty), arg)) in params.iter().zip(args).enumerate() {
+                    if matches!(arg, ast::Expr::Var(var, _, _) if var == name) {
+                        continue;
+                    }
+                    let value = self.emit_operand(arg, ASSIGN_PREC)?;
+                    if args[i + 1..].iter().any(|later| Self::reads(later, name)) {
+                        let temp = self.temps.temp("arg");
+                        code.push_str(&format!("{} {} = {};\n", self.type_to_c(ty), temp, value));
+                        assigns.push_str(&format!("{} = {};\n", mangle(name), temp));
+                    } else {
+                        code.push_str(&format!("{} = {};\n", mangle(name), value));
+                    }
+                }
+                code.push_str(&assigns);
+                code.push_str(&format!("goto {};\n}}\n", label));
+                self.body.push_str(&code);
+            }
+            // The backend only sees the output of `lower::lower_matches`;
+            // a program that skipped it is reported, not a crash.
+            ast::Stmt::LetPattern(..)
+            | ast::Stmt::IfLet(..)
+            | ast::Stmt::WhileLet(..)
+            | ast::Stmt::ForPattern(..)
+            | ast::Stmt::Match(..) => {
+                return Err(CompileError::CodegenError {
+                    message: "Pattern matching reached code generation without being lowered".to_string(),
+                    span: Some(stmt.span()),
+                    file_id: self.file_id,
+                });
+            }
+            // Safe blocks emit their own defers at the end of the block.
+            ast::Stmt::Defer(..) => {
+                return Err(CompileError::CodegenError {
+                    message: "defer is only allowed directly inside a safe block".to_string(),
+                    span: Some(stmt.span()),
+                    file_id: self.file_id,
+                });
+            }
         }
+        let prelude = std::mem::replace(&mut self.prelude, outer);
+        self.body.insert_str(start, &prelude);
         Ok(())
     }
 
+    /// Runs `f`, returning the code it hoisted out of block expressions
+    /// separately instead of leaving it for the enclosing statement.
+    fn hoisting<T, F>(&mut self, f: F) -> Result<(String, T), CompileError>
+    where
+        F: FnOnce(&mut Self) -> Result<T, CompileError>,
+    {
+        let outer = std::mem::take(&mut self.prelude);
+        let result = f(self);
+        let prelude = std::mem::replace(&mut self.prelude, outer);
+        result.map(|value| (prelude, value))
+    }
+
+    /// Runs `f` to emit a loop body, also returning the label that breaks
+    /// out of it jump to, if any do.
+    fn in_loop<T, F>(&mut self, f: F) -> Result<(T, Option<String>), CompileError>
+    where
+        F: FnOnce(&mut Self) -> Result<T, CompileError>,
+    {
+        self.loops.push(LoopExit::default());
+        let result = f(self);
+        let exit = self.loops.pop().and_then(|exit| exit.label);
+        result.map(|code| (code, exit))
+    }
+
+    fn emit_loop_exit(&mut self, label: Option<String>) {
+        if let Some(label) = label {
+            self.body.push_str(&format!("{}:;\n", label));
+        }
+    }
+
+    /// Code for `jump` to the innermost loop. Leaving a safe block with
+    /// deferred statements goes through its cleanup, which then jumps on.
+    /// A `switch` leaves `continue` to the loop around it.
+    fn jump_code(&mut self, jump: Jump, span: Span) -> Result<String, CompileError> {
+        let keyword = if jump == Jump::Break { "break" } else { "continue" };
+        let loops = self.loops.len();
+        // The type checker only lets `break` and `continue` into loop bodies.
+        let Some(exit) = self.loops.last_mut() else {
+            return Err(CompileError::CodegenError {
+                message: format!("{} outside of a loop body", keyword),
+                span: Some(span),
+                file_id: self.file_id,
+            });
+        };
+        if let Some(cleanup) = self.cleanups.last_mut().filter(|cleanup| cleanup.loops == loops) {
+            let flag = cleanup.jump.get_or_insert_with(|| self.temps.temp("jump"));
+            let code = format!("{{ {} = {}; goto {}; }}\n", flag, jump as i32, cleanup.label);
+            if !cleanup.jumps.contains(&jump) {
+                cleanup.jumps.push(jump);
+            }
+            return Ok(code);
+        }
+        if jump == Jump::Break && exit.switches > 0 {
+            let label = exit.label.get_or_insert_with(|| self.temps.label("break"));
+            return Ok(format!("goto {};\n", label));
+        }
+        Ok(format!("{};\n", keyword))
+    }
+
+    fn emit_return(&mut self, value: &str) {
+        let code = self.return_code(value);
+        self.body.push_str(&code);
+    }
+
+    /// Returns `value` from the current function. Inside a safe block with
+    /// deferred statements this jumps to the block's cleanup label instead.
+    /// With exit hooks, `value` is evaluated before they run, so time spent
+    /// computing it is attributed to the function and calls made computing it
+    /// count toward the call depth.
+    fn return_code(&self, value: &str) -> String {
+        if let Some(Cleanup { label, returning: Some((returning, slot)), .. }) = self.cleanups.last() {
+            return format!("{{ {} = {}; {} = 1; goto {}; }}\n", slot, value, returning, label);
+        }
+        if !self.exit_hooks.is_empty() {
+            let ret = self.temps.temp("ret");
+            return format!(
+                "{{ {} {} = {}; {}return {}; }}\n",
+                self.return_type, ret, value, self.exit_hooks, ret
+            );
+        }
+        format!("return {};\n", value)
+    }
+
     fn emit_expr(&mut self, expr: &ast::Expr) -> Result<String, CompileError> {
         match expr {
             ast::Expr::Int(n, _, _) => Ok(n.to_string()),
-            ast::Expr::BinOp(left, op, right, _span, _) => {
-                let left_code = self.emit_expr(left)?;
-                let right_code = self.emit_expr(right)?;
-
-                let result_type = self.unify_types(
-                    &left.get_type(),
-                    &right.get_type(),
-                    expr.span()
-                )?;
-
-
-                let op_str = match op {
-                    ast::BinOp::Add => "+",
-                    ast::BinOp::Sub => "-",
-                    ast::BinOp::Mul => "*",
-                    ast::BinOp::Div => "/",
-                    ast::BinOp::Gt => ">",
-                    ast::BinOp::Eq => "==",
-                    ast::BinOp::Lt => "<",
-                };
-                Ok(format!("({} {} {})", left_code, op_str, right_code))
-            },
-            ast::Expr::Assign(target, value, _, _) => {
-                let target_code = self.emit_expr(target)?;
-                let value_code = self.emit_expr(value)?;
-                Ok(format!("({} = {})", target_code, value_code))
+            ast::Expr::BinOp(..) | ast::Expr::Assign(..) => {
+                let code = self.emit_operand(expr, ASSIGN_PREC)?;
+                Ok(format!("({})", code))
             },
             ast::Expr::Str(s, _, _) => Ok(format!("\"{}\"", s)),
+            ast::Expr::Bool(value, _, _) => {
+                self.includes.borrow_mut().insert("<stdbool.h>");
+                Ok(value.to_string())
+            },
             ast::Expr::Var(name, _, _) => {
                 if name == "true" || name == "false" {
                     self.includes.borrow_mut().insert("<stdbool.h>");
                     Ok(name.clone())
+                } else if name == "None" && self.lookup(name).is_none() {
+                    match expr.get_type() {
+                        ty @ Type::Option(_) => Ok(format!("({}){{ .is_some = false }}", self.type_to_c(&ty))),
+                        _ => Err(CompileError::CodegenError {
+                            message: "Cannot infer the Option type of None".to_string(),
+                            span: Some(expr.span()),
+                            file_id: self.file_id,
+                        }),
+                    }
                 } else {
-                    let var_type = self.variables.borrow().get(name).cloned().unwrap_or(Type::Unknown);
+                    let var_type = self.lookup(name).unwrap_or(Type::Unknown);
                     match var_type {
-                        Type::I32 => Ok(name.clone()),
-                        Type::Bool => Ok(name.clone()),
-                        Type::String => Ok(name.clone()),
+                        Type::Unknown if self.functions_map.contains_key(name) => Ok(self.symbol(name).into_owned()),
+                        Type::I32 | Type::Bool | Type::String => Ok(mangle(name).into_owned()),
+                        Type::Pointer(_) | Type::RawPtr | Type::Named(_) | Type::Option(_) | Type::Tuple(_) | Type::Function(..) => {
+                            Ok(mangle(name).into_owned())
+                        }
                         _ => Err(CompileError::CodegenError {
                             message: format!("Cannot print type {:?}", var_type),
                             span: Some(expr.span()),
@@ -282,16 +2464,8 @@ impl CBackend {
                 }
             },
             ast::Expr::Print(expr, _span, _) => {
-                let value = self.emit_expr(expr)?;
-                let expr_ty = match &**expr {
-                    ast::Expr::Var(name, _, _) => {
-                        self.variables.borrow()
-                            .get(name)
-                            .cloned()
-                            .unwrap_or(Type::Unknown)
-                    }
-                    _ => expr.get_type(),
-                };
+                let value = self.emit_operand(expr, EQUALITY_PREC)?;
+                let expr_ty = expr.get_type();
 
                 let (format_spec, arg) = match expr_ty {
                     Type::I32 => ("%d", value),
@@ -299,8 +2473,12 @@ impl CBackend {
                     Type::String => ("%s", value),
                     Type::Pointer(_) | Type::RawPtr => {
                         self.includes.borrow_mut().insert("<inttypes.h>");
-                        ("%\"PRIuPTR\"", format!("(uintptr_t){}", value))
+                        ("%\"PRIuPTR\"", format!("(uintptr_t)({})", value))
                     },
+                    Type::Named(_) | Type::Option(_) | Type::Tuple(_) => {
+                        let formatter = self.emit_debug_formatter(&expr_ty);
+                        return Ok(format!("({}(stdout, {}), putchar('\\n'));", formatter, value));
+                    }
                     _ => return Err(CompileError::CodegenError {
                         message: format!("Cannot print type {:?}", expr_ty),
                         span: Some(expr.span()),
@@ -309,76 +2487,173 @@ impl CBackend {
                 };
                 Ok(format!("printf(\"{}\\n\", {});", format_spec, arg))
             },
+            ast::Expr::Call(name, args, _, ty) if name == "Some" && !self.functions_map.contains_key(name) => {
+                let value = self.emit_operand(&args[0], EQUALITY_PREC)?;
+                Ok(format!("({}){{ .is_some = true, .value = {} }}", self.type_to_c(ty), value))
+            },
+            ast::Expr::Call(name, args, _, _)
+                if (name == "likely" || name == "unlikely") && !self.functions_map.contains_key(name) =>
+            {
+                let cond = self.emit_expr(&args[0])?;
+                Ok(self.expect(&cond, name == "likely"))
+            },
+            ast::Expr::Call(name, args, span, _)
+                if (name == "assert_eq" || name == "assert_ne") && !self.functions_map.contains_key(name) =>
+            {
+                let check = self.emit_assert_function(&self.expr_type(&args[0]));
+                let left = self.emit_operand(&args[0], EQUALITY_PREC)?;
+                let right = self.emit_operand(&args[1], EQUALITY_PREC)?;
+                let offset = span.start().to_usize();
+                let site = self.config.source_at(offset).site_literal(offset);
+                Ok(format!("{}({}, {}, {}, {})", check, left, right, name == "assert_eq", site))
+            },
             ast::Expr::Call(name, args, _, _) => {
                 let mut args_code = Vec::new();
                 for arg in args {
-                    args_code.push(self.emit_expr(arg)?);
+                    args_code.push(self.emit_operand(arg, EQUALITY_PREC)?);
                 }
-                Ok(format!("{}({})", name, args_code.join(", ")))
+                let callee = match self.lookup(name) {
+                    Some(Type::Function(..)) => mangle(name),
+                    _ => self.symbol(name),
+                };
+                Ok(format!("{}({})", callee, args_code.join(", ")))
             },
-            ast::Expr::IntrinsicCall(name, args, span, _) => match name.as_str() {
-                "__alloc" => {
-                    if args.len() != 1 {
-                        return Err(CompileError::CodegenError {
-                            message: "__alloc expects 1 argument".to_string(),
-                            span: Some(*span),
-                            file_id: self.file_id,
-                        });
+            ast::Expr::IntrinsicCall(name, args, span, _) => {
+                let error = |message: String| CompileError::CodegenError {
+                    message,
+                    span: Some(*span),
+                    file_id: self.file_id,
+                };
+                let Some(intrinsic) = intrinsics::lookup(name) else {
+                    return Err(error(format!("Unknown intrinsic function: {}", name)));
+                };
+                // The type checker reports these; lowered code can still
+                // get here wrong.
+                let arity = intrinsic.arity();
+                if args.len() != arity {
+                    let plural = if arity == 1 { "" } else { "s" };
+                    return Err(error(format!("{} expects {} argument{}, got {}", name, arity, plural, args.len())));
+                }
+                match intrinsic.runtime {
+                    Runtime::Builtin => match BUILTINS.iter().find(|(builtin, _)| builtin == name) {
+                        Some((_, lower)) => lower(self, args, *span),
+                        None => Err(error(format!("{} cannot be compiled on its own", name))),
+                    },
+                    // WASI has no calls to listen on or connect a socket.
+                    Runtime::Net if self.config.is_wasi() => {
+                        Err(error(format!("{} is not available on {}", name, self.config.target_triple)))
                     }
-                    let size = self.emit_expr(&args[0])?;
-                    Ok(format!("malloc({})", size))
-                },
-                "__dealloc" => {
-                    if args.len() != 1 {
-                        return Err(CompileError::CodegenError {
-                            message: "__dealloc expects 1 argument".to_string(),
-                            span: Some(*span),
-                            file_id: self.file_id,
-                        });
+                    runtime => {
+                        self.emit_runtime(runtime);
+                        let args = args.iter()
+                            .map(|arg| self.emit_operand(arg, EQUALITY_PREC))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        Ok(format!("{}({})", intrinsic.runtime_function(), args.join(", ")))
                     }
-                    let ptr = self.emit_expr(&args[0])?;
-                    Ok(format!("free({})", ptr))
                 }
-                _ => Err(CompileError::CodegenError {
-                    message: format!("Unknown intrinsic function: {}", name),
-                    span: Some(*span),
-                    file_id: self.file_id,
-                }),
             },
-            ast::Expr::SafeBlock(stmts, _span, _) => {
-                let mut code = String::new();
-                code.push_str("{\n");
-                let mut defers = Vec::new();
+            ast::Expr::SafeBlock(stmts, span, _) => {
+                let has_defers = stmts.iter().any(|s| matches!(s, ast::Stmt::Defer(..)));
+                let cleanup = has_defers.then(|| Cleanup {
+                    label: self.temps.label("cleanup"),
+                    returning: (self.return_type != "void")
+                        .then(|| (self.temps.temp("returning"), self.temps.temp("retval"))),
+                    jump: None,
+                    jumps: Vec::new(),
+                    loops: self.loops.len(),
+                });
 
-                for stmt in stmts {
-                    match stmt {
-                        ast::Stmt::Defer(expr, _) => {
-                            let expr_code = self.emit_expr(expr)?;
-                            defers.push(expr_code);
-                        },
-                        _ => {
-                            let stmt_code = self.emit_stmt_to_string(stmt)?;
-                            code.push_str(&stmt_code);
+                // Deferred expressions are emitted in the block's scope so
+                // they can refer to its variables.
+                let has_cleanup = cleanup.is_some();
+                self.cleanups.extend(cleanup);
+                let mut defers = Vec::new();
+                let body = self.nested(|this| {
+                    for stmt in stmts {
+                        match stmt {
+                            ast::Stmt::Defer(expr, _) => {
+                                let (prelude, code) = this.hoisting(|this| this.emit_expr(expr))?;
+                                defers.push(format!("{}{}", prelude, code));
+                            }
+                            _ => this.emit_stmt(stmt)?,
                         }
                     }
+                    Ok(())
+                });
+                let cleanup = if has_cleanup { self.cleanups.pop() } else { None };
+                let mut code = String::from("{\n");
+                if let Some(cleanup) = &cleanup {
+                    if let Some((returning, slot)) = &cleanup.returning {
+                        code.push_str(&format!("int {} = 0;\n", returning));
+                        code.push_str(&format!("{} {} = {{0}};\n", self.return_type, slot));
+                    }
+                    if let Some(jump) = &cleanup.jump {
+                        code.push_str(&format!("int {} = 0;\n", jump));
+                    }
                 }
+                code.push_str(&body?);
 
+                // Every exit from the block, normal or by return, break or
+                // continue, runs the deferred statements in reverse order.
+                let used = |cleanup: &&Cleanup| cleanup.returning.is_some() || cleanup.jump.is_some();
+                if let Some(cleanup) = cleanup.as_ref().filter(used) {
+                    code.push_str(&format!("{}:;\n", cleanup.label));
+                }
                 for deferred in defers.into_iter().rev() {
                     code.push_str(&format!("{};\n", deferred));
                 }
+                if let Some(cleanup) = &cleanup {
+                    if let Some((returning, slot)) = &cleanup.returning {
+                        code.push_str(&format!("if ({}) {}", returning, self.return_code(slot)));
+                    }
+                    if let Some(flag) = &cleanup.jump {
+                        for &jump in &cleanup.jumps {
+                            let on = self.jump_code(jump, *span)?;
+                            code.push_str(&format!("if ({} == {}) {}", flag, jump as i32, on));
+                        }
+                    }
+                }
 
                 code.push_str("}\n");
                 Ok(code)
             },
+            ast::Expr::Block(stmts, value, _, _) => {
+                let mut value_code = String::new();
+                let code = self.nested(|this| {
+                    stmts.iter().try_for_each(|stmt| this.emit_stmt(stmt))?;
+                    let (prelude, code) = this.hoisting(|this| this.emit_operand(value, ASSIGN_PREC))?;
+                    this.body.push_str(&prelude);
+                    value_code = code;
+                    Ok(())
+                })?;
+                if self.config.gnu_extensions {
+                    return Ok(format!("({{\n{}{};\n}})", code, value_code));
+                }
+                // Without statement expressions the block runs ahead of the
+                // statement and leaves its value in a temporary.
+                let temp = self.temps.temp("block");
+                let c_ty = self.type_to_c(&self.expr_type(expr));
+                self.prelude.push_str(&format!(
+                    "{} {};\n{{\n{}{} = {};\n}}\n",
+                    c_ty, temp, code, temp, value_code
+                ));
+                Ok(temp)
+            }
+            ast::Expr::If(cond, then_value, else_value, _, _) => {
+                let (code, ternary) = self.emit_conditional(expr, cond, then_value, else_value)?;
+                Ok(if ternary { format!("({})", code) } else { code })
+            }
             ast::Expr::Deref(expr, _, _) => {
                 let inner = self.emit_expr(expr)?;
                 Ok(format!("(*{})", inner))
             }
-            ast::Expr::Cast(expr, target_ty, _, _) => {
-                let expr_code = self.emit_expr(expr)?;
-                let expr_type = expr.get_type();
-
-                let target_c_ty = if expr_type.is_pointer() && *target_ty == Type::I32 {
+            ast::Expr::Cast(inner, target_ty, _, _) => {
+                let mut expr_code = self.emit_expr(inner)?;
+                if let Type::Named(name) = self.expr_type(inner)
+                    && !self.plain_enums.contains(&name) {
+                    expr_code = format!("{}.tag", expr_code);
+                }
+                let target_c_ty = if expr.is_pointer_cast() {
                     self.includes.borrow_mut().insert("<stdint.h>");
                     "uintptr_t".to_string()
                 } else {
@@ -387,16 +2662,233 @@ impl CBackend {
 
                 Ok(format!("({})({})", target_c_ty, expr_code))
             },
+            // Only emitted as the bound of a `for` loop, `i < end - start`.
             ast::Expr::Range(start, end, _, _) => {
-                let start_code = self.emit_expr(start)?;
-                let end_code = self.emit_expr(end)?;
+                let end_code = self.emit_operand(end, ADDITIVE_PREC)?;
+                if matches!(**start, ast::Expr::Int(0, _, _)) {
+                    return Ok(end_code);
+                }
+                let start_code = self.emit_operand(start, MULTIPLICATIVE_PREC)?;
                 Ok(format!("{} - {}", end_code, start_code))
             },
-            _ => Err(CompileError::CodegenError {
-                message: "Unsupported expression".to_string(),
-                span: Some(expr.span()),
-                file_id: self.file_id,
-            }),
+            ast::Expr::StructLit(name, fields, _, _, _) => {
+                let slots = self.bitfields.get(name).cloned().unwrap_or_default();
+                let mut inits = Vec::new();
+                let mut units: Vec<(String, Vec<String>)> = Vec::new();
+                for (field, value) in fields {
+                    let value_code = self.emit_operand(value, EQUALITY_PREC)?;
+                    match slots.get(field) {
+                        Some(slot) => match units.iter_mut().find(|(unit, _)| *unit == slot.unit) {
+                            Some((_, parts)) => parts.push(slot.pack(&value_code)),
+                            None => units.push((slot.unit.clone(), vec![slot.pack(&value_code)])),
+                        },
+                        None => inits.push(format!(".{} = {}", mangle(field), value_code)),
+                    }
+                }
+                for (unit, parts) in units {
+                    inits.push(format!(".{} = {}", unit, parts.join(" | ")));
+                }
+                Ok(format!("({}){{ {} }}", mangle(name), inits.join(", ")))
+            },
+            ast::Expr::Field(base, index, _, _) if matches!(self.expr_type(base), Type::Tuple(_)) => {
+                let base_code = self.emit_expr(base)?;
+                Ok(format!("{}._{}", base_code, index))
+            },
+            ast::Expr::Field(base, field, _, _) => {
+                let base_code = self.emit_expr(base)?;
+                let accessor = if self.expr_type(base).is_pointer() { "->" } else { "." };
+                match self.bitfield_slot(base, field) {
+                    Some(slot) => Ok(slot.read(&format!("{}{}{}", base_code, accessor, slot.unit))),
+                    None => Ok(format!("{}{}{}", base_code, accessor, mangle(field))),
+                }
+            },
+            ast::Expr::Cfg(..) => unreachable!("cfg is folded before code generation"),
+            ast::Expr::IsVariant(value, enum_name, variant, _, _) => {
+                let value_code = self.emit_expr(value)?;
+                Ok(match (enum_name.as_str(), variant.as_str()) {
+                    ("Option", "Some") => format!("({}).is_some", value_code),
+                    ("Option", _) => format!("!({}).is_some", value_code),
+                    _ if self.plain_enums.contains(enum_name) => {
+                        format!("({} == {})", value_code, mangle_variant(enum_name, variant))
+                    }
+                    _ => format!("({}.tag == {})", value_code, mangle_variant(enum_name, variant)),
+                })
+            },
+            ast::Expr::VariantField(value, enum_name, variant, index, _, _) => {
+                let value_code = self.emit_expr(value)?;
+                if enum_name == "Option" {
+                    Ok(format!("({}).value", value_code))
+                } else {
+                    Ok(format!("({}).as.{}._{}", value_code, variant, index))
+                }
+            },
+            ast::Expr::Tuple(items, _, ty) => {
+                let mut inits = Vec::new();
+                for (i, item) in items.iter().enumerate() {
+                    inits.push(format!("._{} = {}", i, self.emit_operand(item, EQUALITY_PREC)?));
+                }
+                Ok(format!("({}){{ {} }}", self.type_to_c(ty), inits.join(", ")))
+            },
+            ast::Expr::EnumVariant(enum_name, variant, args, _, Type::Option(_) | Type::String) => {
+                let value = self.emit_expr(&args[0])?;
+                Ok(format!("{}_{}({})", enum_name, variant, value))
+            },
+            ast::Expr::EnumVariant(enum_name, variant, args, _, _) => {
+                let tag = mangle_variant(enum_name, variant);
+                if self.plain_enums.contains(enum_name) {
+                    return Ok(tag);
+                }
+                if args.is_empty() {
+                    return Ok(format!("({}){{ .tag = {} }}", mangle(enum_name), tag));
+                }
+                let mut values = Vec::new();
+                for arg in args {
+                    values.push(self.emit_expr(arg)?);
+                }
+                Ok(format!(
+                    "({}){{ .tag = {}, .as.{} = {{ {} }} }}",
+                    mangle(enum_name), tag, variant, values.join(", ")
+                ))
+            },
+        }
+    }
+
+    /// Emits `expr` for a position where the surrounding C binds with
+    /// precedence `min`: binary operators and assignments that bind at least
+    /// as tightly are left bare, everything else goes through [`emit_expr`],
+    /// which parenthesizes them.
+    ///
+    /// [`emit_expr`]: Self::emit_expr
+    fn emit_operand(&mut self, expr: &ast::Expr, min: u8) -> Result<String, CompileError> {
+        match expr {
+            ast::Expr::BinOp(left, op, right, _, _) if Self::precedence(op) >= min => {
+                if let Some(code) = self.emit_derived_comparison(left, op, right)? {
+                    return Ok(code);
+                }
+                let prec = Self::precedence(op);
+                // C's binary operators group left to right, so only a right
+                // operand of the same precedence needs parentheses. Operands
+                // of comparisons keep theirs if they are comparisons too,
+                // which C compilers warn about.
+                let (left_min, right_min) = match op {
+                    ast::BinOp::Gt | ast::BinOp::Lt | ast::BinOp::Eq => (ADDITIVE_PREC, ADDITIVE_PREC),
+                    _ => (prec, prec + 1),
+                };
+                let left_code = self.emit_operand(left, left_min)?;
+                let right_code = self.emit_operand(right, right_min)?;
+                self.unify_types(&left.get_type(), &right.get_type(), expr.span())?;
+                Ok(format!("{} {} {}", left_code, Self::binop_to_c(op), right_code))
+            }
+            ast::Expr::Assign(target, value, _, _) if min == ASSIGN_PREC => {
+                let value_code = self.emit_operand(value, ASSIGN_PREC)?;
+                if let ast::Expr::Field(base, field, _, _) = &**target
+                    && let Some(slot) = self.bitfield_slot(base, field) {
+                    let base_code = self.emit_expr(base)?;
+                    let accessor = if self.expr_type(base).is_pointer() { "->" } else { "." };
+                    let unit = format!("{}{}{}", base_code, accessor, slot.unit);
+                    return Ok(format!(
+                        "{} = ({} & ~({} << {})) | {}",
+                        unit, unit, slot.mask(), slot.offset, slot.pack(&value_code)
+                    ));
+                }
+                let target_code = self.emit_expr(target)?;
+                Ok(format!("{} = {}", target_code, value_code))
+            }
+            ast::Expr::If(cond, then_value, else_value, _, _) if min == ASSIGN_PREC => {
+                Ok(self.emit_conditional(expr, cond, then_value, else_value)?.0)
+            }
+            ast::Expr::BinOp(..) | ast::Expr::Assign(..) => {
+                let code = self.emit_operand(expr, ASSIGN_PREC)?;
+                Ok(format!("({})", code))
+            }
+            _ => self.emit_expr(expr),
+        }
+    }
+
+    /// An if-expression as `cond ? a : b`, and `true`, when neither arm has
+    /// statements to run. Otherwise the arms run in an `if` ahead of the
+    /// statement, like block expressions without GNU extensions, and the
+    /// value is left in a temporary.
+    fn emit_conditional(&mut self, expr: &ast::Expr, cond: &ast::Expr, then_value: &ast::Expr, else_value: &ast::Expr) -> Result<(String, bool), CompileError> {
+        let cond_code = self.emit_operand(cond, EQUALITY_PREC)?;
+        let (then_code, then_result) = self.emit_arm(then_value)?;
+        let (else_code, else_result) = self.emit_arm(else_value)?;
+        if then_code.is_empty() && else_code.is_empty() {
+            return Ok((format!("{} ? {} : {}", cond_code, then_result, else_result), true));
+        }
+        let temp = self.temps.temp("if");
+        let c_ty = self.type_to_c(&self.expr_type(expr));
+        self.prelude.push_str(&format!(
+            "{} {};\nif ({}) {{\n{}{} = {};\n}} else {{\n{}{} = {};\n}}\n",
+            c_ty, temp, cond_code, then_code, temp, then_result, else_code, temp, else_result
+        ));
+        Ok((temp, false))
+    }
+
+    /// The statements an if-expression arm runs, with the code its value
+    /// hoisted, and the value.
+    fn emit_arm(&mut self, arm: &ast::Expr) -> Result<(String, String), CompileError> {
+        let (stmts, value) = match arm {
+            ast::Expr::Block(stmts, value, _, _) => (stmts.as_slice(), &**value),
+            _ => (&[][..], arm),
+        };
+        let mut value_code = String::new();
+        let code = self.nested(|this| {
+            stmts.iter().try_for_each(|stmt| this.emit_stmt(stmt))?;
+            let (prelude, code) = this.hoisting(|this| this.emit_operand(value, EQUALITY_PREC))?;
+            this.body.push_str(&prelude);
+            value_code = code;
+            Ok(())
+        })?;
+        Ok((code, value_code))
+    }
+
+    /// Whether `expr` may read the variable `name`.
+    fn reads(expr: &ast::Expr, name: &str) -> bool {
+        match expr {
+            ast::Expr::Var(var, _, _) => var == name,
+            ast::Expr::SafeBlock(..) | ast::Expr::Block(..) => true,
+            _ => expr.children().into_iter().any(|child| Self::reads(child, name)),
+        }
+    }
+
+    fn precedence(op: &ast::BinOp) -> u8 {
+        match op {
+            ast::BinOp::Mul | ast::BinOp::Div => MULTIPLICATIVE_PREC,
+            ast::BinOp::Add | ast::BinOp::Sub => ADDITIVE_PREC,
+            ast::BinOp::Gt | ast::BinOp::Lt => RELATIONAL_PREC,
+            ast::BinOp::Eq => EQUALITY_PREC,
+        }
+    }
+
+    fn binop_to_c(op: &ast::BinOp) -> &'static str {
+        match op {
+            ast::BinOp::Add => "+",
+            ast::BinOp::Sub => "-",
+            ast::BinOp::Mul => "*",
+            ast::BinOp::Div => "/",
+            ast::BinOp::Gt => ">",
+            ast::BinOp::Eq => "==",
+            ast::BinOp::Lt => "<",
+        }
+    }
+
+    /// The type checker's annotation, or for expressions built after type
+    /// checking without one, the type of the variable or function named.
+    fn expr_type(&self, expr: &ast::Expr) -> Type {
+        let checked = expr.get_type();
+        if checked != Type::Unknown {
+            return checked;
+        }
+        match expr {
+            ast::Expr::Var(name, _, _) if name == "true" || name == "false" => Type::Bool,
+            ast::Expr::Var(name, _, _) => {
+                self.lookup(name).unwrap_or_else(|| expr.get_type())
+            }
+            ast::Expr::Call(func_name, _, _, _) => {
+                self.functions_map.get(func_name).cloned().unwrap_or_else(|| expr.get_type())
+            }
+            _ => expr.get_type(),
         }
     }
 
@@ -404,6 +2896,7 @@ impl CBackend {
         match (t1, t2) {
             (Type::I32, Type::I32) => Ok(Type::I32),
             (Type::Unknown, t) | (t, Type::Unknown) => Ok(t.clone()),
+            (a, b) if a == b => Ok(a.clone()),
             _ => Err(CompileError::TypeError {
                 message: format!("Type mismatch: {:?} vs {:?}", t1, t2),
                 span: Some(span),
@@ -412,12 +2905,32 @@ impl CBackend {
         }
     }
     
-    fn emit_stmt_to_string(&mut self, stmt: &ast::Stmt) -> Result<String, CompileError> {
-        let mut buffer = String::new();
-        let original_body = std::mem::take(&mut self.body);
-        self.emit_stmt(stmt)?;
-        buffer = std::mem::replace(&mut self.body, original_body);
-        Ok(buffer)
+    /// Runs `f` in a new variable scope with an empty body buffer and returns
+    /// what it emitted, leaving the enclosing body and scope as they were.
+    fn nested<F>(&mut self, f: F) -> Result<String, CompileError>
+    where
+        F: FnOnce(&mut Self) -> Result<(), CompileError>,
+    {
+        let outer = std::mem::take(&mut self.body);
+        self.scopes.borrow_mut().push(HashMap::new());
+        let result = f(self);
+        self.scopes.borrow_mut().pop();
+        let inner = std::mem::replace(&mut self.body, outer);
+        result.map(|()| inner)
+    }
+
+    /// The statements of a block, without the enclosing braces.
+    fn emit_block(&mut self, stmts: &[ast::Stmt]) -> Result<String, CompileError> {
+        self.nested(|this| stmts.iter().try_for_each(|stmt| this.emit_stmt(stmt)))
+    }
+
+    fn declare(&self, name: &str, ty: Type) {
+        let mut scopes = self.scopes.borrow_mut();
+        scopes.last_mut().expect("global scope").insert(name.to_string(), ty);
+    }
+
+    fn lookup(&self, name: &str) -> Option<Type> {
+        self.scopes.borrow().iter().rev().find_map(|scope| scope.get(name).cloned())
     }
 
     fn type_to_c(&self, ty: &Type) -> String {
@@ -434,13 +2947,12 @@ impl CBackend {
                 format!("{}*", inner_type)
             },
             Type::RawPtr => "void*".to_string(),
+            Type::Named(name) => mangle(name).into_owned(),
+            Type::Option(_) | Type::Tuple(_) | Type::Function(..) => {
+                self.pending_generics.borrow_mut().push(ty.clone());
+                Self::mangle_type(ty)
+            },
             _ => "/* UNSUPPORTED TYPE */".to_string(),
         }
     }
-
-    fn write_output(&self) -> Result<(), CompileError> {
-        let full_output = format!("{}{}", self.header, self.body);
-        std::fs::write("output.c", &full_output)?;
-        Ok(())
-    }
 }
\ No newline at end of file
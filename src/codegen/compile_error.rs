@@ -53,7 +53,7 @@ impl CompileError {
                     .with_message(message);
                 if let Some(span) = span {
                     diagnostic = diagnostic.with_labels(vec![
-                        Label::primary(*file_id, span.clone())
+                        Label::primary(*file_id, *span)
                             .with_message("codegen error occurred here")
                     ]);
                 }
@@ -0,0 +1,63 @@
+//! Indentation of generated C. The C backend emits every line at column 0
+//! (or wherever a runtime snippet happens to put it) and indents the whole
+//! output by brace depth at the end.
+
+/// Re-indents `code` by `width` spaces per enclosing brace. Preprocessor
+/// lines and their continuations stay as they are, and braces inside
+/// literals and comments do not count. A width of 0 leaves `code` as is.
+pub fn indent(code: &str, width: usize) -> String {
+    if width == 0 {
+        return code.to_string();
+    }
+    let mut out = String::with_capacity(code.len() + code.len() / 4);
+    let mut depth = 0usize;
+    let mut in_comment = false;
+    let mut in_directive = false;
+    for line in code.lines() {
+        let trimmed = line.trim_start();
+        if in_directive || (!in_comment && trimmed.starts_with('#')) {
+            out.push_str(line);
+            in_directive = line.ends_with('\\');
+        } else if !trimmed.is_empty() {
+            let closes_first = !in_comment && trimmed.starts_with('}');
+            let level = if closes_first { depth.saturating_sub(1) } else { depth };
+            out.extend(std::iter::repeat_n(' ', level * width));
+            out.push_str(trimmed);
+            depth = depth.saturating_add_signed(brace_balance(trimmed, &mut in_comment));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Opening minus closing braces on `line` outside literals and comments.
+/// `in_comment` carries an unterminated block comment over to the next line.
+fn brace_balance(line: &str, in_comment: &mut bool) -> isize {
+    let mut balance = 0;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if *in_comment {
+            if c == '*' && chars.next_if_eq(&'/').is_some() {
+                *in_comment = false;
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => {
+                while let Some(d) = chars.next() {
+                    if d == '\\' {
+                        chars.next();
+                    } else if d == c {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => break,
+            '/' if chars.next_if_eq(&'*').is_some() => *in_comment = true,
+            '{' => balance += 1,
+            '}' => balance -= 1,
+            _ => {}
+        }
+    }
+    balance
+}
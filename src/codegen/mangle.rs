@@ -0,0 +1,156 @@
+//! C names for Verve identifiers.
+//!
+//! A Verve variable, function, field or type may be named like a C keyword
+//! (`register`, `int`), like something a header of the generated code
+//! declares (`puts`, `time_t`, `SIGINT`) or like something the generated
+//! code relies on (`printf`, `malloc`). Those names, names of the forms the
+//! C standard and POSIX reserve for their headers, and any name that already
+//! starts with the escape prefix, are emitted as `verve_u_<name>`; every
+//! other name is emitted unchanged. Since only prefixed names are ever
+//! escaped, the mapping is one-to-one and stripping the prefix undoes it.
+
+use std::borrow::Cow;
+
+const PREFIX: &str = "verve_u_";
+
+/// C keywords, and the library and runtime names used by generated code.
+const RESERVED: &[&str] = &[
+    // C keywords up to C23
+    "alignas", "alignof", "auto", "bool", "break", "case", "char", "const", "constexpr",
+    "continue", "default", "do", "double", "else", "enum", "extern", "false", "float", "for",
+    "goto", "if", "inline", "int", "long", "nullptr", "register", "restrict", "return",
+    "short", "signed", "sizeof", "static", "static_assert", "struct", "switch",
+    "thread_local", "true", "typedef", "typeof", "typeof_unqual", "union", "unsigned",
+    "void", "volatile", "while", "_Alignas", "_Alignof", "_Atomic", "_Bool", "_Complex",
+    "_Generic", "_Imaginary", "_Noreturn", "_Static_assert", "_Thread_local",
+    // Names built into C compilers, which warn when a program redeclares
+    // the functions among them with another type
+    "alloca", "asm", "bcmp", "bcopy", "bzero", "ffs", "index", "offsetof", "rindex", "va_arg",
+    "va_copy", "va_end", "va_list", "va_start",
+    "acos", "asin", "atan", "atan2", "cbrt", "ceil", "copysign", "cos", "cosh", "erf", "exp",
+    "exp2", "expm1", "fabs", "fdim", "floor", "fma", "fmax", "fmin", "fmod", "frexp", "hypot",
+    "ldexp", "lgamma", "llround", "log", "log10", "log1p", "log2", "lround", "modf", "nan",
+    "nearbyint", "pow", "remainder", "rint", "round", "scalbn", "sin", "sinh", "sqrt", "tan",
+    "tanh", "tgamma", "trunc",
+];
+
+/// The names each header the generated code may include declares, besides
+/// those [`reserved_form`] covers.
+const HEADERS: &[(&str, &[&str])] = &[
+    ("<stdio.h>", &[
+        "BUFSIZ", "FILE", "FILENAME_MAX", "FOPEN_MAX", "L_tmpnam", "NULL", "SEEK_CUR", "SEEK_END",
+        "SEEK_SET", "TMP_MAX", "clearerr", "ctermid", "dprintf", "fclose", "fdopen", "feof",
+        "ferror", "fflush", "fgetc", "fgetpos", "fgets", "fileno", "flockfile", "fmemopen",
+        "fopen", "fprintf", "fputc", "fputs", "fread", "freopen", "fscanf", "fseek", "fseeko",
+        "fsetpos", "ftell", "ftello", "ftrylockfile", "funlockfile", "fwrite", "getc",
+        "getc_unlocked", "getchar", "getchar_unlocked", "getdelim", "getline", "gets",
+        "open_memstream", "pclose", "perror", "popen", "printf", "putc", "putc_unlocked",
+        "putchar", "putchar_unlocked", "puts", "remove", "rename", "renameat", "rewind", "scanf",
+        "setbuf", "setvbuf", "snprintf", "sprintf", "sscanf", "stderr", "stdin", "stdout",
+        "tempnam", "tmpfile", "tmpnam", "ungetc", "vdprintf", "vfprintf", "vfscanf", "vprintf",
+        "vscanf", "vsnprintf", "vsprintf", "vsscanf",
+    ]),
+    ("<stdlib.h>", &[
+        "MB_CUR_MAX", "RAND_MAX", "abort", "abs", "aligned_alloc", "at_quick_exit", "atexit",
+        "atof", "atoi", "atol", "atoll", "bsearch", "calloc", "div", "exit", "free", "getenv",
+        "labs", "ldiv", "llabs", "lldiv", "malloc", "mblen", "mbstowcs", "mbtowc", "mkdtemp",
+        "mkstemp", "posix_memalign", "putenv", "qsort", "quick_exit", "rand", "random",
+        "realloc", "realpath", "setenv", "srand", "srandom", "system", "unsetenv", "wcstombs",
+        "wctomb",
+    ]),
+    ("<string.h>", &["strdup", "strndup", "strnlen", "strtok_r"]),
+    ("<time.h>", &[
+        "CLOCKS_PER_SEC", "TIME_UTC", "asctime", "clock", "clock_getres", "clock_gettime",
+        "ctime", "daylight", "difftime", "getdate", "gmtime", "localtime", "mktime",
+        "nanosleep", "strptime", "time", "timespec", "timespec_get", "timezone", "tm", "tzname",
+        "tzset",
+    ]),
+    ("<signal.h>", &["kill", "killpg", "psignal", "raise", "sigaction", "signal", "sigset", "sigval"]),
+    ("<stdbool.h>", &["__bool_true_false_are_defined"]),
+    ("<inttypes.h>", &["imaxabs", "imaxdiv", "strtoimax", "strtoumax", "wcstoimax", "wcstoumax"]),
+    ("<unistd.h>", &[
+        "access", "alarm", "chdir", "chown", "close", "dup", "dup2", "environ", "execl", "execle",
+        "execlp", "execv", "execve", "execvp", "fork", "fsync", "ftruncate", "getcwd", "getopt",
+        "getpid", "getppid", "isatty", "link", "lseek", "optarg", "opterr", "optind", "optopt",
+        "pause", "pipe", "pread", "pwrite", "read", "readlink", "rmdir", "sleep", "symlink",
+        "sync", "sysconf", "truncate", "unlink", "usleep", "write",
+    ]),
+    ("<sys/socket.h>, <netinet/in.h>, <netdb.h>", &[
+        "accept", "addrinfo", "bind", "connect", "endhostent", "freeaddrinfo", "gai_strerror",
+        "getaddrinfo", "gethostbyaddr", "gethostbyname", "gethostent", "getnameinfo",
+        "getpeername", "getservbyname", "getsockname", "getsockopt", "h_errno", "hostent",
+        "htonl", "htons", "in_addr", "in6_addr", "linger", "listen", "msghdr", "ntohl", "ntohs",
+        "protoent", "recv", "recvfrom", "recvmsg", "send", "sendmsg", "sendto", "servent",
+        "setsockopt", "shutdown", "sockaddr", "sockaddr_in", "sockaddr_in6", "sockaddr_storage",
+        "socket", "socketpair",
+    ]),
+    ("<winsock2.h>, <ws2tcpip.h>", &[
+        "BOOL", "BYTE", "DWORD", "FALSE", "HANDLE", "INVALID_SOCKET", "MAKEWORD", "SOCKET",
+        "SOCKET_ERROR", "TRUE", "WORD", "closesocket", "ioctlsocket", "select",
+    ]),
+];
+
+/// Whether `name` has a form the C standard or POSIX reserves for the
+/// headers: `_` and a capital, a type ending in `_t`, error numbers and
+/// signals (`ENOMEM`, `SIGINT`), limits and format macros of `<stdint.h>`
+/// and `<inttypes.h>`, string functions, and the socket constants. Names
+/// starting with `__` are Verve's own intrinsics and the temporaries its
+/// passes introduce, and are left alone.
+fn reserved_form(name: &str) -> bool {
+    let followed_by = |prefix: &str, next: fn(char) -> bool| {
+        name.strip_prefix(prefix).and_then(|rest| rest.chars().next()).is_some_and(next)
+    };
+    let capital = |c: char| c.is_ascii_uppercase() || c.is_ascii_digit();
+    let upper = |c: char| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_';
+    let lower = |c: char| c.is_ascii_lowercase();
+    const SOCKET_PREFIXES: &[&str] = &[
+        "AF_", "AI_", "INADDR_", "IN6ADDR_", "IPPROTO_", "MSG_", "NI_", "PF_", "SHUT_", "SOCK_",
+        "SOL_", "SOMAXCONN", "SO_", "WSA",
+    ];
+    name.ends_with("_t")
+        || followed_by("_", |c| c.is_ascii_uppercase())
+        || followed_by("E", capital)
+        || ["SIG", "INT", "UINT"].iter().any(|prefix| followed_by(prefix, upper))
+        || ["PRI", "SCN"].iter().any(|prefix| followed_by(prefix, |c| c.is_ascii_lowercase() || c == 'X'))
+        || ["str", "mem", "wcs"].iter().any(|prefix| followed_by(prefix, lower))
+        || SOCKET_PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+}
+
+/// Whether `name` cannot appear in the generated C as it is.
+fn is_reserved(name: &str) -> bool {
+    name.starts_with("verve_")
+        || RESERVED.contains(&name)
+        || HEADERS.iter().any(|(_, names)| names.contains(&name))
+        || reserved_form(name)
+}
+
+/// The C spelling of the Verve identifier `name`.
+pub fn mangle(name: &str) -> Cow<'_, str> {
+    if is_reserved(name) {
+        Cow::Owned(format!("{}{}", PREFIX, name))
+    } else {
+        Cow::Borrowed(name)
+    }
+}
+
+/// The C spelling of the constant for `variant` of the enum `enum_name`.
+pub fn mangle_variant(enum_name: &str, variant: &str) -> String {
+    mangle(&format!("{}_{}", enum_name, variant)).into_owned()
+}
+
+/// Rewrites escaped names in C compiler output back to their Verve spelling.
+pub fn demangle(text: &str) -> String {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(|c: char| is_ident(c)) {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find(|c: char| !is_ident(c)).unwrap_or(rest.len());
+        let ident = &rest[..end];
+        out.push_str(ident.strip_prefix(PREFIX).unwrap_or(ident));
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
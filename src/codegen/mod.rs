@@ -1,25 +1,199 @@
 mod c;
 mod compile_error;
+mod indent;
+mod mangle;
+pub mod runtime;
 
+use crate::host::{self, FileSystem};
 use codespan::FileId;
+use std::collections::BTreeMap;
+use std::rc::Rc;
 pub use compile_error::CompileError;
+pub use mangle::{demangle, mangle, mangle_variant};
 
-pub enum Target {
-    Native(c::CBackend),
+/// A code generator for a typechecked, lowered program.
+pub trait Backend {
+    /// Generates the program's source in the backend's output language.
+    fn compile(&mut self, program: &crate::ast::Program) -> Result<String, CompileError>;
+}
+
+pub type BackendFactory = Box<dyn Fn(CodegenConfig, FileId) -> Box<dyn Backend>>;
+
+/// Backends selectable by name with `--backend`. The built-in C backend is
+/// registered as `c`; embedders can add their own with `register`.
+pub struct BackendRegistry {
+    factories: BTreeMap<String, BackendFactory>,
+}
+
+impl BackendRegistry {
+    /// A registry with no backends; `default()` includes the built-in ones.
+    pub fn empty() -> Self {
+        Self { factories: BTreeMap::new() }
+    }
+
+    /// Registers `factory` under `name`, replacing any backend already
+    /// registered with that name.
+    pub fn register<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn(CodegenConfig, FileId) -> Box<dyn Backend> + 'static,
+    {
+        self.factories.insert(name.to_string(), Box::new(factory));
+    }
+
+    pub fn create(&self, name: &str, config: CodegenConfig, file_id: FileId) -> Option<Box<dyn Backend>> {
+        self.factories.get(name).map(|factory| factory(config, file_id))
+    }
+
+    /// Registered backend names, sorted.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.factories.keys().map(String::as_str)
+    }
+}
+
+impl Default for BackendRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register("c", |config, file_id| Box::new(c::CBackend::new(config, file_id)));
+        registry
+    }
 }
 
 pub struct CodegenConfig {
     pub target_triple: String,
+    /// Lower bitfields to mask/shift operations on plain integer storage,
+    /// for toolchains whose bitfield layout cannot be relied on.
+    pub portable_bitfields: bool,
+    /// Route `__alloc`/`__dealloc` through tracking wrappers that report
+    /// leaks and double frees at exit.
+    pub debug_alloc: bool,
+    /// Emit `#line` directives so C tooling (coverage, debuggers) reports
+    /// Verve source lines.
+    pub line_directives: bool,
+    /// Call profiling hooks on every function entry and exit and write
+    /// per-function timings to `verve.prof` at exit.
+    pub instrument_functions: bool,
+    /// Emit C that cl.exe accepts: MSVC alignment syntax instead of C11's,
+    /// no `max_align_t`, and `system("pause")` to hold the console.
+    pub msvc: bool,
+    /// Use GNU C extensions (`__builtin_expect` and friends) where they help
+    /// the C compiler; the portable spelling is emitted otherwise.
+    pub gnu_extensions: bool,
+    /// The `-O` level of the build, for backends that tune their output to it.
+    pub opt_level: u8,
+    /// The output is the entire program: give every symbol but `main`
+    /// internal linkage, `#[export]` functions included.
+    pub whole_program: bool,
+    /// Abort with "stack overflow in function X" once calls nest deeper
+    /// than this.
+    pub max_call_depth: Option<u32>,
+    /// Spaces per nesting level in the generated code; 0 leaves every line
+    /// where the backend put it.
+    pub indent_width: usize,
+    /// The program's source files, in the order their spans are laid out.
+    pub sources: Vec<SourceFile>,
+    /// What the `build.*()` builtins report.
+    pub build: BuildInfo,
+    /// Declare the parts of the runtime in [`runtime::LINKABLE`] instead of
+    /// emitting them, for linking with `libverve_rt`.
+    pub linked_runtime: bool,
+    /// Where `include_str` and `include_bytes` read files from.
+    pub file_system: Rc<dyn FileSystem>,
 }
 
-impl Target {
-    pub fn create(config: CodegenConfig, file_id: FileId) -> Self {
-        Target::Native(c::CBackend::new(config, file_id))
+impl CodegenConfig {
+    /// Whether the target is WebAssembly with the WASI system interface,
+    /// where wasi-libc implements the C library on WASI imports.
+    pub fn is_wasi(&self) -> bool {
+        self.target_triple.starts_with("wasm32-wasi")
+    }
+
+    /// The source file containing the span offset `offset`.
+    pub fn source_at(&self, offset: usize) -> &SourceFile {
+        self.sources.iter().rev()
+            .find(|source| source.start <= offset)
+            .unwrap_or(&self.sources[0])
     }
+}
+
+/// Facts about the build that the program can ask for: `build.version()`,
+/// `build.target()` (the target triple) and `build.timestamp()`.
+#[derive(Debug, Clone, Default)]
+pub struct BuildInfo {
+    /// `version` of `[package]` in `verve.toml`.
+    pub version: String,
+    /// Seconds since the Unix epoch when the program was compiled.
+    pub timestamp: u64,
+}
 
-    pub fn compile(&mut self, program: &crate::ast::Program) -> Result<(), CompileError> {
-        match self {
-            Target::Native(c_backend) => c_backend.compile(program),
+impl BuildInfo {
+    /// The current time, or `SOURCE_DATE_EPOCH` for reproducible builds.
+    /// Without an operating system there is no clock, and it is 0.
+    pub fn now() -> u64 {
+        if host::is_freestanding() {
+            return 0;
         }
+        std::env::var("SOURCE_DATE_EPOCH").ok()
+            .and_then(|epoch| epoch.parse().ok())
+            .unwrap_or_else(|| {
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_or(0, |elapsed| elapsed.as_secs())
+            })
+    }
+
+    /// The timestamp in UTC as `YYYY-MM-DDTHH:MM:SSZ`.
+    pub fn timestamp_utc(&self) -> String {
+        let days = (self.timestamp / 86400) as i64;
+        let seconds = self.timestamp % 86400;
+        // Howard Hinnant's civil_from_days.
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            year, month, day, seconds / 3600, seconds / 60 % 60, seconds % 60
+        )
     }
-}
\ No newline at end of file
+}
+
+/// A source file of the program, for generated code that reports Verve
+/// locations.
+pub struct SourceFile {
+    pub name: String,
+    pub text: String,
+    /// Offset of the file's first byte in the spans of the program; see
+    /// [`crate::lexer::Lexer::at_offset`].
+    pub start: usize,
+}
+
+impl SourceFile {
+    /// 1-based line and column of a span offset within this file.
+    pub fn location(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.saturating_sub(self.start);
+        let before = &self.text[..offset.min(self.text.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        (line, column)
+    }
+
+    fn escaped_name(&self) -> String {
+        self.name.replace('\\', "\\\\").replace('"', "\\\"")
+    }
+
+    /// The file name as a C string literal.
+    pub fn name_literal(&self) -> String {
+        format!("\"{}\"", self.escaped_name())
+    }
+
+    /// `name:line:column` as a C string literal.
+    pub fn site_literal(&self, offset: usize) -> String {
+        let (line, column) = self.location(offset);
+        format!("\"{}:{}:{}\"", self.escaped_name(), line, column)
+    }
+}
@@ -0,0 +1,289 @@
+//! The parts of the C runtime that do not depend on the program's own
+//! types: panicking, lists and sockets. By default a program gets the
+//! parts it uses amalgamated into its output; with a linked runtime it gets
+//! includes [`HEADER_NAME`], the [`header`] declaring every part, and links
+//! `libverve_rt`, which the driver builds from [`library_source`] once per
+//! target. The string, file and JSON runtimes work on the program's
+//! `Option` and `JsonValue` types, so they are always emitted with the
+//! program.
+//!
+//! The header carries [`ABI_VERSION`], and the generated code and the
+//! library both check it with [`abi_check`], so a runtime built by another
+//! compiler version fails to build instead of linking functions whose
+//! meaning changed.
+
+use crate::intrinsics::Runtime;
+
+/// The archive the driver builds the runtime into.
+pub const LIBRARY_NAME: &str = "libverve_rt.a";
+
+/// The header declaring the linked runtime, next to the archive.
+pub const HEADER_NAME: &str = "verve_rt.h";
+
+/// The version of the linked runtime's interface. Bump it whenever a
+/// function in a [linkable](LINKABLE) part is added, removed, or changes
+/// its signature or behaviour.
+pub const ABI_VERSION: u32 = 1;
+
+/// The parts that can be linked rather than emitted.
+pub const LINKABLE: &[Runtime] = &[Runtime::Panic, Runtime::List, Runtime::Net];
+
+/// C source of a part of the runtime, its functions `static`.
+pub struct Part {
+    pub includes: &'static [&'static str],
+    pub code: String,
+}
+
+/// The part of the runtime for `runtime` on `target_triple`, or `None` when
+/// it is not [linkable](LINKABLE).
+pub fn part(runtime: Runtime, target_triple: &str) -> Option<Part> {
+    match runtime {
+        Runtime::Panic => Some(Part { includes: &[], code: PANIC.to_string() }),
+        Runtime::List => Some(Part { includes: &[], code: LIST.to_string() }),
+        // Sockets are Winsock when the target triple names Windows and BSD
+        // sockets otherwise.
+        Runtime::Net if target_triple.contains("windows") => Some(Part {
+            includes: &["<winsock2.h>", "<ws2tcpip.h>"],
+            code: format!("{}{}", NET_WINDOWS, NET),
+        }),
+        Runtime::Net => Some(Part {
+            includes: &["<netdb.h>", "<netinet/in.h>", "<signal.h>", "<sys/socket.h>", "<unistd.h>"],
+            code: format!("{}{}", NET_UNIX, NET),
+        }),
+        _ => None,
+    }
+}
+
+/// What a program linking the runtime declares in place of `code`: a
+/// prototype for each function defined in it, and the `#pragma comment`
+/// lines that name the libraries it needs on MSVC.
+pub fn prototypes(code: &str) -> String {
+    let mut out = String::new();
+    for line in code.lines() {
+        if line.starts_with("#pragma comment") {
+            out.push_str(line);
+            out.push('\n');
+        } else if let Some(signature) = line.strip_prefix("static ").and_then(|line| line.strip_suffix(" {")) {
+            out.push_str(signature);
+            out.push_str(";\n");
+        }
+    }
+    out.push('\n');
+    out
+}
+
+fn linkable_parts(target_triple: &str) -> Vec<Part> {
+    LINKABLE.iter().filter_map(|&runtime| part(runtime, target_triple)).collect()
+}
+
+/// `verve_rt.h` for `target_triple`: [`ABI_VERSION`], the headers the
+/// runtime needs, and the prototypes of every linkable part.
+pub fn header(target_triple: &str) -> String {
+    let parts = linkable_parts(target_triple);
+    let mut out = format!("// Verve runtime ABI (target: {})\n#ifndef VERVE_RT_H\n#define VERVE_RT_H\n\n", target_triple);
+    out.push_str(&format!("#define VERVE_RT_ABI_VERSION {}\n\n#include <stdio.h>\n#include <stdlib.h>\n", ABI_VERSION));
+    for include in parts.iter().flat_map(|part| part.includes) {
+        out.push_str(&format!("#include {}\n", include));
+    }
+    out.push('\n');
+    for part in &parts {
+        out.push_str(&prototypes(&part.code));
+    }
+    out.push_str("#endif\n");
+    out
+}
+
+/// Includes `verve_rt.h` and stops the C compiler unless it is the
+/// [`ABI_VERSION`] of this compiler.
+pub fn abi_check() -> String {
+    format!(
+        "#include \"{header}\"\n#if !defined(VERVE_RT_ABI_VERSION) || VERVE_RT_ABI_VERSION != {version}\n#error \"{header} is not version {version} of the Verve runtime ABI; rebuild the runtime with this compiler\"\n#endif\n\n",
+        header = HEADER_NAME,
+        version = ABI_VERSION,
+    )
+}
+
+/// The C source of `libverve_rt` for `target_triple`: every linkable part,
+/// with external linkage, checked against `verve_rt.h`.
+pub fn library_source(target_triple: &str) -> String {
+    let mut out = format!("// Verve runtime library (target: {})\n{}", target_triple, abi_check());
+    for part in &linkable_parts(target_triple) {
+        for line in part.code.lines() {
+            out.push_str(line.strip_prefix("static ").unwrap_or(line));
+            out.push('\n');
+        }
+    }
+    out
+}
+
+const PANIC: &str = r#"static void verve_panic(const char *message) {
+    fflush(stdout);
+    fprintf(stderr, "panic: %s\n", message);
+    exit(101);
+}
+
+"#;
+
+/// Growable `int` arrays behind the `__list_*` intrinsics. Indices are not
+/// checked here; the prelude checks them.
+const LIST: &str = r#"typedef struct VerveList {
+    int len, cap;
+    int *items;
+} VerveList;
+
+static void *verve_list_new(void) {
+    return calloc(1, sizeof(VerveList));
+}
+
+static int verve_list_len(void *list) {
+    return ((VerveList *)list)->len;
+}
+
+static void verve_list_push(void *list, int value) {
+    VerveList *l = list;
+    if (l->len == l->cap) {
+        l->cap = l->cap ? l->cap * 2 : 4;
+        l->items = realloc(l->items, l->cap * sizeof *l->items);
+    }
+    l->items[l->len++] = value;
+}
+
+static int verve_list_get(void *list, int i) {
+    return ((VerveList *)list)->items[i];
+}
+
+static void verve_list_set(void *list, int i, int value) {
+    ((VerveList *)list)->items[i] = value;
+}
+
+static void verve_list_free(void *list) {
+    free(((VerveList *)list)->items);
+    free(list);
+}
+
+static void *verve_list_from_bytes(const unsigned char *bytes, int len) {
+    VerveList *l = verve_list_new();
+    for (int i = 0; i < len; i++) {
+        verve_list_push(l, bytes[i]);
+    }
+    return l;
+}
+
+"#;
+
+const NET_WINDOWS: &str = r#"#pragma comment(lib, "ws2_32.lib")
+typedef SOCKET verve_socket_t;
+#define VERVE_INVALID_SOCKET INVALID_SOCKET
+#define verve_closesocket closesocket
+
+static void verve_net_init(void) {
+    static int started = 0;
+    if (!started) {
+        WSADATA data;
+        WSAStartup(MAKEWORD(2, 2), &data);
+        started = 1;
+    }
+}
+
+"#;
+
+/// Writing to a closed connection should fail the write rather than kill
+/// the program.
+const NET_UNIX: &str = r#"typedef int verve_socket_t;
+#define VERVE_INVALID_SOCKET -1
+#define verve_closesocket close
+
+static void verve_net_init(void) {
+    static int started = 0;
+    if (!started) {
+        signal(SIGPIPE, SIG_IGN);
+        started = 1;
+    }
+}
+
+"#;
+
+/// Sockets behind the `__tcp_*`, `__udp_*` and `__net_*` intrinsics.
+/// Handles are `int`s on both kinds, -1 when an operation fails.
+const NET: &str = r#"static int verve_net_open(int type, int port) {
+    verve_net_init();
+    verve_socket_t s = socket(AF_INET, type, 0);
+    if (s == VERVE_INVALID_SOCKET) return -1;
+    int reuse = 1;
+    setsockopt(s, SOL_SOCKET, SO_REUSEADDR, (const char *)&reuse, sizeof reuse);
+    struct sockaddr_in addr = {0};
+    addr.sin_family = AF_INET;
+    addr.sin_addr.s_addr = htonl(INADDR_ANY);
+    addr.sin_port = htons((unsigned short)port);
+    if (bind(s, (struct sockaddr *)&addr, sizeof addr) != 0) {
+        verve_closesocket(s);
+        return -1;
+    }
+    return (int)s;
+}
+
+static struct addrinfo *verve_net_resolve(const char *host, int port, int type) {
+    char service[16];
+    snprintf(service, sizeof service, "%d", port);
+    struct addrinfo hints = {0}, *found = NULL;
+    hints.ai_family = AF_INET;
+    hints.ai_socktype = type;
+    verve_net_init();
+    return getaddrinfo(host, service, &hints, &found) == 0 ? found : NULL;
+}
+
+static int verve_tcp_listen(int port) {
+    int s = verve_net_open(SOCK_STREAM, port);
+    if (s != -1 && listen((verve_socket_t)s, SOMAXCONN) != 0) {
+        verve_closesocket((verve_socket_t)s);
+        return -1;
+    }
+    return s;
+}
+
+static int verve_tcp_accept(int listener) {
+    verve_socket_t s = accept((verve_socket_t)listener, NULL, NULL);
+    return s == VERVE_INVALID_SOCKET ? -1 : (int)s;
+}
+
+static int verve_tcp_connect(const char *host, int port) {
+    struct addrinfo *found = verve_net_resolve(host, port, SOCK_STREAM);
+    int result = -1;
+    for (struct addrinfo *ai = found; ai && result == -1; ai = ai->ai_next) {
+        verve_socket_t s = socket(ai->ai_family, ai->ai_socktype, ai->ai_protocol);
+        if (s == VERVE_INVALID_SOCKET) continue;
+        if (connect(s, ai->ai_addr, (int)ai->ai_addrlen) == 0) {
+            result = (int)s;
+        } else {
+            verve_closesocket(s);
+        }
+    }
+    if (found) freeaddrinfo(found);
+    return result;
+}
+
+static int verve_udp_bind(int port) {
+    return verve_net_open(SOCK_DGRAM, port);
+}
+
+static int verve_udp_send_to(int s, const char *host, int port, const void *buf, int len) {
+    struct addrinfo *found = verve_net_resolve(host, port, SOCK_DGRAM);
+    if (!found) return -1;
+    int sent = (int)sendto((verve_socket_t)s, (const char *)buf, len, 0, found->ai_addr, (int)found->ai_addrlen);
+    freeaddrinfo(found);
+    return sent;
+}
+
+static int verve_net_read(int s, void *buf, int len) {
+    return (int)recv((verve_socket_t)s, (char *)buf, len, 0);
+}
+
+static int verve_net_write(int s, const void *buf, int len) {
+    return (int)send((verve_socket_t)s, (const char *)buf, len, 0);
+}
+
+static void verve_net_close(int s) {
+    verve_closesocket((verve_socket_t)s);
+}
+
+"#;
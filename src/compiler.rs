@@ -0,0 +1,538 @@
+//! The compiler pipeline as a library, for build tools and the language
+//! server: sources are added from memory, diagnostics go to a
+//! [`DiagnosticHandler`], and the generated code is returned rather than
+//! written anywhere.
+
+use crate::ast::Program;
+use crate::codegen::{Backend, BackendRegistry, BuildInfo, CodegenConfig, SourceFile};
+use crate::fix::Suggestion;
+use crate::host::{FileSystem, NativeFs};
+use crate::interface::Interface;
+use crate::lint::{self, Levels, Lint};
+use crate::log::{self, Level};
+use crate::model::{Location, Model};
+use crate::{cfg, escape, ice, json, lexer, loops, lower, parser, prelude, tailcall, typeck};
+use codespan::{FileId, Files, Span};
+use codespan_reporting::diagnostic::{Diagnostic, Severity};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::rc::Rc;
+
+/// The pipeline stage a diagnostic or failure comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Parse,
+    Typecheck,
+    Codegen,
+}
+
+/// Receives diagnostics as the compiler produces them.
+pub trait DiagnosticHandler {
+    fn report(&mut self, stage: Stage, diagnostic: &Diagnostic<FileId>, files: &Files<String>);
+
+    /// Called with the desugared AST before type checking.
+    fn parsed(&mut self, _program: &Program) {}
+
+    /// Called with each fix the diagnostics suggest, its span within the
+    /// file it points into.
+    fn suggest(&mut self, _suggestion: &Suggestion) {}
+}
+
+/// Collects diagnostics instead of reporting them.
+impl DiagnosticHandler for Vec<(Stage, Diagnostic<FileId>)> {
+    fn report(&mut self, stage: Stage, diagnostic: &Diagnostic<FileId>, _files: &Files<String>) {
+        self.push((stage, diagnostic.clone()));
+    }
+}
+
+/// Compilation stopped at `stage`; the details went to the handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompileFailed(pub Stage);
+
+impl fmt::Display for CompileFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Stage::Parse => write!(f, "Parse failed"),
+            Stage::Typecheck => write!(f, "Type check failed"),
+            Stage::Codegen => write!(f, "Code generation failed"),
+        }
+    }
+}
+
+impl std::error::Error for CompileFailed {}
+
+pub struct Compiler {
+    files: Files<String>,
+    backends: BackendRegistry,
+    backend: String,
+    target_triple: String,
+    features: BTreeSet<String>,
+    build: BuildInfo,
+    opt_level: u8,
+    portable_bitfields: bool,
+    debug_alloc: bool,
+    line_directives: bool,
+    instrument_functions: bool,
+    msvc: bool,
+    gnu_extensions: bool,
+    whole_program: bool,
+    linked_runtime: bool,
+    lint_levels: Levels,
+    max_call_depth: Option<u32>,
+    indent_width: usize,
+    file_system: Rc<dyn FileSystem>,
+    interfaces: Vec<Interface>,
+    prelude: FileId,
+    std_modules: Vec<FileId>,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        let mut files = Files::new();
+        let prelude = files.add(prelude::NAME, prelude::SOURCE.to_string());
+        Self {
+            files,
+            backends: BackendRegistry::default(),
+            backend: "c".to_string(),
+            target_triple: "x86_64-pc-windows-msvc".to_string(),
+            features: BTreeSet::new(),
+            build: BuildInfo { version: "0.0.0".to_string(), timestamp: BuildInfo::now() },
+            opt_level: 1,
+            portable_bitfields: false,
+            debug_alloc: false,
+            line_directives: false,
+            instrument_functions: false,
+            msvc: false,
+            gnu_extensions: false,
+            whole_program: false,
+            linked_runtime: false,
+            lint_levels: Levels::default(),
+            max_call_depth: None,
+            indent_width: 4,
+            file_system: Rc::new(NativeFs),
+            interfaces: Vec::new(),
+            prelude,
+            std_modules: Vec::new(),
+        }
+    }
+
+    /// Selects the backend by its registered name.
+    pub fn backend(mut self, name: &str) -> Self {
+        self.backend = name.to_string();
+        self
+    }
+
+    pub fn register_backend<F>(mut self, name: &str, factory: F) -> Self
+    where
+        F: Fn(CodegenConfig, FileId) -> Box<dyn Backend> + 'static,
+    {
+        self.backends.register(name, factory);
+        self
+    }
+
+    pub fn target_triple(mut self, triple: &str) -> Self {
+        self.target_triple = triple.to_string();
+        self
+    }
+
+    /// Enables manifest features, for `#[cfg(feature = "...")]` and
+    /// `cfg(feature = "...")`.
+    pub fn features(mut self, features: impl IntoIterator<Item = String>) -> Self {
+        self.features.extend(features);
+        self
+    }
+
+    /// The package version `build.version()` returns.
+    pub fn version(mut self, version: &str) -> Self {
+        self.build.version = version.to_string();
+        self
+    }
+
+    /// Fixes the time `build.timestamp()` returns, in seconds since the
+    /// Unix epoch, instead of the time of compiling.
+    pub fn build_timestamp(mut self, timestamp: u64) -> Self {
+        self.build.timestamp = timestamp;
+        self
+    }
+
+    /// Level 1 promotes non-escaping allocations to the stack; level 2 also
+    /// unrolls small loops and strength-reduces loop induction products.
+    pub fn opt_level(mut self, level: u8) -> Self {
+        self.opt_level = level;
+        self
+    }
+
+    /// Shorthand for `opt_level(1)` or `opt_level(0)`.
+    pub fn optimize(self, optimize: bool) -> Self {
+        self.opt_level(optimize as u8)
+    }
+
+    pub fn portable_bitfields(mut self, enabled: bool) -> Self {
+        self.portable_bitfields = enabled;
+        self
+    }
+
+    pub fn debug_alloc(mut self, enabled: bool) -> Self {
+        self.debug_alloc = enabled;
+        self
+    }
+
+    pub fn line_directives(mut self, enabled: bool) -> Self {
+        self.line_directives = enabled;
+        self
+    }
+
+    pub fn instrument_functions(mut self, enabled: bool) -> Self {
+        self.instrument_functions = enabled;
+        self
+    }
+
+    /// Emits C for the MSVC toolchain instead of portable C11.
+    pub fn msvc(mut self, enabled: bool) -> Self {
+        self.msvc = enabled;
+        self
+    }
+
+    /// Allows GNU C extensions in the output; cl.exe does not accept them.
+    pub fn gnu_extensions(mut self, enabled: bool) -> Self {
+        self.gnu_extensions = enabled;
+        self
+    }
+
+    /// Internalizes every symbol except `main`, for release builds that
+    /// nothing else links against.
+    pub fn whole_program(mut self, enabled: bool) -> Self {
+        self.whole_program = enabled;
+        self
+    }
+
+    /// Declares the program-independent parts of the runtime instead of
+    /// emitting them, for linking with `libverve_rt`; see
+    /// [`runtime::library_source`](crate::codegen::runtime::library_source).
+    pub fn linked_runtime(mut self, enabled: bool) -> Self {
+        self.linked_runtime = enabled;
+        self
+    }
+
+    /// Sets the level of `lint`: allowed lints are not reported and denied
+    /// ones are errors. Every lint warns by default.
+    pub fn lint_level(mut self, lint: Lint, level: lint::Level) -> Self {
+        self.lint_levels.set(lint, level);
+        self
+    }
+
+    /// Guards every call with a depth check; `None` turns the guard off.
+    pub fn max_call_depth(mut self, depth: Option<u32>) -> Self {
+        self.max_call_depth = depth;
+        self
+    }
+
+    /// Spaces per nesting level in the generated code (4 by default); 0
+    /// turns indentation off.
+    pub fn indent_width(mut self, width: usize) -> Self {
+        self.indent_width = width;
+        self
+    }
+
+    /// Where `include_str` and `include_bytes` read files from; the disk
+    /// by default.
+    pub fn file_system(mut self, file_system: impl FileSystem + 'static) -> Self {
+        self.file_system = Rc::new(file_system);
+        self
+    }
+
+    /// Registers a source held in memory; nothing is read from disk.
+    pub fn add_source(&mut self, name: &str, text: impl Into<String>) -> FileId {
+        self.files.add(name, text.into())
+    }
+
+    /// Makes the types and functions of a separately compiled program
+    /// available to the sources, to be linked in from that program.
+    pub fn add_interface(&mut self, interface: Interface) {
+        self.interfaces.push(interface);
+    }
+
+    /// Registers a standard library module. Like the prelude's, its items
+    /// are added to the programs that use them and do not define them.
+    pub fn add_std_module(&mut self, name: &str, text: impl Into<String>) -> FileId {
+        let file_id = self.files.add(name, text.into());
+        self.std_modules.push(file_id);
+        file_id
+    }
+
+    pub fn files(&self) -> &Files<String> {
+        &self.files
+    }
+
+    /// Compiles the source `file_id` and returns the generated code.
+    pub fn compile(&self, file_id: FileId, handler: &mut dyn DiagnosticHandler) -> Result<String, CompileFailed> {
+        self.compile_files(&[file_id], handler)
+    }
+
+    /// Compiles several sources as one program, merging their functions,
+    /// types and top-level statements in the order given. Each diagnostic
+    /// names the file it is about.
+    ///
+    /// # Panics
+    ///
+    /// If `file_ids` is empty.
+    pub fn compile_files(&self, file_ids: &[FileId], handler: &mut dyn DiagnosticHandler) -> Result<String, CompileFailed> {
+        let (mut program, layout) = self.check_files(file_ids, handler)?;
+        let file_id = file_ids[0];
+        ice::enter("optimization");
+        log::event!(Level::Info, "opt", "optimizing at -O{}", self.opt_level);
+        tailcall::eliminate_tail_calls(&mut program);
+        // Stack promotion would hide allocations from the --debug-alloc tracker.
+        if self.opt_level >= 1 && !self.debug_alloc {
+            log::event!(Level::Debug, "opt", "promoting non-escaping allocations to the stack");
+            escape::stack_allocate(&mut program);
+        }
+        if self.opt_level >= 2 {
+            log::event!(Level::Debug, "opt", "unrolling loops and reducing strength");
+            loops::unroll_loops(&mut program);
+            loops::reduce_strength(&mut program);
+        }
+
+        ice::enter("code generation");
+        let config = CodegenConfig {
+            target_triple: self.target_triple.clone(),
+            portable_bitfields: self.portable_bitfields,
+            debug_alloc: self.debug_alloc,
+            line_directives: self.line_directives,
+            instrument_functions: self.instrument_functions,
+            msvc: self.msvc,
+            gnu_extensions: self.gnu_extensions,
+            opt_level: self.opt_level,
+            whole_program: self.whole_program,
+            max_call_depth: self.max_call_depth,
+            indent_width: self.indent_width,
+            sources: layout.files.iter()
+                .map(|&(id, start)| SourceFile {
+                    name: self.files.name(id).to_string_lossy().into_owned(),
+                    text: self.files.source(id).clone(),
+                    start,
+                })
+                .collect(),
+            build: self.build.clone(),
+            linked_runtime: self.linked_runtime,
+            file_system: self.file_system.clone(),
+        };
+        let Some(mut backend) = self.backends.create(&self.backend, config, file_id) else {
+            let available = self.backends.names().collect::<Vec<_>>().join(", ");
+            let message = format!("Unknown backend '{}' (available: {})", self.backend, available);
+            return Err(self.fail(&layout, Stage::Codegen, vec![Diagnostic::error().with_message(message)], handler));
+        };
+        log::event!(Level::Info, "codegen", "generating code with the '{}' backend for {}", self.backend, self.target_triple);
+        let code = backend.compile(&program)
+            .map_err(|error| self.fail(&layout, Stage::Codegen, vec![error.to_diagnostic()], handler))?;
+        log::event!(Level::Info, "codegen", "generated {} bytes", code.len());
+        Ok(code)
+    }
+
+    /// The semantic model of the sources `file_ids`, checked as one program
+    /// the way [`compile_files`](Self::compile_files) checks them, for tools
+    /// that generate code from the program's declarations.
+    ///
+    /// # Panics
+    ///
+    /// If `file_ids` is empty.
+    pub fn model(&self, file_ids: &[FileId], handler: &mut dyn DiagnosticHandler) -> Result<Model, CompileFailed> {
+        let (program, layout) = self.check_files(file_ids, handler)?;
+        Ok(Model::of(&program, |span| layout.location(&self.files, span)))
+    }
+
+    /// Lexes, parses, lowers and type checks the program made of `file_ids`
+    /// and the library items it uses.
+    fn check_files(&self, file_ids: &[FileId], handler: &mut dyn DiagnosticHandler) -> Result<(Program, SourceLayout), CompileFailed> {
+        // The libraries go last so the sources keep their offsets.
+        let layout = SourceLayout::new(&self.files, &[file_ids, &self.std_modules, &[self.prelude]].concat());
+        let file_id = file_ids[0];
+        ice::enter("lexing");
+        let mut lexers: Vec<_> = layout.files.iter()
+            .map(|&(id, start)| lexer::Lexer::new(&self.files, id).at_offset(start))
+            .collect();
+        let lex_errors: Vec<_> = lexers.iter().flat_map(|lexer| lexer.errors()).collect();
+        if !lex_errors.is_empty() {
+            return Err(self.fail(&layout, Stage::Parse, lex_errors, handler));
+        }
+        let library_lexers = lexers.split_off(file_ids.len());
+        let tokens: Vec<_> = lexers.iter().flat_map(|lexer| lexer.tokens()).collect();
+        log::event!(Level::Info, "lexer", "{} tokens in {} sources", tokens.len(), file_ids.len());
+        let uses_json = json::uses_json(&tokens);
+        ice::enter("parsing");
+        let mut program = Program::default();
+        let mut parse_errors = Vec::new();
+        for lexer in lexers {
+            match parser::Parser::new(lexer).parse() {
+                Ok(file) => program.merge(file),
+                Err(diagnostic) => parse_errors.push(diagnostic),
+            }
+        }
+        let mut library = Program::default();
+        let mut library_tokens = Vec::new();
+        for lexer in library_lexers {
+            library_tokens.extend(lexer.tokens());
+            match parser::Parser::new(lexer).parse() {
+                Ok(file) => library.merge(file),
+                Err(diagnostic) => parse_errors.push(diagnostic),
+            }
+        }
+        if !parse_errors.is_empty() {
+            log::event!(Level::Info, "parser", "{} files failed to parse", parse_errors.len());
+            return Err(self.fail(&layout, Stage::Parse, parse_errors, handler));
+        }
+        log::event!(
+            Level::Info, "parser", "parsed {} functions, {} structs, {} enums and {} top-level statements",
+            program.functions.len(), program.structs.len(), program.enums.len(), program.stmts.len()
+        );
+        ice::enter("lowering");
+        let cfg = cfg::Config { target_triple: self.target_triple.clone(), features: self.features.clone() };
+        cfg::strip_items(&mut program, &cfg);
+        cfg::strip_items(&mut library, &cfg);
+        if uses_json {
+            json::add_json_value(&mut program);
+            json::derive_conversions(&mut program);
+        }
+        lower::lower_program(&mut program);
+        handler.parsed(&program);
+        // After `parsed`, so the handler sees only what the sources define,
+        // and before the libraries, which leave out what they do.
+        for interface in &self.interfaces {
+            interface.add_to(&mut program);
+        }
+        lower::lower_program(&mut library);
+        // `__enumerate` loops are lowered to reads of the prelude's `List`,
+        // and `include_bytes` gives one.
+        let lowered = prelude::identifiers(&tokens)
+            .any(|name| name == "__enumerate" || name == "include_bytes")
+            .then_some("List");
+        prelude::add_prelude(&mut program, library, &library_tokens, prelude::identifiers(&tokens).chain(lowered));
+
+        cfg::fold_program(&mut program, &cfg);
+        log::event!(
+            Level::Info, "lower", "{} functions, {} structs and {} enums with the library items used",
+            program.functions.len(), program.structs.len(), program.enums.len()
+        );
+        ice::enter("type checking");
+        let mut type_checker = typeck::TypeChecker::new(file_id);
+        let checked = type_checker.check(&mut program);
+        let warnings = type_checker.take_warnings().into_iter().map(|warning| (warning, None)).collect();
+        let mut denied = self.report_lints(&layout, warnings, handler);
+        for suggestion in type_checker.take_suggestions() {
+            handler.suggest(&layout.localize_suggestion(suggestion));
+        }
+        if let Err(errors) = checked {
+            log::event!(Level::Info, "typeck", "{} errors", errors.len());
+            return Err(self.fail(&layout, Stage::Typecheck, errors, handler));
+        }
+        log::event!(Level::Info, "typeck", "program type checks");
+        if let Err(errors) = lower::lower_matches(&mut program, file_id) {
+            return Err(self.fail(&layout, Stage::Typecheck, errors, handler));
+        }
+        // Spans past the sources are in the libraries.
+        let sources_end = layout.files.get(file_ids.len()).map_or(usize::MAX, |&(_, start)| start);
+        let in_sources = |span: Span| span != Span::default() && span.start().to_usize() < sources_end;
+        let lints = lint::check(&mut program, in_sources, file_id);
+        log::event!(Level::Info, "lint", "{} lint warnings before levels apply", lints.len());
+        denied |= self.report_lints(&layout, lints, handler);
+        if denied {
+            return Err(CompileFailed(Stage::Typecheck));
+        }
+        Ok((program, layout))
+    }
+
+    /// Reports `warnings` at the levels of their lints, with the fixes they
+    /// suggest unless the lint is allowed. Returns whether any were denied.
+    fn report_lints(
+        &self,
+        layout: &SourceLayout,
+        warnings: Vec<(Diagnostic<FileId>, Option<Suggestion>)>,
+        handler: &mut dyn DiagnosticHandler,
+    ) -> bool {
+        let mut denied = false;
+        for (warning, suggestion) in warnings {
+            let Some(diagnostic) = self.lint_levels.apply(warning) else { continue };
+            denied |= diagnostic.severity == Severity::Error;
+            handler.report(Stage::Typecheck, &layout.localize(diagnostic), &self.files);
+            if let Some(suggestion) = suggestion {
+                handler.suggest(&layout.localize_suggestion(suggestion));
+            }
+        }
+        denied
+    }
+
+    fn fail(&self, layout: &SourceLayout, stage: Stage, diagnostics: Vec<Diagnostic<FileId>>, handler: &mut dyn DiagnosticHandler) -> CompileFailed {
+        for diagnostic in diagnostics {
+            handler.report(stage, &layout.localize(diagnostic), &self.files);
+        }
+        CompileFailed(stage)
+    }
+}
+
+/// Where each source of a program starts in the spans of the whole program.
+/// Files follow one another with a byte between them, so a span at the end
+/// of one file is not mistaken for the start of the next.
+struct SourceLayout {
+    files: Vec<(FileId, usize)>,
+}
+
+impl SourceLayout {
+    fn new(files: &Files<String>, file_ids: &[FileId]) -> Self {
+        let mut start = 0;
+        let files = file_ids.iter()
+            .map(|&id| {
+                let file = (id, start);
+                start += files.source(id).len() + 1;
+                file
+            })
+            .collect();
+        Self { files }
+    }
+
+    /// `diagnostic` with each label moved to the file it points into and
+    /// given a range within that file.
+    fn localize(&self, mut diagnostic: Diagnostic<FileId>) -> Diagnostic<FileId> {
+        for label in &mut diagnostic.labels {
+            let (file_id, start) = self.file_at(label.range.start);
+            label.file_id = file_id;
+            label.range = label.range.start - start..label.range.end - start;
+        }
+        diagnostic
+    }
+
+    /// `suggestion` with its span moved the same way.
+    fn localize_suggestion(&self, mut suggestion: Suggestion) -> Suggestion {
+        let (file_id, start) = self.file_at(suggestion.span.start().to_usize());
+        suggestion.file_id = file_id;
+        suggestion.span = Span::new(suggestion.span.start().0 - start as u32, suggestion.span.end().0 - start as u32);
+        suggestion
+    }
+
+    /// Where `span` starts, or `None` for items with no source, such as
+    /// those of a loaded interface.
+    fn location(&self, files: &Files<String>, span: Span) -> Option<Location> {
+        if span == Span::default() {
+            return None;
+        }
+        let (file_id, start) = self.file_at(span.start().to_usize());
+        let text = files.source(file_id);
+        let offset = (span.start().to_usize() - start).min(text.len());
+        Some(Location {
+            file: files.name(file_id).to_string_lossy().into_owned(),
+            line: text[..offset].matches('\n').count() + 1,
+        })
+    }
+
+    /// The file containing the offset `offset` and where it starts.
+    fn file_at(&self, offset: usize) -> (FileId, usize) {
+        *self.files.iter().rev()
+            .find(|(_, start)| *start <= offset)
+            .unwrap_or(&self.files[0])
+    }
+}
@@ -0,0 +1,82 @@
+//! Dependency ordering shared by the typechecker and the C backend.
+
+use crate::ast::{Expr, Stmt};
+use std::collections::HashMap;
+
+/// Orders the nodes `0..count` so that every node comes after the nodes it
+/// depends on, keeping the original order wherever it is unconstrained.
+///
+/// On a cycle, returns the nodes forming it, starting and ending with the
+/// same node (`a -> b -> a`).
+pub fn topo_sort<F>(count: usize, deps: F) -> Result<Vec<usize>, Vec<usize>>
+where
+    F: Fn(usize) -> Vec<usize>,
+{
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        Unvisited,
+        Visiting,
+        Done,
+    }
+
+    fn visit<F: Fn(usize) -> Vec<usize>>(
+        node: usize,
+        deps: &F,
+        state: &mut [State],
+        stack: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) -> Result<(), Vec<usize>> {
+        match state[node] {
+            State::Done => return Ok(()),
+            State::Visiting => {
+                let start = stack.iter().position(|&n| n == node).unwrap();
+                let mut cycle = stack[start..].to_vec();
+                cycle.push(node);
+                return Err(cycle);
+            }
+            State::Unvisited => {}
+        }
+
+        state[node] = State::Visiting;
+        stack.push(node);
+        for dep in deps(node) {
+            visit(dep, deps, state, stack, order)?;
+        }
+        stack.pop();
+        state[node] = State::Done;
+        order.push(node);
+        Ok(())
+    }
+
+    let mut state = vec![State::Unvisited; count];
+    let mut stack = Vec::new();
+    let mut order = Vec::with_capacity(count);
+    for node in 0..count {
+        visit(node, &deps, &mut state, &mut stack, &mut order)?;
+    }
+    Ok(order)
+}
+
+/// Orders the top-level `let` statements of a program so each global is
+/// initialized after the globals its initializer reads. Returns indices into
+/// `stmts`; a cycle is reported the same way as in [`topo_sort`].
+pub fn global_order(stmts: &[Stmt]) -> Result<Vec<usize>, Vec<usize>> {
+    let globals: Vec<(usize, &str, &Expr)> = stmts.iter().enumerate()
+        .filter_map(|(i, stmt)| match stmt {
+            Stmt::Let(name, _, expr, _) => Some((i, name.as_str(), expr)),
+            _ => None,
+        })
+        .collect();
+    let index: HashMap<&str, usize> = globals.iter().enumerate()
+        .map(|(i, (_, name, _))| (*name, i))
+        .collect();
+
+    let to_stmts = |nodes: Vec<usize>| nodes.into_iter().map(|i| globals[i].0).collect();
+    topo_sort(globals.len(), |i| {
+        globals[i].2.referenced_vars().into_iter()
+            .filter_map(|name| index.get(name).copied())
+            .collect()
+    })
+    .map(to_stmts)
+    .map_err(to_stmts)
+}
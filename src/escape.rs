@@ -0,0 +1,198 @@
+//! Escape analysis for heap allocations. A `let p = __alloc(N)` with a
+//! constant size, whose pointer is only dereferenced and freed inside the
+//! function that made it, is moved to the stack and its frees are dropped.
+
+use crate::ast::{Expr, Function, Program, Stmt};
+use crate::log::{self, Level};
+use std::collections::{HashMap, HashSet};
+
+/// Allocations larger than this stay on the heap.
+const MAX_STACK_ALLOC: i64 = 4096;
+
+/// Rewrites non-escaping allocations in every function to `__stack_alloc`,
+/// which the backend lowers to a block-scoped buffer. Runs after type
+/// checking, so the rewritten intrinsic is never seen by the checker.
+pub fn stack_allocate(program: &mut Program) {
+    for function in &mut program.functions {
+        let local = non_escaping_allocations(function);
+        if !local.is_empty() {
+            rewrite_block(&mut function.body, &local);
+        }
+    }
+}
+
+fn non_escaping_allocations(function: &Function) -> HashSet<String> {
+    let mut analysis = Analysis::default();
+    for (name, _) in &function.params {
+        analysis.declare(name);
+    }
+    for stmt in &function.body {
+        analysis.collect(stmt);
+    }
+
+    let Analysis { declarations, allocations, escaped } = analysis;
+    allocations.into_iter()
+        .filter(|name| {
+            let reason = if declarations[name] > 1 {
+                "the name is shadowed"
+            } else if escaped.contains(name) {
+                "the pointer escapes"
+            } else {
+                log::event!(Level::Debug, "opt", "moved allocation '{}' in '{}' to the stack", name, function.name);
+                return true;
+            };
+            log::event!(Level::Trace, "opt", "allocation '{}' in '{}' stays on the heap: {}", name, function.name, reason);
+            false
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct Analysis {
+    /// How often each name is declared; shadowed names are left alone.
+    declarations: HashMap<String, usize>,
+    allocations: Vec<String>,
+    /// Names used in a way that could let the pointer outlive its block.
+    escaped: HashSet<String>,
+}
+
+impl Analysis {
+    fn declare(&mut self, name: &str) {
+        *self.declarations.entry(name.to_string()).or_default() += 1;
+    }
+
+    fn collect(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let(name, _, init, _) => {
+                self.declare(name);
+                if allocation_size(init).is_some_and(|size| (1..=MAX_STACK_ALLOC).contains(&size)) {
+                    self.allocations.push(name.clone());
+                } else {
+                    self.uses(init);
+                }
+            }
+            Stmt::Static(name, _, init, _) => {
+                self.declare(name);
+                self.uses(init);
+            }
+            Stmt::Expr(expr, _) | Stmt::Defer(expr, _) => {
+                if freed_name(expr).is_none() {
+                    self.uses(expr);
+                }
+            }
+            Stmt::Return(expr, _) => self.uses(expr),
+            Stmt::If(cond, then_branch, else_branch, _) => {
+                self.uses(cond);
+                self.collect_all(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.collect_all(else_branch);
+                }
+            }
+            Stmt::While(cond, body, _) => {
+                self.uses(cond);
+                self.collect_all(body);
+            }
+            Stmt::For(name, range, body, _) => {
+                self.declare(name);
+                self.uses(range);
+                self.collect_all(body);
+            }
+            Stmt::Block(stmts, _) => self.collect_all(stmts),
+            Stmt::Switch(value, _, cases, default, _) => {
+                self.uses(value);
+                for (_, body) in cases {
+                    self.collect_all(body);
+                }
+                if let Some(default) = default {
+                    self.collect_all(default);
+                }
+            }
+            Stmt::TailCall(args, _) => {
+                for arg in args {
+                    self.uses(arg);
+                }
+            }
+            Stmt::Break(_) | Stmt::Continue(_) => {}
+            Stmt::LetPattern(..) | Stmt::IfLet(..) | Stmt::WhileLet(..) | Stmt::ForPattern(..) | Stmt::Match(..) => {
+                unreachable!("escape analysis runs on lowered code")
+            }
+        }
+    }
+
+    fn collect_all(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.collect(stmt);
+        }
+    }
+
+    /// Marks every variable used by `expr` as escaped, except where the use
+    /// only reads or writes through the pointer.
+    fn uses(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Var(name, _, _) => {
+                self.escaped.insert(name.clone());
+            }
+            Expr::Deref(inner, _, _) | Expr::Field(inner, _, _, _) if matches!(**inner, Expr::Var(..)) => {}
+            Expr::SafeBlock(stmts, _, _) => self.collect_all(stmts),
+            Expr::Block(stmts, value, _, _) => {
+                self.collect_all(stmts);
+                self.uses(value);
+            }
+            _ => {
+                for child in expr.children() {
+                    self.uses(child);
+                }
+            }
+        }
+    }
+}
+
+/// The constant size of `__alloc(N)`, possibly behind a pointer cast.
+fn allocation_size(expr: &Expr) -> Option<i64> {
+    match expr {
+        Expr::Cast(inner, _, _, _) => allocation_size(inner),
+        Expr::IntrinsicCall(name, args, _, _) if name == "__alloc" => match args.as_slice() {
+            [Expr::Int(size, _, _)] => Some(*size),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The variable freed by a `__dealloc(p)` or `__free(p)` statement.
+fn freed_name(expr: &Expr) -> Option<&str> {
+    let Expr::IntrinsicCall(name, args, _, _) = expr else { return None };
+    if name != "__dealloc" && name != "__free" {
+        return None;
+    }
+    let mut arg = args.first()?;
+    while let Expr::Cast(inner, _, _, _) = arg {
+        arg = inner;
+    }
+    match arg {
+        Expr::Var(name, _, _) => Some(name),
+        _ => None,
+    }
+}
+
+fn rewrite_block(stmts: &mut Vec<Stmt>, local: &HashSet<String>) {
+    stmts.retain(|stmt| match stmt {
+        Stmt::Expr(expr, _) | Stmt::Defer(expr, _) => !freed_name(expr).is_some_and(|name| local.contains(name)),
+        _ => true,
+    });
+    for stmt in stmts.iter_mut() {
+        if let Stmt::Let(name, _, init, _) = stmt
+            && local.contains(name) {
+            to_stack(init);
+        }
+        stmt.for_each_block(&mut |block| rewrite_block(block, local));
+    }
+}
+
+fn to_stack(expr: &mut Expr) {
+    match expr {
+        Expr::Cast(inner, _, _, _) => to_stack(inner),
+        Expr::IntrinsicCall(name, _, _, _) => *name = "__stack_alloc".to_string(),
+        _ => {}
+    }
+}
@@ -0,0 +1,156 @@
+//! Machine-applicable fixes. Some diagnostics know the exact edit that
+//! resolves them, such as the name a misspelled one was meant to be or the
+//! `#[derive]` a comparison needs; they carry it as a [`Suggestion`], which
+//! `verve fix` applies to the source files, or shows as a diff with
+//! `--dry-run`.
+
+use codespan::{FileId, Span};
+
+/// Replace `span` of `file_id` with `replacement`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    /// What the edit does, also shown as a `help:` note on its diagnostic.
+    pub message: String,
+    pub file_id: FileId,
+    pub span: Span,
+    pub replacement: String,
+}
+
+/// `text` with `suggestions` applied. A suggestion overlapping one earlier
+/// in the text is left out, as is an exact duplicate.
+pub fn apply(text: &str, suggestions: &[&Suggestion]) -> String {
+    let mut sorted = suggestions.to_vec();
+    sorted.sort_by_key(|suggestion| (suggestion.span.start(), suggestion.span.end()));
+    // Comparing a type twice suggests the same `#[derive]` twice.
+    sorted.dedup_by(|a, b| a.span == b.span && a.replacement == b.replacement);
+    let mut out = String::with_capacity(text.len());
+    let mut done = 0;
+    for suggestion in sorted {
+        let (start, end) = (suggestion.span.start().to_usize(), suggestion.span.end().to_usize());
+        if start < done || end > text.len() {
+            continue;
+        }
+        out.push_str(&text[done..start]);
+        out.push_str(&suggestion.replacement);
+        done = end;
+    }
+    out.push_str(&text[done..]);
+    out
+}
+
+/// Lines of context around each change in [`diff`].
+const CONTEXT: usize = 3;
+
+/// The changes from `old` to `new` as a unified diff of the file `name`.
+pub fn diff(name: &str, old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let ops = line_ops(&old, &new);
+
+    let mut out = format!("--- {}\n+++ {}\n", name, name);
+    let changed: Vec<usize> = ops.iter().enumerate()
+        .filter(|(_, op)| !matches!(op, Op::Keep(..)))
+        .map(|(i, _)| i)
+        .collect();
+    let mut i = 0;
+    while i < changed.len() {
+        // A hunk runs on while the next change is within reach of its context.
+        let mut last = i;
+        while last + 1 < changed.len() && changed[last + 1] - changed[last] <= 2 * CONTEXT {
+            last += 1;
+        }
+        let start = changed[i].saturating_sub(CONTEXT);
+        let end = (changed[last] + CONTEXT + 1).min(ops.len());
+        let hunk = &ops[start..end];
+        let (old_start, new_start) = ops[..start].iter().fold((1, 1), |(a, b), op| match op {
+            Op::Keep(..) => (a + 1, b + 1),
+            Op::Remove(_) => (a + 1, b),
+            Op::Add(_) => (a, b + 1),
+        });
+        let old_len = hunk.iter().filter(|op| !matches!(op, Op::Add(_))).count();
+        let new_len = hunk.iter().filter(|op| !matches!(op, Op::Remove(_))).count();
+        out.push_str(&format!("@@ -{},{} +{},{} @@\n", old_start, old_len, new_start, new_len));
+        for op in hunk {
+            match op {
+                Op::Keep(line) => out.push_str(&format!(" {}\n", line)),
+                Op::Remove(line) => out.push_str(&format!("-{}\n", line)),
+                Op::Add(line) => out.push_str(&format!("+{}\n", line)),
+            }
+        }
+        i = last + 1;
+    }
+    out
+}
+
+enum Op<'a> {
+    Keep(&'a str),
+    Remove(&'a str),
+    Add(&'a str),
+}
+
+/// The edit script from `old` to `new` along their longest common
+/// subsequence of lines. Fixes touch few lines, so the common prefix and
+/// suffix are taken off first.
+fn line_ops<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Op<'a>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let (old_mid, new_mid) = (&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix]);
+
+    // lengths[i][j]: the longest common subsequence of old_mid[i..] and new_mid[j..].
+    let mut lengths = vec![vec![0usize; new_mid.len() + 1]; old_mid.len() + 1];
+    for i in (0..old_mid.len()).rev() {
+        for j in (0..new_mid.len()).rev() {
+            lengths[i][j] = if old_mid[i] == new_mid[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut ops: Vec<Op> = old[..prefix].iter().map(|line| Op::Keep(line)).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < old_mid.len() || j < new_mid.len() {
+        if i < old_mid.len() && j < new_mid.len() && old_mid[i] == new_mid[j] {
+            ops.push(Op::Keep(old_mid[i]));
+            i += 1;
+            j += 1;
+        } else if i < old_mid.len() && (j == new_mid.len() || lengths[i + 1][j] >= lengths[i][j + 1]) {
+            ops.push(Op::Remove(old_mid[i]));
+            i += 1;
+        } else {
+            ops.push(Op::Add(new_mid[j]));
+            j += 1;
+        }
+    }
+    ops.extend(old[old.len() - suffix..].iter().map(|line| Op::Keep(line)));
+    ops
+}
+
+/// The name among `candidates` closest to `name` in edit distance, when
+/// it is close enough to be a misspelling of it.
+pub fn closest_name<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = if name.chars().count() <= 4 { 1 } else { 2 };
+    candidates.into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = if ca == *cb { diagonal } else { 1 + diagonal.min(above).min(row[j]) };
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
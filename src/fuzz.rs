@@ -0,0 +1,543 @@
+//! Support for fuzzing the front end. The generators turn the fuzzer's
+//! bytes into source text, either as a stream of tokens, which mostly
+//! exercises the parser's error paths, or as a program shaped like real
+//! ones, which gets through to the type checker and the later passes.
+//! [`reduce`] shrinks a failing input to the few tokens that still fail.
+//! The cargo-fuzz targets in `fuzz/` are built on these; see [`Target`].
+
+use crate::compiler::{Compiler, DiagnosticHandler};
+use crate::lexer::Token;
+use logos::Logos;
+
+/// Reads choices out of the fuzzer's bytes. Once they run out every choice
+/// is 0, so generators put the option that ends the input first.
+pub struct Input<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Input<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn byte(&mut self) -> u8 {
+        match self.data.split_first() {
+            Some((&byte, rest)) => {
+                self.data = rest;
+                byte
+            }
+            None => 0,
+        }
+    }
+
+    /// A number below `n`.
+    pub fn choose(&mut self, n: usize) -> usize {
+        self.byte() as usize % n
+    }
+
+    pub fn pick<T: Copy>(&mut self, options: &[T]) -> T {
+        options[self.choose(options.len())]
+    }
+}
+
+/// Spellings for every token, with a few names, numbers and strings that
+/// mean something to the compiler.
+const TOKENS: &[&str] = &[
+    "fn", "let", "if", "else", "return", "safe", "rawptr", "defer", "as", "print", "while", "for",
+    "break", "continue", "struct", "enum", "union", "static", "match", "case", "in", "i32", "bool",
+    "string", "->", ":", "::", "==", "(", ")", "[", "]", "#", "{", "}", ",", "=", ";", "+", "-",
+    "*", "/", ">", "<", "..", ".", "x", "y", "main", "S", "E", "A", "B", "Some", "None", "Option",
+    "true", "false", "void", "_", "__unwrap", "__is_some", "__alloc", "__chars", "inline", "0", "1",
+    "2147483648", "\"s\"",
+];
+
+/// Source text made of up to one token per byte of `data`.
+pub fn token_stream(data: &[u8]) -> String {
+    let mut input = Input::new(data);
+    let mut tokens = Vec::new();
+    while !input.is_empty() {
+        tokens.push(input.pick(TOKENS));
+    }
+    tokens.join(" ")
+}
+
+/// How deeply generated expressions and blocks nest.
+const MAX_DEPTH: usize = 4;
+
+/// The types generated programs use. Each has a local of that type in
+/// every function, named in [`Ty::local`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ty {
+    I32,
+    Bool,
+    Str,
+    S,
+    E,
+    Opt,
+    Ptr,
+}
+
+const TYS: &[Ty] = &[Ty::I32, Ty::Bool, Ty::Str, Ty::S, Ty::E, Ty::Opt, Ty::Ptr];
+
+impl Ty {
+    fn local(self) -> &'static str {
+        match self {
+            Ty::I32 => "n",
+            Ty::Bool => "b",
+            Ty::Str => "t",
+            Ty::S => "s",
+            Ty::E => "e",
+            Ty::Opt => "o",
+            Ty::Ptr => "p",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Ty::I32 => "i32",
+            Ty::Bool => "bool",
+            Ty::Str => "string",
+            Ty::S => "S",
+            Ty::E => "E",
+            Ty::Opt => "Option<i32>",
+            Ty::Ptr => "*i32",
+        }
+    }
+}
+
+/// A program with a struct `S`, enums `E` and `K`, a few functions and
+/// `main`. Expressions are generated for the type their place needs, so
+/// most programs get through the type checker to the later passes; now and
+/// then one is of another type, which the type checker has to report.
+pub fn program(data: &[u8]) -> String {
+    let mut input = Input::new(data);
+    let functions = (0..1 + input.choose(3))
+        .map(|_| {
+            let params = TYS.iter().copied().filter(|_| input.choose(3) == 1).collect();
+            let ret = if input.choose(4) == 1 { None } else { Some(input.pick(TYS)) };
+            (params, ret)
+        })
+        .collect();
+    let mut generator = Generator { input, out: String::new(), functions };
+    generator.program();
+    generator.out
+}
+
+struct Generator<'a> {
+    input: Input<'a>,
+    out: String,
+    /// Parameter and result types of `f0`, `f1`, ...; `None` for `void`.
+    functions: Vec<(Vec<Ty>, Option<Ty>)>,
+}
+
+impl Generator<'_> {
+    fn program(&mut self) {
+        self.out.push_str("struct S { a: i32, b: bool }\n");
+        self.out.push_str("enum E { A, B(i32), C(string, i32) }\n");
+        self.out.push_str("enum K { R, G }\n");
+        for i in 0..self.functions.len() {
+            let (params, ret) = self.functions[i].clone();
+            self.function(&format!("f{}", i), &params, ret);
+        }
+        self.function("main", &[], Some(Ty::I32));
+    }
+
+    fn function(&mut self, name: &str, params: &[Ty], ret: Option<Ty>) {
+        if self.input.choose(8) == 7 {
+            self.out.push_str(self.input.pick(&["#[inline]\n", "#[export]\n"]));
+        }
+        let params: Vec<_> = params.iter().map(|ty| format!("{}: {}", ty.local(), ty.name())).collect();
+        let ret_name = ret.map_or("void", Ty::name);
+        self.out.push_str(&format!("fn {}({}) -> {} {{\n", name, params.join(", "), ret_name));
+        for ty in TYS {
+            if !params.iter().any(|param| param.starts_with(&format!("{}:", ty.local()))) {
+                self.out.push_str(&format!("let {} = {};\n", ty.local(), Self::initial(*ty)));
+            }
+        }
+        let mut stmts = 0;
+        while stmts < 8 && self.input.choose(4) != 0 {
+            self.stmt(1, ret);
+            stmts += 1;
+        }
+        if let Some(ret) = ret {
+            self.out.push_str("return ");
+            self.expr(ret, 1);
+            self.out.push_str(";\n");
+        }
+        self.out.push_str("}\n");
+    }
+
+    fn initial(ty: Ty) -> &'static str {
+        match ty {
+            Ty::I32 => "0",
+            Ty::Bool => "false",
+            Ty::Str => "\"\"",
+            Ty::S => "(S { a: 0, b: false })",
+            Ty::E => "E::A",
+            Ty::Opt => "Some(0)",
+            Ty::Ptr => "__alloc(4) as *i32",
+        }
+    }
+
+    fn block(&mut self, depth: usize, ret: Option<Ty>) {
+        self.out.push_str("{\n");
+        for _ in 0..self.input.choose(3) {
+            self.stmt(depth + 1, ret);
+        }
+        self.out.push_str("}\n");
+    }
+
+    fn stmt(&mut self, depth: usize, ret: Option<Ty>) {
+        let choice = if depth >= MAX_DEPTH { self.input.choose(3) } else { self.input.choose(16) };
+        match choice {
+            0 => {
+                self.out.push_str("print(");
+                let ty = self.input.pick(&[Ty::I32, Ty::Bool, Ty::Str]);
+                self.expr(ty, depth);
+                self.out.push_str(");\n");
+            }
+            1 => {
+                let ty = self.input.pick(TYS);
+                self.out.push_str(&format!("{} = ", ty.local()));
+                self.expr(ty, depth);
+                self.out.push_str(";\n");
+            }
+            2 => {
+                self.out.push_str(self.input.pick(&["s.a = ", "*p = "]));
+                self.expr(Ty::I32, depth);
+                self.out.push_str(";\n");
+            }
+            3 => {
+                self.out.push_str("if ");
+                self.expr(Ty::Bool, depth);
+                self.out.push(' ');
+                self.block(depth, ret);
+                if self.input.choose(2) == 1 {
+                    self.out.push_str("else ");
+                    self.block(depth, ret);
+                }
+            }
+            4 => {
+                self.out.push_str("while ");
+                self.expr(Ty::Bool, depth);
+                self.out.push(' ');
+                self.block(depth, ret);
+            }
+            5 => {
+                self.out.push_str("for n in ");
+                self.expr(Ty::I32, depth + 1);
+                self.out.push_str("..");
+                self.expr(Ty::I32, depth + 1);
+                self.out.push(' ');
+                self.block(depth, ret);
+            }
+            6 => {
+                self.out.push_str("match e {\n");
+                for case in ["E::A", "E::B(n)", "C(t, _)"] {
+                    if self.input.choose(4) != 3 {
+                        self.out.push_str(&format!("case {}: ", case));
+                        self.stmt(depth + 1, ret);
+                    }
+                }
+                if self.input.choose(2) == 1 {
+                    self.out.push_str("case _: ");
+                    self.stmt(depth + 1, ret);
+                }
+                self.out.push_str("}\n");
+            }
+            7 => {
+                self.out.push_str("match (o, b) {\ncase (Some(n), true): ");
+                self.stmt(depth + 1, ret);
+                self.out.push_str("case (Some(1), _): ");
+                self.stmt(depth + 1, ret);
+                self.out.push_str("case (_, _): ");
+                self.stmt(depth + 1, ret);
+                self.out.push_str("}\n");
+            }
+            8 => {
+                self.out.push_str(self.input.pick(&["if let Some(n) = ", "while let Some(n) = "]));
+                self.expr(Ty::Opt, depth);
+                self.out.push_str(" {\no = None;\n");
+                self.stmt(depth + 1, ret);
+                self.out.push_str("}\n");
+            }
+            9 => {
+                let of_struct = self.input.choose(2) == 1;
+                self.out.push_str(if of_struct { "let S { a: n, b } = (S { a: " } else { "let (n, b) = (" });
+                self.expr(Ty::I32, depth);
+                self.out.push_str(if of_struct { ", b: " } else { ", " });
+                self.expr(Ty::Bool, depth);
+                self.out.push_str(if of_struct { " });\n" } else { ");\n" });
+            }
+            10 => {
+                self.out.push_str("safe {\n");
+                if self.input.choose(2) == 1 {
+                    self.out.push_str("defer __dealloc(p);\n");
+                }
+                self.stmt(depth + 1, ret);
+                self.out.push_str("}\n");
+            }
+            11 => {
+                self.out.push_str("static let n = ");
+                self.out.push_str(self.input.pick(&["0", "1", "(2 * 3)"]));
+                self.out.push_str(";\n");
+            }
+            12 => {
+                self.out.push_str("return ");
+                match ret {
+                    Some(ty) => self.expr(ty, depth),
+                    None => self.call(None, depth),
+                }
+                self.out.push_str(";\n");
+            }
+            13 => self.block(depth, ret),
+            14 => {
+                let ty = self.input.pick(TYS);
+                self.out.push_str(&format!("let {}: {} = ", ty.local(), ty.name()));
+                self.expr(ty, depth);
+                self.out.push_str(";\n");
+            }
+            _ => {
+                self.call(None, depth);
+                self.out.push_str(";\n");
+            }
+        }
+    }
+
+    /// A call to one of the generated functions returning `ret`, or to
+    /// the first one if none does.
+    fn call(&mut self, ret: Option<Ty>, depth: usize) {
+        let candidates: Vec<usize> = (0..self.functions.len()).filter(|&i| self.functions[i].1 == ret).collect();
+        let index = if candidates.is_empty() { 0 } else { candidates[self.input.choose(candidates.len())] };
+        let params = self.functions[index].0.clone();
+        self.out.push_str(&format!("f{}(", index));
+        for (i, ty) in params.into_iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.expr(ty, depth + 1);
+        }
+        self.out.push(')');
+    }
+
+    fn expr(&mut self, ty: Ty, depth: usize) {
+        // Mostly of the type asked for; the last choice, never taken once
+        // the input runs out, is a mistake for the type checker to find.
+        let ty = if self.input.choose(32) == 31 { self.input.pick(TYS) } else { ty };
+        let choice = if depth >= MAX_DEPTH { self.input.choose(2) } else { self.input.choose(8) };
+        match (ty, choice) {
+            (_, 0) => self.out.push_str(ty.local()),
+            (_, 1) | (Ty::Ptr, _) => self.out.push_str(match ty {
+                Ty::I32 => self.input.pick(&["0", "1", "7", "2147483647"]),
+                Ty::Bool => self.input.pick(&["true", "false"]),
+                Ty::Str => self.input.pick(&["\"\"", "\"s\"", "\"héllo\""]),
+                Ty::Opt => "None",
+                _ => Self::initial(ty),
+            }),
+            (_, 2) => {
+                self.out.push_str("({ ");
+                self.stmt(depth + 1, None);
+                self.out.push('(');
+                self.expr(ty, depth + 1);
+                self.out.push_str(") })");
+            }
+            (_, 3) => self.call(Some(ty), depth),
+            (Ty::I32, _) => {
+                let form = self.input.pick(&["(# + #)", "(# - #)", "(# * #)", "(# / #)", "s.a", "(*p)", "__unwrap(o)", "__char_len(t)", "(K::G as i32)"]);
+                self.template(form, Ty::I32, depth);
+            }
+            (Ty::Bool, _) => {
+                let form = self.input.pick(&["(# == #)", "(# < #)", "(# > #)", "s.b", "__is_some(o)"]);
+                self.template(form, Ty::I32, depth);
+            }
+            (Ty::Str, _) => self.template("__unwrap(__str_slice(t, #, #))", Ty::I32, depth),
+            (Ty::S, _) => {
+                let form = self.input.pick(&["(S { a: #, b: b })", "(S { a: #, ..s })"]);
+                self.template(form, Ty::I32, depth);
+            }
+            (Ty::E, _) => {
+                let form = self.input.pick(&["E::B(#)", "E::C(t, #)"]);
+                self.template(form, Ty::I32, depth);
+            }
+            (Ty::Opt, _) => {
+                let form = self.input.pick(&["Some(#)", "None"]);
+                self.template(form, Ty::I32, depth);
+            }
+        }
+    }
+
+    /// Writes `form` with an expression of type `hole` for each `#`.
+    fn template(&mut self, form: &str, hole: Ty, depth: usize) {
+        let mut parts = form.split('#');
+        self.out.push_str(parts.next().unwrap_or_default());
+        for part in parts {
+            self.expr(hole, depth + 1);
+            self.out.push_str(part);
+        }
+    }
+}
+
+/// The fuzz targets in `fuzz/fuzz_targets`, which differ in how they make
+/// source text out of the fuzzer's bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// The bytes as UTF-8 text.
+    Source,
+    /// [`token_stream`].
+    Tokens,
+    /// [`program`].
+    Programs,
+}
+
+impl Target {
+    pub const ALL: [Target; 3] = [Target::Source, Target::Tokens, Target::Programs];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Target::Source => "source",
+            Target::Tokens => "tokens",
+            Target::Programs => "programs",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Target> {
+        Target::ALL.into_iter().find(|target| target.name() == name)
+    }
+
+    /// The source text and optimization level the target compiles for
+    /// `data`. The generated targets take the level from the first byte;
+    /// `source` compiles at -O0 and skips input that is not UTF-8.
+    pub fn input(self, data: &[u8]) -> Option<(String, u8)> {
+        match self {
+            Target::Source => std::str::from_utf8(data).ok().map(|source| (source.to_string(), 0)),
+            Target::Tokens | Target::Programs => {
+                let (&level, rest) = data.split_first()?;
+                let source = if self == Target::Tokens { token_stream(rest) } else { program(rest) };
+                Some((source, level % 3))
+            }
+        }
+    }
+}
+
+/// Compiles `source` at `opt_level`, ignoring compile errors: the fuzz
+/// targets only look for panics.
+pub fn compile(source: &str, opt_level: u8) {
+    let mut compiler = Compiler::new().opt_level(opt_level);
+    let file_id = compiler.add_source("fuzz.vrv", source);
+    let mut diagnostics: Vec<_> = Vec::new();
+    let _ = compiler.compile(file_id, &mut diagnostics as &mut dyn DiagnosticHandler);
+}
+
+/// The message of the panic compiling `source` causes, if it does. The
+/// default panic hook still prints it; set a quiet one to run this many
+/// times.
+pub fn panic_message(source: &str, opt_level: u8) -> Option<String> {
+    let payload = std::panic::catch_unwind(|| compile(source, opt_level)).err()?;
+    Some(match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => "Box<dyn Any>".to_string(),
+    })
+}
+
+/// Shrinks `source` while `fails` still holds for it. Runs of tokens are
+/// removed, halving the run length down to single tokens, and bracketed
+/// groups are removed whole or replaced by their contents, until nothing
+/// more can go. The result is the remaining tokens separated by spaces,
+/// without comments. `fails` must hold for `source` itself.
+pub fn reduce(source: &str, mut fails: impl FnMut(&str) -> bool) -> String {
+    let mut tokens: Vec<&str> = Token::lexer(source)
+        .spanned()
+        .filter(|(token, _)| *token != Ok(Token::BlockComment))
+        .map(|(_, span)| &source[span])
+        .collect();
+    loop {
+        let before = tokens.len();
+        remove_runs(&mut tokens, &mut fails);
+        remove_groups(&mut tokens, &mut fails);
+        if tokens.len() == before {
+            return tokens.join(" ");
+        }
+    }
+}
+
+fn remove_runs(tokens: &mut Vec<&str>, fails: &mut impl FnMut(&str) -> bool) {
+    let mut run = tokens.len().div_ceil(2).max(1);
+    loop {
+        let mut removed = false;
+        let mut start = 0;
+        while start < tokens.len() {
+            let end = (start + run).min(tokens.len());
+            if try_without(tokens, |i| (start..end).contains(&i), fails) {
+                removed = true;
+            } else {
+                start = end;
+            }
+        }
+        if run == 1 && !removed {
+            return;
+        }
+        if !removed {
+            run = run.div_ceil(2);
+        }
+    }
+}
+
+/// Tries each `(`, `[` and `{` group, the innermost last, without its
+/// tokens and then without only its brackets.
+fn remove_groups(tokens: &mut Vec<&str>, fails: &mut impl FnMut(&str) -> bool) {
+    let mut open = 0;
+    while open < tokens.len() {
+        if let Some(close) = closing(tokens, open) {
+            if try_without(tokens, |i| (open..=close).contains(&i), fails) {
+                continue;
+            }
+            if try_without(tokens, |i| i == open || i == close, fails) {
+                continue;
+            }
+        }
+        open += 1;
+    }
+}
+
+/// The index of the bracket closing the one at `open`, if it is one.
+fn closing(tokens: &[&str], open: usize) -> Option<usize> {
+    let close = match tokens[open] {
+        "(" => ")",
+        "[" => "]",
+        "{" => "}",
+        _ => return None,
+    };
+    let mut depth = 0;
+    for (i, &token) in tokens.iter().enumerate().skip(open) {
+        if token == tokens[open] {
+            depth += 1;
+        } else if token == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+/// Removes the tokens whose indices `remove` holds for, if the input still
+/// fails without them.
+fn try_without(tokens: &mut Vec<&str>, remove: impl Fn(usize) -> bool, fails: &mut impl FnMut(&str) -> bool) -> bool {
+    let candidate: Vec<&str> = tokens.iter()
+        .enumerate()
+        .filter(|&(i, _)| !remove(i))
+        .map(|(_, &token)| token)
+        .collect();
+    if fails(&candidate.join(" ")) {
+        *tokens = candidate;
+        true
+    } else {
+        false
+    }
+}
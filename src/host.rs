@@ -0,0 +1,72 @@
+//! What the compiler core asks of the machine it runs on. The pipeline
+//! from lexing to the generated C only reads the files `include_str` and
+//! `include_bytes` name, through a [`FileSystem`], so it runs where there
+//! is no file system at all, such as the browser the
+//! [`playground`](crate::playground) builds for. Running C compilers, build
+//! hooks and programs is left to the driver.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub trait FileSystem {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+}
+
+/// The files of the machine the compiler runs on.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NativeFs;
+
+impl FileSystem for NativeFs {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        std::fs::read(path)
+    }
+}
+
+/// Files held in memory, for embedders without a file system; a path is
+/// found only as it was inserted.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFs {
+    files: HashMap<PathBuf, Vec<u8>>,
+}
+
+impl MemoryFs {
+    pub fn insert(&mut self, path: impl Into<PathBuf>, bytes: impl Into<Vec<u8>>) {
+        self.files.insert(path.into(), bytes.into());
+    }
+}
+
+impl FileSystem for MemoryFs {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.files.get(path).cloned().ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+}
+
+/// Whether the compiler is built for WebAssembly without an operating
+/// system, where there is neither a clock nor files.
+pub const fn is_freestanding() -> bool {
+    cfg!(all(target_arch = "wasm32", target_os = "unknown"))
+}
+
+/// The known triples, each with whether it names the machine the compiler
+/// is built for.
+const NATIVE: &[(bool, &str)] = &[
+    (cfg!(all(target_arch = "aarch64", target_os = "macos")), "aarch64-apple-darwin"),
+    (cfg!(all(target_arch = "aarch64", target_os = "windows")), "aarch64-pc-windows-msvc"),
+    (cfg!(all(target_arch = "aarch64", target_os = "linux")), "aarch64-unknown-linux-gnu"),
+    (cfg!(all(target_arch = "arm", target_os = "linux")), "armv7-unknown-linux-gnueabihf"),
+    (cfg!(all(target_arch = "x86", target_os = "windows")), "i686-pc-windows-msvc"),
+    (cfg!(all(target_arch = "x86", target_os = "linux")), "i686-unknown-linux-gnu"),
+    (cfg!(all(target_arch = "riscv64", target_os = "linux")), "riscv64gc-unknown-linux-gnu"),
+    (cfg!(all(target_arch = "x86_64", target_os = "macos")), "x86_64-apple-darwin"),
+    (cfg!(all(target_arch = "x86_64", target_os = "windows", target_env = "gnu")), "x86_64-pc-windows-gnu"),
+    (cfg!(all(target_arch = "x86_64", target_os = "freebsd")), "x86_64-unknown-freebsd"),
+    (cfg!(all(target_arch = "x86_64", target_os = "linux")), "x86_64-unknown-linux-gnu"),
+];
+
+/// The triple of the machine the compiler runs on, which programs are built
+/// for unless another is asked for. Hosts no known triple names get
+/// `x86_64-pc-windows-msvc`.
+pub fn native_triple() -> &'static str {
+    NATIVE.iter().find(|(native, _)| *native).map_or("x86_64-pc-windows-msvc", |(_, triple)| triple)
+}
@@ -0,0 +1,136 @@
+//! Internal compiler errors. The compiler names the phase it is in as it
+//! goes; when a pass panics, [`catch`] turns the panic into an [`Ice`]
+//! recording that phase, the panic message and a backtrace, and the driver
+//! writes it with the program's sources to a reproduction bundle for the
+//! bug report.
+
+use std::backtrace::Backtrace;
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::Once;
+
+thread_local! {
+    static PHASE: Cell<&'static str> = const { Cell::new("startup") };
+    /// Set while [`catch`] runs, so the panic hook keeps the details
+    /// instead of printing them.
+    static CAUGHT: RefCell<Option<Ice>> = const { RefCell::new(None) };
+    static ARMED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// A panic inside the compiler.
+#[derive(Debug, Clone)]
+pub struct Ice {
+    pub phase: &'static str,
+    pub message: String,
+    /// `file:line:column` of the panic in the compiler's own source.
+    pub location: String,
+    pub backtrace: String,
+}
+
+/// Records that the compiler has moved on to `phase`.
+pub fn enter(phase: &'static str) {
+    PHASE.with(|current| current.set(phase));
+}
+
+/// The phase the compiler is in.
+pub fn phase() -> &'static str {
+    PHASE.with(Cell::get)
+}
+
+/// Runs `f`, returning the [`Ice`] if it panics. The panic is not printed.
+pub fn catch<T>(f: impl FnOnce() -> T) -> Result<T, Ice> {
+    install_hook();
+    enter("startup");
+    let was_armed = ARMED.with(|armed| armed.replace(true));
+    let result = panic::catch_unwind(AssertUnwindSafe(f));
+    ARMED.with(|armed| armed.set(was_armed));
+    result.map_err(|payload| {
+        CAUGHT.with(|caught| caught.borrow_mut().take()).unwrap_or_else(|| Ice {
+            phase: phase(),
+            message: payload_message(payload.as_ref()),
+            location: String::new(),
+            backtrace: String::new(),
+        })
+    })
+}
+
+/// Chains a hook in front of the one installed before, which still handles
+/// panics outside [`catch`].
+fn install_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !ARMED.with(Cell::get) {
+                return previous(info);
+            }
+            let ice = Ice {
+                phase: phase(),
+                message: payload_message(info.payload()),
+                location: info.location().map(ToString::to_string).unwrap_or_default(),
+                backtrace: Backtrace::force_capture().to_string(),
+            };
+            CAUGHT.with(|caught| *caught.borrow_mut() = Some(ice));
+        }));
+    });
+}
+
+fn payload_message(payload: &(dyn std::any::Any + Send)) -> String {
+    match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => "(no message)".to_string(),
+    }
+}
+
+impl Ice {
+    /// What the driver prints, pointing at the bundle when there is one.
+    pub fn report(&self, bundle: Option<&Path>) -> String {
+        let mut out = format!("internal compiler error during {}: {}\n", self.phase, self.message);
+        if !self.location.is_empty() {
+            out.push_str(&format!("note: panicked at {}\n", self.location));
+        }
+        out.push_str("note: this is a bug in the Verve compiler, not in your program\n");
+        if let Some(dir) = bundle {
+            out.push_str(&format!("note: a reproduction bundle was written to {}; please attach it to a bug report\n", dir.display()));
+        }
+        out
+    }
+
+    /// Writes a reproduction bundle to a new directory under `parent`: the
+    /// `sources`, named as they were compiled, and `report.txt` with the
+    /// phase, the panic, `config` (the command line and settings) and the
+    /// backtrace. Returns the directory.
+    pub fn write_bundle(&self, parent: &Path, sources: &[(String, String)], config: &str) -> std::io::Result<PathBuf> {
+        let stamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        let dir = parent.join(format!("verve-ice-{}-{}", stamp, std::process::id()));
+        let source_dir = dir.join("sources");
+        std::fs::create_dir_all(&source_dir)?;
+        for (i, (name, text)) in sources.iter().enumerate() {
+            // Keep names that repeat, as files of the same name in
+            // different directories do, apart.
+            let base = Path::new(name).file_name().map_or_else(|| "stdin.vrv".into(), |base| base.to_string_lossy());
+            let file = if sources[..i].iter().any(|(other, _)| Path::new(other).file_name() == Path::new(name).file_name()) {
+                format!("{}-{}", i, base)
+            } else {
+                base.into_owned()
+            };
+            std::fs::write(source_dir.join(file), text)?;
+        }
+        let report = format!(
+            "phase: {}\npanic: {}\nat: {}\ncompiler: verve {}\n\n[config]\n{}\n\n[sources]\n{}\n\n[backtrace]\n{}\n",
+            self.phase,
+            self.message,
+            self.location,
+            env!("CARGO_PKG_VERSION"),
+            config.trim_end(),
+            sources.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>().join("\n"),
+            self.backtrace,
+        );
+        std::fs::write(dir.join("report.txt"), report)?;
+        Ok(dir)
+    }
+}
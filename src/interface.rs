@@ -0,0 +1,324 @@
+//! Interface files (`.vervei`): what other programs need to call into a
+//! compiled one without its source. They hold the signatures of the
+//! `#[export]` functions and those with a fixed C symbol, and every struct
+//! and enum, in a compact binary form:
+//!
+//! ```text
+//! "VRVI" version:u8 structs enums functions
+//! ```
+//!
+//! where each list is a `u32` count followed by its items, strings are a
+//! `u32` byte length followed by UTF-8, and integers are little-endian.
+//! Globals have internal linkage, so there are no constants to export yet.
+
+use crate::ast::{AttrArg, Attribute, CallConv, EnumDef, Field, Function, Program, StructDef, Type, Variant};
+use codespan::Span;
+
+const MAGIC: &[u8; 4] = b"VRVI";
+const VERSION: u8 = 4;
+
+/// The signature of an exported function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub name: String,
+    pub params: Vec<(String, Type)>,
+    pub return_type: Type,
+    /// The C symbol, when `#[export_name]` or `#[no_mangle]` fixes it.
+    pub symbol: Option<String>,
+    pub call_conv: CallConv,
+}
+
+#[derive(Debug, Default)]
+pub struct Interface {
+    pub structs: Vec<StructDef>,
+    pub enums: Vec<EnumDef>,
+    pub functions: Vec<Signature>,
+}
+
+impl Interface {
+    /// The interface of `program`: its exported functions other than `main`
+    /// and its types.
+    pub fn of(program: &Program) -> Interface {
+        let functions = program.functions.iter()
+            .filter(|func| (func.attr("export").is_some() || func.symbol().is_some()) && !func.is_extern())
+            .filter(|func| func.name != "main")
+            .map(|func| Signature {
+                name: func.name.clone(),
+                params: func.params.clone(),
+                return_type: func.return_type.clone(),
+                symbol: func.symbol().map(String::from),
+                call_conv: func.call_conv,
+            })
+            .collect();
+        Interface {
+            structs: program.structs.clone(),
+            enums: program.enums.clone(),
+            functions,
+        }
+    }
+
+    /// Adds the types to `program` and declares the functions as
+    /// `#[extern]`, to be linked from the program the interface came from.
+    pub fn add_to(&self, program: &mut Program) {
+        program.structs.extend(self.structs.iter().cloned());
+        program.enums.extend(self.enums.iter().cloned());
+        program.functions.extend(self.functions.iter().map(|sig| Function {
+            name: sig.name.clone(),
+            params: sig.params.clone(),
+            param_spans: Vec::new(),
+            return_type: sig.return_type.clone(),
+            body: Vec::new(),
+            attrs: std::iter::once(Attribute { name: "extern".to_string(), args: Vec::new(), span: Span::default() })
+                .chain(sig.symbol.iter().map(|symbol| Attribute {
+                    name: "export_name".to_string(),
+                    args: vec![AttrArg::Str(symbol.clone())],
+                    span: Span::default(),
+                }))
+                .collect(),
+            call_conv: sig.call_conv,
+            span: Span::default(),
+        }));
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Writer(MAGIC.to_vec());
+        out.u8(VERSION);
+        out.list(&self.structs, |out, def| {
+            out.str(&def.name);
+            out.u8(def.is_union as u8);
+            out.attrs(&def.attrs);
+            out.list(&def.fields, |out, field| {
+                out.str(&field.name);
+                out.ty(&field.ty);
+                out.u32(field.bits.map_or(0, |bits| bits + 1));
+            });
+        });
+        out.list(&self.enums, |out, def| {
+            out.str(&def.name);
+            out.attrs(&def.attrs);
+            out.list(&def.variants, |out, variant| {
+                out.str(&variant.name);
+                out.list(&variant.fields, Writer::ty);
+            });
+        });
+        out.list(&self.functions, |out, sig| {
+            out.str(&sig.name);
+            out.list(&sig.params, |out, (name, ty)| {
+                out.str(name);
+                out.ty(ty);
+            });
+            out.ty(&sig.return_type);
+            out.str(sig.symbol.as_deref().unwrap_or(""));
+            out.call_conv(sig.call_conv);
+        });
+        out.0
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Interface, String> {
+        let Some(rest) = bytes.strip_prefix(MAGIC) else {
+            return Err("Not a Verve interface file".to_string());
+        };
+        let mut input = Reader(rest);
+        let version = input.u8()?;
+        if version != VERSION {
+            return Err(format!("Unsupported interface version {} (expected {})", version, VERSION));
+        }
+        let span = Span::default();
+        let structs = input.list(|input| {
+            Ok(StructDef {
+                name: input.str()?,
+                is_union: input.u8()? != 0,
+                attrs: input.attrs()?,
+                fields: input.list(|input| {
+                    Ok(Field {
+                        name: input.str()?,
+                        ty: input.ty()?,
+                        bits: input.u32()?.checked_sub(1),
+                        span,
+                    })
+                })?,
+                span,
+            })
+        })?;
+        let enums = input.list(|input| {
+            Ok(EnumDef {
+                name: input.str()?,
+                attrs: input.attrs()?,
+                variants: input.list(|input| {
+                    Ok(Variant { name: input.str()?, fields: input.list(Reader::ty)?, span })
+                })?,
+                span,
+            })
+        })?;
+        let functions = input.list(|input| {
+            Ok(Signature {
+                name: input.str()?,
+                params: input.list(|input| Ok((input.str()?, input.ty()?)))?,
+                return_type: input.ty()?,
+                symbol: Some(input.str()?).filter(|symbol| !symbol.is_empty()),
+                call_conv: input.call_conv()?,
+            })
+        })?;
+        if !input.0.is_empty() {
+            return Err("Trailing bytes after the interface".to_string());
+        }
+        Ok(Interface { structs, enums, functions })
+    }
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.0.extend(value.as_bytes());
+    }
+
+    fn list<T>(&mut self, items: &[T], mut item: impl FnMut(&mut Self, &T)) {
+        self.u32(items.len() as u32);
+        for value in items {
+            item(self, value);
+        }
+    }
+
+    fn attrs(&mut self, attrs: &[Attribute]) {
+        self.list(attrs, |out, attr| {
+            out.str(&attr.name);
+            out.list(&attr.args, |out, arg| match arg {
+                AttrArg::Ident(name) => {
+                    out.u8(0);
+                    out.str(name);
+                }
+                AttrArg::Int(n) => {
+                    out.u8(1);
+                    out.0.extend(n.to_le_bytes());
+                }
+                AttrArg::Str(text) => {
+                    out.u8(2);
+                    out.str(text);
+                }
+                AttrArg::KeyValue(key, value) => {
+                    out.u8(3);
+                    out.str(key);
+                    out.str(value);
+                }
+            });
+        });
+    }
+
+    fn call_conv(&mut self, call_conv: CallConv) {
+        self.str(call_conv.name());
+    }
+
+    fn ty(&mut self, ty: &Type) {
+        match ty {
+            Type::I32 => self.u8(0),
+            Type::Bool => self.u8(1),
+            Type::String => self.u8(2),
+            Type::Void => self.u8(3),
+            Type::Function(params, ret, call_conv) => {
+                self.u8(4);
+                self.list(params, Self::ty);
+                self.ty(ret);
+                self.call_conv(*call_conv);
+            }
+            Type::Unknown => self.u8(5),
+            Type::Arena => self.u8(6),
+            Type::Pointer(inner) => {
+                self.u8(7);
+                self.ty(inner);
+            }
+            Type::RawPtr => self.u8(8),
+            Type::Named(name) => {
+                self.u8(9);
+                self.str(name);
+            }
+            Type::Option(inner) => {
+                self.u8(10);
+                self.ty(inner);
+            }
+            Type::Tuple(items) => {
+                self.u8(11);
+                self.list(items, Self::ty);
+            }
+        }
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn bytes(&mut self, len: usize) -> Result<&[u8], String> {
+        if self.0.len() < len {
+            return Err("Truncated interface file".to_string());
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn str(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.bytes(len)?.to_vec()).map_err(|_| "Invalid UTF-8 in interface file".to_string())
+    }
+
+    fn list<T>(&mut self, mut item: impl FnMut(&mut Self) -> Result<T, String>) -> Result<Vec<T>, String> {
+        let len = self.u32()?;
+        (0..len).map(|_| item(self)).collect()
+    }
+
+    fn attrs(&mut self) -> Result<Vec<Attribute>, String> {
+        self.list(|input| {
+            Ok(Attribute {
+                name: input.str()?,
+                args: input.list(|input| match input.u8()? {
+                    0 => Ok(AttrArg::Ident(input.str()?)),
+                    1 => Ok(AttrArg::Int(i64::from_le_bytes(input.bytes(8)?.try_into().unwrap()))),
+                    2 => Ok(AttrArg::Str(input.str()?)),
+                    3 => Ok(AttrArg::KeyValue(input.str()?, input.str()?)),
+                    tag => Err(format!("Unknown attribute argument tag {}", tag)),
+                })?,
+                span: Span::default(),
+            })
+        })
+    }
+
+    fn call_conv(&mut self) -> Result<CallConv, String> {
+        let name = self.str()?;
+        CallConv::from_name(&name).ok_or_else(|| format!("Unknown calling convention '{}'", name))
+    }
+
+    fn ty(&mut self) -> Result<Type, String> {
+        Ok(match self.u8()? {
+            0 => Type::I32,
+            1 => Type::Bool,
+            2 => Type::String,
+            3 => Type::Void,
+            4 => Type::Function(self.list(Self::ty)?, Box::new(self.ty()?), self.call_conv()?),
+            5 => Type::Unknown,
+            6 => Type::Arena,
+            7 => Type::Pointer(Box::new(self.ty()?)),
+            8 => Type::RawPtr,
+            9 => Type::Named(self.str()?),
+            10 => Type::Option(Box::new(self.ty()?)),
+            11 => Type::Tuple(self.list(Self::ty)?),
+            tag => return Err(format!("Unknown type tag {}", tag)),
+        })
+    }
+}
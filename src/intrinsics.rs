@@ -0,0 +1,183 @@
+//! The intrinsic registry. Intrinsics are the `__`-prefixed functions the
+//! compiler provides; the prelude and the JSON and string support are built
+//! on them. Each one has an entry here with its signature, which the type
+//! checker checks calls against, and the runtime support the C backend has
+//! to emit for it.
+
+use crate::ast::Type;
+
+/// Runtime support an intrinsic needs in the generated code. Intrinsics
+/// with runtime support compile to a call to `verve_<name>`, the name
+/// without its leading underscores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Runtime {
+    /// Compiled by hand: the intrinsic maps to C directly or depends on
+    /// its arguments' types.
+    Builtin,
+    Net,
+    Strings,
+    Json,
+    Panic,
+    List,
+    Args,
+    Files,
+}
+
+/// What the type checker checks a call to an intrinsic against.
+#[derive(Clone, Copy)]
+pub enum Signature {
+    /// Builds the parameter and result types.
+    Fixed(fn() -> (Vec<Type>, Type)),
+    /// Only the argument count is fixed; the type checker checks the rest
+    /// itself, because the intrinsic is generic over `Option`, takes names
+    /// rather than values, or only makes sense as a loop range.
+    Special { arity: usize },
+}
+
+pub struct Intrinsic {
+    pub name: &'static str,
+    pub signature: Signature,
+    pub runtime: Runtime,
+}
+
+impl Intrinsic {
+    /// The number of arguments a call takes.
+    pub fn arity(&self) -> usize {
+        match self.signature {
+            Fixed(signature) => signature().0.len(),
+            Signature::Special { arity } => arity,
+        }
+    }
+
+    /// The C function an intrinsic with runtime support calls.
+    pub fn runtime_function(&self) -> std::string::String {
+        format!("verve_{}", &self.name[2..])
+    }
+}
+
+/// What `build.name()` can ask for; each is the intrinsic `__build_name`.
+pub const BUILD_INFO: &[&str] = &["version", "target", "timestamp"];
+
+/// The namespaces of `namespace.name(args)` calls, each of which is the
+/// intrinsic `__namespace_name`, with what the parser calls their names.
+pub const NAMESPACES: &[(&str, &str, &[&str])] = &[
+    ("build", "build info", BUILD_INFO),
+    ("signal", "signal function", &["on"]),
+    ("json", "JSON function", &["parse", "stringify", "len", "at", "key", "get", "array", "object", "push", "set"]),
+];
+
+fn json() -> Type {
+    Type::Named(crate::json::JSON_VALUE.to_string())
+}
+
+fn option(inner: Type) -> Type {
+    Type::Option(Box::new(inner))
+}
+
+/// An intrinsic the type checker and the backends handle by hand.
+const fn builtin(name: &'static str, arity: usize) -> Intrinsic {
+    Intrinsic { name, signature: Signature::Special { arity }, runtime: Runtime::Builtin }
+}
+
+use Signature::Fixed;
+use Type::{Bool, I32, RawPtr, String, Void};
+
+/// Every intrinsic, grouped by the runtime support it needs.
+pub static INTRINSICS: &[Intrinsic] = &[
+    Intrinsic { name: "__alloc", signature: Fixed(|| (vec![I32], RawPtr)), runtime: Runtime::Builtin },
+    // Only introduced by escape analysis, for allocations that stay local.
+    Intrinsic { name: "__stack_alloc", signature: Fixed(|| (vec![I32], RawPtr)), runtime: Runtime::Builtin },
+    Intrinsic { name: "__dealloc", signature: Fixed(|| (vec![RawPtr], Void)), runtime: Runtime::Builtin },
+    builtin("__is_some", 1),
+    builtin("__unwrap", 1),
+    builtin("__chars", 1),
+    builtin("__enumerate", 1),
+    builtin("__hash", 1),
+    // `signal.on(SIGINT, handler)`.
+    builtin("__signal_on", 2),
+    // Where the call is written: the source file, its line, and the
+    // function it is in. Constants in the generated code.
+    Intrinsic { name: "__file", signature: Fixed(|| (vec![], String)), runtime: Runtime::Builtin },
+    Intrinsic { name: "__line", signature: Fixed(|| (vec![], I32)), runtime: Runtime::Builtin },
+    Intrinsic { name: "__function", signature: Fixed(|| (vec![], String)), runtime: Runtime::Builtin },
+    // `build.version()` and the rest of `BUILD_INFO`: the package version,
+    // target triple and UTC compile time, as constants in the generated code.
+    Intrinsic { name: "__build_version", signature: Fixed(|| (vec![], String)), runtime: Runtime::Builtin },
+    Intrinsic { name: "__build_target", signature: Fixed(|| (vec![], String)), runtime: Runtime::Builtin },
+    Intrinsic { name: "__build_timestamp", signature: Fixed(|| (vec![], String)), runtime: Runtime::Builtin },
+    // `include_str("path")` and `include_bytes("path")`: the contents of a
+    // file, named by a string literal relative to the source file, read
+    // when compiling and embedded in the generated code. `__include_bytes`
+    // gives the `items` of a new `List` of the bytes.
+    builtin("__include_str", 1),
+    builtin("__include_bytes", 1),
+    // Sockets are `i32` handles, -1 when an operation fails, and buffers
+    // are raw pointers.
+    Intrinsic { name: "__tcp_listen", signature: Fixed(|| (vec![I32], I32)), runtime: Runtime::Net },
+    Intrinsic { name: "__tcp_accept", signature: Fixed(|| (vec![I32], I32)), runtime: Runtime::Net },
+    Intrinsic { name: "__udp_bind", signature: Fixed(|| (vec![I32], I32)), runtime: Runtime::Net },
+    Intrinsic { name: "__tcp_connect", signature: Fixed(|| (vec![String, I32], I32)), runtime: Runtime::Net },
+    Intrinsic { name: "__net_read", signature: Fixed(|| (vec![I32, RawPtr, I32], I32)), runtime: Runtime::Net },
+    Intrinsic { name: "__net_write", signature: Fixed(|| (vec![I32, RawPtr, I32], I32)), runtime: Runtime::Net },
+    Intrinsic {
+        name: "__udp_send_to",
+        signature: Fixed(|| (vec![I32, String, I32, RawPtr, I32], I32)),
+        runtime: Runtime::Net,
+    },
+    Intrinsic { name: "__net_close", signature: Fixed(|| (vec![I32], Void)), runtime: Runtime::Net },
+    // Code points are `i32`s; `__utf8_decode` and `__utf8_next` work on
+    // byte offsets and the rest on code point indices.
+    Intrinsic { name: "__char_len", signature: Fixed(|| (vec![String], I32)), runtime: Runtime::Strings },
+    Intrinsic { name: "__char_at", signature: Fixed(|| (vec![String, I32], I32)), runtime: Runtime::Strings },
+    Intrinsic { name: "__utf8_decode", signature: Fixed(|| (vec![String, I32], I32)), runtime: Runtime::Strings },
+    Intrinsic { name: "__utf8_next", signature: Fixed(|| (vec![String, I32], I32)), runtime: Runtime::Strings },
+    Intrinsic {
+        name: "__str_slice",
+        signature: Fixed(|| (vec![String, I32, I32], option(String))),
+        runtime: Runtime::Strings,
+    },
+    Intrinsic { name: "__utf8_valid", signature: Fixed(|| (vec![String], Bool)), runtime: Runtime::Strings },
+    // Compares bytes; `==` on strings compares pointers.
+    Intrinsic { name: "__str_eq", signature: Fixed(|| (vec![String, String], Bool)), runtime: Runtime::Strings },
+    // `json.parse(text)` and the rest of the `json` namespace.
+    Intrinsic { name: "__json_parse", signature: Fixed(|| (vec![String], option(json()))), runtime: Runtime::Json },
+    Intrinsic { name: "__json_stringify", signature: Fixed(|| (vec![json()], String)), runtime: Runtime::Json },
+    Intrinsic { name: "__json_len", signature: Fixed(|| (vec![json()], I32)), runtime: Runtime::Json },
+    Intrinsic { name: "__json_at", signature: Fixed(|| (vec![json(), I32], json())), runtime: Runtime::Json },
+    Intrinsic { name: "__json_key", signature: Fixed(|| (vec![json(), I32], String)), runtime: Runtime::Json },
+    Intrinsic {
+        name: "__json_get",
+        signature: Fixed(|| (vec![json(), String], option(json()))),
+        runtime: Runtime::Json,
+    },
+    Intrinsic { name: "__json_array", signature: Fixed(|| (vec![], json())), runtime: Runtime::Json },
+    Intrinsic { name: "__json_object", signature: Fixed(|| (vec![], json())), runtime: Runtime::Json },
+    Intrinsic { name: "__json_push", signature: Fixed(|| (vec![json(), json()], Void)), runtime: Runtime::Json },
+    Intrinsic {
+        name: "__json_set",
+        signature: Fixed(|| (vec![json(), String, json()], Void)),
+        runtime: Runtime::Json,
+    },
+    // Prints the message to stderr and exits with status 101.
+    Intrinsic { name: "__panic", signature: Fixed(|| (vec![String], Void)), runtime: Runtime::Panic },
+    // Growable lists of `i32`s behind a raw pointer; `__list_get` does not
+    // check its index, the prelude's `list_get` does.
+    Intrinsic { name: "__list_new", signature: Fixed(|| (vec![], RawPtr)), runtime: Runtime::List },
+    Intrinsic { name: "__list_len", signature: Fixed(|| (vec![RawPtr], I32)), runtime: Runtime::List },
+    Intrinsic { name: "__list_push", signature: Fixed(|| (vec![RawPtr, I32], Void)), runtime: Runtime::List },
+    Intrinsic { name: "__list_get", signature: Fixed(|| (vec![RawPtr, I32], I32)), runtime: Runtime::List },
+    Intrinsic { name: "__list_set", signature: Fixed(|| (vec![RawPtr, I32, I32], Void)), runtime: Runtime::List },
+    Intrinsic { name: "__list_free", signature: Fixed(|| (vec![RawPtr], Void)), runtime: Runtime::List },
+    // Command-line arguments, the program name first; `__arg` is "" past
+    // the end.
+    Intrinsic { name: "__arg_count", signature: Fixed(|| (vec![], I32)), runtime: Runtime::Args },
+    Intrinsic { name: "__arg", signature: Fixed(|| (vec![I32], String)), runtime: Runtime::Args },
+    // Whole-file reads and writes; `None` or `false` when the file cannot
+    // be opened, read or written.
+    Intrinsic { name: "__read_file", signature: Fixed(|| (vec![String], option(String))), runtime: Runtime::Files },
+    Intrinsic { name: "__write_file", signature: Fixed(|| (vec![String, String], Bool)), runtime: Runtime::Files },
+];
+
+pub fn lookup(name: &str) -> Option<&'static Intrinsic> {
+    INTRINSICS.iter().find(|intrinsic| intrinsic.name == name)
+}
@@ -0,0 +1,278 @@
+//! JSON support. Programs that mention `JsonValue` or call a `__json_*`
+//! intrinsic get a builtin `JsonValue` enum, which the C backend's runtime
+//! parses text into and serializes back out of. Types that derive
+//! `Serialize` or `Deserialize` get functions converting them to and from
+//! `JsonValue`, built here as Verve code.
+
+use crate::ast::{Attribute, CallConv, EnumDef, Expr, Function, MatchArm, Pattern, Program, Stmt, Type, Variant};
+use crate::lexer::Token;
+use codespan::Span;
+
+pub const JSON_VALUE: &str = "JsonValue";
+
+/// Whether `tokens` refer to JSON support anywhere.
+pub fn uses_json(tokens: &[(Token, Span)]) -> bool {
+    tokens.iter().any(|(token, _)| matches!(
+        token,
+        Token::Ident(name) if name == JSON_VALUE || name.starts_with("__json_") || name == "Serialize" || name == "Deserialize"
+    ))
+}
+
+/// Adds the `JsonValue` enum to `program`. Arrays and objects are runtime
+/// lists behind a raw pointer, read and built with the `__json_*`
+/// intrinsics; numbers are truncated to `i32`, Verve's only number type.
+pub fn add_json_value(program: &mut Program) {
+    let span = Span::default();
+    let variant = |name: &str, fields: Vec<Type>| Variant { name: name.to_string(), fields, span };
+    program.enums.push(EnumDef {
+        name: JSON_VALUE.to_string(),
+        variants: vec![
+            variant("Null", vec![]),
+            variant("Bool", vec![Type::Bool]),
+            variant("Number", vec![Type::I32]),
+            variant("String", vec![Type::String]),
+            variant("Array", vec![Type::RawPtr]),
+            variant("Object", vec![Type::RawPtr]),
+        ],
+        attrs: Vec::new(),
+        span,
+    });
+}
+
+/// Adds `Name_to_json(value: Name) -> JsonValue` for every struct and plain
+/// enum that derives `Serialize`, and `Name_from_json(json: JsonValue) ->
+/// Option<Name>` for those that derive `Deserialize`. A struct maps to an
+/// object keyed by field name, with `None` fields as `null`; a plain enum
+/// maps to its integer value. A type with a field neither can convert gets
+/// no function, and the type checker reports why.
+pub fn derive_conversions(program: &mut Program) {
+    let mut functions = Vec::new();
+    // The conversions of a deprecated type are deprecated along with it.
+    let deprecated = |attrs: &[Attribute]| attrs.iter().filter(|attr| attr.name == "deprecated").cloned().collect::<Vec<_>>();
+    for def in program.structs.iter().filter(|def| !def.is_union) {
+        let fields: Vec<(&str, &Type)> = def.fields.iter().map(|f| (f.name.as_str(), &f.ty)).collect();
+        for derive in ["Serialize", "Deserialize"] {
+            if derives(program, &def.name, derive) && fields.iter().all(|(_, ty)| convertible(program, derive, ty)) {
+                let build = Build { span: def.span };
+                let function = match derive {
+                    "Serialize" => build.struct_to_json(&def.name, &fields),
+                    _ => build.struct_from_json(&def.name, &fields),
+                };
+                functions.push(Function { attrs: deprecated(&def.attrs), ..function });
+            }
+        }
+    }
+    for def in program.enums.iter().filter(|def| def.is_plain()) {
+        let build = Build { span: def.span };
+        if derives(program, &def.name, "Serialize") {
+            functions.push(Function { attrs: deprecated(&def.attrs), ..build.enum_to_json(&def.name) });
+        }
+        if derives(program, &def.name, "Deserialize") {
+            functions.push(Function { attrs: deprecated(&def.attrs), ..build.enum_from_json(&def.name) });
+        }
+    }
+    program.functions.extend(functions);
+}
+
+/// Whether the struct or enum `name` lists `derive` in a `#[derive]`.
+fn derives(program: &Program, name: &str, derive: &str) -> bool {
+    let attrs = program.structs.iter().find(|def| def.name == name).map(|def| &def.attrs)
+        .or_else(|| program.enums.iter().find(|def| def.name == name).map(|def| &def.attrs));
+    attrs.is_some_and(|attrs| attrs.iter().any(|attr| {
+        attr.name == "derive" && attr.args.iter().any(|arg| matches!(arg, crate::ast::AttrArg::Ident(a) if a == derive))
+    }))
+}
+
+/// Whether a field of type `ty` converts: integers, bools, strings,
+/// `JsonValue`s and types deriving `derive`, or an `Option` of one.
+fn convertible(program: &Program, derive: &str, ty: &Type) -> bool {
+    match ty {
+        Type::I32 | Type::Bool | Type::String => true,
+        Type::Named(name) if name == JSON_VALUE => true,
+        Type::Named(name) => {
+            let plain = program.enums.iter().any(|def| def.name == *name && def.is_plain());
+            let record = program.structs.iter().any(|def| def.name == *name && !def.is_union);
+            (plain || record) && derives(program, name, derive)
+        }
+        Type::Option(inner) => !matches!(**inner, Type::Option(_)) && convertible(program, derive, inner),
+        _ => false,
+    }
+}
+
+/// Builds the nodes of derived functions, all spanning the type definition.
+struct Build {
+    span: Span,
+}
+
+impl Build {
+    fn var(&self, name: &str) -> Expr {
+        Expr::Var(name.to_string(), self.span, Type::Unknown)
+    }
+
+    fn call(&self, name: &str, args: Vec<Expr>) -> Expr {
+        Expr::Call(name.to_string(), args, self.span, Type::Unknown)
+    }
+
+    fn intrinsic(&self, name: &str, args: Vec<Expr>) -> Expr {
+        Expr::IntrinsicCall(name.to_string(), args, self.span, Type::Unknown)
+    }
+
+    fn json(&self, variant: &str, args: Vec<Expr>) -> Expr {
+        Expr::EnumVariant(JSON_VALUE.to_string(), variant.to_string(), args, self.span, Type::Unknown)
+    }
+
+    fn key(&self, name: &str) -> Expr {
+        Expr::Str(name.to_string(), self.span, Type::String)
+    }
+
+    fn bind(&self, name: &str) -> Pattern {
+        Pattern::Bind(name.to_string(), self.span)
+    }
+
+    fn some(&self, pattern: Pattern) -> Pattern {
+        Pattern::Variant(None, "Some".to_string(), vec![pattern], self.span)
+    }
+
+    fn json_pattern(&self, variant: &str, args: Vec<Pattern>) -> Pattern {
+        Pattern::Variant(Some(JSON_VALUE.to_string()), variant.to_string(), args, self.span)
+    }
+
+    fn arm(&self, pattern: Pattern, body: Vec<Stmt>) -> MatchArm {
+        MatchArm { pattern, guard: None, body, span: self.span }
+    }
+
+    fn matching(&self, value: Expr, arms: Vec<MatchArm>) -> Stmt {
+        Stmt::Match(value, arms, self.span, Type::Unknown)
+    }
+
+    fn ret(&self, value: Expr) -> Stmt {
+        Stmt::Return(value, self.span)
+    }
+
+    fn function(&self, name: String, param: (&str, Type), return_type: Type, body: Vec<Stmt>) -> Function {
+        Function {
+            name,
+            params: vec![(param.0.to_string(), param.1)],
+            param_spans: Vec::new(),
+            return_type,
+            body,
+            attrs: Vec::new(),
+            call_conv: CallConv::C,
+            span: self.span,
+        }
+    }
+
+    /// `value` of the non-`Option` type `ty` as a `JsonValue`.
+    fn encode(&self, ty: &Type, value: Expr) -> Expr {
+        match ty {
+            Type::I32 => self.json("Number", vec![value]),
+            Type::Bool => self.json("Bool", vec![value]),
+            Type::String => self.json("String", vec![value]),
+            Type::Named(name) if name == JSON_VALUE => value,
+            Type::Named(name) => self.call(&format!("{}_to_json", name), vec![value]),
+            _ => unreachable!("only convertible types are derived"),
+        }
+    }
+
+    fn struct_to_json(&self, name: &str, fields: &[(&str, &Type)]) -> Function {
+        let set = |field: &str, value: Expr| {
+            Stmt::Expr(self.intrinsic("__json_set", vec![self.var("object"), self.key(field), value]), self.span)
+        };
+        let mut body = vec![Stmt::Let("object".to_string(), None, self.intrinsic("__json_object", vec![]), self.span)];
+        for &(field, ty) in fields {
+            let value = Expr::Field(Box::new(self.var("value")), field.to_string(), self.span, Type::Unknown);
+            body.push(match ty {
+                Type::Option(inner) => self.matching(value, vec![
+                    self.arm(self.some(self.bind("item")), vec![set(field, self.encode(inner, self.var("item")))]),
+                    self.arm(Pattern::Wildcard(self.span), vec![set(field, self.json("Null", vec![]))]),
+                ]),
+                _ => set(field, self.encode(ty, value)),
+            });
+        }
+        body.push(self.ret(self.var("object")));
+        let named = Type::Named(name.to_string());
+        self.function(format!("{}_to_json", name), ("value", named), Type::Named(JSON_VALUE.to_string()), body)
+    }
+
+    /// Statements converting `json`, a `JsonValue`, to the non-`Option` type
+    /// `ty` in the variable `target`, then running `then`; for any other
+    /// JSON the function returns `None`.
+    fn decode(&self, ty: &Type, json: Expr, target: &str, then: Vec<Stmt>) -> Vec<Stmt> {
+        let fail = || self.arm(Pattern::Wildcard(self.span), vec![self.ret(self.var("None"))]);
+        let variant = match ty {
+            Type::I32 => "Number",
+            Type::Bool => "Bool",
+            Type::String => "String",
+            Type::Named(name) if name == JSON_VALUE => {
+                let mut stmts = vec![Stmt::Let(target.to_string(), None, json, self.span)];
+                stmts.extend(then);
+                return stmts;
+            }
+            Type::Named(name) => {
+                let converted = self.call(&format!("{}_from_json", name), vec![json]);
+                return vec![self.matching(converted, vec![self.arm(self.some(self.bind(target)), then), fail()])];
+            }
+            _ => unreachable!("only convertible types are derived"),
+        };
+        vec![self.matching(json, vec![self.arm(self.json_pattern(variant, vec![self.bind(target)]), then), fail()])]
+    }
+
+    fn struct_from_json(&self, name: &str, fields: &[(&str, &Type)]) -> Function {
+        let values = fields.iter().map(|&(field, _)| (field.to_string(), self.var(&format!("f_{}", field)))).collect();
+        let built = Expr::StructLit(name.to_string(), values, None, self.span, Type::Unknown);
+        let mut body = vec![self.ret(self.call("Some", vec![built]))];
+        // Each field wraps the conversions of the fields after it.
+        for &(field, ty) in fields.iter().rev() {
+            let get = self.intrinsic("__json_get", vec![self.var("json"), self.key(field)]);
+            let (target, raw) = (format!("f_{}", field), format!("j_{}", field));
+            body = match ty {
+                Type::Option(inner) => {
+                    let item = format!("v_{}", field);
+                    let store = Expr::Assign(
+                        Box::new(self.var(&target)),
+                        Box::new(self.call("Some", vec![self.var(&item)])),
+                        self.span,
+                        Type::Unknown,
+                    );
+                    let convert = self.decode(inner, self.var(&raw), &item, vec![Stmt::Expr(store, self.span)]);
+                    let mut stmts = vec![
+                        Stmt::Let(target, Some(ty.clone()), self.var("None"), self.span),
+                        self.matching(get, vec![
+                            self.arm(self.some(self.json_pattern("Null", vec![])), vec![]),
+                            self.arm(self.some(self.bind(&raw)), convert),
+                            self.arm(Pattern::Wildcard(self.span), vec![]),
+                        ]),
+                    ];
+                    stmts.extend(body);
+                    stmts
+                }
+                _ => {
+                    let convert = self.decode(ty, self.var(&raw), &target, body);
+                    vec![self.matching(get, vec![
+                        self.arm(self.some(self.bind(&raw)), convert),
+                        self.arm(Pattern::Wildcard(self.span), vec![self.ret(self.var("None"))]),
+                    ])]
+                }
+            };
+        }
+        let result = Type::Option(Box::new(Type::Named(name.to_string())));
+        self.function(format!("{}_from_json", name), ("json", Type::Named(JSON_VALUE.to_string())), result, body)
+    }
+
+    fn enum_to_json(&self, name: &str) -> Function {
+        let number = Expr::Cast(Box::new(self.var("value")), Type::I32, self.span, Type::Unknown);
+        let body = vec![self.ret(self.json("Number", vec![number]))];
+        let named = Type::Named(name.to_string());
+        self.function(format!("{}_to_json", name), ("value", named), Type::Named(JSON_VALUE.to_string()), body)
+    }
+
+    fn enum_from_json(&self, name: &str) -> Function {
+        let from = Expr::EnumVariant(name.to_string(), "from".to_string(), vec![self.var("n")], self.span, Type::Unknown);
+        let body = vec![self.matching(self.var("json"), vec![
+            self.arm(self.json_pattern("Number", vec![self.bind("n")]), vec![self.ret(from)]),
+            self.arm(Pattern::Wildcard(self.span), vec![self.ret(self.var("None"))]),
+        ])];
+        let result = Type::Option(Box::new(Type::Named(name.to_string())));
+        self.function(format!("{}_from_json", name), ("json", Type::Named(JSON_VALUE.to_string())), result, body)
+    }
+}
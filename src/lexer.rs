@@ -1,4 +1,5 @@
 use codespan::{FileId, Files, Span};
+use codespan_reporting::diagnostic::{Diagnostic, Label};
 use logos::Logos;
 
 #[derive(Logos, Debug, PartialEq, Clone)]
@@ -27,6 +28,22 @@ pub enum Token {
     KwWhile,
     #[token("for")]
     KwFor,
+    #[token("break")]
+    KwBreak,
+    #[token("continue")]
+    KwContinue,
+    #[token("struct")]
+    KwStruct,
+    #[token("enum")]
+    KwEnum,
+    #[token("union")]
+    KwUnion,
+    #[token("static")]
+    KwStatic,
+    #[token("match")]
+    KwMatch,
+    #[token("case")]
+    KwCase,
     
     #[regex(r#""([^"\\]|\\.)*""#, |lex| lex.slice()[1..lex.slice().len()-1].to_string())]
     Str(String),
@@ -40,12 +57,20 @@ pub enum Token {
     Arrow,
     #[token(":")]
     Colon,
+    #[token("::")]
+    ColonColon,
     #[token("==")]
     EqEq,
     #[token("(")]
     LParen,
     #[token(")")]
     RParen,
+    #[token("[")]
+    LBracket,
+    #[token("]")]
+    RBracket,
+    #[token("#")]
+    Hash,
     #[token("{")]
     LBrace,
     #[token("}")]
@@ -72,9 +97,11 @@ pub enum Token {
     KwIn,
     #[token("..")]
     DotDot,
+    #[token(".")]
+    Dot,
     
     
-    #[regex(r"[a-zA-Z_][a-zA-Z0-9_]*", |lex| lex.slice().to_string())]
+    #[regex(r"[\p{XID_Start}_]\p{XID_Continue}*", |lex| lex.slice().to_string())]
     Ident(String),
 
     #[regex(r"[0-9]+", |lex| lex.slice().parse().ok())]
@@ -83,17 +110,63 @@ pub enum Token {
     #[regex(r"[ \t\n]+", logos::skip)]
     Whitespace,
 
+    /// `/* ... */`, which may nest. Kept as trivia rather than skipped so
+    /// tools that reproduce the source can find the comments again.
+    #[token("/*", block_comment)]
+    BlockComment,
+
     Error,
 }
 
+/// Consumes the rest of a block comment whose `/*` was just read, counting
+/// nested ones. An unterminated comment runs to the end of the input and is
+/// an error.
+fn block_comment(lex: &mut logos::Lexer<Token>) -> Result<(), ()> {
+    let rest = lex.remainder().as_bytes();
+    let mut depth = 1;
+    let mut i = 0;
+    while i < rest.len() {
+        if rest[i..].starts_with(b"/*") {
+            depth += 1;
+            i += 2;
+        } else if rest[i..].starts_with(b"*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                lex.bump(i);
+                return Ok(());
+            }
+        } else {
+            i += 1;
+        }
+    }
+    lex.bump(rest.len());
+    Err(())
+}
+
 pub struct Lexer<'a> {
     pub(crate) files: &'a Files<String>,
     pub(crate) file_id: FileId,
+    /// Where the file starts among the sources of the program; see
+    /// [`Lexer::at_offset`].
+    pub(crate) base: usize,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(files: &'a Files<String>, file_id: FileId) -> Self {
-        Self { files, file_id }
+        Self { files, file_id, base: 0 }
+    }
+
+    /// Shifts every span and diagnostic range by `base`, so the spans of a
+    /// program made of several files stay apart. The compiler maps them back
+    /// to each file when it reports them.
+    pub fn at_offset(mut self, base: usize) -> Self {
+        self.base = base;
+        self
+    }
+
+    fn span(&self, range: std::ops::Range<usize>) -> Span {
+        Span::new((self.base + range.start) as u32, (self.base + range.end) as u32)
     }
 
     pub fn tokens(&self) -> Vec<(Token, Span)> {
@@ -101,8 +174,46 @@ impl<'a> Lexer<'a> {
         Token::lexer(source)
             .spanned()
             .filter_map(|(token, span)| match token {
-                Ok(token) if token != Token::Error => Some((token, Span::new(span.start as u32, span.end as u32))),
-                _ => None,
+                Ok(Token::Error | Token::BlockComment) | Err(_) => None,
+                Ok(token) => Some((token, self.span(span))),
+            })
+            .collect()
+    }
+
+    /// Spans of the comments, in source order.
+    pub fn comments(&self) -> Vec<Span> {
+        Token::lexer(self.files.source(self.file_id))
+            .spanned()
+            .filter(|(token, _)| *token == Ok(Token::BlockComment))
+            .map(|(_, span)| self.span(span))
+            .collect()
+    }
+
+    /// One diagnostic per run of adjacent characters that start no token.
+    /// Lexing goes on past them, so every bad character is reported at once.
+    pub fn errors(&self) -> Vec<Diagnostic<FileId>> {
+        let source = self.files.source(self.file_id);
+        let mut runs: Vec<std::ops::Range<usize>> = Vec::new();
+        for (token, span) in Token::lexer(source).spanned() {
+            if matches!(token, Ok(Token::Error) | Err(_)) {
+                match runs.last_mut() {
+                    Some(run) if run.end == span.start => run.end = span.end,
+                    _ => runs.push(span),
+                }
+            }
+        }
+        runs.into_iter()
+            .map(|run| {
+                let text = &source[run.clone()];
+                if text.starts_with("/*") {
+                    return Diagnostic::error()
+                        .with_message("Unterminated block comment")
+                        .with_labels(vec![Label::primary(self.file_id, self.span(run.start..run.start + 2))]);
+                }
+                let noun = if text.chars().count() == 1 { "character" } else { "characters" };
+                Diagnostic::error()
+                    .with_message(format!("Invalid {} '{}'", noun, text.escape_debug()))
+                    .with_labels(vec![Label::primary(self.file_id, self.span(run))])
             })
             .collect()
     }
@@ -1,7 +1,34 @@
 pub mod lexer;
 pub mod parser;
+pub mod lower;
 pub mod ast;
+pub mod cfg;
 pub mod typeck;
 pub mod codegen;
+pub mod compiler;
+pub mod deps;
+pub mod escape;
+pub mod interface;
+pub mod intrinsics;
+pub mod json;
+pub mod lint;
+pub mod log;
+pub mod manifest;
+pub mod model;
+pub mod prelude;
+pub mod stdlib;
+pub mod target;
+pub mod loops;
+pub mod tailcall;
+pub mod profile;
+pub mod testing;
+pub mod fuzz;
+pub mod playground;
+pub mod pretty;
+pub mod sandbox;
+pub mod fix;
+pub mod host;
+pub mod ice;
+pub mod target_dir;
 
 pub mod cli;
\ No newline at end of file
@@ -0,0 +1,274 @@
+//! Lints: warnings about code that compiles but is probably a mistake. Each
+//! lint has a name, which its diagnostics carry as their code, and a
+//! [`Level`]: `warn` unless the driver's `-A`, `-W` and `-D` options change
+//! it, and a denied lint fails the build. The type checker finds uses of
+//! deprecated items; [`check`] looks for the rest in the checked program.
+
+use crate::ast::{Expr, Field, Function, Program, Stmt, Type};
+use crate::fix::Suggestion;
+use codespan::{FileId, Span};
+use codespan_reporting::diagnostic::{Diagnostic, Label, Severity};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// Uses of `#[deprecated]` functions and types.
+    Deprecated,
+    /// Struct fields that are never read.
+    DeadFields,
+    /// Function parameters that are never used.
+    UnusedParameters,
+}
+
+impl Lint {
+    pub const ALL: &[Lint] = &[Lint::Deprecated, Lint::DeadFields, Lint::UnusedParameters];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::Deprecated => "deprecated",
+            Lint::DeadFields => "dead_fields",
+            Lint::UnusedParameters => "unused_parameters",
+        }
+    }
+
+    /// The lints `name` stands for: the lint of that name, or all of them
+    /// for `warnings`.
+    pub fn select(name: &str) -> Option<&'static [Lint]> {
+        if name == "warnings" {
+            return Some(Self::ALL);
+        }
+        let index = Self::ALL.iter().position(|lint| lint.name() == name)?;
+        Some(&Self::ALL[index..=index])
+    }
+
+    /// The lint `diagnostic` reports, if any.
+    pub fn of(diagnostic: &Diagnostic<FileId>) -> Option<Lint> {
+        let code = diagnostic.code.as_deref()?;
+        Self::ALL.iter().copied().find(|lint| lint.name() == code)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+/// The level of each lint.
+#[derive(Debug, Clone, Default)]
+pub struct Levels {
+    levels: HashMap<Lint, Level>,
+}
+
+impl Levels {
+    pub fn set(&mut self, lint: Lint, level: Level) {
+        self.levels.insert(lint, level);
+    }
+
+    pub fn get(&self, lint: Lint) -> Level {
+        self.levels.get(&lint).copied().unwrap_or(Level::Warn)
+    }
+
+    /// `diagnostic` at the level of its lint: `None` when the lint is
+    /// allowed and an error when it is denied. Diagnostics of no lint are
+    /// returned as they are.
+    pub fn apply(&self, mut diagnostic: Diagnostic<FileId>) -> Option<Diagnostic<FileId>> {
+        match Lint::of(&diagnostic).map(|lint| self.get(lint)) {
+            Some(Level::Allow) => None,
+            Some(Level::Deny) => {
+                diagnostic.severity = Severity::Error;
+                Some(diagnostic)
+            }
+            Some(Level::Warn) | None => Some(diagnostic),
+        }
+    }
+}
+
+/// Finds dead fields and unused parameters in the structs and functions of
+/// `program` whose span `in_sources` accepts, so that library items are
+/// left alone. Names starting with `_` are exempt. Each warning comes with
+/// the fix it suggests, if any. The program is only borrowed mutably to
+/// walk it.
+pub fn check(
+    program: &mut Program,
+    in_sources: impl Fn(Span) -> bool,
+    file_id: FileId,
+) -> Vec<(Diagnostic<FileId>, Option<Suggestion>)> {
+    let mut warnings = Vec::new();
+    for func in &mut program.functions {
+        if in_sources(func.span) {
+            warnings.extend(unused_parameters(func, file_id).into_iter().map(|(warning, fix)| (warning, Some(fix))));
+        }
+    }
+
+    let mut reads = Reads {
+        structs: program.structs.iter().map(|def| (def.name.clone(), def.fields.clone())).collect(),
+        payloads: program.enums.iter()
+            .map(|def| (def.name.clone(), def.variants.iter().flat_map(|variant| variant.fields.clone()).collect()))
+            .collect(),
+        fields: HashSet::new(),
+        printed: HashSet::new(),
+    };
+    let mut facing_c = HashSet::new();
+    for func in &program.functions {
+        if func.is_exported() {
+            for ty in func.params.iter().map(|(_, ty)| ty).chain([&func.return_type]) {
+                named_types(ty, &mut facing_c);
+            }
+        }
+    }
+    for func in &mut program.functions {
+        for_each_expr(&mut func.body, &mut |expr| reads.visit(expr));
+    }
+    for_each_expr(&mut program.stmts, &mut |expr| reads.visit(expr));
+    let fields = reads.fields;
+
+    for def in &program.structs {
+        // Union members are read through one another, C reads the structs
+        // the program shares with it, and derived code reads every field.
+        if !in_sources(def.span) || def.is_union || facing_c.contains(&def.name) || def.attr("derive").is_some() {
+            continue;
+        }
+        for field in &def.fields {
+            if !field.name.starts_with('_') && !fields.contains(&(def.name.clone(), field.name.clone())) {
+                warnings.push((
+                    Diagnostic::warning()
+                        .with_message(format!("Field '{}' of '{}' is never read", field.name, def.name))
+                        .with_code(Lint::DeadFields.name())
+                        .with_labels(vec![Label::primary(file_id, field.span)]),
+                    None,
+                ));
+            }
+        }
+    }
+    warnings
+}
+
+/// Exported functions keep the signature their C callers expect, used or
+/// not. The fix renames the parameter to start with `_`.
+fn unused_parameters(func: &mut Function, file_id: FileId) -> Vec<(Diagnostic<FileId>, Suggestion)> {
+    if func.is_exported() {
+        return Vec::new();
+    }
+    fn uses(expr: &Expr, used: &mut HashSet<String>) {
+        if let Expr::Var(name, _, _) = expr {
+            used.insert(name.clone());
+        }
+        for child in expr.children() {
+            uses(child, used);
+        }
+    }
+
+    let mut used = HashSet::new();
+    for_each_expr(&mut func.body, &mut |expr| uses(expr, &mut used));
+    func.params.iter()
+        .enumerate()
+        .filter(|(_, (name, _))| !name.starts_with('_') && !used.contains(name))
+        .map(|(i, (name, _))| {
+            let span = func.param_spans.get(i).copied().unwrap_or(func.span);
+            let message = format!("rename it '_{}' if that is intended", name);
+            let warning = Diagnostic::warning()
+                .with_message(format!("Parameter '{}' of '{}' is never used", name, func.name))
+                .with_code(Lint::UnusedParameters.name())
+                .with_labels(vec![Label::primary(file_id, span)])
+                .with_notes(vec![format!("help: {}", message)]);
+            let start = span.start();
+            (warning, Suggestion { message, file_id, span: Span::new(start, start), replacement: "_".to_string() })
+        })
+        .collect()
+}
+
+/// The struct fields a program reads.
+struct Reads {
+    structs: HashMap<String, Vec<Field>>,
+    /// The payload types of each enum's variants.
+    payloads: HashMap<String, Vec<Type>>,
+    /// Struct and field names.
+    fields: HashSet<(String, String)>,
+    /// Types whose printing was already accounted for.
+    printed: HashSet<String>,
+}
+
+impl Reads {
+    fn visit(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Field(base, field, _, _) => {
+                let struct_ty = match base.get_type() {
+                    Type::Pointer(inner) => *inner,
+                    ty => ty,
+                };
+                if let Type::Named(name) = struct_ty {
+                    self.fields.insert((name, field.clone()));
+                }
+                self.visit(base);
+            }
+            // Storing to a field is no read of it.
+            Expr::Assign(target, value, _, _) => {
+                self.visit_place(target);
+                self.visit(value);
+            }
+            Expr::Print(value, _, _) => {
+                self.print(&value.get_type());
+                self.visit(value);
+            }
+            _ => {
+                for child in expr.children() {
+                    self.visit(child);
+                }
+            }
+        }
+    }
+
+    fn visit_place(&mut self, place: &Expr) {
+        match place {
+            Expr::Field(base, _, _, _) => self.visit_place(base),
+            Expr::Var(..) => {}
+            _ => self.visit(place),
+        }
+    }
+
+    /// Printing a value prints every field in it.
+    fn print(&mut self, ty: &Type) {
+        match ty {
+            Type::Named(name) if self.printed.insert(name.clone()) => {
+                for field in self.structs.get(name).cloned().unwrap_or_default() {
+                    self.fields.insert((name.clone(), field.name));
+                    self.print(&field.ty);
+                }
+                for payload in self.payloads.get(name).cloned().unwrap_or_default() {
+                    self.print(&payload);
+                }
+            }
+            Type::Option(inner) => self.print(inner),
+            Type::Tuple(items) => items.iter().for_each(|item| self.print(item)),
+            _ => {}
+        }
+    }
+}
+
+/// The structs and enums `ty` is made of, through pointers too.
+fn named_types(ty: &Type, names: &mut HashSet<String>) {
+    match ty {
+        Type::Named(name) => {
+            names.insert(name.clone());
+        }
+        Type::Pointer(inner) | Type::Option(inner) => named_types(inner, names),
+        Type::Tuple(items) => items.iter().for_each(|item| named_types(item, names)),
+        Type::Function(params, result, _) => {
+            params.iter().chain([&**result]).for_each(|item| named_types(item, names));
+        }
+        _ => {}
+    }
+}
+
+/// Calls `f` on the outermost expressions of `stmts` and of the blocks
+/// nested in them, leaving `f` to descend into each.
+fn for_each_expr(stmts: &mut [Stmt], f: &mut dyn FnMut(&Expr)) {
+    for stmt in stmts.iter_mut() {
+        for expr in stmt.exprs_mut() {
+            f(expr);
+        }
+        stmt.for_each_block(&mut |block| for_each_expr(block, f));
+    }
+}
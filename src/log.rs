@@ -0,0 +1,142 @@
+//! The compiler's own log, for reporting miscompilations and debugging
+//! passes. Each pass logs events under a module name from [`MODULES`] at a
+//! [`Level`]; a [`Filter`] such as `info,codegen=debug` picks which events
+//! are written, to stderr or to [`capture`]. Nothing is logged until
+//! [`set_filter`] enables it. Like the phase [`ice`](crate::ice) records,
+//! the filter is per thread: an embedder sets it on the thread that
+//! compiles.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::time::Instant;
+
+/// The modules events are logged under.
+pub const MODULES: &[&str] = &["driver", "lexer", "parser", "lower", "resolve", "typeck", "lint", "opt", "codegen"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub const ALL: &[Level] = &[Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+
+    fn parse(name: &str) -> Result<Level, String> {
+        Self::ALL.iter().copied().find(|level| level.name() == name).ok_or_else(|| {
+            let names: Vec<&str> = Self::ALL.iter().map(|level| level.name()).collect();
+            format!("unknown log level '{}' (expected one of {})", name, names.join(", "))
+        })
+    }
+}
+
+/// The most detailed level logged for each module.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    /// For modules without a level of their own; `None` logs nothing.
+    default: Option<Level>,
+    modules: Vec<(&'static str, Level)>,
+}
+
+impl Filter {
+    /// Parses comma-separated directives: a level for every module, or
+    /// `module=level` for one, as in `warn,typeck=debug`. Later directives
+    /// override earlier ones.
+    pub fn parse(spec: &str) -> Result<Filter, String> {
+        let mut filter = Filter::default();
+        for directive in spec.split(',').map(str::trim).filter(|directive| !directive.is_empty()) {
+            match directive.split_once('=') {
+                Some((module, level)) => {
+                    let Some(&module) = MODULES.iter().find(|&&name| name == module.trim()) else {
+                        return Err(format!("unknown log module '{}' (expected one of {})", module.trim(), MODULES.join(", ")));
+                    };
+                    filter.modules.retain(|(name, _)| *name != module);
+                    filter.modules.push((module, Level::parse(level.trim())?));
+                }
+                None => filter.default = Some(Level::parse(directive)?),
+            }
+        }
+        Ok(filter)
+    }
+
+    /// Logs everything at `level` or above.
+    pub fn at(level: Level) -> Filter {
+        Filter { default: Some(level), modules: Vec::new() }
+    }
+
+    pub fn enabled(&self, module: &str, level: Level) -> bool {
+        let limit = self.modules.iter().find(|(name, _)| *name == module).map(|&(_, level)| level).or(self.default);
+        limit.is_some_and(|limit| level <= limit)
+    }
+}
+
+thread_local! {
+    static FILTER: RefCell<Filter> = RefCell::new(Filter::default());
+    /// Where [`capture`] collects events; stderr when `None`.
+    static CAPTURED: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+    static START: Instant = Instant::now();
+}
+
+/// Sets which events this thread logs.
+pub fn set_filter(filter: Filter) {
+    FILTER.with(|current| *current.borrow_mut() = filter);
+}
+
+pub fn enabled(module: &str, level: Level) -> bool {
+    FILTER.with(|filter| filter.borrow().enabled(module, level))
+}
+
+/// Writes an event; use [`event!`](crate::log::event), which skips
+/// formatting events the filter leaves out.
+pub fn write(module: &str, level: Level, message: fmt::Arguments) {
+    let elapsed = START.with(Instant::elapsed);
+    let line = format!("[{:>8.3}ms {:<5} {}] {}", elapsed.as_secs_f64() * 1000.0, level.name(), module, message);
+    let line = CAPTURED.with(|captured| match &mut *captured.borrow_mut() {
+        Some(lines) => {
+            lines.push(line);
+            None
+        }
+        None => Some(line),
+    });
+    if let Some(line) = line {
+        eprintln!("{}", line);
+    }
+}
+
+/// Runs `f` logging with `filter`, and returns the events instead of
+/// writing them.
+pub fn capture<T>(filter: Filter, f: impl FnOnce() -> T) -> (T, Vec<String>) {
+    let previous_filter = FILTER.with(|current| current.replace(filter));
+    let previous = CAPTURED.with(|captured| captured.replace(Some(Vec::new())));
+    let result = f();
+    let lines = CAPTURED.with(|captured| captured.replace(previous)).unwrap_or_default();
+    FILTER.with(|current| *current.borrow_mut() = previous_filter);
+    (result, lines)
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __log_event {
+    ($level:expr, $module:expr, $($arg:tt)+) => {
+        if $crate::log::enabled($module, $level) {
+            $crate::log::write($module, $level, format_args!($($arg)+));
+        }
+    };
+}
+
+/// `event!(Level::Debug, "codegen", "emitting {}", name)` logs under the
+/// module `codegen` when the filter lets debug events through.
+pub use crate::__log_event as event;
@@ -0,0 +1,218 @@
+//! Loop optimizations enabled at `-O2`. Small `for` loops with a constant
+//! trip count are unrolled, and in the loops that remain, products of the
+//! loop variable and a constant become running sums.
+//!
+//! A `for i in a..b` loop runs `i` from 0 up to `b - a`, as the backend
+//! emits it, so both passes count from zero.
+
+use crate::ast::{self, BinOp, Expr, Program, Stmt, Type};
+use crate::log::{self, Level};
+use codespan::Span;
+
+/// Loops running more often than this stay rolled.
+const MAX_UNROLL_TRIPS: i64 = 8;
+/// Loops whose body (nested statements included) is larger than this stay
+/// rolled.
+const MAX_UNROLL_STMTS: usize = 8;
+
+/// Replaces small constant-trip-count loops by one block per iteration,
+/// each binding the loop variable to its constant value.
+pub fn unroll_loops(program: &mut Program) {
+    for function in &mut program.functions {
+        unroll_block(&mut function.body, &function.name);
+    }
+}
+
+/// Replaces `i * c` in a loop over `i` by a variable that starts at zero and
+/// grows by `c` at the end of every iteration.
+pub fn reduce_strength(program: &mut Program) {
+    let mut reducer = Reducer { temps: 0 };
+    for function in &mut program.functions {
+        reducer.reduce_block(&mut function.body, &function.name);
+    }
+}
+
+fn unroll_block(stmts: &mut [Stmt], function: &str) {
+    for stmt in stmts.iter_mut() {
+        stmt.for_each_block(&mut |block| unroll_block(block, function));
+        let Stmt::For(var, range, body, span) = stmt else { continue };
+        let Some(trips) = trip_count(range) else {
+            log::event!(Level::Trace, "opt", "loop over '{}' in '{}' stays rolled: no constant trip count", var, function);
+            continue;
+        };
+        let summary = Summary::of(body, var);
+        // Each copy of a static would be a separate variable, and a copy
+        // has no loop left to break or continue.
+        let reason = if trips > MAX_UNROLL_TRIPS {
+            Some(format!("{} trips", trips))
+        } else if summary.stmts > MAX_UNROLL_STMTS {
+            Some(format!("{} statements in the body", summary.stmts))
+        } else if summary.statics {
+            Some("the body declares a static".to_string())
+        } else if summary.clobbered {
+            Some("the body assigns the loop variable".to_string())
+        } else if ast::breaks_out(body) || ast::continues(body) {
+            Some("the body breaks or continues".to_string())
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            log::event!(Level::Trace, "opt", "loop over '{}' in '{}' stays rolled: {}", var, function, reason);
+            continue;
+        }
+        log::event!(Level::Debug, "opt", "unrolled loop over '{}' in '{}' into {} copies", var, function, trips.max(0));
+
+        let span = *span;
+        // The body gets a scope of its own so it may shadow the loop variable.
+        let iterations = (0..trips.max(0))
+            .map(|i| {
+                let index = Stmt::Let(var.clone(), Some(Type::I32), Expr::Int(i, span, Type::I32), span);
+                Stmt::Block(vec![index, Stmt::Block(body.clone(), span)], span)
+            })
+            .collect();
+        *stmt = Stmt::Block(iterations, span);
+    }
+}
+
+fn trip_count(range: &Expr) -> Option<i64> {
+    match range {
+        Expr::Range(start, end, _, _) => match (&**start, &**end) {
+            (Expr::Int(start, _, _), Expr::Int(end, _, _)) => Some(end - start),
+            _ => None,
+        },
+        Expr::Int(n, _, _) => Some(*n),
+        _ => None,
+    }
+}
+
+struct Reducer {
+    temps: usize,
+}
+
+impl Reducer {
+    fn reduce_block(&mut self, stmts: &mut [Stmt], function: &str) {
+        for stmt in stmts.iter_mut() {
+            stmt.for_each_block(&mut |block| self.reduce_block(block, function));
+            let Some(strides) = self.reduce_loop(stmt) else { continue };
+            if let Stmt::For(var, ..) = stmt {
+                log::event!(Level::Debug, "opt", "reduced {} products of '{}' in '{}' to running sums", strides.len(), var, function);
+            }
+
+            let span = stmt.span();
+            let mut block = strides.into_iter()
+                .map(|(_, name)| Stmt::Let(name, Some(Type::I32), Expr::Int(0, span, Type::I32), span))
+                .collect::<Vec<_>>();
+            block.push(std::mem::replace(stmt, Stmt::Block(Vec::new(), span)));
+            *stmt = Stmt::Block(block, span);
+        }
+    }
+
+    /// Rewrites the products in the `for` loop `stmt`, returning the running
+    /// sums it now needs declared, or `None` if nothing changed.
+    fn reduce_loop(&mut self, stmt: &mut Stmt) -> Option<Vec<(i64, String)>> {
+        let Stmt::For(var, _, body, span) = stmt else { return None };
+        if Summary::of(body, var).clobbered || ast::continues(body) {
+            return None;
+        }
+
+        let mut strides: Vec<(i64, String)> = Vec::new();
+        for_each_expr(body, &mut |expr| {
+            let Some(stride) = induction_product(expr, var) else { return false };
+            let name = match strides.iter().find(|(s, _)| *s == stride) {
+                Some((_, name)) => name.clone(),
+                None => {
+                    let name = format!("__stride{}", self.temps);
+                    self.temps += 1;
+                    strides.push((stride, name.clone()));
+                    name
+                }
+            };
+            *expr = Expr::Var(name, expr.span(), Type::I32);
+            true
+        });
+        if strides.is_empty() {
+            return None;
+        }
+
+        // Without a `continue` in the body, every iteration that goes on to
+        // the next one passes through these.
+        for (stride, name) in &strides {
+            body.push(increment(name, *stride, *span));
+        }
+        Some(strides)
+    }
+}
+
+/// The constant `c` if `expr` is `var * c` or `c * var`.
+fn induction_product(expr: &Expr, var: &str) -> Option<i64> {
+    let Expr::BinOp(left, BinOp::Mul, right, _, _) = expr else { return None };
+    match (&**left, &**right) {
+        (Expr::Var(name, _, _), Expr::Int(c, _, _)) | (Expr::Int(c, _, _), Expr::Var(name, _, _)) if name == var => Some(*c),
+        _ => None,
+    }
+}
+
+fn increment(name: &str, stride: i64, span: Span) -> Stmt {
+    let var = || Box::new(Expr::Var(name.to_string(), span, Type::I32));
+    let sum = Expr::BinOp(var(), BinOp::Add, Box::new(Expr::Int(stride, span, Type::I32)), span, Type::I32);
+    Stmt::Expr(Expr::Assign(var(), Box::new(sum), span, Type::Void), span)
+}
+
+/// What the loop passes need to know about a loop body.
+#[derive(Default)]
+struct Summary {
+    stmts: usize,
+    statics: bool,
+    /// The loop variable is assigned or redeclared somewhere in the body.
+    clobbered: bool,
+}
+
+impl Summary {
+    fn of(body: &mut [Stmt], var: &str) -> Summary {
+        let mut summary = Summary::default();
+        summary.scan(body, var);
+        summary
+    }
+
+    fn scan(&mut self, stmts: &mut [Stmt], var: &str) {
+        for stmt in stmts.iter_mut() {
+            self.stmts += 1;
+            match stmt {
+                Stmt::Static(..) => self.statics = true,
+                Stmt::Let(name, ..) | Stmt::For(name, ..) if name == var => self.clobbered = true,
+                _ => {}
+            }
+            for expr in stmt.exprs_mut() {
+                if assigns(expr, var) {
+                    self.clobbered = true;
+                }
+            }
+            stmt.for_each_block(&mut |block| self.scan(block, var));
+        }
+    }
+}
+
+fn assigns(expr: &Expr, var: &str) -> bool {
+    matches!(expr, Expr::Assign(target, ..) if matches!(&**target, Expr::Var(name, _, _) if name == var))
+        || expr.children().into_iter().any(|child| assigns(child, var))
+}
+
+/// Calls `f` on every expression in `stmts`, outermost first, including
+/// those in nested blocks. `f` returns whether it replaced the expression,
+/// in which case the replacement is not descended into.
+fn for_each_expr(stmts: &mut [Stmt], f: &mut dyn FnMut(&mut Expr) -> bool) {
+    fn visit(expr: &mut Expr, f: &mut dyn FnMut(&mut Expr) -> bool) {
+        if !f(expr) {
+            for child in expr.children_mut() {
+                visit(child, f);
+            }
+        }
+    }
+
+    for stmt in stmts.iter_mut() {
+        for expr in stmt.exprs_mut() {
+            visit(expr, f);
+        }
+        stmt.for_each_block(&mut |block| for_each_expr(block, f));
+    }
+}
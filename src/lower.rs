@@ -0,0 +1,604 @@
+//! Desugaring passes. [`lower_program`] runs between parsing and type
+//! checking; [`lower_matches`] runs after type checking, once patterns are
+//! resolved. Together they leave the backends only the core statement forms.
+
+use crate::ast::{BinOp, Expr, MatchArm, Pattern, Program, Stmt, Type};
+use crate::log::{self, Level};
+use codespan::{FileId, Span};
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use std::collections::HashMap;
+
+/// Expands every destructuring `let` into a temporary holding the value,
+/// followed by one plain `let` per bound name, rewrites `if let` and
+/// `while let` in terms of `match`, and turns `for c in chars(s)` and
+/// `for (i, x) in __enumerate(list)` into `while` loops over offsets.
+/// `chars` is `__chars` unless the program defines a function by that name.
+pub fn lower_program(program: &mut Program) {
+    let chars = !program.functions.iter().any(|function| function.name == "chars");
+    let mut lowerer = Lowerer { temps: 0, chars, function: String::from("the top level") };
+    lowerer.lower_block(&mut program.stmts);
+    for function in &mut program.functions {
+        lowerer.function = format!("'{}'", function.name);
+        lowerer.lower_block(&mut function.body);
+    }
+}
+
+/// Compiles every `match` into a decision tree of conditionals and switches
+/// on enum tags that tests each part of the scrutinee at most once per
+/// path. Reports matches that leave some value unhandled.
+pub fn lower_matches(program: &mut Program, file_id: FileId) -> Result<(), Vec<Diagnostic<FileId>>> {
+    let mut structs = HashMap::new();
+    for def in program.structs.iter().filter(|def| !def.is_union) {
+        let fields: Vec<(String, Type)> = def.fields.iter().map(|f| (f.name.clone(), f.ty.clone())).collect();
+        structs.insert(def.name.clone(), fields);
+    }
+    let mut enums = HashMap::new();
+    for def in &program.enums {
+        let variants: Vec<(String, Vec<Type>)> = def.variants.iter().map(|v| (v.name.clone(), v.fields.clone())).collect();
+        enums.insert(def.name.clone(), variants);
+    }
+
+    let mut compiler = MatchCompiler {
+        structs,
+        enums,
+        temps: 0,
+        errors: Vec::new(),
+        file_id,
+        function: String::from("the top level"),
+    };
+    compiler.lower_block(&mut program.stmts);
+    for function in &mut program.functions {
+        compiler.function = format!("'{}'", function.name);
+        compiler.lower_block(&mut function.body);
+    }
+
+    if compiler.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(compiler.errors)
+    }
+}
+
+struct Lowerer {
+    temps: usize,
+    /// Whether a call to `chars` is `__chars`.
+    chars: bool,
+    /// Where the statements being lowered are, for the log.
+    function: String,
+}
+
+impl Lowerer {
+    fn lower_block(&mut self, stmts: &mut Vec<Stmt>) {
+        for mut stmt in std::mem::take(stmts) {
+            stmt.for_each_block(&mut |block| self.lower_block(block));
+            match stmt {
+                Stmt::LetPattern(pattern, value, span) => {
+                    log::event!(Level::Debug, "lower", "split a destructuring let in {} into plain lets", self.function);
+                    self.bind(pattern, value, span, stmts)
+                }
+                Stmt::IfLet(pattern, value, then_branch, else_branch, span) => {
+                    log::event!(Level::Debug, "lower", "rewrote an if let in {} as a match", self.function);
+                    let otherwise = else_branch.unwrap_or_default();
+                    stmts.push(Self::match_or(pattern, value, then_branch, otherwise, span));
+                }
+                Stmt::WhileLet(pattern, value, body, span) => {
+                    log::event!(Level::Debug, "lower", "rewrote a while let in {} as a match in a while loop", self.function);
+                    self.while_let(pattern, value, body, span, stmts)
+                }
+                Stmt::For(var, Expr::IntrinsicCall(name, mut args, _, _), body, span) if name == "__chars" && args.len() == 1 => {
+                    let text = args.pop().unwrap();
+                    stmts.push(self.for_chars(var, text, body, span));
+                }
+                Stmt::For(var, Expr::Call(name, mut args, _, _), body, span)
+                    if self.chars && name == "chars" && args.len() == 1 =>
+                {
+                    let text = args.pop().unwrap();
+                    stmts.push(self.for_chars(var, text, body, span));
+                }
+                Stmt::ForPattern(pattern, Expr::IntrinsicCall(name, mut args, _, _), body, span)
+                    if name == "__enumerate" && args.len() == 1 =>
+                {
+                    let list = args.pop().unwrap();
+                    stmts.push(self.for_enumerate(pattern, list, body, span));
+                }
+                Stmt::ForPattern(pattern, range, body, span) => {
+                    let item = format!("__item{}", self.temps);
+                    self.temps += 1;
+                    let mut step = Vec::new();
+                    self.bind(pattern, Expr::Var(item.clone(), span, Type::Unknown), span, &mut step);
+                    step.push(Stmt::Block(body, span));
+                    stmts.push(Stmt::For(item, range, step, span));
+                }
+                stmt => stmts.push(stmt),
+            }
+        }
+    }
+
+    /// `match value { case pattern: then_branch case _: otherwise }`
+    fn match_or(pattern: Pattern, value: Expr, then_branch: Vec<Stmt>, otherwise: Vec<Stmt>, span: Span) -> Stmt {
+        let arms = vec![
+            MatchArm { pattern, guard: None, body: then_branch, span },
+            MatchArm { pattern: Pattern::Wildcard(span), guard: None, body: otherwise, span },
+        ];
+        Stmt::Match(value, arms, span, Type::Unknown)
+    }
+
+    /// The loop runs on a flag that the fallback case clears, so `break`
+    /// and `continue` in the body refer to it like any other loop:
+    ///
+    /// ```text
+    /// let __loop0 = true;
+    /// while __loop0 { match value { case pattern: body case _: __loop0 = false; } }
+    /// ```
+    fn while_let(&mut self, pattern: Pattern, value: Expr, body: Vec<Stmt>, span: Span, out: &mut Vec<Stmt>) {
+        let flag = format!("__loop{}", self.temps);
+        self.temps += 1;
+        let flag_var = Expr::Var(flag.clone(), span, Type::Bool);
+        let stop = Stmt::Expr(
+            Expr::Assign(Box::new(flag_var.clone()), Box::new(Expr::Bool(false, span, Type::Bool)), span, Type::Bool),
+            span,
+        );
+
+        out.push(Stmt::Let(flag, Some(Type::Bool), Expr::Bool(true, span, Type::Bool), span));
+        out.push(Stmt::While(flag_var, vec![Self::match_or(pattern, value, body, vec![stop], span)], span));
+    }
+
+    /// Steps through the string a code point at a time by byte offset:
+    ///
+    /// ```text
+    /// { let __chars0 = text; let __offset0 = 0;
+    ///   while __utf8_decode(__chars0, __offset0) > 0 {
+    ///       let var = __utf8_decode(__chars0, __offset0);
+    ///       __offset0 = __utf8_next(__chars0, __offset0);
+    ///       body } }
+    /// ```
+    fn for_chars(&mut self, var: String, text: Expr, body: Vec<Stmt>, span: Span) -> Stmt {
+        log::event!(Level::Debug, "lower", "lowered a 'for {} in chars' loop in {} to byte offsets", var, self.function);
+        let chars = format!("__chars{}", self.temps);
+        let offset = format!("__offset{}", self.temps);
+        self.temps += 1;
+        let read = |name: &str, ty: Type| Expr::Var(name.to_string(), span, ty);
+        let call = |name: &str| Expr::IntrinsicCall(
+            name.to_string(),
+            vec![read(&chars, Type::String), read(&offset, Type::I32)],
+            span,
+            Type::I32,
+        );
+
+        let more = Expr::BinOp(Box::new(call("__utf8_decode")), BinOp::Gt, Box::new(Expr::Int(0, span, Type::I32)), span, Type::Bool);
+        let advance = Expr::Assign(Box::new(read(&offset, Type::I32)), Box::new(call("__utf8_next")), span, Type::Void);
+        let step = vec![
+            Stmt::Let(var, Some(Type::I32), call("__utf8_decode"), span),
+            Stmt::Expr(advance, span),
+            Stmt::Block(body, span),
+        ];
+        Stmt::Block(vec![
+            Stmt::Let(chars, Some(Type::String), text, span),
+            Stmt::Let(offset, Some(Type::I32), Expr::Int(0, span, Type::I32), span),
+            Stmt::While(more, step, span),
+        ], span)
+    }
+
+    /// Walks the list by index, with no iterator in between:
+    ///
+    /// ```text
+    /// { let __list0: List = list; let __index0 = 0;
+    ///   while __index0 < __list_len(__list0.items) {
+    ///       let i = __index0;
+    ///       let x = __list_get(__list0.items, __index0);
+    ///       __index0 = __index0 + 1;
+    ///       body } }
+    /// ```
+    ///
+    /// The length is read on every step, so items pushed by the body are
+    /// visited and the index never passes the end.
+    fn for_enumerate(&mut self, pattern: Pattern, list: Expr, body: Vec<Stmt>, span: Span) -> Stmt {
+        log::event!(Level::Debug, "lower", "lowered an __enumerate loop in {} to an index loop", self.function);
+        let items = format!("__list{}", self.temps);
+        let index = format!("__index{}", self.temps);
+        self.temps += 1;
+        let index_var = Expr::Var(index.clone(), span, Type::I32);
+        let handle = Expr::Field(Box::new(Expr::Var(items.clone(), span, Type::Unknown)), "items".to_string(), span, Type::RawPtr);
+        let call = |name: &str, args: Vec<Expr>| Expr::IntrinsicCall(name.to_string(), args, span, Type::I32);
+
+        let more = Expr::BinOp(
+            Box::new(index_var.clone()),
+            BinOp::Lt,
+            Box::new(call("__list_len", vec![handle.clone()])),
+            span,
+            Type::Bool,
+        );
+        let item = call("__list_get", vec![handle, index_var.clone()]);
+        let mut step = Vec::new();
+        match pattern {
+            Pattern::Tuple(mut parts, _) if parts.len() == 2 => {
+                let value = parts.pop().unwrap();
+                self.bind(parts.pop().unwrap(), index_var.clone(), span, &mut step);
+                self.bind(value, item, span, &mut step);
+            }
+            pattern => self.bind(pattern, Expr::Tuple(vec![index_var.clone(), item], span, Type::Unknown), span, &mut step),
+        }
+        let next = Expr::BinOp(Box::new(index_var.clone()), BinOp::Add, Box::new(Expr::Int(1, span, Type::I32)), span, Type::I32);
+        step.push(Stmt::Expr(Expr::Assign(Box::new(index_var), Box::new(next), span, Type::Void), span));
+        step.push(Stmt::Block(body, span));
+        Stmt::Block(vec![
+            Stmt::Let(items, Some(Type::Named("List".to_string())), list, span),
+            Stmt::Let(index, Some(Type::I32), Expr::Int(0, span, Type::I32), span),
+            Stmt::While(more, step, span),
+        ], span)
+    }
+
+    fn bind(&mut self, pattern: Pattern, value: Expr, span: Span, out: &mut Vec<Stmt>) {
+        match pattern {
+            Pattern::Wildcard(_) => {}
+            Pattern::Bind(name, _) => out.push(Stmt::Let(name, None, value, span)),
+            Pattern::Tuple(items, _) => {
+                let holes = Type::Tuple(vec![Type::Unknown; items.len()]);
+                let temp = self.temp(value, holes, span, out);
+                for (index, item) in items.into_iter().enumerate() {
+                    let read = Expr::Field(Box::new(temp.clone()), index.to_string(), item.span(), Type::Unknown);
+                    self.bind(item, read, span, out);
+                }
+            }
+            Pattern::Struct(name, fields, _) => {
+                let temp = self.temp(value, Type::Named(name), span, out);
+                for (field, item) in fields {
+                    let read = Expr::Field(Box::new(temp.clone()), field, item.span(), Type::Unknown);
+                    self.bind(item, read, span, out);
+                }
+            }
+            Pattern::Int(..) | Pattern::Bool(..) | Pattern::Str(..) | Pattern::Variant(..) => {
+                unreachable!("the parser rejects refutable patterns in let")
+            }
+        }
+    }
+
+    /// Evaluates `value` once into a fresh variable and returns a read of it.
+    fn temp(&mut self, value: Expr, ty: Type, span: Span, out: &mut Vec<Stmt>) -> Expr {
+        let name = format!("__destructure{}", self.temps);
+        self.temps += 1;
+        let read = Expr::Var(name.clone(), value.span(), Type::Unknown);
+        out.push(Stmt::Let(name, Some(ty), value, span));
+        read
+    }
+}
+
+/// A value being matched on: an expression reading part of the scrutinee.
+#[derive(Clone)]
+struct Occurrence {
+    expr: Expr,
+    ty: Type,
+}
+
+/// One case of a match, with the patterns still to test against each
+/// occurrence and the names bound by the parts already tested.
+struct Row {
+    patterns: Vec<Pattern>,
+    bindings: Vec<(String, Occurrence)>,
+    guard: Option<Expr>,
+    body: Vec<Stmt>,
+}
+
+/// What a refutable pattern tests its occurrence against.
+#[derive(Clone, PartialEq)]
+enum Head {
+    Variant(String, String),
+    Int(i64),
+    Bool(bool),
+    Str(String),
+}
+
+struct MatchCompiler {
+    structs: HashMap<String, Vec<(String, Type)>>,
+    enums: HashMap<String, Vec<(String, Vec<Type>)>>,
+    temps: usize,
+    errors: Vec<Diagnostic<FileId>>,
+    file_id: FileId,
+    /// Where the matches being compiled are, for the log.
+    function: String,
+}
+
+impl MatchCompiler {
+    fn lower_block(&mut self, stmts: &mut Vec<Stmt>) {
+        for mut stmt in std::mem::take(stmts) {
+            stmt.for_each_block(&mut |block| self.lower_block(block));
+            match stmt {
+                Stmt::Match(scrutinee, arms, span, ty) => {
+                    let lowered = self.lower_match(scrutinee, arms, span, ty);
+                    stmts.push(lowered);
+                }
+                stmt => stmts.push(stmt),
+            }
+        }
+    }
+
+    fn lower_match(&mut self, scrutinee: Expr, arms: Vec<MatchArm>, span: Span, ty: Type) -> Stmt {
+        let name = self.fresh("__match");
+        let occurrence = Occurrence {
+            expr: Expr::Var(name.clone(), scrutinee.span(), ty.clone()),
+            ty: ty.clone(),
+        };
+        let rows = arms.into_iter()
+            .map(|arm| Row {
+                patterns: vec![arm.pattern],
+                bindings: Vec::new(),
+                guard: arm.guard,
+                body: arm.body,
+            })
+            .collect();
+
+        let mut exhaustive = true;
+        let mut stmts = vec![Stmt::Let(name, Some(ty.clone()), scrutinee, span)];
+        stmts.extend(self.compile(vec![occurrence], rows, span, &mut exhaustive));
+        log::event!(
+            Level::Debug, "lower", "compiled a match on {} in {} to a decision tree{}",
+            ty, self.function, if exhaustive { "" } else { " that misses some values" }
+        );
+        if !exhaustive {
+            self.errors.push(
+                Diagnostic::error()
+                    .with_message(format!("Match on {} does not handle every value; add a 'case _:'", ty))
+                    .with_labels(vec![Label::primary(self.file_id, span)]),
+            );
+        }
+        Stmt::Block(stmts, span)
+    }
+
+    fn fresh(&mut self, prefix: &str) -> String {
+        let name = format!("{}{}", prefix, self.temps);
+        self.temps += 1;
+        name
+    }
+
+    /// Builds the decision tree selecting the first row whose patterns match
+    /// the occurrences. Clears `exhaustive` if some path selects no row.
+    fn compile(&mut self, occurrences: Vec<Occurrence>, mut rows: Vec<Row>, span: Span, exhaustive: &mut bool) -> Vec<Stmt> {
+        if rows.is_empty() {
+            *exhaustive = false;
+            return Vec::new();
+        }
+
+        let column = rows[0].patterns.iter()
+            .position(|p| !matches!(p, Pattern::Wildcard(_) | Pattern::Bind(..)));
+        let Some(column) = column else {
+            let row = rows.remove(0);
+            return self.leaf(occurrences, row, rows, span, exhaustive);
+        };
+
+        let occurrence = occurrences[column].clone();
+        match &rows[0].patterns[column] {
+            Pattern::Tuple(..) | Pattern::Struct(..) => {
+                let parts = self.parts(&occurrence);
+                let rows = rows.into_iter()
+                    .map(|row| Self::expand(row, column, &occurrence, &parts))
+                    .collect();
+                let parts = parts.into_iter().map(|(_, part)| part).collect();
+                self.compile(Self::splice(&occurrences, column, parts), rows, span, exhaustive)
+            }
+            _ => self.switch(occurrences, column, rows, span, exhaustive),
+        }
+    }
+
+    /// Binds the names of a row whose patterns all matched, then runs its
+    /// body if the guard holds, falling back to the remaining rows if not.
+    fn leaf(&mut self, occurrences: Vec<Occurrence>, mut row: Row, rest: Vec<Row>, span: Span, exhaustive: &mut bool) -> Vec<Stmt> {
+        for (pattern, occurrence) in row.patterns.iter().zip(&occurrences) {
+            if let Pattern::Bind(name, _) = pattern {
+                row.bindings.push((name.clone(), occurrence.clone()));
+            }
+        }
+        let mut stmts: Vec<Stmt> = row.bindings.into_iter()
+            .map(|(name, occurrence)| Stmt::Let(name, Some(occurrence.ty), occurrence.expr, span))
+            .collect();
+
+        let Some(guard) = row.guard else {
+            stmts.extend(row.body);
+            return stmts;
+        };
+
+        // The bindings live in their own scope so they cannot shadow names
+        // used by the fallback rows; a flag carries the guard's result out.
+        let flag = self.fresh("__guard");
+        let flag_var = Expr::Var(flag.clone(), span, Type::Bool);
+        let mut body = vec![Stmt::Expr(
+            Expr::Assign(Box::new(flag_var.clone()), Box::new(Expr::Bool(true, span, Type::Bool)), span, Type::Bool),
+            span,
+        )];
+        body.extend(row.body);
+        stmts.push(Stmt::If(guard, body, None, span));
+
+        let failed = Expr::BinOp(
+            Box::new(flag_var),
+            BinOp::Eq,
+            Box::new(Expr::Bool(false, span, Type::Bool)),
+            span,
+            Type::Bool,
+        );
+        vec![
+            Stmt::Let(flag, Some(Type::Bool), Expr::Bool(false, span, Type::Bool), span),
+            Stmt::Block(stmts, span),
+            Stmt::If(failed, self.compile(occurrences, rest, span, exhaustive), None, span),
+        ]
+    }
+
+    /// Branches on the variant or literal at `column`, one branch per head
+    /// appearing in that column plus a default branch for the rest.
+    fn switch(&mut self, occurrences: Vec<Occurrence>, column: usize, rows: Vec<Row>, span: Span, exhaustive: &mut bool) -> Vec<Stmt> {
+        let occurrence = occurrences[column].clone();
+        let mut heads: Vec<Head> = Vec::new();
+        for row in &rows {
+            if let Some(head) = Self::head(&row.patterns[column])
+                && !heads.contains(&head) {
+                heads.push(head);
+            }
+        }
+        let complete = match &heads[0] {
+            Head::Variant(enum_name, _) => heads.len() == self.variant_count(enum_name),
+            Head::Bool(_) => heads.len() == 2,
+            Head::Int(_) | Head::Str(_) => false,
+        };
+
+        let mut branches = Vec::new();
+        for head in &heads {
+            let fields = self.head_fields(&occurrence, head);
+            let specialized = rows.iter()
+                .filter_map(|row| Self::specialize(row, column, &occurrence, head, fields.len()))
+                .collect();
+            let tree = self.compile(Self::splice(&occurrences, column, fields), specialized, span, exhaustive);
+            branches.push((head.clone(), tree));
+        }
+        let default = (!complete).then(|| {
+            let defaults = rows.iter()
+                .filter(|row| Self::head(&row.patterns[column]).is_none())
+                .filter_map(|row| Self::specialize(row, column, &occurrence, &heads[0], 0))
+                .collect();
+            self.compile(Self::splice(&occurrences, column, Vec::new()), defaults, span, exhaustive)
+        });
+
+        // User enums switch on the tag; `Option` and literals test in turn.
+        if let (Type::Named(enum_name), Head::Variant(..)) = (&occurrence.ty, &heads[0]) {
+            let cases = branches.into_iter()
+                .map(|(head, tree)| match head {
+                    Head::Variant(_, variant) => (variant, tree),
+                    _ => unreachable!("a column holds one kind of head"),
+                })
+                .collect();
+            return vec![Stmt::Switch(occurrence.expr, enum_name.clone(), cases, default, span)];
+        }
+
+        let mut tree = match default {
+            Some(tree) => tree,
+            None => branches.pop().map(|(_, tree)| tree).unwrap_or_default(),
+        };
+        while let Some((head, branch)) = branches.pop() {
+            let test = Self::test(&occurrence, &head, span);
+            tree = vec![Stmt::If(test, branch, (!tree.is_empty()).then_some(tree), span)];
+        }
+        tree
+    }
+
+    fn head(pattern: &Pattern) -> Option<Head> {
+        match pattern {
+            Pattern::Variant(enum_name, variant, _, _) => {
+                Some(Head::Variant(enum_name.clone().unwrap_or_default(), variant.clone()))
+            }
+            Pattern::Int(n, _) => Some(Head::Int(*n)),
+            Pattern::Bool(b, _) => Some(Head::Bool(*b)),
+            Pattern::Str(value, _) => Some(Head::Str(value.clone())),
+            _ => None,
+        }
+    }
+
+    fn variant_count(&self, enum_name: &str) -> usize {
+        match enum_name {
+            "Option" => 2,
+            name => self.enums.get(name).map_or(0, |variants| variants.len()),
+        }
+    }
+
+    /// The payload occurrences of `head` within `occurrence`.
+    fn head_fields(&self, occurrence: &Occurrence, head: &Head) -> Vec<Occurrence> {
+        let Head::Variant(enum_name, variant) = head else { return Vec::new() };
+        let types = match (&occurrence.ty, variant.as_str()) {
+            (Type::Option(inner), "Some") => vec![(**inner).clone()],
+            (Type::Option(_), _) => Vec::new(),
+            _ => self.enums.get(enum_name)
+                .and_then(|variants| variants.iter().find(|(name, _)| name == variant))
+                .map(|(_, fields)| fields.clone())
+                .unwrap_or_default(),
+        };
+        let span = occurrence.expr.span();
+        types.into_iter().enumerate()
+            .map(|(i, ty)| Occurrence {
+                expr: Expr::VariantField(Box::new(occurrence.expr.clone()), enum_name.clone(), variant.clone(), i, span, ty.clone()),
+                ty,
+            })
+            .collect()
+    }
+
+    fn test(occurrence: &Occurrence, head: &Head, span: Span) -> Expr {
+        let value = Box::new(occurrence.expr.clone());
+        match head {
+            Head::Variant(enum_name, variant) => {
+                Expr::IsVariant(value, enum_name.clone(), variant.clone(), span, Type::Bool)
+            }
+            Head::Int(n) => Expr::BinOp(value, BinOp::Eq, Box::new(Expr::Int(*n, span, Type::I32)), span, Type::Bool),
+            Head::Bool(b) => Expr::BinOp(value, BinOp::Eq, Box::new(Expr::Bool(*b, span, Type::Bool)), span, Type::Bool),
+            // `==` on strings compares pointers, so compare the bytes.
+            Head::Str(text) => {
+                let args = vec![*value, Expr::Str(text.clone(), span, Type::String)];
+                Expr::IntrinsicCall("__str_eq".to_string(), args, span, Type::Bool)
+            }
+        }
+    }
+
+    /// The row as seen on the branch where `column` holds `head`, or `None`
+    /// if the row's pattern there requires a different head.
+    fn specialize(row: &Row, column: usize, occurrence: &Occurrence, head: &Head, arity: usize) -> Option<Row> {
+        let (parts, binding) = match &row.patterns[column] {
+            Pattern::Wildcard(span) => (vec![Pattern::Wildcard(*span); arity], None),
+            Pattern::Bind(name, span) => (vec![Pattern::Wildcard(*span); arity], Some(name.clone())),
+            pattern => {
+                if Self::head(pattern).as_ref() != Some(head) {
+                    return None;
+                }
+                match pattern {
+                    Pattern::Variant(_, _, args, _) => (args.clone(), None),
+                    _ => (Vec::new(), None),
+                }
+            }
+        };
+
+        let mut bindings = row.bindings.clone();
+        if let Some(name) = binding {
+            bindings.push((name, occurrence.clone()));
+        }
+        let mut patterns = row.patterns.clone();
+        patterns.splice(column..=column, parts);
+        Some(Row { patterns, bindings, guard: row.guard.clone(), body: row.body.clone() })
+    }
+
+    /// Named parts of a tuple or struct occurrence, in declaration order.
+    fn parts(&self, occurrence: &Occurrence) -> Vec<(String, Occurrence)> {
+        let types: Vec<(String, Type)> = match &occurrence.ty {
+            Type::Tuple(items) => items.iter().cloned().enumerate().map(|(i, ty)| (i.to_string(), ty)).collect(),
+            Type::Named(name) => self.structs.get(name).cloned().unwrap_or_default(),
+            _ => Vec::new(),
+        };
+        let span = occurrence.expr.span();
+        types.into_iter()
+            .map(|(name, ty)| {
+                let expr = Expr::Field(Box::new(occurrence.expr.clone()), name.clone(), span, ty.clone());
+                (name, Occurrence { expr, ty })
+            })
+            .collect()
+    }
+
+    /// Replaces the tuple or struct pattern at `column` with one pattern per part.
+    fn expand(mut row: Row, column: usize, occurrence: &Occurrence, parts: &[(String, Occurrence)]) -> Row {
+        let pattern = row.patterns[column].clone();
+        let items: Vec<Pattern> = match pattern {
+            Pattern::Tuple(items, _) => items,
+            Pattern::Struct(_, fields, span) => parts.iter()
+                .map(|(name, _)| {
+                    fields.iter()
+                        .find(|(field, _)| field == name)
+                        .map_or(Pattern::Wildcard(span), |(_, item)| item.clone())
+                })
+                .collect(),
+            Pattern::Bind(name, span) => {
+                row.bindings.push((name, occurrence.clone()));
+                vec![Pattern::Wildcard(span); parts.len()]
+            }
+            other => vec![Pattern::Wildcard(other.span()); parts.len()],
+        };
+        row.patterns.splice(column..=column, items);
+        row
+    }
+
+    fn splice(occurrences: &[Occurrence], column: usize, parts: Vec<Occurrence>) -> Vec<Occurrence> {
+        let mut result = occurrences.to_vec();
+        result.splice(column..=column, parts);
+        result
+    }
+}
@@ -1,21 +1,110 @@
-use verve_lang::{lexer, parser, typeck, codegen, cli::{Args, Command}};
+use verve_lang::ast::Program;
+use verve_lang::cli::{Args, Command, CompileOptions, PrintRequest, ProfileCommand, RuntimeMode, Sanitizer};
+use verve_lang::codegen::runtime;
+use verve_lang::compiler::{Compiler, DiagnosticHandler, Stage};
+use verve_lang::fix::{self, Suggestion};
+use verve_lang::ice::{self, Ice};
+use verve_lang::interface::Interface;
+use verve_lang::sandbox::{self, Limits, Outcome};
+use verve_lang::target::{Target, TARGETS};
+use verve_lang::target_dir::{self, BuildDir, TargetDir};
+use verve_lang::{lint, log, manifest, profile, stdlib};
 
 use clap::Parser;
 use codespan::{FileId, Files};
-use codespan_reporting::diagnostic::Diagnostic;
-use std::fmt;
-use std::path::PathBuf;
+use codespan_reporting::diagnostic::{Diagnostic, Severity};
+use std::time::Duration;
 
-#[derive(Debug)]
-struct MyError(Diagnostic<FileId>);
+/// Prints diagnostics to stderr, and the AST too when `--verbose` is set.
+/// Keeps the interface of the program when `--emit-interface` asks for it.
+struct StderrReporter {
+    verbose: bool,
+    interface: Option<Interface>,
+}
 
-impl fmt::Display for MyError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self.0)
+impl DiagnosticHandler for StderrReporter {
+    fn report(&mut self, stage: Stage, diagnostic: &Diagnostic<FileId>, _files: &Files<String>) {
+        let prefix = match stage {
+            _ if diagnostic.severity == Severity::Warning => "Warning",
+            Stage::Parse => "Parse error",
+            Stage::Typecheck => "Type error",
+            Stage::Codegen => "Codegen error",
+        };
+        eprintln!("{}: {:?}", prefix, diagnostic);
+    }
+
+    fn parsed(&mut self, program: &Program) {
+        if self.verbose {
+            println!("Parsed AST:\n{:#?}", program);
+        }
+        if let Some(interface) = &mut self.interface {
+            *interface = Interface::of(program);
+        }
     }
 }
 
-impl std::error::Error for MyError {}
+/// Keeps the fixes the diagnostics suggest, for `verve fix`.
+#[derive(Default)]
+struct Fixes(Vec<Suggestion>);
+
+impl DiagnosticHandler for Fixes {
+    fn report(&mut self, _stage: Stage, _diagnostic: &Diagnostic<FileId>, _files: &Files<String>) {}
+
+    fn suggest(&mut self, suggestion: &Suggestion) {
+        if !self.0.contains(suggestion) {
+            self.0.push(suggestion.clone());
+        }
+    }
+}
+
+/// Compiles the program and applies the fixes its diagnostics suggest to
+/// the files among `file_ids`, or prints them as a diff with `dry_run`.
+fn fix_sources(compiler: &Compiler, file_ids: &[FileId], config: &str, dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut fixes = Fixes::default();
+    // Whether it compiles is for the usual build to report.
+    if let Err(ice) = ice::catch(|| compiler.compile_files(file_ids, &mut fixes)) {
+        report_ice(&ice, compiler, file_ids, config);
+    }
+    let mut applied = 0;
+    for &file_id in file_ids {
+        let suggestions: Vec<&Suggestion> = fixes.0.iter().filter(|s| s.file_id == file_id).collect();
+        let name = compiler.files().name(file_id).to_string_lossy().into_owned();
+        if suggestions.is_empty() || name == "<stdin>" {
+            continue;
+        }
+        let old = compiler.files().source(file_id);
+        let new = fix::apply(old, &suggestions);
+        if dry_run {
+            print!("{}", fix::diff(&name, old, &new));
+        } else {
+            std::fs::write(&name, &new).map_err(|e| format!("Cannot write {}: {}", name, e))?;
+            for suggestion in &suggestions {
+                println!("{}: {}", name, suggestion.message);
+            }
+        }
+        applied += suggestions.len();
+    }
+    if applied == 0 {
+        println!("No fixes to apply");
+    }
+    Ok(())
+}
+
+/// Prints the internal compiler error with a reproduction bundle written
+/// to the temp directory, and exits with the status of a Rust panic.
+fn report_ice(ice: &Ice, compiler: &Compiler, file_ids: &[FileId], config: &str) -> ! {
+    let sources: Vec<(String, String)> = file_ids.iter()
+        .map(|&id| (compiler.files().name(id).to_string_lossy().into_owned(), compiler.files().source(id).clone()))
+        .collect();
+    match ice.write_bundle(&std::env::temp_dir(), &sources, config) {
+        Ok(dir) => eprint!("{}", ice.report(Some(&dir))),
+        Err(e) => {
+            eprint!("{}", ice.report(None));
+            eprintln!("note: cannot write a reproduction bundle: {}", e);
+        }
+    }
+    std::process::exit(101);
+}
 
 fn check_dependencies() -> Result<(), Box<dyn std::error::Error>> {
     #[cfg(target_os = "windows")]
@@ -59,70 +148,344 @@ fn get_msvc_lib_paths() -> Result<Vec<String>, Box<dyn std::error::Error>> {
     Ok(paths)
 }
 
+/// Runs the `[build]` hook `name`, `script`, in the directory of the
+/// manifest at `manifest_path`. The environment tells it the target, the
+/// profile, the build directory and where the generated C goes.
+fn run_hook(name: &str, script: &str, manifest_path: &std::path::Path, build: &BuildDir, target_triple: &str, opt_level: u8, verbose: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if verbose {
+        println!("Running build.{} hook: {}", name, script);
+    }
+    let dir = manifest_path.parent().unwrap_or(std::path::Path::new("."));
+    let status = manifest::hook_command(script, dir)
+        .env("VERVE_TARGET", target_triple)
+        .env("VERVE_PROFILE", target_dir::profile(opt_level))
+        .env("VERVE_OUT_DIR", std::path::absolute(build.path())?)
+        .env("VERVE_C_SOURCE", std::path::absolute(build.c_source())?)
+        .status()
+        .map_err(|e| format!("Cannot run build.{} hook: {}", name, e))?;
+    if !status.success() {
+        return Err(format!("build.{} hook `{}` failed ({})", name, script, status).into());
+    }
+    Ok(())
+}
+
+/// Builds the runtime library for `target_triple` in `dir`, with the
+/// `verve_rt.h` it is declared in, unless it was already built from the
+/// same source. Returns the C compiler arguments that find the header and
+/// link the archive.
+fn build_runtime(dir: &std::path::Path, sdk: &std::path::Path, target_triple: &str, verbose: bool) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let source = runtime::library_source(target_triple);
+    let header = runtime::header(target_triple);
+    let source_path = dir.join("verve_rt.c");
+    let header_path = dir.join(runtime::HEADER_NAME);
+    let library = dir.join(runtime::LIBRARY_NAME);
+    let args = vec![format!("-I{}", dir.display()), library.display().to_string()];
+    let unchanged = |path: &std::path::Path, text: &str| std::fs::read_to_string(path).is_ok_and(|built| built == text);
+    if library.exists() && unchanged(&source_path, &source) && unchanged(&header_path, &header) {
+        log::event!(log::Level::Debug, "driver", "{} is up to date", library.display());
+        return Ok(args);
+    }
+    std::fs::create_dir_all(dir)?;
+    std::fs::write(&header_path, header)?;
+    std::fs::write(&source_path, source)?;
+    let object = dir.join("verve_rt.o");
+    let (cc, ar) = verve_lang::cli::runtime_tools(sdk, target_triple);
+    let mut cc_args = vec!["-c".to_string(), "-O2".to_string()];
+    if verve_lang::cli::is_wasi(target_triple) {
+        cc_args.push(format!("--target={}", target_triple));
+        cc_args.push(format!("--sysroot={}", sdk.join("share").join("wasi-sysroot").display()));
+    }
+    cc_args.extend([source_path.display().to_string(), "-o".to_string(), object.display().to_string()]);
+    let ar_args = vec!["rcs".to_string(), library.display().to_string(), object.display().to_string()];
+    for (tool, args) in [(&cc, cc_args), (&ar, ar_args)] {
+        if verbose {
+            println!("Invoking {} with args: {:?}", tool.display(), args);
+        }
+        let status = std::process::Command::new(tool).args(&args).status()
+            .map_err(|e| format!("Cannot run {}: {}", tool.display(), e))?;
+        if !status.success() {
+            // A later build must not take the failed one for up to date.
+            let _ = std::fs::remove_file(&source_path);
+            return Err(format!("Building the runtime library failed ({} {})", tool.display(), status).into());
+        }
+    }
+    if verbose {
+        println!("Runtime library: {}", library.display());
+    }
+    Ok(args)
+}
+
+fn print_info(request: PrintRequest, target_triple: &str) -> Result<(), Box<dyn std::error::Error>> {
+    match request {
+        PrintRequest::TargetList => {
+            for triple in TARGETS {
+                println!("{}", triple);
+            }
+        }
+        PrintRequest::TargetInfo => {
+            let target = Target::parse(target_triple)?;
+            println!("{}", target);
+            match target.c_compiler(&wasi_sdk()) {
+                Some(cc) => println!("c_compiler = {}", cc.display()),
+                None => println!("c_compiler = none (only output.c is written)"),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Where wasi-sdk is: `$WASI_SDK_PATH`, or the default install location.
+fn wasi_sdk() -> std::path::PathBuf {
+    std::env::var_os("WASI_SDK_PATH")
+        .map(std::path::PathBuf::from)
+        .unwrap_or_else(|| verve_lang::cli::DEFAULT_WASI_SDK.into())
+}
+
+/// Removes the target directory of the project in the working directory.
+fn clean() -> Result<(), Box<dyn std::error::Error>> {
+    let target = TargetDir::for_project(manifest::locate(&[]).as_deref());
+    if target.clean().map_err(|e| format!("Cannot remove {}: {}", target.root().display(), e))? {
+        println!("Removed {}", target.root().display());
+    } else {
+        println!("Nothing to clean");
+    }
+    Ok(())
+}
+
+fn print_profile_report(files: &[std::path::PathBuf]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut runs = Vec::new();
+    for file in files {
+        let text = std::fs::read_to_string(file)
+            .map_err(|e| format!("Cannot read profile {}: {}", file.display(), e))?;
+        runs.push(profile::parse(&text).map_err(|e| format!("{}: {}", file.display(), e))?);
+    }
+    print!("{}", profile::report(&profile::aggregate(&runs)));
+    Ok(())
+}
+
+/// Formats generated C with clang-format in the given style.
+fn run_clang_format(code: String, style: &str) -> Result<String, Box<dyn std::error::Error>> {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = std::process::Command::new("clang-format")
+        .arg(format!("--style={}", style))
+        .arg("--assume-filename=output.c")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Cannot run clang-format: {}", e))?;
+    // Feed stdin from another thread so a full stdout pipe cannot stall both.
+    let mut stdin = child.stdin.take().unwrap();
+    let writer = std::thread::spawn(move || stdin.write_all(code.as_bytes()));
+    let output = child.wait_with_output()?;
+    writer.join().unwrap()?;
+    if !output.status.success() {
+        return Err("clang-format failed".into());
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     check_dependencies()?;
     let args = Args::parse();
+    if let Some(request) = args.print {
+        return print_info(request, &args.options.target_triple);
+    }
 
-    let (input, output, optimize, target_triple, verbose) = match args.command {
-        Some(Command::Run {
-                 input,
-                 output,
-                 optimize,
-                 target_triple,
-                 verbose,
-             }) => (input, output, optimize, target_triple, verbose),
-        None => (
-            args.input.unwrap(),
-            args.output,
-            args.optimize,
-            args.target_triple,
-            args.verbose,
-        ),
+    let (inputs, options, fix, run) = match args.command {
+        Some(Command::Run { inputs, timeout, memory_limit, options }) => {
+            let limits = Limits { timeout: timeout.map(Duration::from_secs), memory: memory_limit };
+            (inputs, options, None, Some(limits))
+        }
+        Some(Command::Fix { inputs, dry_run, options }) => (inputs, options, Some(dry_run), None),
+        Some(Command::Profile { command: ProfileCommand::Report { files } }) => {
+            return print_profile_report(&files);
+        }
+        Some(Command::Clean) => return clean(),
+        None => (args.inputs, args.options, None, None),
     };
+    let CompileOptions { output, optimize, opt_level, backend, target_triple, features, no_default_features, verbose, portable_bitfields, debug_alloc, coverage, instrument_functions, stack_guard, msvc, gnu_extensions, whole_program, fast_math, sanitize, indent_width, clang_format, runtime: runtime_mode, allow, warn, deny, emit_interface, log: log_filter } = options;
 
+    let log_filter = match log_filter {
+        Some(filter) => filter,
+        None => match std::env::var("VERVE_LOG") {
+            Ok(spec) => log::Filter::parse(&spec).map_err(|e| format!("VERVE_LOG: {}", e))?,
+            Err(_) if verbose => log::Filter::at(log::Level::Info),
+            Err(_) => log::Filter::default(),
+        },
+    };
+    log::set_filter(log_filter);
+    Target::parse(&target_triple)?;
+    let wasi = verve_lang::cli::is_wasi(&target_triple);
+    let c_opt_level = if optimize { opt_level } else { 0 };
+    let manifest_path = manifest::locate(&inputs);
+    let manifest = manifest_path.as_deref().map(manifest::load).transpose()?;
+    let target = TargetDir::for_project(manifest_path.as_deref());
+    let build = target.build(&target_triple, c_opt_level);
+    if let Some(path) = &manifest_path {
+        log::event!(log::Level::Info, "driver", "using {}", path.display());
+    }
+    log::event!(log::Level::Info, "driver", "building into {}", build.path().display());
+    let output = output.unwrap_or_else(|| build.binary(verve_lang::cli::default_output(&target_triple)));
+    // Hooks run once there is a build directory to tell them about.
+    let pre_hook = manifest.as_ref().and_then(|manifest| manifest.build.pre.as_deref());
+    if let (Some(path), Some(script)) = (&manifest_path, pre_hook) {
+        build.create()?;
+        run_hook("pre", script, path, &build, &target_triple, c_opt_level, verbose)?;
+    }
+    let features = match &manifest {
+        Some(manifest) => manifest.resolve(&features, !no_default_features)?,
+        None if features.is_empty() => Default::default(),
+        None => return Err(format!("--features needs a {} with a [features] table", manifest::FILE_NAME).into()),
+    };
+    if verbose && !features.is_empty() {
+        println!("Features: {}", features.iter().cloned().collect::<Vec<_>>().join(", "));
+    }
+    // What the reproduction bundle of an internal compiler error records.
+    let config = format!(
+        "command: {}\nbackend: {}\ntarget: {}\nopt-level: {}\nfeatures: {}",
+        std::env::args().collect::<Vec<_>>().join(" "),
+        backend,
+        target_triple,
+        c_opt_level,
+        features.iter().cloned().collect::<Vec<_>>().join(", "),
+    );
+    let mut compiler = Compiler::new()
+        .backend(&backend)
+        .target_triple(&target_triple)
+        .features(features)
+        // Stack promotion would hide allocations from AddressSanitizer's
+        // heap checks.
+        .opt_level(if sanitize.contains(&Sanitizer::Address) { 0 } else { c_opt_level })
+        .portable_bitfields(portable_bitfields)
+        .debug_alloc(debug_alloc)
+        .line_directives(coverage)
+        .instrument_functions(instrument_functions)
+        .max_call_depth(stack_guard)
+        .msvc(msvc || (cfg!(target_os = "windows") && !gnu_extensions && !wasi))
+        .gnu_extensions(gnu_extensions)
+        .whole_program(whole_program)
+        .linked_runtime(runtime_mode == RuntimeMode::Linked)
+        .indent_width(indent_width);
+    for (lints, level) in [(allow, lint::Level::Allow), (warn, lint::Level::Warn), (deny, lint::Level::Deny)] {
+        for &lint in lints.into_iter().flatten() {
+            compiler = compiler.lint_level(lint, level);
+        }
+    }
+    if let Some(version) = manifest.as_ref().and_then(|manifest| manifest.version.as_deref()) {
+        compiler = compiler.version(version);
+    }
+    match stdlib::locate() {
+        Some(dir) => {
+            if verbose {
+                println!("Standard library: {}", dir.display());
+            }
+            for (path, text) in stdlib::modules(&dir)? {
+                compiler.add_std_module(&path.to_string_lossy(), text);
+            }
+        }
+        None => {
+            if let Some(dir) = std::env::var_os(stdlib::ENV_VAR) {
+                return Err(format!("{}: {} is not a directory", stdlib::ENV_VAR, dir.to_string_lossy()).into());
+            }
+        }
+    }
+    let mut file_ids = Vec::new();
+    for input in &inputs {
+        if input.extension().is_some_and(|ext| ext == "vervei") {
+            let interface = Interface::from_bytes(&std::fs::read(input)?)
+                .map_err(|e| format!("{}: {}", input.display(), e))?;
+            compiler.add_interface(interface);
+        } else if input.as_os_str() == "-" {
+            let mut content = String::new();
+            std::io::Read::read_to_string(&mut std::io::stdin(), &mut content)?;
+            file_ids.push(compiler.add_source("<stdin>", content));
+        } else {
+            let content = std::fs::read_to_string(input)?;
+            file_ids.push(compiler.add_source(input.to_str().unwrap(), content));
+        }
+    }
 
+    if file_ids.is_empty() {
+        return Err("No source files to compile".into());
+    }
+    if let Some(dry_run) = fix {
+        return fix_sources(&compiler, &file_ids, &config, dry_run);
+    }
+    let mut reporter = StderrReporter { verbose, interface: emit_interface.as_ref().map(|_| Interface::default()) };
+    let compiled = ice::catch(|| compiler.compile_files(&file_ids, &mut reporter))
+        .unwrap_or_else(|ice| report_ice(&ice, &compiler, &file_ids, &config));
+    let mut code = compiled.map_err(|e| e.to_string())?;
+    if let (Some(path), Some(interface)) = (&emit_interface, &reporter.interface) {
+        std::fs::write(path, interface.to_bytes())?;
+    }
+    if let Some(style) = clang_format {
+        code = run_clang_format(code, &style)?;
+    }
+    build.create()?;
+    let c_source = build.c_source();
+    log::event!(log::Level::Info, "driver", "writing {}", c_source.display());
+    std::fs::write(&c_source, code)?;
+    let post_hook = manifest.as_ref().and_then(|manifest| manifest.build.post.as_deref());
+    if let (Some(path), Some(script)) = (&manifest_path, post_hook) {
+        run_hook("post", script, path, &build, &target_triple, c_opt_level, verbose)?;
+    }
 
-    let mut files = Files::new();
-    let content = std::fs::read_to_string(&input)?;
-    let file_id = files.add(input.to_str().unwrap(), content);
-
-    let lexer = lexer::Lexer::new(&files, file_id);
-    let mut parser = parser::Parser::new(lexer);
-    let mut program = parser.parse().map_err(MyError)?;
-
+    let c_flags = verve_lang::cli::optimization_flags(c_opt_level, &sanitize, fast_math);
     if verbose {
-        println!("Parsed AST:\n{:#?}", program);
+        println!("C optimization flags: {}", c_flags.join(" "));
     }
+    let sdk = wasi_sdk();
+    let runtime_args = match runtime_mode {
+        RuntimeMode::Linked => build_runtime(&target.runtime(&target_triple), &sdk, &target_triple, verbose)?,
+        RuntimeMode::Bundled => Vec::new(),
+    };
 
-    let mut type_checker = typeck::TypeChecker::new(file_id);
-    if let Err(errors) = type_checker.check(&mut program) {
-        for error in errors {
-            eprintln!("Type error: {:?}", error);
+    if wasi {
+        if !sanitize.is_empty() {
+            return Err(format!("Sanitizers are not available for {}", target_triple).into());
         }
-        return Err("Type check failed".into());
+        let (clang, mut clang_args) = verve_lang::cli::wasi_clang_command(&sdk, &target_triple, &c_flags, &c_source, &output);
+        clang_args.extend(runtime_args.iter().cloned());
+        if verbose {
+            println!("Invoking {} with args: {:?}", clang.display(), clang_args);
+        }
+        let result = std::process::Command::new(&clang).args(&clang_args).output()
+            .map_err(|e| format!("Cannot run {}: {} (set WASI_SDK_PATH to the wasi-sdk directory)", clang.display(), e))?;
+        print!("{}", String::from_utf8_lossy(&result.stdout));
+        eprint!("{}", verve_lang::codegen::demangle(&String::from_utf8_lossy(&result.stderr)));
+        if !result.status.success() {
+            return Err("C compilation failed".into());
+        }
+        println!("Program compiled to: {}", output.display());
+        return run.map_or(Ok(()), |limits| run_program(&output, wasi, &limits));
     }
 
-    let config = codegen::CodegenConfig {
-        target_triple: target_triple.clone(),
-    };
-    let mut target = codegen::Target::create(config, file_id);
-    target.compile(&program)?;
-
     #[cfg(target_os = "windows")]
     {
         let msvc_lib_paths = get_msvc_lib_paths()?;
-        let mut clang_args = vec![
-            if optimize { "-O3" } else { "-O0" }.to_string(),
-            "output.c".to_string(),
+        let mut clang_args = c_flags;
+        // link.exe cannot read the LLVM bitcode objects -flto produces.
+        if clang_args.iter().any(|flag| flag == "-flto") {
+            clang_args.push("-fuse-ld=lld".to_string());
+        }
+        clang_args.extend([
+            c_source.to_str().unwrap().to_string(),
             "-o".to_string(),
             output.to_str().unwrap().to_string(),
-        ];
+        ]);
+        clang_args.extend(runtime_args.iter().cloned());
 
         for path in msvc_lib_paths {
             clang_args.push("-L".to_string());
             clang_args.push(path);
         }
 
+        clang_args.extend(verve_lang::cli::sanitizer_flags(&sanitize));
+        if coverage {
+            clang_args.push("--coverage".to_string());
+        }
         clang_args.extend_from_slice(&[
             "-lmsvcrt".to_string(),
             "-Xlinker".to_string(),
@@ -133,12 +496,55 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             println!("Invoking clang with args: {:?}", clang_args);
         }
 
-        let status = std::process::Command::new("clang").args(&clang_args).status()?;
-        if !status.success() {
+        // Report C diagnostics with Verve names rather than escaped C ones.
+        let result = std::process::Command::new("clang").args(&clang_args).output()?;
+        print!("{}", String::from_utf8_lossy(&result.stdout));
+        eprint!("{}", verve_lang::codegen::demangle(&String::from_utf8_lossy(&result.stderr)));
+        if !result.status.success() {
+            return Err("C compilation failed".into());
+        }
+    }
+
+    // Elsewhere the driver only writes the C, but running needs a binary.
+    #[cfg(not(target_os = "windows"))]
+    if run.is_some() {
+        let (cc, _) = verve_lang::cli::runtime_tools(&sdk, &target_triple);
+        let mut cc_args = c_flags;
+        cc_args.extend([c_source.display().to_string(), "-o".to_string(), output.display().to_string()]);
+        cc_args.extend(runtime_args.iter().cloned());
+        cc_args.extend(verve_lang::cli::sanitizer_flags(&sanitize));
+        if coverage {
+            cc_args.push("--coverage".to_string());
+        }
+        if verbose {
+            println!("Invoking {} with args: {:?}", cc.display(), cc_args);
+        }
+        let result = std::process::Command::new(&cc).args(&cc_args).output()
+            .map_err(|e| format!("Cannot run {}: {} (set CC to a C compiler)", cc.display(), e))?;
+        eprint!("{}", verve_lang::codegen::demangle(&String::from_utf8_lossy(&result.stderr)));
+        if !result.status.success() {
             return Err("C compilation failed".into());
         }
     }
 
     println!("Program compiled to: {}", output.display());
-    Ok(())
+    run.map_or(Ok(()), |limits| run_program(&output, wasi, &limits))
+}
+
+/// Runs the program `verve run` built, WASI ones under wasmtime, within
+/// `limits`, and exits with its status.
+fn run_program(output: &std::path::Path, wasi: bool, limits: &Limits) -> Result<(), Box<dyn std::error::Error>> {
+    // Relative to the working directory, not looked up in PATH.
+    let program = std::path::absolute(output)?;
+    let mut command = if wasi {
+        let mut command = sandbox::command(std::path::Path::new("wasmtime"), limits);
+        command.args(["--dir", "."]).arg(&program);
+        command
+    } else {
+        sandbox::command(&program, limits)
+    };
+    match sandbox::run(&mut command, limits).map_err(|e| format!("Cannot run {}: {}", output.display(), e))? {
+        Outcome::Exited { status, .. } => std::process::exit(status.code().unwrap_or(1)),
+        Outcome::TimedOut(timeout) => Err(format!("{} timed out after {:?}", output.display(), timeout).into()),
+    }
 }
\ No newline at end of file
@@ -0,0 +1,179 @@
+//! The project manifest, `verve.toml`. Three things in it mean anything to
+//! the compiler so far. `version` under `[package]` is what
+//! `build.version()` returns. Each entry of `[features]` names an optional
+//! feature and the features it turns on in turn, and `default` lists those
+//! enabled unless `--no-default-features` is given. Sources test for a
+//! feature with `#[cfg(feature = "name")]`. `pre` and `post` under `[build]`
+//! are shell commands the driver runs in the project directory before the
+//! sources are read and after the C is generated.
+//!
+//! The file is read as the subset of TOML these need: `[table]` headers,
+//! `key = "string"`, `key = ["list", "of", "strings"]`, which may span
+//! lines, and `#` comments. Other entries are skipped unread.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+pub const FILE_NAME: &str = "verve.toml";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Manifest {
+    /// The package version, when `[package]` gives one.
+    pub version: Option<String>,
+    /// Each feature and the features it enables.
+    pub features: BTreeMap<String, Vec<String>>,
+    /// The `[build]` hooks.
+    pub build: BuildHooks,
+}
+
+/// Shell commands run around a build, to generate sources or post-process
+/// the output.
+#[derive(Debug, Default, PartialEq)]
+pub struct BuildHooks {
+    pub pre: Option<String>,
+    pub post: Option<String>,
+}
+
+impl Manifest {
+    pub fn parse(text: &str) -> Result<Manifest, String> {
+        let mut manifest = Manifest::default();
+        let mut table = String::new();
+        let mut lines = text.lines().enumerate();
+        while let Some((index, line)) = lines.next() {
+            let mut line = strip_comment(line).trim().to_string();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+                table = name.trim().to_string();
+                continue;
+            }
+            // A list continues up to its closing bracket.
+            while line.contains('[') && !line.ends_with(']') {
+                let Some((_, next)) = lines.next() else { break };
+                line.push(' ');
+                line.push_str(strip_comment(next).trim());
+            }
+            let error = |message: &str| format!("line {}: {}", index + 1, message);
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error("expected 'key = value'"));
+            };
+            let key = key.trim().trim_matches('"').to_string();
+            if table == "package" && key == "version" {
+                let Some(version) = parse_string(value.trim()) else {
+                    return Err(error("version must be a string"));
+                };
+                manifest.version = Some(version);
+                continue;
+            }
+            if table == "build" {
+                let hook = match key.as_str() {
+                    "pre" => &mut manifest.build.pre,
+                    "post" => &mut manifest.build.post,
+                    _ => return Err(error(&format!("unknown build hook '{}' (expected pre or post)", key))),
+                };
+                let Some(command) = parse_string(value.trim()) else {
+                    return Err(error(&format!("build hook '{}' must be a string", key)));
+                };
+                *hook = Some(command);
+                continue;
+            }
+            // Other tables are for other tools.
+            if table != "features" {
+                continue;
+            }
+            let Some(enables) = parse_list(value.trim()) else {
+                return Err(error(&format!("feature '{}' must be a list of feature names", key)));
+            };
+            manifest.features.insert(key, enables);
+        }
+
+        for (feature, enables) in &manifest.features {
+            if let Some(unknown) = enables.iter().find(|name| !manifest.features.contains_key(*name)) {
+                return Err(format!("feature '{}' enables unknown feature '{}'", feature, unknown));
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// The features a build with `requested` enables, including what they
+    /// enable in turn, plus `default` unless `default_features` is false.
+    pub fn resolve(&self, requested: &[String], default_features: bool) -> Result<BTreeSet<String>, String> {
+        let mut pending: Vec<&str> = requested.iter().map(String::as_str).collect();
+        if default_features && self.features.contains_key("default") {
+            pending.push("default");
+        }
+        let mut enabled = BTreeSet::new();
+        while let Some(name) = pending.pop() {
+            let Some(enables) = self.features.get(name) else {
+                return Err(format!("Unknown feature '{}' (not in [features] of {})", name, FILE_NAME));
+            };
+            if enabled.insert(name.to_string()) {
+                pending.extend(enables.iter().map(String::as_str));
+            }
+        }
+        // `default` is a set of features rather than one to test for.
+        enabled.remove("default");
+        Ok(enabled)
+    }
+}
+
+/// The manifest of the project `inputs` belong to: the nearest `verve.toml`
+/// in the directory of the first source file or above it. Sources read from
+/// stdin start the search at the working directory.
+pub fn locate(inputs: &[PathBuf]) -> Option<PathBuf> {
+    let first = inputs.iter().find(|input| input.as_os_str() != "-");
+    let start = match first.and_then(|input| input.parent()) {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => std::env::current_dir().ok()?,
+    };
+    start.ancestors().map(|dir| dir.join(FILE_NAME)).find(|path| path.is_file())
+}
+
+/// The command running the build hook `script` with the platform's shell,
+/// in `dir`.
+pub fn hook_command(script: &str, dir: &Path) -> std::process::Command {
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = std::process::Command::new("cmd");
+        command.arg("/C");
+        command
+    } else {
+        let mut command = std::process::Command::new("sh");
+        command.arg("-c");
+        command
+    };
+    command.arg(script).current_dir(dir);
+    command
+}
+
+/// Reads and parses the manifest at `path`.
+pub fn load(path: &Path) -> Result<Manifest, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+    Manifest::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_list(text: &str) -> Option<Vec<String>> {
+    let items = text.strip_prefix('[')?.strip_suffix(']')?;
+    items.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(parse_string)
+        .collect()
+}
+
+fn parse_string(text: &str) -> Option<String> {
+    let inner = text.strip_prefix('"')?.strip_suffix('"')?;
+    (!inner.contains('"')).then(|| inner.to_string())
+}
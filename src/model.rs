@@ -0,0 +1,263 @@
+//! The semantic model of a checked program, for tools that generate code
+//! from Verve declarations: bindings, protocol stubs, database mappings.
+//! `Compiler::model` builds it once the program type checks. It describes
+//! the program's types, functions and globals in types of its own rather
+//! than the AST's, which change as the compiler does, and types refer to
+//! each other by [`TypeId`] instead of by name.
+//!
+//! Each kind of item is numbered in name order, so the ids depend on which
+//! items there are and not on the order they are declared in or the files
+//! they come from. The model includes the prelude and standard library
+//! items the program uses; their [`Location`] names the library's file.
+
+use crate::ast::{self, AttrArg, Program, Stmt};
+use codespan::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TypeId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FunctionId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GlobalId(pub u32);
+
+/// The type of a value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeRef {
+    I32,
+    Bool,
+    String,
+    Void,
+    RawPtr,
+    Arena,
+    Pointer(Box<TypeRef>),
+    Option(Box<TypeRef>),
+    Tuple(Vec<TypeRef>),
+    Function { params: Vec<TypeRef>, result: Box<TypeRef>, call_conv: &'static str },
+    /// A struct, union or enum of the program.
+    Named(TypeId),
+}
+
+/// Where an item is declared: the source file, as it was added to the
+/// compiler, and the 1-based line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+}
+
+/// An attribute on an item, its arguments as written: `#[align(16)]` has
+/// the argument `16` and `#[cfg(feature = "json")]` `feature = "json"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attribute {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub name: String,
+    pub ty: TypeRef,
+    /// The width of a bitfield member.
+    pub bits: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Variant {
+    pub name: String,
+    pub fields: Vec<TypeRef>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeKind {
+    Struct(Vec<Field>),
+    Union(Vec<Field>),
+    Enum(Vec<Variant>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeDecl {
+    pub id: TypeId,
+    pub name: String,
+    pub kind: TypeKind,
+    pub attributes: Vec<Attribute>,
+    pub location: Option<Location>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionDecl {
+    pub id: FunctionId,
+    pub name: String,
+    pub params: Vec<(String, TypeRef)>,
+    pub result: TypeRef,
+    /// Whether other C translation units can call the function.
+    pub exported: bool,
+    /// Whether the function is only declared, to be linked in.
+    pub is_extern: bool,
+    /// The C symbol, when `#[export_name]` or `#[no_mangle]` fixes it.
+    pub symbol: Option<String>,
+    pub call_conv: &'static str,
+    pub attributes: Vec<Attribute>,
+    pub location: Option<Location>,
+}
+
+/// A top-level `let`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlobalDecl {
+    pub id: GlobalId,
+    pub name: String,
+    pub ty: TypeRef,
+    pub location: Option<Location>,
+}
+
+/// The declarations of a checked program, each list in id order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Model {
+    pub types: Vec<TypeDecl>,
+    pub functions: Vec<FunctionDecl>,
+    pub globals: Vec<GlobalDecl>,
+}
+
+impl Model {
+    /// The model of the type checked `program`, with `locate` giving where
+    /// the item at a span is declared.
+    pub fn of(program: &Program, locate: impl Fn(Span) -> Option<Location>) -> Model {
+        let mut type_names: Vec<&str> = program.structs.iter().map(|def| def.name.as_str())
+            .chain(program.enums.iter().map(|def| def.name.as_str()))
+            .collect();
+        type_names.sort_unstable();
+        let resolver = Resolver { type_names };
+
+        let mut types: Vec<TypeDecl> = program.structs.iter()
+            .map(|def| {
+                let fields = def.fields.iter()
+                    .map(|field| Field { name: field.name.clone(), ty: resolver.resolve(&field.ty), bits: field.bits })
+                    .collect();
+                TypeDecl {
+                    id: resolver.id(&def.name),
+                    name: def.name.clone(),
+                    kind: if def.is_union { TypeKind::Union(fields) } else { TypeKind::Struct(fields) },
+                    attributes: attributes(&def.attrs),
+                    location: locate(def.span),
+                }
+            })
+            .chain(program.enums.iter().map(|def| TypeDecl {
+                id: resolver.id(&def.name),
+                name: def.name.clone(),
+                kind: TypeKind::Enum(def.variants.iter()
+                    .map(|variant| Variant {
+                        name: variant.name.clone(),
+                        fields: variant.fields.iter().map(|ty| resolver.resolve(ty)).collect(),
+                    })
+                    .collect()),
+                attributes: attributes(&def.attrs),
+                location: locate(def.span),
+            }))
+            .collect();
+        types.sort_by_key(|decl| decl.id);
+
+        let mut functions: Vec<&ast::Function> = program.functions.iter().collect();
+        functions.sort_by(|a, b| a.name.cmp(&b.name));
+        let functions = functions.into_iter().enumerate()
+            .map(|(i, func)| FunctionDecl {
+                id: FunctionId(i as u32),
+                name: func.name.clone(),
+                params: func.params.iter().map(|(name, ty)| (name.clone(), resolver.resolve(ty))).collect(),
+                result: resolver.resolve(&func.return_type),
+                exported: func.is_exported(),
+                is_extern: func.is_extern(),
+                symbol: func.symbol().map(String::from),
+                call_conv: func.call_conv.name(),
+                attributes: attributes(&func.attrs),
+                location: locate(func.span),
+            })
+            .collect();
+
+        let mut globals: Vec<(&String, TypeRef, Span)> = program.stmts.iter()
+            .filter_map(|stmt| match stmt {
+                Stmt::Let(name, ty, init, span) => {
+                    Some((name, resolver.resolve(ty.as_ref().unwrap_or(&init.get_type())), *span))
+                }
+                _ => None,
+            })
+            .collect();
+        globals.sort_by(|a, b| a.0.cmp(b.0));
+        let globals = globals.into_iter().enumerate()
+            .map(|(i, (name, ty, span))| GlobalDecl { id: GlobalId(i as u32), name: name.clone(), ty, location: locate(span) })
+            .collect();
+
+        Model { types, functions, globals }
+    }
+
+    pub fn type_decl(&self, id: TypeId) -> &TypeDecl {
+        &self.types[id.0 as usize]
+    }
+
+    pub fn function(&self, id: FunctionId) -> &FunctionDecl {
+        &self.functions[id.0 as usize]
+    }
+
+    pub fn global(&self, id: GlobalId) -> &GlobalDecl {
+        &self.globals[id.0 as usize]
+    }
+
+    pub fn type_named(&self, name: &str) -> Option<&TypeDecl> {
+        self.types.iter().find(|decl| decl.name == name)
+    }
+
+    pub fn function_named(&self, name: &str) -> Option<&FunctionDecl> {
+        self.functions.iter().find(|decl| decl.name == name)
+    }
+}
+
+/// Turns AST types into [`TypeRef`]s.
+struct Resolver<'a> {
+    /// The program's struct, union and enum names, sorted, so a name's
+    /// index is its id.
+    type_names: Vec<&'a str>,
+}
+
+impl Resolver<'_> {
+    fn id(&self, name: &str) -> TypeId {
+        let index = self.type_names.binary_search(&name).expect("checked programs only name types they define");
+        TypeId(index as u32)
+    }
+
+    fn resolve(&self, ty: &ast::Type) -> TypeRef {
+        match ty {
+            ast::Type::I32 => TypeRef::I32,
+            ast::Type::Bool => TypeRef::Bool,
+            ast::Type::String => TypeRef::String,
+            ast::Type::Void => TypeRef::Void,
+            ast::Type::RawPtr => TypeRef::RawPtr,
+            ast::Type::Arena => TypeRef::Arena,
+            ast::Type::Pointer(inner) => TypeRef::Pointer(Box::new(self.resolve(inner))),
+            ast::Type::Option(inner) => TypeRef::Option(Box::new(self.resolve(inner))),
+            ast::Type::Tuple(items) => TypeRef::Tuple(items.iter().map(|item| self.resolve(item)).collect()),
+            ast::Type::Function(params, result, call_conv) => TypeRef::Function {
+                params: params.iter().map(|param| self.resolve(param)).collect(),
+                result: Box::new(self.resolve(result)),
+                call_conv: call_conv.name(),
+            },
+            ast::Type::Named(name) => TypeRef::Named(self.id(name)),
+            ast::Type::Unknown => unreachable!("checked programs have no unknown types"),
+        }
+    }
+}
+
+fn attributes(attrs: &[ast::Attribute]) -> Vec<Attribute> {
+    attrs.iter()
+        .map(|attr| Attribute {
+            name: attr.name.clone(),
+            args: attr.args.iter()
+                .map(|arg| match arg {
+                    AttrArg::Ident(name) => name.clone(),
+                    AttrArg::Int(n) => n.to_string(),
+                    AttrArg::Str(s) => format!("{:?}", s),
+                    AttrArg::KeyValue(key, value) => format!("{} = {:?}", key, value),
+                })
+                .collect(),
+        })
+        .collect()
+}
@@ -1,21 +1,36 @@
-use super::{ast, lexer::{Lexer, Token}};
-use codespan::{FileId, Files, Span};
+use super::{ast, intrinsics, lexer::{Lexer, Token}};
+use codespan::{FileId, Span};
 use codespan_reporting::diagnostic::{Diagnostic, Label};
 
-pub struct Parser<'a> {
+pub struct Parser {
     tokens: Vec<(Token, Span)>,
+    /// Whether a line break separates each token from the one before it.
+    line_breaks: Vec<bool>,
     current: usize,
-    files: &'a Files<String>,
     file_id: FileId,
+    /// The start of the file, where errors at its end are reported.
+    file_start: Span,
+    no_struct_literal: bool,
 }
 
-impl<'a> Parser<'a> {
-    pub fn new(lexer: Lexer<'a>) -> Self {
+impl Parser {
+    pub fn new(lexer: Lexer<'_>) -> Self {
+        let tokens = lexer.tokens();
+        let source = lexer.files.source(lexer.file_id);
+        let line_breaks = tokens.iter()
+            .scan(lexer.base, |end, (_, span)| {
+                let gap = &source[*end - lexer.base..span.start().to_usize() - lexer.base];
+                *end = span.end().to_usize();
+                Some(gap.contains('\n'))
+            })
+            .collect();
         Self {
-            tokens: lexer.tokens(),
+            tokens,
+            line_breaks,
             current: 0,
-            files: lexer.files,
             file_id: lexer.file_id,
+            file_start: Span::new(lexer.base as u32, lexer.base as u32),
+            no_struct_literal: false,
         }
     }
 
@@ -23,11 +38,24 @@ impl<'a> Parser<'a> {
         let mut program = ast::Program {
             stmts: Vec::new(),
             functions: Vec::new(),
+            structs: Vec::new(),
+            enums: Vec::new(),
         };
 
         while !self.is_at_end() {
-            if self.check(Token::KwFn) {
-                program.functions.push(self.parse_function()?);
+            let attrs = self.parse_attributes()?;
+            if let Some(attr) = attrs.first()
+                && !self.check(Token::KwStruct) && !self.check(Token::KwUnion) && !self.at_function()
+                && !self.check(Token::KwEnum) {
+                return self.error("Attributes are only supported on functions, structs, unions and enums", attr.span);
+            }
+
+            if self.at_function() {
+                program.functions.push(self.parse_function(attrs)?);
+            } else if self.check(Token::KwStruct) || self.check(Token::KwUnion) {
+                program.structs.push(self.parse_struct(attrs)?);
+            } else if self.check(Token::KwEnum) {
+                program.enums.push(self.parse_enum(attrs)?);
             } else {
                 program.stmts.push(self.parse_stmt()?);
             }
@@ -37,6 +65,11 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_type(&mut self) -> Result<ast::Type, Diagnostic<FileId>> {
+        if self.at_extern() {
+            let call_conv = self.parse_call_conv()?;
+            self.expect(Token::KwFn)?;
+            return self.parse_function_type(call_conv);
+        }
         let next = self.advance().map(|(t, s)| (t.clone(), *s));
 
         match next {
@@ -48,11 +81,57 @@ impl<'a> Parser<'a> {
                 let target_type = self.parse_type()?;
                 Ok(ast::Type::Pointer(Box::new(target_type)))
             },
+            Some((Token::Ident(name), _)) if name == "Option" && self.check(Token::Lt) => {
+                self.advance();
+                let inner = self.parse_type()?;
+                self.expect(Token::Gt)?;
+                Ok(ast::Type::Option(Box::new(inner)))
+            },
+            Some((Token::KwFn, _)) => self.parse_function_type(ast::CallConv::C),
+            Some((Token::Ident(name), _)) if name == "void" => Ok(ast::Type::Void),
+            Some((Token::Ident(name), _)) => Ok(ast::Type::Named(name)),
+            Some((Token::LParen, span)) => {
+                let mut items = Vec::new();
+                while !self.check(Token::RParen) {
+                    items.push(self.parse_type()?);
+                    if !self.check(Token::Comma) {
+                        break;
+                    }
+                    self.advance();
+                }
+                self.expect(Token::RParen)?;
+                if items.len() < 2 {
+                    return self.error("Tuple types need at least two elements", span);
+                }
+                Ok(ast::Type::Tuple(items))
+            },
             Some((_, span)) => self.error("Expected type annotation", span),
-            None => self.error("Expected type annotation", Span::new(0, 0)),
+            None => self.error("Expected type annotation", self.file_start),
         }
     }
 
+    /// The rest of `fn(T, ...) -> R` after `fn`; without an arrow, `R` is
+    /// void.
+    fn parse_function_type(&mut self, call_conv: ast::CallConv) -> Result<ast::Type, Diagnostic<FileId>> {
+        self.expect(Token::LParen)?;
+        let mut params = Vec::new();
+        while !self.check(Token::RParen) {
+            params.push(self.parse_type()?);
+            if !self.check(Token::Comma) {
+                break;
+            }
+            self.advance();
+        }
+        self.expect(Token::RParen)?;
+        let ret = if self.check(Token::Arrow) {
+            self.advance();
+            self.parse_type()?
+        } else {
+            ast::Type::Void
+        };
+        Ok(ast::Type::Function(params, Box::new(ret), call_conv))
+    }
+
     fn parse_unary(&mut self) -> Result<ast::Expr, Diagnostic<FileId>> {
         if self.check(Token::Star) {
             let op_span = self.peek().map(|(_, s)| *s).unwrap();
@@ -68,14 +147,44 @@ impl<'a> Parser<'a> {
         self.expect(Token::KwReturn)?;
         let ret_span = self.previous().map(|(_, s)| *s).unwrap();
         let expr = self.parse_expr()?;
-        self.expect(Token::Semi)?;
+        self.expect_terminator()?;
         let end_span = self.previous().map(|(_, s)| *s).unwrap();
         Ok(ast::Stmt::Return(expr, Span::new(ret_span.start(), end_span.end())))
     }
 
-    fn parse_function(&mut self) -> Result<ast::Function, Diagnostic<FileId>> {
+    /// Whether a function, possibly `extern "..."`, starts here.
+    fn at_function(&self) -> bool {
+        self.check(Token::KwFn) || self.at_extern()
+    }
+
+    /// Whether `extern "convention"` starts here.
+    fn at_extern(&self) -> bool {
+        self.check(Token::Ident("extern".to_string()))
+            && matches!(self.tokens.get(self.current + 1), Some((Token::Str(_), _)))
+    }
+
+    /// `extern "convention"` before `fn`, if present.
+    fn parse_call_conv(&mut self) -> Result<ast::CallConv, Diagnostic<FileId>> {
+        if !self.at_extern() {
+            return Ok(ast::CallConv::C);
+        }
+        self.advance();
+        match self.advance().cloned() {
+            Some((Token::Str(name), span)) => match ast::CallConv::from_name(&name) {
+                Some(call_conv) => Ok(call_conv),
+                None => self.error(
+                    &format!("Unknown calling convention '{}'; expected \"{}\"", name, ast::CallConv::NAMES.join("\" or \"")),
+                    span,
+                ),
+            },
+            _ => unreachable!("at_extern saw the string"),
+        }
+    }
+
+    fn parse_function(&mut self, attrs: Vec<ast::Attribute>) -> Result<ast::Function, Diagnostic<FileId>> {
+        let start_span = self.peek().map(|(_, s)| *s).unwrap_or(self.file_start);
+        let call_conv = self.parse_call_conv()?;
         self.expect(Token::KwFn)?;
-        let start_span = self.previous().map(|(_, s)| *s).unwrap();
 
         let token = self.advance().cloned();
         let (name, _name_span) = match token.as_ref() {
@@ -84,24 +193,26 @@ impl<'a> Parser<'a> {
                 return self.error("Expected function name", *span);
             }
             None => {
-                return self.error("Expected function name", Span::new(0, 0));
+                return self.error("Expected function name", self.file_start);
             }
         };
 
         self.expect(Token::LParen)?;
         let mut params = Vec::new();
+        let mut param_spans = Vec::new();
         while !self.check(Token::RParen) {
             let token = self.advance().cloned();
 
-            let (param_name, _param_span) = match token.as_ref() {
+            let (param_name, param_span) = match token.as_ref() {
                 Some((Token::Ident(name), span)) => (name.clone(), *span),
                 Some((_, span)) => return self.error("Expected parameter name", *span),
-                None => return self.error("Expected parameter name", Span::new(0, 0)),
+                None => return self.error("Expected parameter name", self.file_start),
             };
 
             self.expect(Token::Colon)?;
             let param_type = self.parse_type()?;
             params.push((param_name, param_type));
+            param_spans.push(param_span);
 
             if !self.check(Token::Comma) {
                 break;
@@ -128,8 +239,147 @@ impl<'a> Parser<'a> {
         Ok(ast::Function {
             name,
             params,
+            param_spans,
             return_type,
             body,
+            attrs,
+            call_conv,
+            span: Span::new(start_span.start(), end_span.end()),
+        })
+    }
+
+    fn parse_attributes(&mut self) -> Result<Vec<ast::Attribute>, Diagnostic<FileId>> {
+        let mut attrs = Vec::new();
+        while self.check(Token::Hash) {
+            self.advance();
+            let start_span = self.previous().map(|(_, s)| *s).unwrap();
+            self.expect(Token::LBracket)?;
+            let (name, _) = self.expect_ident("Expected attribute name")?;
+            let mut args = Vec::new();
+            if self.check(Token::LParen) {
+                self.advance();
+                while !self.check(Token::RParen) {
+                    let arg = match self.advance().cloned() {
+                        Some((Token::Ident(key), _)) if self.check(Token::Eq) => {
+                            self.advance();
+                            match self.advance().cloned() {
+                                Some((Token::Str(value), _)) => ast::AttrArg::KeyValue(key, value),
+                                Some((_, span)) => return self.error("Expected a string value", span),
+                                None => return self.error("Expected a string value", self.file_start),
+                            }
+                        }
+                        Some((Token::Ident(ident), _)) => ast::AttrArg::Ident(ident),
+                        Some((Token::Int(n), _)) => ast::AttrArg::Int(n),
+                        Some((Token::Str(value), _)) => ast::AttrArg::Str(value),
+                        Some((_, span)) => return self.error("Expected attribute argument", span),
+                        None => return self.error("Expected attribute argument", self.file_start),
+                    };
+                    args.push(arg);
+                    if !self.check(Token::Comma) {
+                        break;
+                    }
+                    self.advance();
+                }
+                self.expect(Token::RParen)?;
+            }
+            self.expect(Token::RBracket)?;
+            let end_span = self.previous().map(|(_, s)| *s).unwrap();
+            attrs.push(ast::Attribute {
+                name,
+                args,
+                span: Span::new(start_span.start(), end_span.end()),
+            });
+        }
+        Ok(attrs)
+    }
+
+    fn parse_struct(&mut self, attrs: Vec<ast::Attribute>) -> Result<ast::StructDef, Diagnostic<FileId>> {
+        let is_union = self.check(Token::KwUnion);
+        self.advance();
+        let start_span = self.previous().map(|(_, s)| *s).unwrap();
+        let name = self.expect_ident(if is_union { "Expected union name" } else { "Expected struct name" })?.0;
+
+        self.expect(Token::LBrace)?;
+        let mut fields = Vec::new();
+        while !self.check(Token::RBrace) {
+            let (field_name, field_span) = self.expect_ident("Expected field name")?;
+            self.expect(Token::Colon)?;
+            let ty = self.parse_type()?;
+            let bits = if self.check(Token::Colon) {
+                self.advance();
+                match self.advance().cloned() {
+                    Some((Token::Int(n), _)) => Some(n as u32),
+                    Some((_, span)) => return self.error("Expected bitfield width", span),
+                    None => return self.error("Expected bitfield width", self.file_start),
+                }
+            } else {
+                None
+            };
+            let end_span = self.previous().map(|(_, s)| *s).unwrap();
+            fields.push(ast::Field {
+                name: field_name,
+                ty,
+                bits,
+                span: Span::new(field_span.start(), end_span.end()),
+            });
+
+            if !self.check(Token::Comma) {
+                break;
+            }
+            self.advance();
+        }
+        self.expect(Token::RBrace)?;
+
+        let end_span = self.previous().map(|(_, s)| *s).unwrap();
+        Ok(ast::StructDef {
+            name,
+            fields,
+            is_union,
+            attrs,
+            span: Span::new(start_span.start(), end_span.end()),
+        })
+    }
+
+    fn parse_enum(&mut self, attrs: Vec<ast::Attribute>) -> Result<ast::EnumDef, Diagnostic<FileId>> {
+        self.expect(Token::KwEnum)?;
+        let start_span = self.previous().map(|(_, s)| *s).unwrap();
+        let name = self.expect_ident("Expected enum name")?.0;
+
+        self.expect(Token::LBrace)?;
+        let mut variants = Vec::new();
+        while !self.check(Token::RBrace) {
+            let (variant_name, variant_span) = self.expect_ident("Expected variant name")?;
+            let mut fields = Vec::new();
+            if self.check(Token::LParen) {
+                self.advance();
+                while !self.check(Token::RParen) {
+                    fields.push(self.parse_type()?);
+                    if !self.check(Token::Comma) {
+                        break;
+                    }
+                    self.advance();
+                }
+                self.expect(Token::RParen)?;
+            }
+            let end_span = self.previous().map(|(_, s)| *s).unwrap();
+            variants.push(ast::Variant {
+                name: variant_name,
+                fields,
+                span: Span::new(variant_span.start(), end_span.end()),
+            });
+
+            if !self.check(Token::Comma) {
+                break;
+            }
+            self.advance();
+        }
+        self.expect(Token::RBrace)?;
+
+        let end_span = self.previous().map(|(_, s)| *s).unwrap();
+        Ok(ast::EnumDef {
+            name,
+            variants,
+            attrs,
             span: Span::new(start_span.start(), end_span.end()),
         })
     }
@@ -137,7 +387,16 @@ impl<'a> Parser<'a> {
     fn parse_stmt(&mut self) -> Result<ast::Stmt, Diagnostic<FileId>> {
         if self.check(Token::KwLet) {
             self.advance();
-            self.parse_let(true)
+            let destructures = self.check(Token::LParen)
+                || (matches!(self.peek(), Some((Token::Ident(_), _)))
+                    && matches!(self.tokens.get(self.current + 1), Some((Token::LBrace, _))));
+            if destructures {
+                self.parse_let_pattern()
+            } else {
+                self.parse_let(true)
+            }
+        } else if self.check(Token::KwStatic) {
+            self.parse_static()
         } else if self.check(Token::KwIf) {
             self.parse_if()
         } else if self.check(Token::KwReturn) {
@@ -148,6 +407,12 @@ impl<'a> Parser<'a> {
           self.parse_while()
         } else if self.check(Token::KwFor) {
             self.parse_for()
+        } else if self.check(Token::KwBreak) || self.check(Token::KwContinue) {
+            self.parse_jump()
+        } else if self.check(Token::KwMatch) {
+            self.parse_match()
+        } else if self.check(Token::LBrace) {
+            self.parse_block_stmt()
         } else {
             let expr = self.parse_expr()?;
             let span = expr.span();
@@ -156,11 +421,32 @@ impl<'a> Parser<'a> {
         }
     }
 
+    /// A block in statement position; it needs no value at the end.
+    fn parse_block_stmt(&mut self) -> Result<ast::Stmt, Diagnostic<FileId>> {
+        self.expect(Token::LBrace)?;
+        let start_span = self.previous().map(|(_, s)| *s).unwrap();
+        let mut stmts = Vec::new();
+        while !self.check(Token::RBrace) {
+            stmts.push(self.parse_stmt()?);
+        }
+        self.expect(Token::RBrace)?;
+        let end_span = self.previous().map(|(_, s)| *s).unwrap();
+        Ok(ast::Stmt::Block(stmts, Span::new(start_span.start(), end_span.end())))
+    }
+
+    /// `break` or `continue`.
+    fn parse_jump(&mut self) -> Result<ast::Stmt, Diagnostic<FileId>> {
+        let (token, span) = self.advance().cloned().unwrap();
+        self.expect_terminator()?;
+        let span = Span::new(span.start(), self.previous().unwrap().1.end());
+        Ok(if token == Token::KwBreak { ast::Stmt::Break(span) } else { ast::Stmt::Continue(span) })
+    }
+
     fn parse_defer(&mut self) -> Result<ast::Stmt, Diagnostic<FileId>> {
         self.expect(Token::KwDefer)?;
         let start_span = self.previous().map(|(_, s)| *s).unwrap();
         let expr = self.parse_expr()?;
-        self.expect(Token::Semi)?;
+        self.expect_terminator()?;
         let end_span = self.previous().map(|(_, s)| *s).unwrap();
         Ok(ast::Stmt::Defer(expr, Span::new(start_span.start(), end_span.end())))
     }
@@ -170,7 +456,8 @@ impl<'a> Parser<'a> {
         self.expect(Token::KwWhile)?;
         let while_span = self.previous().map(|(_, s)| *s).unwrap();
         
-        let condition = self.parse_expr()?;
+        let pattern = self.parse_let_head()?;
+        let condition = self.parse_condition()?;
 
         self.expect(Token::LBrace)?;
         let mut body = Vec::new();
@@ -179,25 +466,28 @@ impl<'a> Parser<'a> {
         }
         self.expect(Token::RBrace)?;
 
-        Ok(ast::Stmt::While(
-            condition,
-            body,
-            Span::new(while_span.start(), self.previous().unwrap().1.end()),
-        ))
+        let span = Span::new(while_span.start(), self.previous().unwrap().1.end());
+        Ok(match pattern {
+            Some(pattern) => ast::Stmt::WhileLet(pattern, condition, body, span),
+            None => ast::Stmt::While(condition, body, span),
+        })
     }
 
     fn parse_for(&mut self) -> Result<ast::Stmt, Diagnostic<FileId>> {
         self.expect(Token::KwFor)?;
         let for_span = self.previous().map(|(_, s)| *s).unwrap();
         
-        let (ident, ident_span) = match self.advance().cloned() {
-            Some((Token::Ident(name), span)) => (name, span),
-            _ => return self.error("Expected identifier after 'for'", Span::new(0, 0)),
-        };
+        if !matches!(self.peek(), Some((Token::Ident(_) | Token::LParen, _))) {
+            return self.error("Expected identifier after 'for'", self.file_start);
+        }
+        let pattern = self.parse_pattern()?;
+        if !pattern.is_irrefutable() {
+            return self.error("Refutable pattern in 'for'", pattern.span());
+        }
         
         self.expect(Token::KwIn)?;
         
-        let range_expr = self.parse_expr()?;
+        let range_expr = self.parse_condition()?;
         
         self.expect(Token::LBrace)?;
         let mut body = Vec::new();
@@ -206,12 +496,11 @@ impl<'a> Parser<'a> {
         }
         self.expect(Token::RBrace)?;
         
-        Ok(ast::Stmt::For(
-            ident,
-            range_expr,
-            body,
-            Span::new(for_span.start(), self.previous().unwrap().1.end()),
-        ))
+        let span = Span::new(for_span.start(), self.previous().unwrap().1.end());
+        Ok(match pattern {
+            ast::Pattern::Bind(name, _) => ast::Stmt::For(name, range_expr, body, span),
+            pattern => ast::Stmt::ForPattern(pattern, range_expr, body, span),
+        })
     }
 
     fn parse_print(&mut self) -> Result<ast::Stmt, Diagnostic<FileId>> {
@@ -220,7 +509,7 @@ impl<'a> Parser<'a> {
         self.expect(Token::LParen)?;
         let expr = self.parse_expr()?;
         self.expect(Token::RParen)?;
-        self.expect(Token::Semi)?;
+        self.expect_terminator()?;
         let end_span = self.previous().map(|(_, s)| *s).unwrap();
         Ok(ast::Stmt::Expr(
             ast::Expr::Print(Box::new(expr), Span::new(start_span.start(), end_span.end()), ast::Type::Void),
@@ -231,36 +520,65 @@ impl<'a> Parser<'a> {
     fn parse_if(&mut self) -> Result<ast::Stmt, Diagnostic<FileId>> {
         self.expect(Token::KwIf)?;
         let if_span = self.previous().map(|(_, s)| *s).unwrap();
-        let condition = self.parse_expr()?;
+        let pattern = self.parse_let_head()?;
+        let condition = self.parse_condition()?;
         self.expect(Token::LBrace)?;
         let mut then_branch = Vec::new();
         while !self.check(Token::RBrace) {
             then_branch.push(self.parse_stmt()?);
         }
         self.expect(Token::RBrace)?;
-        let then_end = self.previous().map(|(_, s)| *s).unwrap();
 
         let mut else_branch = None;
-        let else_span = if self.check(Token::KwElse) {
+        if self.check(Token::KwElse) {
             self.advance();
-            self.expect(Token::LBrace)?;
-            let mut else_body = Vec::new();
-            while !self.check(Token::RBrace) {
-                else_body.push(self.parse_stmt()?);
-            }
-            self.expect(Token::RBrace)?;
+            // `else if` is an else branch holding just the next `if`.
+            let else_body = if self.check(Token::KwIf) {
+                vec![self.parse_if()?]
+            } else {
+                self.expect(Token::LBrace)?;
+                let mut else_body = Vec::new();
+                while !self.check(Token::RBrace) {
+                    else_body.push(self.parse_stmt()?);
+                }
+                self.expect(Token::RBrace)?;
+                else_body
+            };
             else_branch = Some(else_body);
-            self.previous().map(|(_, s)| *s).unwrap()
-        } else {
-            then_end
-        };
+        }
+        let end = self.previous().map(|(_, s)| *s).unwrap();
 
-        Ok(ast::Stmt::If(
-            condition,
-            then_branch,
-            else_branch,
-            Span::new(if_span.start(), else_span.end()),
-        ))
+        let span = Span::new(if_span.start(), end.end());
+        Ok(match pattern {
+            Some(pattern) => ast::Stmt::IfLet(pattern, condition, then_branch, else_branch, span),
+            None => ast::Stmt::If(condition, then_branch, else_branch, span),
+        })
+    }
+
+    /// Parses the `let pattern =` of `if let` and `while let`, if present.
+    fn parse_let_head(&mut self) -> Result<Option<ast::Pattern>, Diagnostic<FileId>> {
+        if !self.check(Token::KwLet) {
+            return Ok(None);
+        }
+        self.advance();
+        let pattern = self.parse_pattern()?;
+        self.expect(Token::Eq)?;
+        Ok(Some(pattern))
+    }
+
+    fn parse_static(&mut self) -> Result<ast::Stmt, Diagnostic<FileId>> {
+        self.expect(Token::KwStatic)?;
+        let start_span = self.previous().map(|(_, s)| *s).unwrap();
+        self.expect(Token::KwLet)?;
+        match self.parse_let(true)? {
+            ast::Stmt::Let(name, ty, expr, span) => Ok(ast::Stmt::Static(
+                name,
+                ty,
+                expr,
+                Span::new(start_span.start(), span.end()),
+            )),
+            _ => unreachable!(),
+        }
     }
 
     fn parse_let(&mut self, expect_semi: bool) -> Result<ast::Stmt, Diagnostic<FileId>> {
@@ -269,7 +587,7 @@ impl<'a> Parser<'a> {
         let (ident, _) = match token.as_ref() {
             Some((Token::Ident(name), span)) => (name.clone(), *span),
             Some((_, span)) => return self.error("Expected identifier", *span),
-            None => return self.error("Expected identifier", Span::new(0, 0)),
+            None => return self.error("Expected identifier", self.file_start),
         };
 
         let type_annot = if self.check(Token::Colon) {
@@ -282,7 +600,7 @@ impl<'a> Parser<'a> {
         self.expect(Token::Eq)?;
         let expr = self.parse_expr()?;
         if expect_semi {
-            self.expect(Token::Semi)?;
+            self.expect_terminator()?;
         }
         let end_span = if expect_semi {
             self.previous().map(|(_, s)| *s).unwrap()
@@ -297,10 +615,154 @@ impl<'a> Parser<'a> {
         ))
     }
 
+    fn parse_let_pattern(&mut self) -> Result<ast::Stmt, Diagnostic<FileId>> {
+        let let_span = self.previous().map(|(_, s)| *s).unwrap();
+        let pattern = self.parse_pattern()?;
+        if !pattern.is_irrefutable() {
+            return self.error("Refutable pattern in 'let'; use 'match' or 'if let'", pattern.span());
+        }
+        self.expect(Token::Eq)?;
+        let expr = self.parse_expr()?;
+        self.expect_terminator()?;
+        let end_span = self.previous().map(|(_, s)| *s).unwrap();
+        Ok(ast::Stmt::LetPattern(pattern, expr, Span::new(let_span.start(), end_span.end())))
+    }
+
+    /// Parses `match scrutinee { case pattern if guard: stmts ... }`. A case
+    /// body runs up to the next `case` or the closing brace.
+    fn parse_match(&mut self) -> Result<ast::Stmt, Diagnostic<FileId>> {
+        self.expect(Token::KwMatch)?;
+        let match_span = self.previous().map(|(_, s)| *s).unwrap();
+        let scrutinee = self.parse_condition()?;
+        self.expect(Token::LBrace)?;
+
+        let mut arms = Vec::new();
+        while self.check(Token::KwCase) {
+            self.advance();
+            let case_span = self.previous().map(|(_, s)| *s).unwrap();
+            let pattern = self.parse_pattern()?;
+            let guard = if self.check(Token::KwIf) {
+                self.advance();
+                Some(self.parse_expr()?)
+            } else {
+                None
+            };
+            self.expect(Token::Colon)?;
+
+            let mut body = Vec::new();
+            while !self.check(Token::KwCase) && !self.check(Token::RBrace) && !self.is_at_end() {
+                body.push(self.parse_stmt()?);
+            }
+            let end_span = self.previous().map(|(_, s)| *s).unwrap();
+            arms.push(ast::MatchArm {
+                pattern,
+                guard,
+                body,
+                span: Span::new(case_span.start(), end_span.end()),
+            });
+        }
+        if !self.check(Token::RBrace) {
+            let span = self.peek().map(|(_, s)| *s).unwrap_or(self.file_start);
+            return self.error("Expected 'case' or '}' in match", span);
+        }
+        self.advance();
+        let end_span = self.previous().map(|(_, s)| *s).unwrap();
+
+        Ok(ast::Stmt::Match(
+            scrutinee,
+            arms,
+            Span::new(match_span.start(), end_span.end()),
+            ast::Type::Unknown,
+        ))
+    }
+
+    fn parse_patterns(&mut self, close: Token) -> Result<Vec<ast::Pattern>, Diagnostic<FileId>> {
+        let mut items = Vec::new();
+        while !self.check(close.clone()) {
+            items.push(self.parse_pattern()?);
+            if !self.check(Token::Comma) {
+                break;
+            }
+            self.advance();
+        }
+        self.expect(close)?;
+        Ok(items)
+    }
+
+    /// Parses `_`, `name`, a literal, `(p, q, ...)`, `Name { field, field: p, ... }`
+    /// or a variant `Enum::Variant(p, ...)` / `Variant(p, ...)`.
+    fn parse_pattern(&mut self) -> Result<ast::Pattern, Diagnostic<FileId>> {
+        let token = self.advance().cloned();
+        match token {
+            Some((Token::Int(n), span)) => Ok(ast::Pattern::Int(n, span)),
+            Some((Token::Str(value), span)) => Ok(ast::Pattern::Str(value, span)),
+            Some((Token::Ident(name), span)) if name == "_" => Ok(ast::Pattern::Wildcard(span)),
+            Some((Token::Ident(name), span)) if name == "true" || name == "false" => {
+                Ok(ast::Pattern::Bool(name == "true", span))
+            },
+            Some((Token::Ident(name), start_span)) if self.check(Token::ColonColon) => {
+                self.advance();
+                let (variant, variant_span) = self.expect_ident("Expected variant name after '::'")?;
+                let args = if self.check(Token::LParen) {
+                    self.advance();
+                    self.parse_patterns(Token::RParen)?
+                } else {
+                    Vec::new()
+                };
+                let end_span = self.previous().map(|(_, s)| *s).unwrap_or(variant_span);
+                Ok(ast::Pattern::Variant(Some(name), variant, args, Span::new(start_span.start(), end_span.end())))
+            },
+            Some((Token::Ident(name), start_span)) if self.check(Token::LParen) => {
+                self.advance();
+                let args = self.parse_patterns(Token::RParen)?;
+                let end_span = self.previous().map(|(_, s)| *s).unwrap();
+                Ok(ast::Pattern::Variant(None, name, args, Span::new(start_span.start(), end_span.end())))
+            },
+            Some((Token::LParen, start_span)) => {
+                let items = self.parse_patterns(Token::RParen)?;
+                let end_span = self.previous().map(|(_, s)| *s).unwrap();
+                Ok(ast::Pattern::Tuple(items, Span::new(start_span.start(), end_span.end())))
+            },
+            Some((Token::Ident(name), start_span)) if self.check(Token::LBrace) => {
+                self.advance();
+                let mut fields = Vec::new();
+                while !self.check(Token::RBrace) {
+                    let (field, field_span) = self.expect_ident("Expected field name")?;
+                    let pattern = if self.check(Token::Colon) {
+                        self.advance();
+                        self.parse_pattern()?
+                    } else {
+                        ast::Pattern::Bind(field.clone(), field_span)
+                    };
+                    fields.push((field, pattern));
+                    if !self.check(Token::Comma) {
+                        break;
+                    }
+                    self.advance();
+                }
+                self.expect(Token::RBrace)?;
+                let end_span = self.previous().map(|(_, s)| *s).unwrap();
+                Ok(ast::Pattern::Struct(name, fields, Span::new(start_span.start(), end_span.end())))
+            },
+            Some((Token::Ident(name), span)) => Ok(ast::Pattern::Bind(name, span)),
+            Some((_, span)) => self.error("Expected pattern", span),
+            None => self.error("Expected pattern", self.file_start),
+        }
+    }
+
     fn parse_expr(&mut self) -> Result<ast::Expr, Diagnostic<FileId>> {
         self.parse_assignment()
     }
 
+    /// Parses the head of an `if`/`while`/`for`, where `Name {` opens the
+    /// body rather than a struct literal.
+    fn parse_condition(&mut self) -> Result<ast::Expr, Diagnostic<FileId>> {
+        let old = std::mem::replace(&mut self.no_struct_literal, true);
+        let expr = self.parse_expr();
+        self.no_struct_literal = old;
+        expr
+    }
+
     fn parse_assignment(&mut self) -> Result<ast::Expr, Diagnostic<FileId>> {
         let expr = self.parse_equality()?;
         if self.check(Token::Eq) {
@@ -384,8 +846,19 @@ impl<'a> Parser<'a> {
     fn parse_primary(&mut self) -> Result<ast::Expr, Diagnostic<FileId>> {
         let mut expr = self.parse_atom()?;
         loop {
-            if self.check(Token::DotDot) {
-                let dotdot_span = self.peek().unwrap().1;
+            if self.check(Token::Dot) {
+                self.advance();
+                let (field, field_span) = match self.peek().cloned() {
+                    Some((Token::Int(index), span)) => {
+                        self.advance();
+                        (index.to_string(), span)
+                    },
+                    _ => self.expect_ident("Expected field name after '.'")?,
+                };
+                let span = Span::new(expr.span().start(), field_span.end());
+                expr = ast::Expr::Field(Box::new(expr), field, span, ast::Type::Unknown);
+            } else if self.check(Token::DotDot) {
+                let _dotdot_span = self.peek().unwrap().1;
                 self.advance();
                 let end = self.parse_atom()?;
                 let span = Span::new(expr.span().start(), end.span().end());
@@ -412,27 +885,70 @@ impl<'a> Parser<'a> {
             },
             Some((Token::Str(value), span)) => Ok(ast::Expr::Str(value, span, ast::Type::String)),
             Some((Token::Ident(name), span)) => {
-                if self.check(Token::LParen) {
+                if self.check(Token::ColonColon) {
+                    self.parse_enum_variant(name, span)
+                } else if name == "cfg" && self.check(Token::LParen) {
+                    self.parse_cfg(span)
+                } else if (name == "include_str" || name == "include_bytes") && self.check(Token::LParen) {
+                    self.parse_include(name, span)
+                } else if intrinsics::NAMESPACES.iter().any(|(namespace, _, _)| *namespace == name)
+                    && self.check(Token::Dot)
+                    && matches!(self.tokens.get(self.current + 2), Some((Token::LParen, _))) {
+                    self.parse_namespace_call(name)
+                } else if self.check(Token::LParen) && !self.line_breaks[self.current] {
+                    // `(` at the start of a line begins the next statement
+                    // instead of calling the name that ended the last one.
                     self.parse_function_call(name, span)
+                } else if self.check(Token::LBrace) && !self.no_struct_literal {
+                    self.parse_struct_literal(name, span)
                 } else {
                     Ok(ast::Expr::Var(name, span, ast::Type::Unknown))
                 }
             },
-            Some((Token::LParen, _)) => {
-                let expr = self.parse_expr()?;
-                self.expect(Token::RParen).map_err(|e| {
-                    e.with_message("Missing closing parenthesis")
-                })?;
-                Ok(expr)
+            Some((Token::LParen, start_span)) => {
+                let old = std::mem::replace(&mut self.no_struct_literal, false);
+                let expr = self.parse_paren_contents(start_span);
+                self.no_struct_literal = old;
+                expr
             }
             Some((Token::KwSafe, span)) => {
                 self.parse_safe_block(span)
             },
+            Some((Token::KwIf, span)) => self.parse_if_expr(span),
+            Some((Token::LBrace, span)) => {
+                let old = std::mem::replace(&mut self.no_struct_literal, false);
+                let expr = self.parse_block_expr(span);
+                self.no_struct_literal = old;
+                expr
+            }
             Some((_, span)) => self.error("Expected primary expression", span),
-            None => self.error("Expected primary expression", Span::new(0, 0)),
+            None => self.error("Expected primary expression", self.file_start),
         }
     }
 
+    /// Parses a parenthesized expression or, if it contains a comma, a tuple.
+    fn parse_paren_contents(&mut self, start_span: Span) -> Result<ast::Expr, Diagnostic<FileId>> {
+        let first = self.parse_expr()?;
+        if !self.check(Token::Comma) {
+            self.expect(Token::RParen).map_err(|e| {
+                e.with_message("Missing closing parenthesis")
+            })?;
+            return Ok(first);
+        }
+
+        let mut items = vec![first];
+        while self.check(Token::Comma) {
+            self.advance();
+            if self.check(Token::RParen) {
+                break;
+            }
+            items.push(self.parse_expr()?);
+        }
+        self.expect(Token::RParen)?;
+        let end_span = self.previous().map(|(_, s)| *s).unwrap();
+        Ok(ast::Expr::Tuple(items, Span::new(start_span.start(), end_span.end()), ast::Type::Unknown))
+    }
+
     fn parse_function_call(&mut self, name: String, span: Span) -> Result<ast::Expr, Diagnostic<FileId>> {
         self.expect(Token::LParen)?;
         let mut args = Vec::new();
@@ -447,6 +963,75 @@ impl<'a> Parser<'a> {
         Ok(ast::Expr::Call(name, args, span, ast::Type::Unknown))
     }
 
+    /// `cfg(key = "value")`.
+    fn parse_cfg(&mut self, start_span: Span) -> Result<ast::Expr, Diagnostic<FileId>> {
+        self.expect(Token::LParen)?;
+        let (key, _) = self.expect_ident("Expected a key such as 'target_os' in 'cfg'")?;
+        self.expect(Token::Eq)?;
+        let value = match self.advance().cloned() {
+            Some((Token::Str(value), _)) => value,
+            Some((_, span)) => return self.error("Expected a string value in 'cfg'", span),
+            None => return self.error("Expected a string value in 'cfg'", self.file_start),
+        };
+        self.expect(Token::RParen)?;
+        let end_span = self.previous().map(|(_, s)| *s).unwrap();
+        Ok(ast::Expr::Cfg(key, value, Span::new(start_span.start(), end_span.end()), ast::Type::Unknown))
+    }
+
+    fn parse_struct_literal(&mut self, name: String, start_span: Span) -> Result<ast::Expr, Diagnostic<FileId>> {
+        self.expect(Token::LBrace)?;
+        let mut fields = Vec::new();
+        let mut base = None;
+        while !self.check(Token::RBrace) {
+            if self.check(Token::DotDot) {
+                self.advance();
+                base = Some(Box::new(self.parse_expr()?));
+                break;
+            }
+            let (field, _) = self.expect_ident("Expected field name")?;
+            self.expect(Token::Colon)?;
+            fields.push((field, self.parse_expr()?));
+            if !self.check(Token::Comma) {
+                break;
+            }
+            self.advance();
+        }
+        self.expect(Token::RBrace)?;
+        let end_span = self.previous().map(|(_, s)| *s).unwrap();
+        Ok(ast::Expr::StructLit(
+            name.clone(),
+            fields,
+            base,
+            Span::new(start_span.start(), end_span.end()),
+            ast::Type::Named(name),
+        ))
+    }
+
+    fn parse_enum_variant(&mut self, enum_name: String, start_span: Span) -> Result<ast::Expr, Diagnostic<FileId>> {
+        self.expect(Token::ColonColon)?;
+        let (variant, _) = self.expect_ident("Expected variant name after '::'")?;
+        let mut args = Vec::new();
+        if self.check(Token::LParen) {
+            self.advance();
+            while !self.check(Token::RParen) {
+                args.push(self.parse_expr()?);
+                if !self.check(Token::Comma) {
+                    break;
+                }
+                self.advance();
+            }
+            self.expect(Token::RParen)?;
+        }
+        let end_span = self.previous().map(|(_, s)| *s).unwrap();
+        Ok(ast::Expr::EnumVariant(
+            enum_name.clone(),
+            variant,
+            args,
+            Span::new(start_span.start(), end_span.end()),
+            ast::Type::Named(enum_name),
+        ))
+    }
+
     fn parse_safe_block(&mut self, start_span: Span) -> Result<ast::Expr, Diagnostic<FileId>> {
         self.expect(Token::LBrace)?;
         let mut stmts = Vec::new();
@@ -462,6 +1047,92 @@ impl<'a> Parser<'a> {
         Ok(ast::Expr::SafeBlock(stmts, Span::new(start_span.start(), end_span.end()), ast::Type::Void))
     }
 
+    /// The rest of `{ stmts value }` after the `{`. The value is the final
+    /// expression, the one not followed by a `;` before the `}`.
+    fn parse_block_expr(&mut self, start_span: Span) -> Result<ast::Expr, Diagnostic<FileId>> {
+        let mut stmts = Vec::new();
+        let value = loop {
+            match self.peek() {
+                Some((Token::RBrace, span)) => {
+                    return self.error("Expected a value at the end of the block", *span);
+                }
+                Some((Token::KwLet | Token::KwStatic | Token::KwIf | Token::KwReturn | Token::KwPrint
+                    | Token::KwWhile | Token::KwFor | Token::KwBreak | Token::KwContinue | Token::KwMatch
+                    | Token::LBrace, _)) => {
+                    stmts.push(self.parse_stmt()?);
+                    continue;
+                }
+                _ => {}
+            }
+            let expr = self.parse_expr()?;
+            if self.check(Token::RBrace) {
+                break expr;
+            }
+            self.expect_terminator()?;
+            let span = expr.span();
+            stmts.push(ast::Stmt::Expr(expr, span));
+        };
+        self.expect(Token::RBrace)?;
+        let end_span = self.previous().map(|(_, s)| *s).unwrap();
+        Ok(ast::Expr::Block(stmts, Box::new(value), Span::new(start_span.start(), end_span.end()), ast::Type::Unknown))
+    }
+
+    /// `if cond { a } else { b }` after the `if`, as an expression: the
+    /// arms are block expressions, and the else arm is required. `else if`
+    /// nests another if-expression as the else arm.
+    fn parse_if_expr(&mut self, start_span: Span) -> Result<ast::Expr, Diagnostic<FileId>> {
+        let condition = self.parse_condition()?;
+        let then_value = self.parse_arm()?;
+        if !self.check(Token::KwElse) {
+            return self.error("An if expression needs an else branch", then_value.span());
+        }
+        self.advance();
+        let else_value = match self.peek() {
+            Some((Token::KwIf, span)) => {
+                let span = *span;
+                self.advance();
+                self.parse_if_expr(span)?
+            }
+            _ => self.parse_arm()?,
+        };
+        let span = Span::new(start_span.start(), else_value.span().end());
+        Ok(ast::Expr::If(Box::new(condition), Box::new(then_value), Box::new(else_value), span, ast::Type::Unknown))
+    }
+
+    /// A `{ stmts value }` arm of an if-expression.
+    fn parse_arm(&mut self) -> Result<ast::Expr, Diagnostic<FileId>> {
+        self.expect(Token::LBrace)?;
+        let start_span = self.previous().map(|(_, s)| *s).unwrap();
+        let old = std::mem::replace(&mut self.no_struct_literal, false);
+        let arm = self.parse_block_expr(start_span);
+        self.no_struct_literal = old;
+        arm
+    }
+
+    /// `namespace.name(args)`, after the namespace, such as `build.version()`:
+    /// the `__namespace_name` intrinsic.
+    fn parse_namespace_call(&mut self, namespace: String) -> Result<ast::Expr, Diagnostic<FileId>> {
+        let (_, what, names) = intrinsics::NAMESPACES.iter().find(|(n, _, _)| *n == namespace).unwrap();
+        self.expect(Token::Dot)?;
+        let (name, span) = self.expect_ident(&format!("Expected {} name after '{}.'", what, namespace))?;
+        if !names.contains(&name.as_str()) {
+            return self.error(&format!("Unknown {} '{}': expected {}", what, name, names.join(", ")), span);
+        }
+        self.parse_intrinsic_call(format!("__{}_{}", namespace, name), span)
+    }
+
+    /// `include_str(path)` or `include_bytes(path)`, after the name. The
+    /// bytes are a prelude `List` around the `__include_bytes` intrinsic.
+    fn parse_include(&mut self, name: String, span: Span) -> Result<ast::Expr, Diagnostic<FileId>> {
+        let call = self.parse_intrinsic_call(format!("__{}", name), span)?;
+        if name == "include_str" {
+            return Ok(call);
+        }
+        let span = Span::new(span.start(), call.span().end());
+        let list = "List".to_string();
+        Ok(ast::Expr::StructLit(list.clone(), vec![("items".to_string(), call)], None, span, ast::Type::Named(list)))
+    }
+
     fn parse_intrinsic_call(&mut self, name: String, span: Span) -> Result<ast::Expr, Diagnostic<FileId>> {
         self.expect(Token::LParen)?;
         let mut args = Vec::new();
@@ -478,8 +1149,12 @@ impl<'a> Parser<'a> {
         self.peek().map(|(t, _)| t == &expected).unwrap_or(false)
     }
 
+    /// The next token, consumed, or `None` at the end of the input.
     fn advance(&mut self) -> Option<&(Token, Span)> {
-        if !self.is_at_end() { self.current += 1; }
+        if self.is_at_end() {
+            return None;
+        }
+        self.current += 1;
         self.previous()
     }
 
@@ -492,11 +1167,32 @@ impl<'a> Parser<'a> {
             self.advance();
             Ok(())
         } else {
-            let span = self.peek().map(|(_, s)| *s).unwrap_or(Span::new(0, 0));
+            let span = self.peek().map(|(_, s)| *s).unwrap_or(self.file_start);
             self.error(&format!("Expected '{:?}'", expected), span)
         }
     }
 
+    /// Consumes a statement-ending `;`, which may be left out before a line
+    /// break, a closing brace or the end of the input.
+    fn expect_terminator(&mut self) -> Result<(), Diagnostic<FileId>> {
+        let at_line_end = self.is_at_end()
+            || self.check(Token::RBrace)
+            || self.line_breaks.get(self.current).copied().unwrap_or(true);
+        if self.check(Token::Semi) || !at_line_end {
+            self.expect(Token::Semi)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn expect_ident(&mut self, msg: &str) -> Result<(String, Span), Diagnostic<FileId>> {
+        match self.advance().cloned() {
+            Some((Token::Ident(name), span)) => Ok((name, span)),
+            Some((_, span)) => self.error(msg, span),
+            None => self.error(msg, self.file_start),
+        }
+    }
+
     fn error<T>(&self, msg: &str, span: Span) -> Result<T, Diagnostic<FileId>> {
         Err(Diagnostic::error()
             .with_message(msg)
@@ -0,0 +1,133 @@
+//! The compiler for the web playground: one source in, the C it compiles to
+//! out, with nothing read from disk and no clock. The library builds for
+//! the browser with
+//!
+//! ```text
+//! cargo rustc --lib --release --target wasm32-unknown-unknown --crate-type cdylib
+//! ```
+//!
+//! and there exports `verve_compile` for JavaScript, which answers with the
+//! [`to_json`] of [`compile`]'s result.
+
+use crate::compiler::{Compiler, Stage};
+use crate::host::MemoryFs;
+use codespan::{FileId, Files};
+use codespan_reporting::diagnostic::Severity;
+
+/// The name the source is compiled under, as diagnostics show it.
+pub const SOURCE_NAME: &str = "playground.vrv";
+
+/// The target the generated C is for.
+pub const TARGET: &str = "x86_64-unknown-linux-gnu";
+
+/// A diagnostic of a failed compile, located within the source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub stage: Stage,
+    pub severity: Severity,
+    pub message: String,
+    /// The 1-based line and column of the primary label, if there is one.
+    pub location: Option<(usize, usize)>,
+}
+
+/// Compiles `source` to C, or returns every diagnostic, warnings included,
+/// if it does not compile. `include_str` and `include_bytes` find no files,
+/// and `build.timestamp()` is 0.
+pub fn compile(source: &str) -> Result<String, Vec<Diagnostic>> {
+    let mut compiler = Compiler::new()
+        .target_triple(TARGET)
+        .build_timestamp(0)
+        .file_system(MemoryFs::default());
+    let file_id = compiler.add_source(SOURCE_NAME, source);
+    let mut reported = Vec::new();
+    compiler.compile(file_id, &mut reported).map_err(|_| {
+        reported.iter().map(|(stage, diagnostic)| locate(*stage, diagnostic, compiler.files())).collect()
+    })
+}
+
+fn locate(stage: Stage, diagnostic: &codespan_reporting::diagnostic::Diagnostic<FileId>, files: &Files<String>) -> Diagnostic {
+    let location = diagnostic.labels.first()
+        .and_then(|label| files.location(label.file_id, label.range.start as u32).ok())
+        .map(|location| (location.line.to_usize() + 1, location.column.to_usize() + 1));
+    Diagnostic { stage, severity: diagnostic.severity, message: diagnostic.message.clone(), location }
+}
+
+/// `result` as JSON: `{"ok":true,"code":"..."}`, or `{"ok":false,
+/// "diagnostics":[...]}` with each diagnostic's `severity`, `message`, and
+/// `line` and `column` when it has a location.
+pub fn to_json(result: &Result<String, Vec<Diagnostic>>) -> String {
+    match result {
+        Ok(code) => format!("{{\"ok\":true,\"code\":{}}}", json_string(code)),
+        Err(diagnostics) => {
+            let diagnostics: Vec<String> = diagnostics.iter()
+                .map(|diagnostic| {
+                    let severity = match diagnostic.severity {
+                        Severity::Bug | Severity::Error => "error",
+                        Severity::Warning => "warning",
+                        Severity::Note | Severity::Help => "note",
+                    };
+                    let location = diagnostic.location
+                        .map(|(line, column)| format!(",\"line\":{},\"column\":{}", line, column))
+                        .unwrap_or_default();
+                    format!("{{\"severity\":\"{}\",\"message\":{}{}}}", severity, json_string(&diagnostic.message), location)
+                })
+                .collect();
+            format!("{{\"ok\":false,\"diagnostics\":[{}]}}", diagnostics.join(","))
+        }
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// The interface JavaScript calls. Buffers cross it as pointers into the
+/// module's memory: `verve_alloc` reserves one for the source, and
+/// `verve_compile` answers with a buffer holding the JSON's length as four
+/// little-endian bytes and then the JSON. Both are given back with
+/// `verve_free` and their full length.
+#[cfg(target_arch = "wasm32")]
+mod exports {
+    fn leak(bytes: Vec<u8>) -> *mut u8 {
+        Box::into_raw(bytes.into_boxed_slice()) as *mut u8
+    }
+
+    #[unsafe(no_mangle)]
+    pub extern "C" fn verve_alloc(len: usize) -> *mut u8 {
+        leak(vec![0; len])
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` and `len` must come from `verve_alloc` or `verve_compile`.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn verve_free(ptr: *mut u8, len: usize) {
+        drop(unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(ptr, len)) });
+    }
+
+    /// # Safety
+    ///
+    /// `ptr` must point to `len` bytes of the module's memory.
+    #[unsafe(no_mangle)]
+    pub unsafe extern "C" fn verve_compile(ptr: *const u8, len: usize) -> *mut u8 {
+        let source = String::from_utf8_lossy(unsafe { std::slice::from_raw_parts(ptr, len) });
+        let json = super::to_json(&super::compile(&source));
+        let mut bytes = (json.len() as u32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(json.as_bytes());
+        leak(bytes)
+    }
+}
@@ -0,0 +1,70 @@
+//! The prelude: functions and types every program can use without
+//! defining them, written in Verve on top of intrinsics. A program gets the
+//! prelude items it names, and the items those name in turn; an item of
+//! the program's own with the same name takes precedence. Standard library
+//! modules (see [`crate::stdlib`]) are added the same way.
+
+use crate::ast::Program;
+use crate::lexer::Token;
+use crate::log::{self, Level};
+use codespan::Span;
+use std::collections::HashSet;
+
+pub const NAME: &str = "<prelude>";
+pub const SOURCE: &str = include_str!("prelude.vrv");
+
+/// The identifiers in `tokens`.
+pub fn identifiers<'a>(tokens: impl IntoIterator<Item = &'a (Token, Span)>) -> impl Iterator<Item = &'a str> {
+    tokens.into_iter().filter_map(|(token, _)| match token {
+        Token::Ident(name) => Some(name.as_str()),
+        _ => None,
+    })
+}
+
+/// Adds the items of `prelude` that `program` uses to `program`. `prelude`
+/// holds the standard library modules too. `used` are the identifiers in
+/// the program's sources and `tokens` the prelude's own, which tell what
+/// each prelude item uses.
+pub fn add_prelude<'a>(
+    program: &mut Program,
+    prelude: Program,
+    tokens: &'a [(Token, Span)],
+    used: impl IntoIterator<Item = &'a str>,
+) {
+    let defined: HashSet<String> = program.functions.iter().map(|f| f.name.clone())
+        .chain(program.structs.iter().map(|s| s.name.clone()))
+        .chain(program.enums.iter().map(|e| e.name.clone()))
+        .collect();
+    for name in prelude.functions.iter().map(|f| &f.name).filter(|name| defined.contains(*name)) {
+        log::event!(Level::Trace, "resolve", "'{}' resolves to the program's own, over the library's", name);
+    }
+    let items: Vec<(&str, Span)> = prelude.functions.iter().map(|f| (f.name.as_str(), f.span))
+        .chain(prelude.structs.iter().map(|s| (s.name.as_str(), s.span)))
+        .chain(prelude.enums.iter().map(|e| (e.name.as_str(), e.span)))
+        .collect();
+
+    let mut needed: HashSet<&str> = used.into_iter().collect();
+    let mut wanted = vec![false; items.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (i, &(name, span)) in items.iter().enumerate() {
+            if wanted[i] || !needed.contains(name) || defined.contains(name) {
+                continue;
+            }
+            log::event!(Level::Debug, "resolve", "'{}' resolves to the library's", name);
+            wanted[i] = true;
+            changed = true;
+            let inside = tokens.iter().filter(|(_, s)| s.start() >= span.start() && s.end() <= span.end());
+            needed.extend(identifiers(inside));
+        }
+    }
+
+    let mut wanted = wanted.into_iter();
+    let functions: Vec<_> = prelude.functions.into_iter().filter(|_| wanted.next().unwrap()).collect();
+    let structs: Vec<_> = prelude.structs.into_iter().filter(|_| wanted.next().unwrap()).collect();
+    let enums: Vec<_> = prelude.enums.into_iter().filter(|_| wanted.next().unwrap()).collect();
+    program.functions.extend(functions);
+    program.structs.extend(structs);
+    program.enums.extend(enums);
+}
@@ -0,0 +1,344 @@
+//! Prints an AST back as Verve source. Parsing the output gives the same
+//! tree again, up to spans: parentheses go in where the grammar needs them,
+//! around operands of looser operators and around struct literals in
+//! conditions, where a `{` would open the body instead.
+//!
+//! The parser's output round-trips. Lowered code does not, as the nodes
+//! lowering introduces (tail calls, variant tests and reads) have no syntax;
+//! they are printed in a readable form for debugging only.
+
+use crate::ast::{AttrArg, Attribute, BinOp, CallConv, EnumDef, Expr, Function, MatchArm, Pattern, Program, Stmt, StructDef, Type};
+
+const INDENT: &str = "    ";
+
+/// Binding strength, loosest first; an operand that binds more loosely than
+/// its position allows is parenthesized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Prec {
+    Assign,
+    Equality,
+    Comparison,
+    Additive,
+    Multiplicative,
+    Unary,
+    Postfix,
+    Atom,
+}
+
+/// The whole program: structs and unions, enums, functions, then the
+/// top-level statements.
+pub fn program(program: &Program) -> String {
+    let mut items: Vec<String> = Vec::new();
+    items.extend(program.structs.iter().map(struct_def));
+    items.extend(program.enums.iter().map(enum_def));
+    items.extend(program.functions.iter().map(function));
+    if !program.stmts.is_empty() {
+        items.push(block_body(&program.stmts, 0));
+    }
+    items.join("\n")
+}
+
+fn attributes(attrs: &[Attribute]) -> String {
+    attrs.iter()
+        .map(|attr| {
+            if attr.args.is_empty() {
+                return format!("#[{}]\n", attr.name);
+            }
+            let args: Vec<String> = attr.args.iter()
+                .map(|arg| match arg {
+                    AttrArg::Ident(name) => name.clone(),
+                    AttrArg::Int(n) => n.to_string(),
+                    AttrArg::Str(value) => format!("\"{}\"", value),
+                    AttrArg::KeyValue(key, value) => format!("{} = \"{}\"", key, value),
+                })
+                .collect();
+            format!("#[{}({})]\n", attr.name, args.join(", "))
+        })
+        .collect()
+}
+
+pub fn struct_def(def: &StructDef) -> String {
+    let mut out = attributes(&def.attrs);
+    out.push_str(&format!("{} {} {{\n", if def.is_union { "union" } else { "struct" }, def.name));
+    for field in &def.fields {
+        out.push_str(&format!("{}{}: {}", INDENT, field.name, field.ty));
+        if let Some(bits) = field.bits {
+            out.push_str(&format!(" : {}", bits));
+        }
+        out.push_str(",\n");
+    }
+    out.push_str("}\n");
+    out
+}
+
+pub fn enum_def(def: &EnumDef) -> String {
+    let mut out = attributes(&def.attrs);
+    out.push_str(&format!("enum {} {{\n", def.name));
+    for variant in &def.variants {
+        out.push_str(INDENT);
+        out.push_str(&variant.name);
+        if !variant.fields.is_empty() {
+            out.push_str(&format!("({})", list(&variant.fields, |ty| ty.to_string())));
+        }
+        out.push_str(",\n");
+    }
+    out.push_str("}\n");
+    out
+}
+
+pub fn function(func: &Function) -> String {
+    let mut out = attributes(&func.attrs);
+    let params = list(&func.params, |(name, ty)| format!("{}: {}", name, ty));
+    if func.call_conv != CallConv::C {
+        out.push_str(&format!("extern \"{}\" ", func.call_conv.name()));
+    }
+    out.push_str(&format!("fn {}({})", func.name, params));
+    if func.return_type != Type::Void {
+        out.push_str(&format!(" -> {}", func.return_type));
+    }
+    out.push_str(&format!(" {{\n{}}}\n", block_body(&func.body, 1)));
+    out
+}
+
+fn list<T>(items: &[T], f: impl Fn(&T) -> String) -> String {
+    items.iter().map(f).collect::<Vec<_>>().join(", ")
+}
+
+/// The statements of a block, one per line at `depth` levels of indentation.
+fn block_body(stmts: &[Stmt], depth: usize) -> String {
+    stmts.iter().map(|s| stmt(s, depth)).collect()
+}
+
+/// `{`, the statements one level deeper, and `}` at `depth`.
+fn block(stmts: &[Stmt], depth: usize) -> String {
+    format!("{{\n{}{}}}", block_body(stmts, depth + 1), INDENT.repeat(depth))
+}
+
+/// A statement on its own line(s), indented `depth` levels.
+pub fn stmt(stmt: &Stmt, depth: usize) -> String {
+    let indent = INDENT.repeat(depth);
+    let line = match stmt {
+        Stmt::Let(name, ty, value, _) => format!("let {}{} = {};", name, annotation(ty), expr(value, depth)),
+        Stmt::Static(name, ty, value, _) => format!("static let {}{} = {};", name, annotation(ty), expr(value, depth)),
+        Stmt::Expr(value, _) => format!("{};", statement_expr(value, depth)),
+        Stmt::If(cond, then_branch, else_branch, _) => {
+            format!("if {} {}{}", condition(cond, depth), block(then_branch, depth), else_block(else_branch, depth))
+        }
+        Stmt::Return(value, _) => format!("return {};", expr(value, depth)),
+        Stmt::Defer(value, _) => format!("defer {};", expr(value, depth)),
+        Stmt::While(cond, body, _) => format!("while {} {}", condition(cond, depth), block(body, depth)),
+        Stmt::For(name, range, body, _) => {
+            format!("for {} in {} {}", name, condition(range, depth), block(body, depth))
+        }
+        Stmt::Break(_) => "break;".to_string(),
+        Stmt::Continue(_) => "continue;".to_string(),
+        Stmt::ForPattern(p, range, body, _) => {
+            format!("for {} in {} {}", pattern(p), condition(range, depth), block(body, depth))
+        }
+        Stmt::LetPattern(p, value, _) => format!("let {} = {};", pattern(p), expr(value, depth)),
+        Stmt::IfLet(p, value, then_branch, else_branch, _) => format!(
+            "if let {} = {} {}{}",
+            pattern(p),
+            condition(value, depth),
+            block(then_branch, depth),
+            else_block(else_branch, depth)
+        ),
+        Stmt::WhileLet(p, value, body, _) => {
+            format!("while let {} = {} {}", pattern(p), condition(value, depth), block(body, depth))
+        }
+        Stmt::Match(scrutinee, arms, _, _) => {
+            let arms: String = arms.iter().map(|arm| match_arm(arm, depth + 1)).collect();
+            format!("match {} {{\n{}{}}}", condition(scrutinee, depth), arms, indent)
+        }
+        Stmt::Block(stmts, _) => block(stmts, depth),
+        Stmt::Switch(value, enum_name, cases, default, _) => {
+            let inner = "    ".repeat(depth + 1);
+            let mut arms: String = cases.iter()
+                .map(|(variant, body)| format!("{}case {}::{}: {}\n", inner, enum_name, variant, block(body, depth + 1)))
+                .collect();
+            if let Some(default) = default {
+                arms.push_str(&format!("{}case _: {}\n", inner, block(default, depth + 1)));
+            }
+            format!("match {} {{\n{}{}}}", condition(value, depth), arms, indent)
+        }
+        Stmt::TailCall(args, _) => format!("/* tail call */ ({});", list(args, |arg| expr(arg, depth))),
+    };
+    format!("{}{}\n", indent, line)
+}
+
+fn annotation(ty: &Option<Type>) -> String {
+    ty.as_ref().map(|ty| format!(": {}", ty)).unwrap_or_default()
+}
+
+fn else_block(else_branch: &Option<Vec<Stmt>>, depth: usize) -> String {
+    match else_branch.as_deref() {
+        // An else branch holding just an `if` is how `else if` parses.
+        Some([nested @ (Stmt::If(..) | Stmt::IfLet(..))]) => format!(" else {}", stmt(nested, depth).trim()),
+        Some(stmts) => format!(" else {}", block(stmts, depth)),
+        None => String::new(),
+    }
+}
+
+fn match_arm(arm: &MatchArm, depth: usize) -> String {
+    let guard = arm.guard.as_ref().map(|guard| format!(" if {}", expr(guard, depth))).unwrap_or_default();
+    format!("{}case {}{}:\n{}", INDENT.repeat(depth), pattern(&arm.pattern), guard, block_body(&arm.body, depth + 1))
+}
+
+pub fn pattern(p: &Pattern) -> String {
+    match p {
+        Pattern::Wildcard(_) => "_".to_string(),
+        Pattern::Bind(name, _) => name.clone(),
+        Pattern::Int(n, _) => n.to_string(),
+        Pattern::Bool(b, _) => b.to_string(),
+        Pattern::Str(value, _) => format!("\"{}\"", value),
+        Pattern::Tuple(items, _) => format!("({})", list(items, pattern)),
+        Pattern::Struct(name, fields, _) => {
+            let fields = list(fields, |(field, p)| match p {
+                Pattern::Bind(bound, _) if bound == field => field.clone(),
+                p => format!("{}: {}", field, pattern(p)),
+            });
+            format!("{} {{ {} }}", name, fields)
+        }
+        Pattern::Variant(Some(enum_name), variant, args, _) if args.is_empty() => format!("{}::{}", enum_name, variant),
+        Pattern::Variant(Some(enum_name), variant, args, _) => format!("{}::{}({})", enum_name, variant, list(args, pattern)),
+        Pattern::Variant(None, variant, args, _) => format!("{}({})", variant, list(args, pattern)),
+    }
+}
+
+/// An expression statement or block value. A leading `{` would start a
+/// block statement there, so such expressions are parenthesized.
+fn statement_expr(e: &Expr, depth: usize) -> String {
+    let code = expr(e, depth);
+    if code.starts_with('{') || code.starts_with("if ") { format!("({})", code) } else { code }
+}
+
+/// The head of an `if`, `while`, `for` or `match`, where struct literals
+/// need parentheses.
+fn condition(e: &Expr, depth: usize) -> String {
+    Printer { depth, in_condition: true }.expr(e, Prec::Assign)
+}
+
+pub fn expr(e: &Expr, depth: usize) -> String {
+    Printer { depth, in_condition: false }.expr(e, Prec::Assign)
+}
+
+struct Printer {
+    /// Indentation of the statement the expression is in, for the blocks
+    /// inside it.
+    depth: usize,
+    in_condition: bool,
+}
+
+impl Printer {
+    /// `e` in a position that takes operators binding at least as tightly
+    /// as `min`.
+    fn expr(&self, e: &Expr, min: Prec) -> String {
+        let (code, prec) = self.unparenthesized(e);
+        if prec < min { format!("({})", code) } else { code }
+    }
+
+    /// Within parentheses or braces, struct literals are fine again. The
+    /// parentheses of calls do not count.
+    fn nested(&self) -> Printer {
+        Printer { depth: self.depth, in_condition: false }
+    }
+
+    fn args(&self, args: &[Expr]) -> String {
+        list(args, |arg| self.expr(arg, Prec::Assign))
+    }
+
+    fn unparenthesized(&self, e: &Expr) -> (String, Prec) {
+        match e {
+            Expr::Int(n, _, _) => (n.to_string(), Prec::Atom),
+            Expr::Bool(b, _, _) => (b.to_string(), Prec::Atom),
+            Expr::Str(value, _, _) => (format!("\"{}\"", value), Prec::Atom),
+            Expr::Var(name, _, _) => (name.clone(), Prec::Atom),
+            Expr::BinOp(left, op, right, _, _) => {
+                let (symbol, prec) = match op {
+                    BinOp::Eq => ("==", Prec::Equality),
+                    BinOp::Gt => (">", Prec::Comparison),
+                    BinOp::Lt => ("<", Prec::Comparison),
+                    BinOp::Add => ("+", Prec::Additive),
+                    BinOp::Sub => ("-", Prec::Additive),
+                    BinOp::Mul => ("*", Prec::Multiplicative),
+                    BinOp::Div => ("/", Prec::Multiplicative),
+                };
+                // Left-associative: only the right operand needs to bind
+                // more tightly.
+                let right_prec = match prec {
+                    Prec::Equality => Prec::Comparison,
+                    Prec::Comparison => Prec::Additive,
+                    Prec::Additive => Prec::Multiplicative,
+                    _ => Prec::Unary,
+                };
+                (format!("{} {} {}", self.expr(left, prec), symbol, self.expr(right, right_prec)), prec)
+            }
+            Expr::Assign(target, value, _, _) => {
+                (format!("{} = {}", self.expr(target, Prec::Equality), self.expr(value, Prec::Assign)), Prec::Assign)
+            }
+            Expr::Deref(inner, _, _) => (format!("*{}", self.expr(inner, Prec::Unary)), Prec::Unary),
+            Expr::Cast(inner, ty, _, _) => (format!("{} as {}", self.expr(inner, Prec::Postfix), ty), Prec::Postfix),
+            Expr::Field(base, field, _, _) => (format!("{}.{}", self.expr(base, Prec::Postfix), field), Prec::Postfix),
+            // The end of a range is a single atom; `a..b.c` reads `.c` of
+            // the range.
+            Expr::Range(start, end, _, _) => {
+                (format!("{}..{}", self.expr(start, Prec::Postfix), self.expr(end, Prec::Atom)), Prec::Postfix)
+            }
+            Expr::Call(name, args, _, _) | Expr::IntrinsicCall(name, args, _, _) => {
+                (format!("{}({})", name, self.args(args)), Prec::Atom)
+            }
+            Expr::Print(inner, _, _) => (format!("print({})", self.nested().expr(inner, Prec::Assign)), Prec::Atom),
+            Expr::EnumVariant(enum_name, variant, args, _, _) if args.is_empty() => {
+                (format!("{}::{}", enum_name, variant), Prec::Atom)
+            }
+            Expr::EnumVariant(enum_name, variant, args, _, _) => {
+                (format!("{}::{}({})", enum_name, variant, self.args(args)), Prec::Atom)
+            }
+            Expr::Tuple(items, _, _) if items.len() == 1 => (format!("({},)", self.nested().args(items)), Prec::Atom),
+            Expr::Tuple(items, _, _) => (format!("({})", self.nested().args(items)), Prec::Atom),
+            Expr::StructLit(name, fields, base, _, _) => {
+                let nested = self.nested();
+                let mut parts: Vec<String> = fields.iter()
+                    .map(|(field, value)| format!("{}: {}", field, nested.expr(value, Prec::Assign)))
+                    .collect();
+                if let Some(base) = base {
+                    parts.push(format!("..{}", nested.expr(base, Prec::Assign)));
+                }
+                let code = format!("{} {{ {} }}", name, parts.join(", "));
+                // `if S { ... }` would open the body at the `{`.
+                if self.in_condition { (format!("({})", code), Prec::Atom) } else { (code, Prec::Atom) }
+            }
+            Expr::SafeBlock(stmts, _, _) => {
+                // `defer` parses only directly in a safe block.
+                let body: String = stmts.iter().map(|s| stmt(s, self.depth + 1)).collect();
+                let code = format!("safe {{\n{}{}}}", body, INDENT.repeat(self.depth));
+                // Its statements would be parsed as still in the condition.
+                if self.in_condition { (format!("({})", code), Prec::Atom) } else { (code, Prec::Atom) }
+            }
+            Expr::Block(stmts, value, _, _) => {
+                let inner = INDENT.repeat(self.depth + 1);
+                let value = statement_expr(value, self.depth + 1);
+                let code = format!("{{\n{}{}{}\n{}}}", block_body(stmts, self.depth + 1), inner, value, INDENT.repeat(self.depth));
+                (code, Prec::Atom)
+            }
+            // Parenthesized as an operand, where `+ 1` after the else arm
+            // would read as part of it.
+            Expr::If(cond, then_value, else_value, _, _) => {
+                let code = format!(
+                    "if {} {} else {}",
+                    condition(cond, self.depth),
+                    self.nested().expr(then_value, Prec::Assign),
+                    self.nested().expr(else_value, Prec::Assign)
+                );
+                (code, Prec::Assign)
+            }
+            Expr::Cfg(key, value, _, _) => (format!("cfg({} = \"{}\")", key, value), Prec::Atom),
+            Expr::IsVariant(inner, enum_name, variant, _, _) => {
+                (format!("/* is {}::{} */ {}", enum_name, variant, self.expr(inner, Prec::Postfix)), Prec::Atom)
+            }
+            Expr::VariantField(inner, enum_name, variant, index, _, _) => {
+                (format!("/* {}::{}.{} */ {}", enum_name, variant, index, self.expr(inner, Prec::Postfix)), Prec::Atom)
+            }
+        }
+    }
+}
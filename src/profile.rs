@@ -0,0 +1,92 @@
+//! Reading and reporting the profiles written by `--instrument-functions`.
+//!
+//! An instrumented program writes `verve.prof` at exit: a header line
+//! followed by one tab-separated `name calls total_ns self_ns` line per
+//! function that ran.
+
+use std::collections::HashMap;
+
+pub const PROFILE_FILE: &str = "verve.prof";
+pub const PROFILE_HEADER: &str = "# verve profile v1";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionProfile {
+    pub name: String,
+    pub calls: u64,
+    /// Time from entry to exit, including callees.
+    pub total_ns: u64,
+    /// Time spent in the function's own body.
+    pub self_ns: u64,
+}
+
+pub fn parse(text: &str) -> Result<Vec<FunctionProfile>, String> {
+    let mut lines = text.lines();
+    if lines.next().map(str::trim) != Some(PROFILE_HEADER) {
+        return Err(format!("Not a Verve profile (expected '{}' header)", PROFILE_HEADER));
+    }
+
+    let mut profiles = Vec::new();
+    for (index, line) in lines.enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some(profile) = parse_line(line) else {
+            return Err(format!("Malformed profile line {}: '{}'", index + 2, line));
+        };
+        profiles.push(profile);
+    }
+    Ok(profiles)
+}
+
+fn parse_line(line: &str) -> Option<FunctionProfile> {
+    let [name, calls, total, self_time] = line.split('\t').collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    Some(FunctionProfile {
+        name: name.to_string(),
+        calls: calls.parse().ok()?,
+        total_ns: total.parse().ok()?,
+        self_ns: self_time.parse().ok()?,
+    })
+}
+
+/// Sums the entries for each function across runs, ordered by self time.
+pub fn aggregate(runs: &[Vec<FunctionProfile>]) -> Vec<FunctionProfile> {
+    let mut totals: HashMap<&str, FunctionProfile> = HashMap::new();
+    for profile in runs.iter().flatten() {
+        let entry = totals.entry(&profile.name).or_insert_with(|| FunctionProfile {
+            name: profile.name.clone(),
+            calls: 0,
+            total_ns: 0,
+            self_ns: 0,
+        });
+        entry.calls += profile.calls;
+        entry.total_ns += profile.total_ns;
+        entry.self_ns += profile.self_ns;
+    }
+
+    let mut profiles = totals.into_values().collect::<Vec<_>>();
+    profiles.sort_by(|a, b| b.self_ns.cmp(&a.self_ns).then_with(|| a.name.cmp(&b.name)));
+    profiles
+}
+
+/// A flat profile table, one row per function.
+pub fn report(profiles: &[FunctionProfile]) -> String {
+    let all_self: u64 = profiles.iter().map(|p| p.self_ns).sum();
+    let mut out = format!(
+        "{:>7}  {:>12}  {:>12}  {:>10}  {}\n",
+        "self %", "self ms", "total ms", "calls", "function"
+    );
+    for profile in profiles {
+        let percent = if all_self == 0 { 0.0 } else { profile.self_ns as f64 * 100.0 / all_self as f64 };
+        out.push_str(&format!(
+            "{:>6.2}%  {:>12.3}  {:>12.3}  {:>10}  {}\n",
+            percent,
+            profile.self_ns as f64 / 1e6,
+            profile.total_ns as f64 / 1e6,
+            profile.calls,
+            profile.name
+        ));
+    }
+    out
+}
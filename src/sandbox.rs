@@ -0,0 +1,106 @@
+//! Limits on the programs `verve run` and the run-pass harness execute, so
+//! a runaway loop fails instead of hanging CI. A program that outlives its
+//! timeout is killed. On Linux the address space can be capped as well,
+//! through the shell's `ulimit -v`, which makes allocations past the cap
+//! fail as they would on a smaller machine.
+
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus};
+use std::time::{Duration, Instant};
+
+/// How often a running program is checked for having exited.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub timeout: Option<Duration>,
+    /// Address space in bytes; only enforced on Linux.
+    pub memory: Option<u64>,
+}
+
+/// How a limited program ended.
+#[derive(Debug)]
+pub enum Outcome {
+    /// It exited; the output is empty for streams that were not piped.
+    Exited { status: ExitStatus, stdout: Vec<u8>, stderr: Vec<u8> },
+    /// It ran past the timeout and was killed.
+    TimedOut(Duration),
+}
+
+/// A command running `program` within the memory limit of `limits`.
+pub fn command(program: &Path, limits: &Limits) -> Command {
+    match limits.memory {
+        Some(bytes) if cfg!(target_os = "linux") => {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(format!("ulimit -v {} && exec \"$0\" \"$@\"", bytes.div_ceil(1024))).arg(program);
+            command
+        }
+        _ => Command::new(program),
+    }
+}
+
+/// Runs `command` to completion or until the timeout of `limits`,
+/// collecting whatever it writes to piped streams.
+pub fn run(command: &mut Command, limits: &Limits) -> io::Result<Outcome> {
+    let mut child = command.spawn()?;
+    // Drain the pipes while waiting, or a chatty program blocks on a full
+    // pipe and looks like it hangs.
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+    let status = match wait(&mut child, limits.timeout)? {
+        Some(status) => status,
+        None => {
+            child.kill()?;
+            child.wait()?;
+            return Ok(Outcome::TimedOut(limits.timeout.unwrap_or_default()));
+        }
+    };
+    let collect = |reader: std::thread::JoinHandle<Vec<u8>>| reader.join().unwrap_or_default();
+    Ok(Outcome::Exited { status, stdout: collect(stdout), stderr: collect(stderr) })
+}
+
+/// The exit status of `child`, or `None` if `timeout` passes first.
+fn wait(child: &mut Child, timeout: Option<Duration>) -> io::Result<Option<ExitStatus>> {
+    let Some(timeout) = timeout else {
+        return child.wait().map(Some);
+    };
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            return Ok(None);
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn drain(stream: Option<impl Read + Send + 'static>) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(mut stream) = stream {
+            let _ = stream.read_to_end(&mut bytes);
+        }
+        bytes
+    })
+}
+
+/// Parses a memory size: bytes, or a number with a `K`, `M` or `G` suffix
+/// for binary kilo-, mega- and gigabytes.
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let (digits, unit) = match text.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((i, _)) => text.split_at(i),
+        None => (text, ""),
+    };
+    let shift = match unit.to_ascii_uppercase().as_str() {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        _ => return Err(format!("unknown size unit '{}' (expected K, M or G)", unit)),
+    };
+    let value: u64 = digits.parse().map_err(|_| format!("invalid size '{}'", text))?;
+    value.checked_mul(1 << shift).ok_or_else(|| format!("size '{}' is too large", text))
+}
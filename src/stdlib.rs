@@ -0,0 +1,52 @@
+//! The standard library: the Verve modules in the `std/` directory shipped
+//! with the compiler. A program gets the items it uses, as with the
+//! prelude, but the modules are read from disk, so they can change without
+//! rebuilding the compiler.
+
+use std::path::{Path, PathBuf};
+
+/// Names the standard library directory, overriding the search path.
+pub const ENV_VAR: &str = "VERVE_STD";
+
+/// Where to look for the standard library, in order. `verve_std` is the
+/// value of `VERVE_STD`, which is the only place looked at when set.
+/// Otherwise the candidates are `std/` next to the executable `exe`,
+/// `lib/verve/std` under the prefix it is installed in, and the source tree
+/// the compiler was built from.
+pub fn search_path(verve_std: Option<PathBuf>, exe: Option<&Path>) -> Vec<PathBuf> {
+    if let Some(dir) = verve_std {
+        return vec![dir];
+    }
+    let mut dirs = Vec::new();
+    if let Some(bin) = exe.and_then(Path::parent) {
+        dirs.push(bin.join("std"));
+        if let Some(prefix) = bin.parent() {
+            dirs.push(prefix.join("lib").join("verve").join("std"));
+        }
+    }
+    dirs.push(Path::new(env!("CARGO_MANIFEST_DIR")).join("std"));
+    dirs
+}
+
+/// The first directory on the search path of this process that exists.
+pub fn locate() -> Option<PathBuf> {
+    let exe = std::env::current_exe().ok();
+    search_path(std::env::var_os(ENV_VAR).map(PathBuf::from), exe.as_deref())
+        .into_iter()
+        .find(|dir| dir.is_dir())
+}
+
+/// The paths and sources of the `.vrv` modules in `dir`, sorted by path.
+pub fn modules(dir: &Path) -> std::io::Result<Vec<(PathBuf, String)>> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "vrv") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    paths.into_iter()
+        .map(|path| std::fs::read_to_string(&path).map(|text| (path, text)))
+        .collect()
+}
@@ -0,0 +1,78 @@
+//! Tail-call elimination for self-recursive functions. A `return f(...)`
+//! inside `f` becomes a [`Stmt::TailCall`], which the backend lowers to
+//! reassigning the parameters and jumping back to the top of the function,
+//! so tail recursion runs in constant stack space.
+
+use crate::ast::{Expr, Program, Stmt};
+use crate::log::{self, Level};
+use std::collections::HashSet;
+
+/// Rewrites self tail calls in every function. Returns inside safe blocks
+/// are left alone, since they have to run the block's deferred statements
+/// first, as are functions that shadow a parameter, where the reassignment
+/// would hit the wrong variable.
+pub fn eliminate_tail_calls(program: &mut Program) {
+    for function in &mut program.functions {
+        let mut declared = HashSet::new();
+        collect_declarations(&mut function.body, &mut declared);
+        if let Some((param, _)) = function.params.iter().find(|(name, _)| declared.contains(name)) {
+            log::event!(Level::Trace, "opt", "tail calls in '{}' kept: parameter '{}' is shadowed", function.name, param);
+            continue;
+        }
+        rewrite_block(&mut function.body, &function.name);
+    }
+}
+
+/// Whether `stmts` re-enter their function through a tail call.
+pub fn has_tail_call(stmts: &[Stmt]) -> bool {
+    stmts.iter().any(|stmt| match stmt {
+        Stmt::TailCall(..) => true,
+        Stmt::If(_, then_branch, else_branch, _) => {
+            has_tail_call(then_branch) || else_branch.as_deref().is_some_and(has_tail_call)
+        }
+        Stmt::While(_, body, _) | Stmt::For(_, _, body, _) | Stmt::Block(body, _) => has_tail_call(body),
+        Stmt::Switch(_, _, cases, default, _) => {
+            cases.iter().any(|(_, body)| has_tail_call(body)) || default.as_deref().is_some_and(has_tail_call)
+        }
+        _ => false,
+    })
+}
+
+fn rewrite_block(stmts: &mut [Stmt], name: &str) {
+    for stmt in stmts.iter_mut() {
+        match stmt {
+            Stmt::Return(Expr::Call(callee, args, _, _), span) if callee == name => {
+                log::event!(Level::Debug, "opt", "rewrote tail call of '{}' to a jump", name);
+                *stmt = Stmt::TailCall(std::mem::take(args), *span);
+            }
+            Stmt::If(_, then_branch, else_branch, _) => {
+                rewrite_block(then_branch, name);
+                if let Some(else_branch) = else_branch {
+                    rewrite_block(else_branch, name);
+                }
+            }
+            Stmt::While(_, body, _) | Stmt::For(_, _, body, _) | Stmt::Block(body, _) => rewrite_block(body, name),
+            Stmt::Switch(_, _, cases, default, _) => {
+                for (_, body) in cases {
+                    rewrite_block(body, name);
+                }
+                if let Some(default) = default {
+                    rewrite_block(default, name);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn collect_declarations(stmts: &mut [Stmt], declared: &mut HashSet<String>) {
+    for stmt in stmts.iter_mut() {
+        match stmt {
+            Stmt::Let(name, ..) | Stmt::Static(name, ..) | Stmt::For(name, ..) => {
+                declared.insert(name.clone());
+            }
+            _ => {}
+        }
+        stmt.for_each_block(&mut |block| collect_declarations(block, declared));
+    }
+}
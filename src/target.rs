@@ -0,0 +1,93 @@
+//! What the compiler knows about a target triple. A triple is accepted
+//! when its architecture and operating system are ones `cfg` can tell
+//! apart, so programs compiled for it see meaningful `target_arch` and
+//! `target_os` values; [`TARGETS`] lists the triples known to work, and a
+//! triple a letter or two away from one of them is taken for a typo.
+
+use crate::{cfg, cli, fix};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// The triples the runtime has been built and tested for.
+pub const TARGETS: &[&str] = &[
+    "aarch64-apple-darwin",
+    "aarch64-pc-windows-msvc",
+    "aarch64-unknown-linux-gnu",
+    "armv7-unknown-linux-gnueabihf",
+    "i686-pc-windows-msvc",
+    "i686-unknown-linux-gnu",
+    "riscv64gc-unknown-linux-gnu",
+    "wasm32-wasi",
+    "wasm32-wasip1",
+    "x86_64-apple-darwin",
+    "x86_64-pc-windows-gnu",
+    "x86_64-pc-windows-msvc",
+    "x86_64-unknown-freebsd",
+    "x86_64-unknown-linux-gnu",
+];
+
+/// The properties of a target triple.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub triple: String,
+    pub arch: &'static str,
+    pub os: &'static str,
+    pub family: &'static str,
+    pub pointer_width: u32,
+}
+
+impl Target {
+    /// The properties of `triple`, or why it cannot be compiled for.
+    pub fn parse(triple: &str) -> Result<Target, String> {
+        let value = |key| cfg::value(key, triple).unwrap_or("unknown");
+        let (arch, os) = (value("target_arch"), value("target_os"));
+        let close = if TARGETS.contains(&triple) { None } else { fix::closest_name(triple, TARGETS.iter().copied()) };
+        let problem = if triple.split('-').count() < 2 {
+            Some("a triple names at least an architecture and an operating system".to_string())
+        } else if arch == "unknown" {
+            Some(format!("unknown architecture '{}'", triple.split('-').next().unwrap_or("")))
+        } else if os == "none" {
+            Some("unknown operating system".to_string())
+        } else if close.is_some() {
+            Some("not a known target".to_string())
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            let hint = match close {
+                Some(close) => format!("did you mean '{}'?", close),
+                None => "--print target-list shows the known targets".to_string(),
+            };
+            return Err(format!("invalid target triple '{}': {}; {}", triple, problem, hint));
+        }
+        let pointer_width = match arch {
+            "x86_64" | "aarch64" | "riscv64" => 64,
+            _ => 32,
+        };
+        Ok(Target { triple: triple.to_string(), arch, os, family: value("target_family"), pointer_width })
+    }
+
+    /// The C compiler the driver builds programs for this target with,
+    /// given the wasi-sdk at `sdk`. `None` when the driver only writes the
+    /// C, as it does for native targets on hosts other than Windows.
+    pub fn c_compiler(&self, sdk: &Path) -> Option<PathBuf> {
+        if cli::is_wasi(&self.triple) {
+            Some(sdk.join("bin").join("clang"))
+        } else if cfg!(target_os = "windows") {
+            Some("clang".into())
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    /// `key = value` lines, in the form `--print target-info` shows.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "triple = {}", self.triple)?;
+        writeln!(f, "target_arch = {}", self.arch)?;
+        writeln!(f, "target_os = {}", self.os)?;
+        writeln!(f, "target_family = {}", self.family)?;
+        write!(f, "pointer_width = {}", self.pointer_width)
+    }
+}
@@ -0,0 +1,111 @@
+//! The `target/` directory the driver builds into, next to `verve.toml` or
+//! in the working directory when there is none. Each target triple and
+//! profile gets its own build directory, `target/<triple>/<profile>/`, with
+//! the generated C in `c/` and executables in `bin/`, so a debug build does
+//! not overwrite a release build or a build for another target. A linked
+//! runtime is built once per triple, in `target/<triple>/runtime/`. The
+//! first build leaves a [`MARKER`] file in `target/`, and only a directory
+//! holding one is ever cleaned.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const DIR_NAME: &str = "target";
+
+/// The file that marks a target directory as the compiler's own.
+pub const MARKER: &str = ".verve-target";
+
+/// The target directory of a project.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetDir {
+    root: PathBuf,
+}
+
+/// The build directory of one target triple and profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildDir {
+    root: PathBuf,
+    path: PathBuf,
+}
+
+/// The profile a build at C optimization level `opt_level` belongs to:
+/// `debug` at 0, `release` at 1, and `release-O<level>` above, so builds
+/// at different levels never share a directory.
+pub fn profile(opt_level: u8) -> String {
+    match opt_level {
+        0 => "debug".to_string(),
+        1 => "release".to_string(),
+        level => format!("release-O{}", level),
+    }
+}
+
+impl TargetDir {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// The target directory next to the manifest at `manifest`, or in the
+    /// working directory without one.
+    pub fn for_project(manifest: Option<&Path>) -> Self {
+        let dir = manifest.and_then(Path::parent).unwrap_or(Path::new(""));
+        Self::new(dir.join(DIR_NAME))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// The build directory for `target_triple` at C optimization level
+    /// `opt_level`. Nothing is created until [`BuildDir::create`].
+    pub fn build(&self, target_triple: &str, opt_level: u8) -> BuildDir {
+        BuildDir { root: self.root.clone(), path: self.root.join(target_triple).join(profile(opt_level)) }
+    }
+
+    /// Where the runtime library for `target_triple` is built, shared by
+    /// every profile.
+    pub fn runtime(&self, target_triple: &str) -> PathBuf {
+        self.root.join(target_triple).join("runtime")
+    }
+
+    /// Removes the target directory with everything built in it. Returns
+    /// whether there was one; a directory without the [`MARKER`] a build
+    /// leaves is not the compiler's, and is refused.
+    pub fn clean(&self) -> io::Result<bool> {
+        if !self.root.exists() {
+            return Ok(false);
+        }
+        if !self.root.join(MARKER).is_file() {
+            return Err(io::Error::other(format!("it has no {} file, so verve did not create it", MARKER)));
+        }
+        std::fs::remove_dir_all(&self.root)?;
+        Ok(true)
+    }
+}
+
+impl BuildDir {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Where the generated C of the program goes.
+    pub fn c_source(&self) -> PathBuf {
+        self.path.join("c").join("output.c")
+    }
+
+    /// Where an executable called `name` goes.
+    pub fn binary(&self, name: impl AsRef<Path>) -> PathBuf {
+        self.path.join("bin").join(name)
+    }
+
+    /// Creates the `c/` and `bin/` directories, and the [`MARKER`] in the
+    /// target directory.
+    pub fn create(&self) -> io::Result<()> {
+        std::fs::create_dir_all(self.path.join("c"))?;
+        std::fs::create_dir_all(self.path.join("bin"))?;
+        let marker = self.root.join(MARKER);
+        if !marker.exists() {
+            std::fs::write(marker, "Created by verve; `verve clean` removes this directory.\n")?;
+        }
+        Ok(())
+    }
+}
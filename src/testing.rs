@@ -0,0 +1,311 @@
+//! Golden-file end-to-end tests. A run-pass test is a Verve program with
+//! the output it must print stored next to it (`name.vrv`, `name.stdout`).
+//! [`RunPass`] compiles the program with [`Compiler`] at every optimization
+//! level, builds each result with a C compiler, runs the binary and compares
+//! what it prints with the expected output. Programs run within
+//! [`Limits`], ten seconds each by default, so one that never finishes
+//! fails its test instead of hanging the suite.
+//!
+//! A snapshot test pins the C a program compiles to (`name.vrv`,
+//! `name.snap`), so changes to code generation show up as diffs to review.
+//! [`Snapshots`] [normalizes](normalize) the C first, so that snapshots do
+//! not change with the build time or when a temporary is added upstream.
+
+use crate::compiler::{Compiler, DiagnosticHandler, Stage};
+use crate::fix;
+use crate::sandbox::{self, Limits, Outcome};
+use crate::stdlib;
+use codespan::{FileId, Files};
+use codespan_reporting::diagnostic::{Diagnostic, Severity};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// Optimization levels every program is run at; all must print the same.
+pub const OPT_LEVELS: [u8; 3] = [0, 1, 2];
+
+/// How long a program may run before it fails, unless `VERVE_TEST_TIMEOUT`
+/// sets another number of seconds.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct RunPass {
+    /// The C compiler, `$CC` or `cc` by default.
+    pub cc: String,
+    /// Where generated C, binaries and files the programs write go.
+    pub work_dir: PathBuf,
+    /// Standard library modules to compile the programs with.
+    pub std_modules: Vec<(PathBuf, String)>,
+    /// Write the output of each program as its expected output instead of
+    /// comparing; set by `VERVE_BLESS=1` by default.
+    pub bless: bool,
+    /// What each program may use: [`DEFAULT_TIMEOUT`], or the seconds in
+    /// `VERVE_TEST_TIMEOUT` (0 for none), and the memory in
+    /// `VERVE_TEST_MEMORY_LIMIT`, as `512M` for instance.
+    pub limits: Limits,
+}
+
+impl RunPass {
+    /// A harness configured from the environment, working in a fresh
+    /// directory under the system temporary directory.
+    pub fn from_env() -> std::io::Result<RunPass> {
+        let invalid = |var: &str, e: String| std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("{}: {}", var, e));
+        let timeout = match std::env::var("VERVE_TEST_TIMEOUT") {
+            Ok(seconds) => match seconds.parse::<u64>() {
+                Ok(0) => None,
+                Ok(seconds) => Some(Duration::from_secs(seconds)),
+                Err(e) => return Err(invalid("VERVE_TEST_TIMEOUT", e.to_string())),
+            },
+            Err(_) => Some(DEFAULT_TIMEOUT),
+        };
+        let memory = std::env::var("VERVE_TEST_MEMORY_LIMIT").ok()
+            .map(|size| sandbox::parse_size(&size).map_err(|e| invalid("VERVE_TEST_MEMORY_LIMIT", e)))
+            .transpose()?;
+        let work_dir = std::env::temp_dir().join(format!("verve-run-pass-{}", std::process::id()));
+        std::fs::create_dir_all(&work_dir)?;
+        let std_modules = match stdlib::locate() {
+            Some(dir) => stdlib::modules(&dir)?,
+            None => Vec::new(),
+        };
+        Ok(RunPass {
+            cc: std::env::var("CC").unwrap_or_else(|_| "cc".to_string()),
+            work_dir,
+            std_modules,
+            bless: std::env::var_os("VERVE_BLESS").is_some_and(|value| value == "1"),
+            limits: Limits { timeout, memory },
+        })
+    }
+
+    /// Compiles, builds and runs `program` at `opt_level` and returns what
+    /// it printed. Compile errors, C compiler errors, a failing exit status
+    /// and running out of time are errors.
+    pub fn run(&self, program: &Path, opt_level: u8) -> Result<String, String> {
+        let source = std::fs::read_to_string(program).map_err(|e| format!("{}: {}", program.display(), e))?;
+        let mut compiler = Compiler::new().target_triple(crate::host::native_triple()).opt_level(opt_level);
+        for (path, text) in &self.std_modules {
+            compiler.add_std_module(&path.to_string_lossy(), text.as_str());
+        }
+        let file_id = compiler.add_source(&program.to_string_lossy(), source);
+        let mut diagnostics = Collected(Vec::new());
+        let code = compiler.compile(file_id, &mut diagnostics)
+            .map_err(|failed| format!("{}:\n{}", failed, diagnostics.0.join("\n")))?;
+
+        let stem = program.file_stem().unwrap_or_default().to_string_lossy();
+        let c_file = self.work_dir.join(format!("{}-O{}.c", stem, opt_level));
+        let binary = self.work_dir.join(format!("{}-O{}{}", stem, opt_level, std::env::consts::EXE_SUFFIX));
+        std::fs::write(&c_file, code).map_err(|e| format!("{}: {}", c_file.display(), e))?;
+        let build = Command::new(&self.cc)
+            .arg(&c_file)
+            .arg("-o")
+            .arg(&binary)
+            .output()
+            .map_err(|e| format!("Cannot run {}: {} (set CC to a C compiler)", self.cc, e))?;
+        if !build.status.success() {
+            return Err(format!("{} failed on {}:\n{}", self.cc, c_file.display(), String::from_utf8_lossy(&build.stderr)));
+        }
+
+        // With stdin closed, the pause at the end of `main` returns at once.
+        let outcome = sandbox::run(
+            sandbox::command(&binary, &self.limits)
+                .current_dir(&self.work_dir)
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped()),
+            &self.limits,
+        );
+        let (status, stdout, stderr) = match outcome.map_err(|e| format!("Cannot run {}: {}", binary.display(), e))? {
+            Outcome::Exited { status, stdout, stderr } => (status, stdout, stderr),
+            Outcome::TimedOut(timeout) => {
+                return Err(format!("{} at -O{} timed out after {:?}", program.display(), opt_level, timeout));
+            }
+        };
+        let stdout = String::from_utf8_lossy(&stdout).into_owned();
+        if !status.success() {
+            return Err(format!(
+                "{} exited with {}\nSTDOUT:\n{}\nSTDERR:\n{}",
+                binary.display(),
+                status,
+                stdout,
+                String::from_utf8_lossy(&stderr)
+            ));
+        }
+        Ok(stdout)
+    }
+
+    /// Runs `program` at every level in [`OPT_LEVELS`] and checks its
+    /// output against `name.stdout`, or writes that file when blessing.
+    pub fn check(&self, program: &Path) -> Result<(), String> {
+        let expected_file = program.with_extension("stdout");
+        let mut outputs = Vec::new();
+        for opt_level in OPT_LEVELS {
+            outputs.push((opt_level, self.run(program, opt_level)?));
+        }
+        if self.bless {
+            return std::fs::write(&expected_file, &outputs[0].1).map_err(|e| format!("{}: {}", expected_file.display(), e));
+        }
+
+        let expected = std::fs::read_to_string(&expected_file)
+            .map_err(|e| format!("{}: {} (run with VERVE_BLESS=1 to create it)", expected_file.display(), e))?;
+        for (opt_level, output) in outputs {
+            if output != expected {
+                return Err(format!(
+                    "{} at -O{} printed\n{}\nbut {} expects\n{}",
+                    program.display(),
+                    opt_level,
+                    output,
+                    expected_file.display(),
+                    expected
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The target snapshots are compiled for.
+pub const SNAPSHOT_TARGET: &str = "x86_64-unknown-linux-gnu";
+
+pub struct Snapshots {
+    /// Standard library modules to compile the programs with.
+    pub std_modules: Vec<(PathBuf, String)>,
+    /// Write the C of each program as its snapshot instead of comparing;
+    /// set by `VERVE_BLESS=1` by default.
+    pub bless: bool,
+}
+
+impl Snapshots {
+    /// A harness configured from the environment.
+    pub fn from_env() -> std::io::Result<Snapshots> {
+        let std_modules = match stdlib::locate() {
+            Some(dir) => stdlib::modules(&dir)?,
+            None => Vec::new(),
+        };
+        Ok(Snapshots { std_modules, bless: std::env::var_os("VERVE_BLESS").is_some_and(|value| value == "1") })
+    }
+
+    /// The normalized C of `program`, compiled for [`SNAPSHOT_TARGET`] with
+    /// the default options. The source is named by its file name alone, so
+    /// the C does not depend on where the repository is checked out.
+    pub fn compile(&self, program: &Path) -> Result<String, String> {
+        let source = std::fs::read_to_string(program).map_err(|e| format!("{}: {}", program.display(), e))?;
+        let mut compiler = Compiler::new().target_triple(SNAPSHOT_TARGET);
+        for (path, text) in &self.std_modules {
+            compiler.add_std_module(&path.to_string_lossy(), text.as_str());
+        }
+        let name = program.file_name().unwrap_or_default().to_string_lossy();
+        let file_id = compiler.add_source(&name, source);
+        let mut diagnostics = Collected(Vec::new());
+        let code = compiler.compile(file_id, &mut diagnostics)
+            .map_err(|failed| format!("{}:\n{}", failed, diagnostics.0.join("\n")))?;
+        Ok(normalize(&code))
+    }
+
+    /// Checks the C of `program` against `name.snap`, or writes that file
+    /// when blessing. A mismatch is reported as a diff from the snapshot.
+    pub fn check(&self, program: &Path) -> Result<(), String> {
+        let snapshot_file = program.with_extension("snap");
+        let code = self.compile(program).map_err(|e| format!("{}: {}", program.display(), e))?;
+        if self.bless {
+            return std::fs::write(&snapshot_file, &code).map_err(|e| format!("{}: {}", snapshot_file.display(), e));
+        }
+        let snapshot = std::fs::read_to_string(&snapshot_file)
+            .map_err(|e| format!("{}: {} (run with VERVE_BLESS=1 to create it)", snapshot_file.display(), e))?;
+        if code != snapshot {
+            return Err(format!(
+                "{} no longer compiles to its snapshot (run with VERVE_BLESS=1 to accept the change):\n{}",
+                program.display(),
+                fix::diff(&snapshot_file.display().to_string(), &snapshot, &code)
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// `code` with what changes between otherwise identical compilations
+/// replaced: the header comment loses the target, `verve_build_*`
+/// constants their values, and the temporaries numbered with counters are
+/// numbered from 0 per kind in the order they first appear.
+pub fn normalize(code: &str) -> String {
+    let mut renamed: HashMap<String, String> = HashMap::new();
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    let mut out = String::with_capacity(code.len());
+    for line in code.lines() {
+        if line.starts_with("// Generated by Verve Compiler") {
+            out.push_str("// Generated by Verve Compiler");
+        } else if let Some(rest) = line.strip_prefix("static const char verve_build_")
+            && let Some((name, _)) = rest.split_once(" = ")
+        {
+            out.push_str(&format!("static const char verve_build_{} = \"<build>\";", name));
+        } else {
+            out.push_str(&renumber(line, &mut renamed, &mut counts));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Prefixes of the names lowering and the C backend number with counters.
+/// The number follows the prefix directly, except that temporaries and
+/// labels may have a hint in between, as `__tmp_block3`.
+const NUMBERED: &[&str] = &[
+    "__tmp_", "__label_", "__match", "__guard", "__destructure", "__item", "__list", "__index",
+    "__loop", "__stride", "__chars", "__offset", "__bits",
+];
+
+fn renumber(line: &str, renamed: &mut HashMap<String, String>, counts: &mut HashMap<&'static str, usize>) -> String {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let mut out = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find("__") {
+        // Only whole identifiers, not `__` inside one.
+        let start = if rest[..start].ends_with(is_ident) { start + 2 } else { start };
+        let end = rest[start..].find(|c: char| !is_ident(c)).map_or(rest.len(), |i| start + i);
+        let name = &rest[start..end];
+        out.push_str(&rest[..start]);
+        let hint = name.trim_end_matches(|c: char| c.is_ascii_digit());
+        let prefix = NUMBERED.iter().copied().find(|prefix| {
+            hint.len() < name.len() && (hint == *prefix || (prefix.ends_with('_') && hint.starts_with(prefix)))
+        });
+        match prefix {
+            Some(prefix) => {
+                let new_name = renamed.entry(name.to_string()).or_insert_with(|| {
+                    let count = counts.entry(prefix).or_default();
+                    *count += 1;
+                    format!("{}{}", hint, *count - 1)
+                });
+                out.push_str(new_name);
+            }
+            None => out.push_str(name),
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// The `.vrv` files in `dir`, sorted.
+pub fn programs(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut programs = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "vrv") {
+            programs.push(path);
+        }
+    }
+    programs.sort();
+    Ok(programs)
+}
+
+/// Diagnostics formatted one per line.
+struct Collected(Vec<String>);
+
+impl DiagnosticHandler for Collected {
+    fn report(&mut self, stage: Stage, diagnostic: &Diagnostic<FileId>, files: &Files<String>) {
+        let location = diagnostic.labels.first()
+            .and_then(|label| files.location(label.file_id, label.range.start as u32).ok())
+            .map(|loc| format!(" at {}:{}", loc.line.number(), loc.column.number()))
+            .unwrap_or_default();
+        let kind = if diagnostic.severity == Severity::Warning { "warning" } else { "error" };
+        self.0.push(format!("{:?} {}{}: {}", stage, kind, location, diagnostic.message));
+    }
+}
@@ -1,13 +1,27 @@
-use super::ast::{self, BinOp, Expr, Stmt, Type};
+use super::ast::{self, BinOp, CallConv, Expr, Pattern, Stmt, Type};
+use super::intrinsics::{self, Signature};
+use super::codegen::mangle;
+use super::fix::{self, Suggestion};
+use super::lint::Lint;
+use super::{cfg, deps, log};
 use codespan::{FileId, Span};
 use codespan_reporting::diagnostic::{Diagnostic, Label};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+
+/// Signals a handler can be installed for with `signal.on`.
+pub const SIGNALS: &[&str] = &["SIGINT", "SIGTERM"];
+
+/// What `#[derive(...)]` can name.
+const DERIVABLE: &[&str] = &["Eq", "Ord", "Hash", "Serialize", "Deserialize"];
 
 #[derive(Debug)]
 struct Context {
     variables: HashMap<String, Type>,
     current_return_type: Type,
     in_safe: bool,
+    /// Loops whose body encloses the statement being checked, and so
+    /// what `break` and `continue` can jump out of.
+    loops: usize,
 }
 
 impl Context {
@@ -16,6 +30,7 @@ impl Context {
             variables: HashMap::new(),
             current_return_type: Type::Void,
             in_safe: false,
+            loops: 0,
         }
     }
 }
@@ -23,8 +38,22 @@ impl Context {
 #[derive(Debug)]
 pub struct TypeChecker {
     errors: Vec<Diagnostic<FileId>>,
+    warnings: Vec<Diagnostic<FileId>>,
     context: Context,
-    functions: HashMap<String, (Vec<Type>, Type)>, 
+    functions: HashMap<String, (Vec<Type>, Type, CallConv)>,
+    structs: HashMap<String, Vec<ast::Field>>,
+    unions: HashSet<String>,
+    enums: HashMap<String, Vec<ast::Variant>>,
+    /// What each user type derives: `Eq`, `Ord`, `Hash` or several.
+    derives: HashMap<String, Vec<String>>,
+    /// `#[deprecated]` functions and types, with the note to show at uses.
+    deprecated_functions: HashMap<String, Option<String>>,
+    deprecated_types: HashMap<String, Option<String>>,
+    /// Uses inside deprecated items do not warn.
+    in_deprecated: bool,
+    /// Where each struct and enum is defined, for fixes that add to it.
+    definitions: HashMap<String, Span>,
+    suggestions: Vec<Suggestion>,
     file_id: FileId,
 }
 
@@ -33,18 +62,73 @@ impl TypeChecker {
         TypeChecker {
             file_id,
             errors: Vec::new(),
+            warnings: Vec::new(),
             context: Context::new(),
             functions: HashMap::new(),
+            structs: HashMap::new(),
+            unions: HashSet::new(),
+            enums: HashMap::new(),
+            derives: HashMap::new(),
+            deprecated_functions: HashMap::new(),
+            deprecated_types: HashMap::new(),
+            in_deprecated: false,
+            definitions: HashMap::new(),
+            suggestions: Vec::new(),
         }
     }
 
+    /// The warnings found by [`TypeChecker::check`], which does not fail
+    /// because of them.
+    pub fn take_warnings(&mut self) -> Vec<Diagnostic<FileId>> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// The fixes suggested for the errors found by [`TypeChecker::check`].
+    pub fn take_suggestions(&mut self) -> Vec<Suggestion> {
+        std::mem::take(&mut self.suggestions)
+    }
+
     pub fn check(&mut self, program: &mut ast::Program) -> Result<(), Vec<Diagnostic<FileId>>> {
+        // Checks that stop early return what they found on top of the
+        // errors already reported, which is often nothing.
+        let result = self.check_program(program);
+        let mut errors = std::mem::take(&mut self.errors);
+        if let Err(more) = result {
+            errors.extend(more);
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn check_program(&mut self, program: &mut ast::Program) -> Result<(), Vec<Diagnostic<FileId>>> {
+        self.collect_types(program);
+
         for func in &mut program.functions {
             let params: Vec<Type> = func.params.iter().map(|(_, t)| t.clone()).collect();
-            self.functions.insert(
+            let previous = self.functions.insert(
                 func.name.clone(),
-                (params, func.return_type.clone())
+                (params, func.return_type.clone(), func.call_conv)
             );
+            if previous.is_some() {
+                self.report_error(&format!("Function '{}' is defined more than once", func.name), func.span);
+            }
+            if let Some(note) = deprecation(&func.attrs) {
+                self.deprecated_functions.insert(func.name.clone(), note);
+            }
+        }
+        let mut symbols = HashMap::new();
+        for func in &program.functions {
+            let symbol = func.symbol().unwrap_or(&func.name);
+            match symbols.insert(symbol, &func.name) {
+                Some(other) if *other != func.name => self.report_error(
+                    &format!("Functions '{}' and '{}' are both emitted as the C symbol '{}'", other, func.name, symbol),
+                    func.span,
+                ),
+                _ => {}
+            }
         }
 
         for func in &mut program.functions {
@@ -52,7 +136,15 @@ impl TypeChecker {
             self.check_function(func)?;
         }
 
-        for stmt in &mut program.stmts {
+        // Globals in a cycle have no type to give their readers, which would
+        // only report them again as undefined.
+        let Some(order) = self.order_globals(&program.stmts) else {
+            return Err(std::mem::take(&mut self.errors));
+        };
+        for i in order {
+            self.check_stmt(&mut program.stmts[i])?;
+        }
+        for stmt in program.stmts.iter_mut().filter(|s| !matches!(s, Stmt::Let(..))) {
             self.check_stmt(stmt)?;
         }
 
@@ -64,11 +156,530 @@ impl TypeChecker {
     }
 
 
+    fn order_globals(&mut self, stmts: &[Stmt]) -> Option<Vec<usize>> {
+        match deps::global_order(stmts) {
+            Ok(order) => Some(order),
+            Err(cycle) => {
+                let names = cycle.iter()
+                    .filter_map(|&i| match &stmts[i] {
+                        Stmt::Let(name, ..) => Some(name.as_str()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+                self.report_error(&format!("Global initializers form a cycle: {}", names), stmts[cycle[0]].span());
+                None
+            }
+        }
+    }
+
+    fn collect_types(&mut self, program: &ast::Program) {
+        for def in &program.structs {
+            if self.is_type_defined(&def.name) {
+                self.report_error(&format!("Type '{}' is defined more than once", def.name), def.span);
+                continue;
+            }
+            self.structs.insert(def.name.clone(), def.fields.clone());
+            self.definitions.insert(def.name.clone(), def.span);
+            if def.is_union {
+                self.unions.insert(def.name.clone());
+            }
+        }
+        for def in &program.enums {
+            if self.is_type_defined(&def.name) {
+                self.report_error(&format!("Type '{}' is defined more than once", def.name), def.span);
+                continue;
+            }
+            self.enums.insert(def.name.clone(), def.variants.clone());
+            self.definitions.insert(def.name.clone(), def.span);
+        }
+        for (name, attrs) in program.structs.iter().map(|def| (&def.name, &def.attrs))
+            .chain(program.enums.iter().map(|def| (&def.name, &def.attrs))) {
+            if let Some(note) = deprecation(attrs) {
+                self.deprecated_types.insert(name.clone(), note);
+            }
+            let traits = attrs.iter()
+                .filter(|attr| attr.name == "derive")
+                .flat_map(|attr| attr.args.iter())
+                .filter_map(|arg| match arg {
+                    ast::AttrArg::Ident(name) => Some(name.clone()),
+                    _ => None,
+                })
+                .collect();
+            self.derives.insert(name.clone(), traits);
+        }
+
+        for def in &program.structs {
+            self.in_deprecated = self.deprecated_types.contains_key(&def.name);
+            self.check_layout_attrs(def);
+            let mut seen = HashSet::new();
+            for field in &def.fields {
+                if !seen.insert(&field.name) {
+                    self.report_error(
+                        &format!("Field '{}' is declared more than once in '{}'", field.name, def.name),
+                        field.span,
+                    );
+                }
+                self.check_type_defined(&field.ty, field.span);
+                if let Some(bits) = field.bits {
+                    self.check_bitfield(field, bits, def.is_union);
+                }
+            }
+            for derived in self.derived(&def.name) {
+                for field in &def.fields {
+                    if !self.supports(&derived, &field.ty) {
+                        self.report_error(
+                            &format!("Cannot derive {} for '{}': field '{}' has type {}, which does not support it", derived, def.name, field.name, field.ty),
+                            field.span,
+                        );
+                    }
+                }
+            }
+        }
+        for def in &program.enums {
+            self.in_deprecated = self.deprecated_types.contains_key(&def.name);
+            for attr in &def.attrs {
+                self.check_derive_attr(attr);
+            }
+            for derived in self.derived(&def.name) {
+                if matches!(derived.as_str(), "Serialize" | "Deserialize") && !def.is_plain() {
+                    self.report_error(
+                        &format!("Cannot derive {} for '{}': only structs and enums without values convert to JSON", derived, def.name),
+                        def.span,
+                    );
+                    continue;
+                }
+                for variant in &def.variants {
+                    for ty in &variant.fields {
+                        if !self.supports(&derived, ty) {
+                            self.report_error(
+                                &format!("Cannot derive {} for '{}': variant '{}' holds {}, which does not support it", derived, def.name, variant.name, ty),
+                                variant.span,
+                            );
+                        }
+                    }
+                }
+            }
+            let mut seen = HashSet::new();
+            for variant in &def.variants {
+                if !seen.insert(&variant.name) {
+                    self.report_error(
+                        &format!("Variant '{}' is declared more than once in '{}'", variant.name, def.name),
+                        variant.span,
+                    );
+                }
+                for ty in &variant.fields {
+                    self.check_type_defined(ty, variant.span);
+                }
+            }
+        }
+        self.in_deprecated = false;
+    }
+
+    fn check_layout_attrs(&mut self, def: &ast::StructDef) {
+        for attr in &def.attrs {
+            match (attr.name.as_str(), attr.args.as_slice()) {
+                ("repr", [ast::AttrArg::Ident(repr)]) if repr == "C" => {}
+                ("packed", []) => {}
+                ("align", [ast::AttrArg::Int(n)]) if *n > 0 && (*n as u64).is_power_of_two() => {}
+                ("repr", _) => self.report_error("Only #[repr(C)] is supported", attr.span),
+                ("packed", _) => self.report_error("#[packed] takes no arguments", attr.span),
+                ("align", _) => self.report_error("#[align(N)] requires a power-of-two alignment", attr.span),
+                ("derive", _) if def.is_union => {
+                    self.report_error(&format!("Union '{}' cannot use #[derive]", def.name), attr.span)
+                }
+                _ => self.check_derive_attr(attr),
+            }
+        }
+
+        if let (Some(_), Some(align)) = (def.attr("packed"), def.attr("align")) {
+            self.report_error(
+                &format!("'{}' cannot be both packed and aligned", def.name),
+                align.span,
+            );
+        }
+        if let Some(align) = def.attr("align") {
+            match def.fields.first() {
+                None => self.report_error(
+                    &format!("#[align] on '{}' requires at least one field", def.name),
+                    align.span,
+                ),
+                Some(field) if field.bits.is_some() => self.report_error(
+                    &format!("#[align] on '{}' requires the first field not to be a bitfield", def.name),
+                    align.span,
+                ),
+                _ => {}
+            }
+        }
+    }
+
+    /// Accepts `#[derive(...)]` of `Eq`, `Ord`, `Hash`, `Serialize` and
+    /// `Deserialize`; any other attribute is unknown here.
+    fn check_derive_attr(&mut self, attr: &ast::Attribute) {
+        if attr.name == "cfg" {
+            self.report_cfg_attr(attr);
+            return;
+        }
+        if attr.name == "deprecated" {
+            self.check_deprecated_attr(attr);
+            return;
+        }
+        if attr.name != "derive" {
+            self.report_error(&format!("Unknown attribute '{}'", attr.name), attr.span);
+            return;
+        }
+        let known = |arg: &ast::AttrArg| matches!(arg, ast::AttrArg::Ident(name) if DERIVABLE.contains(&name.as_str()));
+        if attr.args.is_empty() || !attr.args.iter().all(known) {
+            self.report_error("#[derive] takes Eq, Ord, Hash, Serialize and Deserialize", attr.span);
+        }
+    }
+
+    /// What `name` derives.
+    fn derived(&self, name: &str) -> Vec<String> {
+        self.derives.get(name).cloned().unwrap_or_default()
+    }
+
+    fn derives(&self, ty: &Type, comparison: &str) -> bool {
+        matches!(ty, Type::Named(name) if self.derives.get(name).is_some_and(|traits| traits.iter().any(|t| t == comparison)))
+    }
+
+    /// Whether a value of type `ty` can be compared or hashed by a derived
+    /// `derived`: `Eq` and `Hash` take what `==` takes, `Ord` integers,
+    /// bools and strings, `Serialize` and `Deserialize` those, JSON values
+    /// and options of either, and all take user types that derive them.
+    fn supports(&self, derived: &str, ty: &Type) -> bool {
+        match (derived, ty) {
+            (_, Type::I32 | Type::Bool | Type::String) => true,
+            ("Serialize" | "Deserialize", Type::Named(name)) if name == super::json::JSON_VALUE => true,
+            ("Serialize" | "Deserialize", Type::Option(inner)) => {
+                !matches!(**inner, Type::Option(_)) && self.supports(derived, inner)
+            }
+            ("Eq" | "Hash", Type::Pointer(_) | Type::RawPtr) => true,
+            ("Eq" | "Hash", Type::Named(name)) if self.enums.contains_key(name) && !self.is_aggregate(ty) => true,
+            _ => self.derives(ty, derived),
+        }
+    }
+
+    fn check_bitfield(&mut self, field: &ast::Field, bits: u32, in_union: bool) {
+        let max_bits = match field.ty {
+            Type::I32 => 32,
+            Type::Bool => 1,
+            _ => {
+                self.report_error(
+                    &format!("Bitfield '{}' must have an integer or bool type, got {}", field.name, field.ty),
+                    field.span,
+                );
+                return;
+            }
+        };
+        if bits == 0 || bits > max_bits {
+            self.report_error(
+                &format!("Bitfield '{}' width must be between 1 and {}, got {}", field.name, max_bits, bits),
+                field.span,
+            );
+        }
+        if in_union {
+            self.report_error(&format!("Union field '{}' cannot be a bitfield", field.name), field.span);
+        }
+    }
+
+    fn is_type_defined(&self, name: &str) -> bool {
+        self.structs.contains_key(name) || self.enums.contains_key(name)
+    }
+
+    /// Structs, payload-carrying enums, options and tuples have no C
+    /// comparison operators.
+    fn is_aggregate(&self, ty: &Type) -> bool {
+        match ty {
+            Type::Option(_) | Type::Tuple(_) => true,
+            Type::Named(name) => {
+                self.structs.contains_key(name)
+                    || self.enums.get(name).is_some_and(|v| v.iter().any(|v| !v.fields.is_empty()))
+            }
+            _ => false,
+        }
+    }
+
+    /// Payload types of `variant` in the enum (or `Option`) type `ty`.
+    fn variant_fields(&self, ty: &Type, variant: &str) -> Option<Vec<Type>> {
+        match ty {
+            Type::Option(inner) => match variant {
+                "Some" => Some(vec![(**inner).clone()]),
+                "None" => Some(Vec::new()),
+                _ => None,
+            },
+            Type::Named(name) => self.enums.get(name)?
+                .iter()
+                .find(|v| v.name == variant)
+                .map(|v| v.fields.clone()),
+            _ => None,
+        }
+    }
+
+    /// Checks `pattern` against a value of type `ty` and binds its names in the
+    /// current scope. Bare names of unit variants (and `None`) become variant
+    /// patterns, and variant patterns get their enum filled in.
+    fn check_pattern(&mut self, pattern: &mut Pattern, ty: &Type) {
+        if let Pattern::Bind(name, span) = pattern
+            && self.variant_fields(ty, name).is_some_and(|fields| fields.is_empty()) {
+            *pattern = Pattern::Variant(None, name.clone(), Vec::new(), *span);
+        }
+
+        match pattern {
+            Pattern::Wildcard(_) => {}
+            Pattern::Bind(name, _) => {
+                self.context.variables.insert(name.clone(), ty.clone());
+            }
+            Pattern::Int(_, span) => {
+                if !matches!(ty, Type::I32 | Type::Unknown) {
+                    self.report_error(&format!("Integer pattern cannot match {}", ty), *span);
+                }
+            }
+            Pattern::Bool(_, span) => {
+                if !matches!(ty, Type::Bool | Type::Unknown) {
+                    self.report_error(&format!("Boolean pattern cannot match {}", ty), *span);
+                }
+            }
+            Pattern::Str(_, span) => {
+                if !matches!(ty, Type::String | Type::Unknown) {
+                    self.report_error(&format!("String pattern cannot match {}", ty), *span);
+                }
+            }
+            Pattern::Tuple(items, span) => {
+                let types = match ty {
+                    Type::Tuple(types) if types.len() == items.len() => types.clone(),
+                    _ => {
+                        if *ty != Type::Unknown {
+                            self.report_error(&format!("Tuple pattern with {} elements cannot match {}", items.len(), ty), *span);
+                        }
+                        vec![Type::Unknown; items.len()]
+                    }
+                };
+                for (item, item_ty) in items.iter_mut().zip(&types) {
+                    self.check_pattern(item, item_ty);
+                }
+            }
+            Pattern::Struct(name, fields, span) => {
+                let def_fields = self.structs.get(name).cloned();
+                if def_fields.is_none() || self.unions.contains(name) {
+                    self.report_error(&format!("Undefined struct '{}'", name), *span);
+                } else if *ty != Type::Named(name.clone()) && *ty != Type::Unknown {
+                    self.report_error(&format!("Pattern of struct '{}' cannot match {}", name, ty), *span);
+                }
+                for (field, item) in fields.iter_mut() {
+                    let field_ty = match def_fields.as_ref().map(|defs| defs.iter().find(|f| f.name == *field)) {
+                        Some(Some(def)) => def.ty.clone(),
+                        Some(None) => {
+                            self.report_error(&format!("Struct '{}' has no field '{}'", name, field), item.span());
+                            Type::Unknown
+                        }
+                        None => Type::Unknown,
+                    };
+                    self.check_pattern(item, &field_ty);
+                }
+            }
+            Pattern::Variant(enum_name, variant, args, span) => {
+                let actual = match ty {
+                    Type::Option(_) => Some("Option".to_string()),
+                    Type::Named(name) if self.enums.contains_key(name) => Some(name.clone()),
+                    _ => None,
+                };
+                let fields = match (&enum_name, &actual) {
+                    (Some(given), Some(actual)) if given != actual => {
+                        self.report_error(&format!("Pattern of enum '{}' cannot match {}", given, ty), *span);
+                        None
+                    }
+                    (_, None) => {
+                        if *ty != Type::Unknown {
+                            self.report_error(&format!("Variant pattern '{}' cannot match {}", variant, ty), *span);
+                        }
+                        None
+                    }
+                    (_, Some(_)) => {
+                        let fields = self.variant_fields(ty, variant);
+                        if fields.is_none() {
+                            self.report_error(&format!("Type {} has no variant '{}'", ty, variant), *span);
+                        }
+                        fields
+                    }
+                };
+                *enum_name = actual.or(enum_name.take());
+
+                let fields = fields.unwrap_or_else(|| vec![Type::Unknown; args.len()]);
+                if fields.len() != args.len() {
+                    self.report_error(
+                        &format!("Variant '{}' has {} fields, but the pattern has {}", variant, fields.len(), args.len()),
+                        *span,
+                    );
+                }
+                for (i, arg) in args.iter_mut().enumerate() {
+                    self.check_pattern(arg, fields.get(i).unwrap_or(&Type::Unknown));
+                }
+            }
+        }
+    }
+
+    fn pattern_bindings<'a>(pattern: &'a Pattern, names: &mut Vec<(&'a str, Span)>) {
+        match pattern {
+            Pattern::Bind(name, span) => names.push((name, *span)),
+            Pattern::Wildcard(_) | Pattern::Int(..) | Pattern::Bool(..) | Pattern::Str(..) => {}
+            Pattern::Tuple(items, _) | Pattern::Variant(_, _, items, _) => {
+                for item in items {
+                    Self::pattern_bindings(item, names);
+                }
+            }
+            Pattern::Struct(_, fields, _) => {
+                for (_, item) in fields {
+                    Self::pattern_bindings(item, names);
+                }
+            }
+        }
+    }
+
+    /// Lowered tuple patterns annotate their temporary with a tuple of unknown
+    /// element types; check the arity and take the element types from the value.
+    fn fill_tuple_pattern(&mut self, decl_ty: &mut Option<Type>, expr_ty: &Type, span: Span) {
+        let Some(Type::Tuple(holes)) = decl_ty else { return };
+        if holes.iter().any(|t| *t != Type::Unknown) {
+            return;
+        }
+        match expr_ty {
+            Type::Tuple(items) if items.len() != holes.len() => self.report_error(
+                &format!("Pattern has {} elements but the value has type {}", holes.len(), expr_ty),
+                span,
+            ),
+            Type::Tuple(_) | Type::Unknown => {}
+            _ => self.report_error(&format!("Cannot destructure {} as a tuple", expr_ty), span),
+        }
+        *decl_ty = Some(expr_ty.clone());
+    }
+
+    /// Variables and field paths can be read repeatedly without side effects.
+    fn is_place(expr: &Expr) -> bool {
+        match expr {
+            Expr::Var(..) => true,
+            Expr::Field(base, ..) => Self::is_place(base),
+            _ => false,
+        }
+    }
+
+    fn check_type_defined(&mut self, ty: &Type, span: Span) {
+        match ty {
+            Type::Named(name) if !self.is_type_defined(name) => {
+                self.report_error(&format!("Undefined type '{}'", name), span);
+            }
+            Type::Named(name) => self.check_deprecated_type(name, span),
+            Type::Pointer(inner) => self.check_type_defined(inner, span),
+            Type::Function(params, ret, _) => {
+                for param in params {
+                    self.check_type_defined(param, span);
+                }
+                self.check_type_defined(ret, span);
+            }
+            _ => {}
+        }
+    }
+
+    fn check_deprecated_function(&mut self, name: &str, span: Span) {
+        if let Some(note) = self.deprecated_functions.get(name).cloned() {
+            self.report_deprecated(&format!("Function '{}'", name), note, span);
+        }
+    }
+
+    fn check_deprecated_type(&mut self, name: &str, span: Span) {
+        if let Some(note) = self.deprecated_types.get(name).cloned() {
+            self.report_deprecated(&format!("Type '{}'", name), note, span);
+        }
+    }
+
+    fn report_deprecated(&mut self, item: &str, note: Option<String>, span: Span) {
+        if self.in_deprecated {
+            return;
+        }
+        let message = match note {
+            Some(note) => format!("{} is deprecated: {}", item, note),
+            None => format!("{} is deprecated", item),
+        };
+        self.warnings.push(
+            Diagnostic::warning()
+                .with_message(message)
+                .with_code(Lint::Deprecated.name())
+                .with_labels(vec![Label::primary(self.file_id, span)]),
+        );
+    }
+
+    /// Well-formed `#[cfg]` attributes were removed with the items they
+    /// ruled out, so any left are malformed.
+    fn report_cfg_attr(&mut self, attr: &ast::Attribute) {
+        self.report_error(
+            &format!("#[cfg] takes one key = \"value\", with key one of {}", cfg::KEYS.join(", ")),
+            attr.span,
+        );
+    }
+
+    /// A symbol that `mangle` would escape is one the generated code or C
+    /// itself already uses.
+    fn check_symbol(&mut self, symbol: &str, span: Span) {
+        if mangle(symbol) != symbol {
+            self.report_error(&format!("'{}' is reserved in the generated C and cannot be a symbol", symbol), span);
+        }
+    }
+
+    fn check_deprecated_attr(&mut self, attr: &ast::Attribute) {
+        if !matches!(attr.args.as_slice(), [] | [ast::AttrArg::Str(_)]) {
+            self.report_error("#[deprecated] takes at most a note, as in #[deprecated(\"use foo2\")]", attr.span);
+        }
+    }
+
+    fn check_function_attrs(&mut self, func: &ast::Function) {
+        for attr in &func.attrs {
+            match (attr.name.as_str(), attr.args.as_slice()) {
+                ("export", []) => {}
+                ("export", _) => self.report_error("#[export] takes no arguments", attr.span),
+                // Inline functions are emitted `static inline`, which rules
+                // out external linkage.
+                ("inline", []) if func.is_exported() => {
+                    self.report_error("Exported functions cannot be #[inline]", attr.span)
+                }
+                ("inline", []) => {}
+                ("inline", _) => self.report_error("#[inline] takes no arguments", attr.span),
+                ("extern", []) if !func.body.is_empty() => {
+                    self.report_error("#[extern] functions have no body", attr.span)
+                }
+                ("extern", []) => {}
+                ("extern", _) => self.report_error("#[extern] takes no arguments", attr.span),
+                ("cfg", _) => self.report_cfg_attr(attr),
+                ("deprecated", _) => self.check_deprecated_attr(attr),
+                ("export_name" | "no_mangle", _) if func.name == "main" => {
+                    self.report_error(&format!("main cannot have #[{}]", attr.name), attr.span)
+                }
+                ("export_name", [ast::AttrArg::Str(symbol)]) if is_c_identifier(symbol) => {
+                    self.check_symbol(symbol, attr.span)
+                }
+                ("export_name", _) => self.report_error(
+                    "#[export_name] takes the C symbol name, as in #[export_name(\"on_load\")]",
+                    attr.span,
+                ),
+                ("no_mangle", []) => self.check_symbol(&func.name, attr.span),
+                ("no_mangle", _) => self.report_error("#[no_mangle] takes no arguments", attr.span),
+                (name, _) => self.report_error(&format!("Unknown attribute '{}'", name), attr.span),
+            }
+        }
+    }
+
     fn check_function(&mut self, func: &mut ast::Function) -> Result<(), Vec<Diagnostic<FileId>>> {
+        log::event!(log::Level::Debug, "typeck", "checking function '{}'", func.name);
+        self.check_function_attrs(func);
+        self.in_deprecated = func.attr("deprecated").is_some();
+        // The return value of `main` is the exit status.
+        if func.name == "main" && !matches!(func.return_type, Type::Void | Type::I32) {
+            self.report_error(&format!("main must return i32 or nothing, not {}", func.return_type), func.span);
+        }
         let mut local_ctx = Context::new();
         local_ctx.current_return_type = func.return_type.clone();
 
         for (name, ty) in &func.params {
+            self.check_type_defined(ty, func.span);
             local_ctx.variables.insert(name.clone(), ty.clone());
         }
 
@@ -79,14 +690,19 @@ impl TypeChecker {
             }
         }
         self.context = old_ctx;
+        self.in_deprecated = false;
 
         Ok(())
     }
 
     fn check_stmt(&mut self, stmt: &mut Stmt) -> Result<(), Vec<Diagnostic<FileId>>> {
         match stmt {
-            Stmt::Let(name, decl_ty, expr, _) => {
+            Stmt::Let(name, decl_ty, expr, span) | Stmt::Static(name, decl_ty, expr, span) => {
                 let expr_ty = self.check_expr(expr).unwrap_or(Type::Unknown);
+                self.fill_tuple_pattern(decl_ty, &expr_ty, *span);
+                if let Some(decl_ty) = decl_ty {
+                    self.check_type_defined(decl_ty, *span);
+                }
 
                 if let Some(decl_ty) = decl_ty {
                     if !Self::is_convertible(&expr_ty, decl_ty) {
@@ -95,6 +711,12 @@ impl TypeChecker {
                             expr.span(),
                         );
                     }
+                    Self::resolve_none(expr, decl_ty);
+                } else if expr_ty == Type::Option(Box::new(Type::Unknown)) {
+                    self.report_error(
+                        &format!("Cannot infer the type of '{}'; add an Option<T> annotation", name),
+                        *span,
+                    );
                 }
 
                 let ty = decl_ty.clone().unwrap_or(expr_ty);
@@ -115,6 +737,7 @@ impl TypeChecker {
             Stmt::Return(expr, _) => {
                 let expr_ty = self.check_expr(expr).unwrap_or(Type::Unknown);
                 let expected_type = self.context.current_return_type.clone();
+                Self::resolve_none(expr, &expected_type);
                 self.expect_type(&expr_ty, &expected_type, expr.span())?;
             },
             Stmt::Defer(expr, span) => {
@@ -127,32 +750,77 @@ impl TypeChecker {
                     );
                 }
 
-                if let Expr::IntrinsicCall(name, _, _, _) = expr {
-                    if !self.context.in_safe && (name == "__dealloc" || name == "__free") {
-                        self.report_error(
-                            "Memory operations require safe context",
-                            *span
-                        );
-                    }
+                if let Expr::IntrinsicCall(name, _, _, _) = expr
+                    && !self.context.in_safe && (name == "__dealloc" || name == "__free") {
+                    self.report_error(
+                        "Memory operations require safe context",
+                        *span
+                    );
                 }
             },
             Stmt::While(cond, body, _) => {
-                let cond_ty = self.check_expr(cond)?;
+                let cond_ty = self.check_loop_head(cond)?;
                 self.expect_type(&cond_ty, &Type::Bool, cond.span())?;
-                self.check_block(body)?;
+                self.check_loop_body(body)?;
             },
             Stmt::For(name, range, body, _) => {
-                let range_ty = self.check_expr(range)?;
+                let range_ty = self.check_loop_head(range)?;
                 self.expect_type(&range_ty, &Type::Unknown, range.span())?;
 
                 self.context.variables.insert(name.clone(), Type::I32);
-                self.check_block(body)?;
+                self.check_loop_body(body)?;
+            }
+            Stmt::Break(span) => self.check_jump("break", *span),
+            Stmt::Continue(span) => self.check_jump("continue", *span),
+            Stmt::LetPattern(..) | Stmt::IfLet(..) | Stmt::WhileLet(..) | Stmt::ForPattern(..) => {
+                unreachable!("pattern sugar is lowered before type checking")
+            }
+            Stmt::Switch(..) => unreachable!("matches are lowered after type checking"),
+            Stmt::Match(scrutinee, arms, _, scrutinee_ty) => {
+                let ty = self.check_expr(scrutinee).unwrap_or(Type::Unknown);
+                *scrutinee_ty = ty.clone();
+
+                for arm in arms.iter_mut() {
+                    let old_vars = self.context.variables.clone();
+                    self.check_pattern(&mut arm.pattern, &ty);
+                    let mut names = Vec::new();
+                    Self::pattern_bindings(&arm.pattern, &mut names);
+                    let mut seen = HashSet::new();
+                    for (name, span) in names {
+                        if !seen.insert(name) {
+                            self.report_error(&format!("'{}' is bound more than once in the pattern", name), span);
+                        }
+                    }
+
+                    if let Some(guard) = &mut arm.guard {
+                        let guard_ty = self.check_expr(guard).unwrap_or(Type::Unknown);
+                        if guard_ty != Type::Bool && guard_ty != Type::Unknown {
+                            self.report_error(&format!("Match guard must be bool, got {}", guard_ty), guard.span());
+                        }
+                    }
+                    self.check_block(&mut arm.body)?;
+                    self.context.variables = old_vars;
+                }
             }
+            Stmt::Block(stmts, _) => self.check_block(stmts)?,
+            Stmt::TailCall(..) => unreachable!("tail calls are introduced after type checking"),
         }
         Ok(())
     }
 
-    fn check_expr(&mut self, expr:  &mut Expr) -> Result<Type, Vec<Diagnostic<FileId>>> {
+    /// Checks `expr` and records its type on it, so later passes read the
+    /// checked type rather than working it out again.
+    fn check_expr(&mut self, expr: &mut Expr) -> Result<Type, Vec<Diagnostic<FileId>>> {
+        let ty = self.infer_expr(expr)?;
+        if let Some(slot) = expr.type_slot()
+            && ty != Type::Unknown
+        {
+            *slot = ty.clone();
+        }
+        Ok(ty)
+    }
+
+    fn infer_expr(&mut self, expr: &mut Expr) -> Result<Type, Vec<Diagnostic<FileId>>> {
         match expr {
             Expr::Int(_, _, _) => Ok(Type::I32),
             Expr::Bool(_, _, _) => Ok(Type::Bool),
@@ -160,14 +828,23 @@ impl TypeChecker {
             Expr::Var(name, span, _) => {
                 match name.as_str() {
                     "true" | "false" => Ok(Type::Bool),
-                    _ => self.context
-                        .variables
-                        .get(name)
-                        .cloned()
-                        .ok_or_else(|| {
+                    "None" if !self.context.variables.contains_key(name) => {
+                        Ok(Type::Option(Box::new(Type::Unknown)))
+                    }
+                    _ if !self.context.variables.contains_key(name) && self.functions.contains_key(name) => {
+                        let (params, ret, call_conv) = self.functions[name.as_str()].clone();
+                        self.check_deprecated_function(name, *span);
+                        Ok(Type::Function(params, Box::new(ret), call_conv))
+                    }
+                    _ => match self.context.variables.get(name) {
+                        Some(ty) => Ok(ty.clone()),
+                        None => {
                             self.report_error(&format!("Undefined variable '{}'", name), *span);
-                            vec![]
-                        }),
+                            let candidates = self.context.variables.keys().cloned().collect();
+                            self.suggest_name(name, *span, candidates);
+                            Err(vec![])
+                        }
+                    },
                 }
             }
             Expr::BinOp(left, op, right, span, expr_type) => {
@@ -186,8 +863,20 @@ impl TypeChecker {
                             Type::Unknown
                         }
                     }
+                    BinOp::Eq if left_ty == right_ty && self.derives(&left_ty, "Eq") => Type::Bool,
+                    BinOp::Gt | BinOp::Lt if left_ty == right_ty && self.derives(&left_ty, "Ord") => Type::Bool,
+                    BinOp::Gt | BinOp::Eq | BinOp::Lt
+                        if left_ty == right_ty && matches!(left_ty, Type::Named(_)) && self.is_aggregate(&left_ty) => {
+                        let comparison = if matches!(op, BinOp::Eq) { "Eq" } else { "Ord" };
+                        self.report_error(
+                            &format!("Cannot compare {} and {} without #[derive({})]", left_ty, right_ty, comparison),
+                            *span,
+                        );
+                        self.suggest_derive(&left_ty, comparison);
+                        Type::Bool
+                    }
                     BinOp::Gt | BinOp::Eq => {
-                        if Self::is_convertible(&left_ty, &right_ty) {
+                        if Self::is_convertible(&left_ty, &right_ty) && !self.is_aggregate(&left_ty) {
                             Type::Bool
                         } else {
                             self.report_error(
@@ -238,13 +927,86 @@ impl TypeChecker {
                         *span
                     );
                 }
+                Self::resolve_none(value, &target_ty);
 
                 Ok(Type::Void)
             },
-            Expr::Call(name, args, span, _) => {
-                let Some((param_types, return_type)) = self.functions.get(name).cloned() else {
-                    self.report_error(&format!("Undefined function '{}'", name), *span);
+            Expr::Call(name, args, span, call_ty) if name == "Some" && !self.functions.contains_key(name) => {
+                if args.len() != 1 {
+                    self.report_error(&format!("Some expects 1 argument, got {}", args.len()), *span);
+                    return Ok(Type::Unknown);
+                }
+                let inner = self.check_expr(&mut args[0])?;
+                *call_ty = Type::Option(Box::new(inner));
+                Ok(call_ty.clone())
+            },
+            Expr::Call(name, args, span, call_ty)
+                if (name == "likely" || name == "unlikely") && !self.functions.contains_key(name) =>
+            {
+                if args.len() != 1 {
+                    self.report_error(&format!("{} expects 1 argument, got {}", name, args.len()), *span);
                     return Ok(Type::Unknown);
+                }
+                let cond_ty = self.check_expr(&mut args[0])?;
+                if !Self::is_convertible(&cond_ty, &Type::Bool) {
+                    self.report_error(&format!("{} expects a bool, got {}", name, cond_ty), args[0].span());
+                }
+                *call_ty = Type::Bool;
+                Ok(Type::Bool)
+            },
+            Expr::Call(name, args, span, call_ty)
+                if (name == "assert_eq" || name == "assert_ne") && !self.functions.contains_key(name) =>
+            {
+                if args.len() != 2 {
+                    self.report_error(&format!("{} expects 2 arguments, got {}", name, args.len()), *span);
+                    return Ok(Type::Unknown);
+                }
+                let mut left_ty = self.check_expr(&mut args[0])?;
+                let mut right_ty = self.check_expr(&mut args[1])?;
+                Self::resolve_none(&mut args[0], &right_ty);
+                Self::resolve_none(&mut args[1], &left_ty);
+                if Self::is_convertible(&left_ty, &right_ty) && matches!(&left_ty, Type::Option(inner) if **inner == Type::Unknown) {
+                    left_ty = right_ty.clone();
+                } else if Self::is_convertible(&right_ty, &left_ty) && matches!(&right_ty, Type::Option(inner) if **inner == Type::Unknown) {
+                    right_ty = left_ty.clone();
+                }
+                if left_ty == Type::Unknown || right_ty == Type::Unknown {
+                    // Already reported.
+                } else if left_ty != right_ty {
+                    self.report_error(
+                        &format!("{} expects two values of the same type, got {} and {}", name, left_ty, right_ty),
+                        *span,
+                    );
+                } else if self.is_aggregate(&left_ty) && matches!(left_ty, Type::Named(_)) && !self.derives(&left_ty, "Eq") {
+                    self.report_error(&format!("Cannot compare {} and {} without #[derive(Eq)]", left_ty, right_ty), *span);
+                    self.suggest_derive(&left_ty, "Eq");
+                } else if !self.supports("Eq", &left_ty) || !self.is_printable(&left_ty, &mut Vec::new()) {
+                    self.report_error(&format!("{} cannot compare values of type {}", name, left_ty), *span);
+                }
+                *call_ty = Type::Void;
+                Ok(Type::Void)
+            },
+            Expr::Call(name, args, span, _) => {
+                // A variable holding a function shadows functions of its name.
+                let (param_types, return_type) = match self.context.variables.get(name) {
+                    Some(Type::Function(params, ret, _)) => (params.clone(), (**ret).clone()),
+                    Some(ty) => {
+                        self.report_error(&format!("'{}' is a {}, not a function", name, ty), *span);
+                        return Ok(Type::Unknown);
+                    }
+                    None => {
+                        let Some((param_types, return_type, _)) = self.functions.get(name).cloned() else {
+                            self.report_error(&format!("Undefined function '{}'", name), *span);
+                            let candidates = self.functions.keys().cloned().collect();
+                            self.suggest_name(name, *span, candidates);
+                            for arg in args.iter_mut() {
+                                let _ = self.check_expr(arg);
+                            }
+                            return Ok(Type::Unknown);
+                        };
+                        self.check_deprecated_function(name, *span);
+                        (param_types, return_type)
+                    }
                 };
 
                 if args.len() != param_types.len() {
@@ -256,6 +1018,7 @@ impl TypeChecker {
 
                 for (i, (arg, param_ty)) in args.iter_mut().zip(param_types.iter()).enumerate() {
                     let arg_ty = self.check_expr(arg).unwrap_or(Type::Unknown);
+                    Self::resolve_none(arg, param_ty);
                     if !Self::is_convertible(&arg_ty, param_ty) {
                         self.report_error(
                             &format!("Argument {}: expected {}, got {}", i + 1, param_ty, arg_ty),
@@ -266,23 +1029,25 @@ impl TypeChecker {
 
                 Ok(return_type)
             },
-            Expr::IntrinsicCall(name, args, span, _) => match name.as_str() {
-                "__alloc" => {
-                    if args.len() != 1 {
-                        self.report_error("__alloc expects 1 argument", *span);
-                    }
-                    Ok(Type::RawPtr)
-                }
-                "__dealloc" => {
-                    if args.len() != 1 {
-                        self.report_error("__dealloc expects 1 argument", *span);
-                    }
-                    Ok(Type::Void)
-                }
-                _ => {
+            Expr::IntrinsicCall(name, args, span, slot) => {
+                let Some(intrinsic) = intrinsics::lookup(name) else {
                     self.report_error(&format!("Undefined intrinsic '{}'", name), *span);
-                    Ok(Type::Unknown)
+                    return Ok(Type::Unknown);
+                };
+                let arity = intrinsic.arity();
+                if args.len() != arity {
+                    self.report_error(
+                        &format!("{} expects {} argument{}, got {}", name, arity, if arity == 1 { "" } else { "s" }, args.len()),
+                        *span,
+                    );
+                    return Ok(Type::Unknown);
                 }
+                let result = match intrinsic.signature {
+                    Signature::Fixed(signature) => self.check_intrinsic_args(name, args, signature)?,
+                    Signature::Special { .. } => self.check_special_intrinsic(name, args, *span)?,
+                };
+                *slot = result.clone();
+                Ok(result)
             },
             Expr::SafeBlock(stmts, _, _) => {
                 let old_in_safe = self.context.in_safe;
@@ -293,6 +1058,40 @@ impl TypeChecker {
                 result?;
                 Ok(Type::Void)
             },
+            Expr::Block(stmts, value, _, _) => {
+                let old_vars = self.context.variables.clone();
+                let result = stmts.iter_mut()
+                    .try_for_each(|stmt| self.check_stmt(stmt))
+                    .and_then(|()| self.check_expr(value));
+                self.context.variables = old_vars;
+                let ty = result?;
+                if ty == Type::Void {
+                    self.report_error("Block expression must end with a value", value.span());
+                }
+                Ok(ty)
+            },
+            Expr::If(cond, then_value, else_value, _, _) => {
+                let cond_ty = self.check_expr(cond).unwrap_or(Type::Unknown);
+                self.expect_type(&cond_ty, &Type::Bool, cond.span())?;
+                let then_ty = self.check_expr(then_value)?;
+                let else_ty = self.check_expr(else_value)?;
+                let ty = match (&then_ty, &else_ty) {
+                    _ if then_ty == else_ty => then_ty,
+                    (Type::Option(inner), Type::Option(_)) if **inner == Type::Unknown => else_ty,
+                    (Type::Option(_), Type::Option(inner)) if **inner == Type::Unknown => then_ty,
+                    _ => {
+                        self.report_error(
+                            &format!("if and else have different types: {} and {}", then_ty, else_ty),
+                            else_value.span(),
+                        );
+                        return Err(vec![]);
+                    }
+                };
+                // A `None` arm takes the type of the other.
+                Self::resolve_arm(then_value, &ty);
+                Self::resolve_arm(else_value, &ty);
+                Ok(ty)
+            },
             Expr::Cast(expr, target_ty, span, _) => {
                 let source_ty = self.check_expr(expr)?;
 
@@ -303,6 +1102,7 @@ impl TypeChecker {
                     (Type::I32, Type::Pointer(_)) => Ok(target_ty.clone()),
                     (Type::I32, Type::I32) => Ok(source_ty),
                     (Type::I32, Type::Bool) => Ok(target_ty.clone()),
+                    (Type::Named(name), Type::I32) if self.enums.contains_key(name) => Ok(target_ty.clone()),
 
                     _ => {
                         if !Self::is_convertible(&source_ty, target_ty) {
@@ -317,7 +1117,7 @@ impl TypeChecker {
                     }
                 }
             },
-            Expr::Range(start, end, span, _) => {
+            Expr::Range(start, end, _, _) => {
                 let start_ty = self.check_expr(start)?;
                 let end_ty = self.check_expr(end)?;
 
@@ -331,13 +1131,227 @@ impl TypeChecker {
 
                 Ok(Type::Unknown)
             },
+            Expr::StructLit(name, fields, base, span, _) => {
+                let Some(def_fields) = self.structs.get(name).cloned() else {
+                    self.report_error(&format!("Undefined struct '{}'", name), *span);
+                    return Ok(Type::Unknown);
+                };
+                self.check_deprecated_type(name, *span);
+
+                let mut seen = HashSet::new();
+                for (field_name, value) in fields.iter_mut() {
+                    let value_ty = self.check_expr(value).unwrap_or(Type::Unknown);
+                    let Some(field) = def_fields.iter().find(|f| f.name == *field_name) else {
+                        self.report_error(
+                            &format!("Struct '{}' has no field '{}'", name, field_name),
+                            value.span(),
+                        );
+                        continue;
+                    };
+                    if !seen.insert(field_name.clone()) {
+                        self.report_error(
+                            &format!("Field '{}' is specified more than once", field_name),
+                            value.span(),
+                        );
+                    }
+                    if !Self::is_convertible(&value_ty, &field.ty) {
+                        self.report_error(
+                            &format!("Field '{}': expected {}, got {}", field_name, field.ty, value_ty),
+                            value.span(),
+                        );
+                    }
+                    Self::resolve_none(value, &field.ty);
+                }
+
+                if let Some(mut base_expr) = base.take() {
+                    let base_ty = self.check_expr(&mut base_expr).unwrap_or(Type::Unknown);
+                    if self.unions.contains(name) {
+                        self.report_error(
+                            &format!("Union '{}' literal cannot use update syntax", name),
+                            base_expr.span(),
+                        );
+                    } else if base_ty != Type::Named(name.clone()) {
+                        self.report_error(
+                            &format!("Update base: expected {}, got {}", name, base_ty),
+                            base_expr.span(),
+                        );
+                    } else if !Self::is_place(&base_expr) {
+                        self.report_error(
+                            "Update base must be a variable or field; bind it with 'let' first",
+                            base_expr.span(),
+                        );
+                    } else {
+                        for field in def_fields.iter().filter(|f| seen.insert(f.name.clone())) {
+                            let read = Expr::Field(base_expr.clone(), field.name.clone(), base_expr.span(), field.ty.clone());
+                            fields.push((field.name.clone(), read));
+                        }
+                    }
+                }
+
+                if self.unions.contains(name) {
+                    if seen.len() != 1 {
+                        self.report_error(
+                            &format!("Union '{}' literal must initialize exactly one field", name),
+                            *span,
+                        );
+                    }
+                    return Ok(Type::Named(name.clone()));
+                }
+
+                for field in &def_fields {
+                    if !seen.contains(&field.name) {
+                        self.report_error(
+                            &format!("Missing field '{}' in '{}' literal", field.name, name),
+                            *span,
+                        );
+                    }
+                }
+
+                Ok(Type::Named(name.clone()))
+            },
+            Expr::Field(base, field, span, field_ty) => {
+                let base_ty = self.check_expr(base)?;
+                if let Type::Tuple(items) = &base_ty {
+                    let Some(item) = field.parse::<usize>().ok().and_then(|i| items.get(i)) else {
+                        self.report_error(
+                            &format!("Tuple {} has no element '{}'", base_ty, field),
+                            *span,
+                        );
+                        return Ok(Type::Unknown);
+                    };
+                    *field_ty = item.clone();
+                    return Ok(item.clone());
+                }
+                let struct_name = match &base_ty {
+                    Type::Named(name) => Some(name.clone()),
+                    Type::Pointer(inner) => match &**inner {
+                        Type::Named(name) => Some(name.clone()),
+                        _ => None,
+                    },
+                    _ => None,
+                };
+
+                let Some(def_fields) = struct_name.as_ref().and_then(|n| self.structs.get(n)).cloned() else {
+                    self.report_error(
+                        &format!("Type {} has no fields", base_ty),
+                        *span,
+                    );
+                    return Ok(Type::Unknown);
+                };
+
+                if struct_name.as_ref().is_some_and(|n| self.unions.contains(n)) && !self.context.in_safe {
+                    self.report_error(
+                        &format!("Accessing union field '{}' requires safe context", field),
+                        *span,
+                    );
+                }
+
+                match def_fields.iter().find(|f| f.name == *field) {
+                    Some(def) => {
+                        *field_ty = def.ty.clone();
+                        Ok(def.ty.clone())
+                    }
+                    None => {
+                        self.report_error(
+                            &format!("Struct '{}' has no field '{}'", base_ty, field),
+                            *span,
+                        );
+                        Ok(Type::Unknown)
+                    }
+                }
+            },
+            Expr::IsVariant(.., ty) | Expr::VariantField(.., ty) => Ok(ty.clone()),
+            Expr::Cfg(key, _, span, ty) => {
+                if !cfg::KEYS.contains(&key.as_str()) {
+                    self.report_error(
+                        &format!("Unknown cfg key '{}': expected {}", key, cfg::KEYS.join(", ")),
+                        *span,
+                    );
+                }
+                *ty = Type::Bool;
+                Ok(Type::Bool)
+            }
+            Expr::Tuple(items, _, tuple_ty) => {
+                let mut types = Vec::new();
+                for item in items.iter_mut() {
+                    types.push(self.check_expr(item).unwrap_or(Type::Unknown));
+                }
+                *tuple_ty = Type::Tuple(types);
+                Ok(tuple_ty.clone())
+            },
+            Expr::EnumVariant(enum_name, variant, args, span, variant_ty) => {
+                let Some(variants) = self.enums.get(enum_name).cloned() else {
+                    self.report_error(&format!("Undefined enum '{}'", enum_name), *span);
+                    return Ok(Type::Unknown);
+                };
+                self.check_deprecated_type(enum_name, *span);
+                if variant == "from" && !variants.iter().any(|v| v.name == "from") {
+                    if variants.iter().any(|v| !v.fields.is_empty()) {
+                        self.report_error(
+                            &format!("Enum '{}' has variants with values and cannot be built from an integer", enum_name),
+                            *span,
+                        );
+                    }
+                    if args.len() != 1 {
+                        self.report_error(&format!("{}::from expects 1 argument", enum_name), *span);
+                        return Ok(Type::Unknown);
+                    }
+                    let arg_ty = self.check_expr(&mut args[0])?;
+                    self.expect_type(&arg_ty, &Type::I32, args[0].span())?;
+                    *variant_ty = Type::Option(Box::new(Type::Named(enum_name.clone())));
+                    return Ok(variant_ty.clone());
+                }
+                if variant == "to_string" && !variants.iter().any(|v| v.name == "to_string") {
+                    if variants.iter().any(|v| !v.fields.is_empty()) {
+                        self.report_error(
+                            &format!("Enum '{}' has variants with values and cannot be converted to a string", enum_name),
+                            *span,
+                        );
+                    }
+                    if args.len() != 1 {
+                        self.report_error(&format!("{}::to_string expects 1 argument", enum_name), *span);
+                        return Ok(Type::Unknown);
+                    }
+                    let arg_ty = self.check_expr(&mut args[0])?;
+                    self.expect_type(&arg_ty, &Type::Named(enum_name.clone()), args[0].span())?;
+                    *variant_ty = Type::String;
+                    return Ok(Type::String);
+                }
+                let Some(def) = variants.iter().find(|v| v.name == *variant) else {
+                    self.report_error(
+                        &format!("Enum '{}' has no variant '{}'", enum_name, variant),
+                        *span,
+                    );
+                    return Ok(Type::Unknown);
+                };
+
+                if args.len() != def.fields.len() {
+                    self.report_error(
+                        &format!(
+                            "Variant '{}::{}' expects {} values, got {}",
+                            enum_name, variant, def.fields.len(), args.len()
+                        ),
+                        *span,
+                    );
+                }
+
+                for (i, (arg, field_ty)) in args.iter_mut().zip(def.fields.iter()).enumerate() {
+                    let arg_ty = self.check_expr(arg).unwrap_or(Type::Unknown);
+                    Self::resolve_none(arg, field_ty);
+                    if !Self::is_convertible(&arg_ty, field_ty) {
+                        self.report_error(
+                            &format!("Value {}: expected {}, got {}", i + 1, field_ty, arg_ty),
+                            arg.span(),
+                        );
+                    }
+                }
+
+                Ok(Type::Named(enum_name.clone()))
+            },
             Expr::Print(expr, span, _) => {
                 let expr_ty = self.check_expr(expr)?;
 
-                if !matches!(
-                expr_ty,
-                Type::I32 | Type::Bool | Type::String | Type::RawPtr | Type::Pointer(_)
-            ) {
+                if !self.is_printable(&expr_ty, &mut Vec::new()) {
                     self.report_error(
                         &format!("Cannot print value of type {}", expr_ty),
                         *span,
@@ -363,6 +1377,88 @@ impl TypeChecker {
         }
     }
 
+    /// Checks the arguments of a call to an intrinsic with a fixed
+    /// signature and returns its result type.
+    fn check_intrinsic_args(
+        &mut self,
+        name: &str,
+        args: &mut [Expr],
+        signature: fn() -> (Vec<Type>, Type),
+    ) -> Result<Type, Vec<Diagnostic<FileId>>> {
+        let (params, result) = signature();
+        for (arg, param) in args.iter_mut().zip(&params) {
+            let arg_ty = self.check_expr(arg)?;
+            // String literals can be sent as they are.
+            let sendable = arg_ty == Type::String && *param == Type::RawPtr && name != "__net_read";
+            if !Self::is_convertible(&arg_ty, param) && !sendable && arg_ty != Type::Unknown {
+                self.report_error(&format!("{} expects {}, got {}", name, param, arg_ty), arg.span());
+            }
+        }
+        Ok(result)
+    }
+
+    /// Checks a call to one of the intrinsics the registry leaves to the
+    /// type checker, whose argument count is already right, and returns
+    /// its result type.
+    fn check_special_intrinsic(&mut self, name: &str, args: &mut [Expr], span: Span) -> Result<Type, Vec<Diagnostic<FileId>>> {
+        match name {
+            "__is_some" | "__unwrap" => Ok(match self.check_expr(&mut args[0])? {
+                Type::Option(inner) if name == "__unwrap" => *inner,
+                Type::Option(_) => Type::Bool,
+                other => {
+                    self.report_error(&format!("{} expects an Option, got {}", name, other), args[0].span());
+                    Type::Unknown
+                }
+            }),
+            "__hash" => {
+                let ty = self.check_expr(&mut args[0])?;
+                if !self.supports("Hash", &ty) {
+                    self.report_error(&format!("Cannot hash {} without #[derive(Hash)]", ty), args[0].span());
+                    self.suggest_derive(&ty, "Hash");
+                }
+                Ok(Type::I32)
+            }
+            "__include_str" | "__include_bytes" => {
+                if !matches!(args[0], Expr::Str(..)) {
+                    self.report_error(&format!("{} expects a string literal path", &name[2..]), args[0].span());
+                }
+                Ok(if name == "__include_str" { Type::String } else { Type::RawPtr })
+            }
+            "__chars" => {
+                self.report_error("__chars(s) can only be the range of a for loop", span);
+                Ok(Type::Unknown)
+            }
+            "__enumerate" => {
+                self.report_error("__enumerate(list) can only be the range of a 'for (index, item)' loop", span);
+                Ok(Type::Unknown)
+            }
+            "__signal_on" => {
+                // Both arguments are names rather than values: a signal
+                // and the function to run when it arrives.
+                match &args[0] {
+                    Expr::Var(signal, _, _) if SIGNALS.contains(&signal.as_str()) => {}
+                    other => self.report_error(
+                        &format!("signal.on expects one of {}", SIGNALS.join(", ")),
+                        other.span(),
+                    ),
+                }
+                match &args[1] {
+                    Expr::Var(handler, _, _) => match self.functions.get(handler) {
+                        Some((params, Type::Void, CallConv::C)) if params.is_empty() => {}
+                        Some(_) => self.report_error(
+                            &format!("Signal handler '{}' must take no arguments and return nothing", handler),
+                            args[1].span(),
+                        ),
+                        None => self.report_error(&format!("Undefined function '{}'", handler), args[1].span()),
+                    },
+                    other => self.report_error("signal.on expects a function name", other.span()),
+                }
+                Ok(Type::Void)
+            }
+            _ => unreachable!("every special intrinsic is checked above"),
+        }
+    }
+
     fn is_convertible(from: &Type, to: &Type) -> bool {
         match (from, to) {
             (Type::I32, Type::Bool) => true,
@@ -372,10 +1468,64 @@ impl TypeChecker {
             (Type::I32, Type::Pointer(_)) => true,
             (Type::I32, Type::I32) => true,
             (Type::Pointer(a), Type::Pointer(b)) => a == b,
+            (Type::Option(a), Type::Option(_)) if **a == Type::Unknown => true,
             _ => from == to
         }
     }
 
+    /// Whether `print` can show a value of `ty`: a scalar or pointer, or a
+    /// struct, enum, `Option` or tuple of printable parts. A union has no
+    /// record of which field holds a value. `seen` holds the types being
+    /// checked, which a type that contains itself by value would revisit.
+    fn is_printable(&self, ty: &Type, seen: &mut Vec<String>) -> bool {
+        match ty {
+            Type::I32 | Type::Bool | Type::String | Type::RawPtr | Type::Pointer(_) => true,
+            Type::Option(inner) => self.is_printable(inner, seen),
+            Type::Tuple(items) => items.iter().all(|item| self.is_printable(item, seen)),
+            Type::Named(name) if self.unions.contains(name) || seen.contains(name) => false,
+            Type::Named(name) => {
+                seen.push(name.clone());
+                let printable = match (self.structs.get(name), self.enums.get(name)) {
+                    (Some(fields), _) => fields.iter().all(|field| self.is_printable(&field.ty, seen)),
+                    (None, Some(variants)) => variants.iter()
+                        .flat_map(|variant| variant.fields.iter())
+                        .all(|field| self.is_printable(field, seen)),
+                    (None, None) => false,
+                };
+                seen.pop();
+                printable
+            }
+            _ => false,
+        }
+    }
+
+    /// `None` carries no payload to infer its type from, so it takes the
+    /// Option type expected at the place it is used.
+    fn resolve_none(expr: &mut Expr, expected: &Type) {
+        if let Expr::Var(name, _, ty) = expr
+            && name == "None"
+            && matches!(expected, Type::Option(_)) {
+            *ty = expected.clone();
+        }
+    }
+
+    /// Gives the value an if-expression arm yields, and the arm, type `ty`
+    /// if it is `None`.
+    fn resolve_arm(arm: &mut Expr, ty: &Type) {
+        match arm {
+            Expr::Block(_, value, _, slot) => {
+                Self::resolve_arm(value, ty);
+                *slot = ty.clone();
+            }
+            Expr::If(_, then_value, else_value, _, slot) => {
+                Self::resolve_arm(then_value, ty);
+                Self::resolve_arm(else_value, ty);
+                *slot = ty.clone();
+            }
+            _ => Self::resolve_none(arm, ty),
+        }
+    }
+
     fn check_block(&mut self, stmts: &mut [Stmt]) -> Result<(), Vec<Diagnostic<FileId>>> {
         let old_vars = self.context.variables.clone();
         for stmt in stmts {
@@ -385,6 +1535,56 @@ impl TypeChecker {
         Ok(())
     }
 
+    /// A loop condition or range. It is evaluated before every step, so a
+    /// jump in it would not belong to any loop body.
+    fn check_loop_head(&mut self, expr: &mut Expr) -> Result<Type, Vec<Diagnostic<FileId>>> {
+        let loops = std::mem::take(&mut self.context.loops);
+        let result = self.check_expr(expr);
+        self.context.loops = loops;
+        result
+    }
+
+    fn check_loop_body(&mut self, body: &mut [Stmt]) -> Result<(), Vec<Diagnostic<FileId>>> {
+        self.context.loops += 1;
+        let result = self.check_block(body);
+        self.context.loops -= 1;
+        result
+    }
+
+    /// `break` and `continue` need a loop body around them.
+    fn check_jump(&mut self, keyword: &str, span: Span) {
+        if self.context.loops == 0 {
+            self.report_error(&format!("{} outside of a loop body", keyword), span);
+        }
+    }
+
+    /// Attaches a fix to the error just reported: replace `span` with
+    /// `replacement`.
+    fn suggest(&mut self, message: &str, span: Span, replacement: String) {
+        if let Some(error) = self.errors.last_mut() {
+            error.notes.push(format!("help: {}", message));
+        }
+        self.suggestions.push(Suggestion { message: message.to_string(), file_id: self.file_id, span, replacement });
+    }
+
+    /// Suggests the closest of `candidates` for `name`, misspelled at the
+    /// start of `span`.
+    fn suggest_name(&mut self, name: &str, span: Span, candidates: Vec<String>) {
+        if let Some(closest) = fix::closest_name(name, candidates.iter().map(String::as_str)) {
+            let span = Span::new(span.start(), span.start().0 + name.len() as u32);
+            self.suggest(&format!("did you mean '{}'?", closest), span, closest.to_string());
+        }
+    }
+
+    /// Suggests deriving `derived` on the type `ty` names.
+    fn suggest_derive(&mut self, ty: &Type, derived: &str) {
+        if let Type::Named(name) = ty
+            && let Some(span) = self.definitions.get(name) {
+            let at = Span::new(span.start(), span.start());
+            self.suggest(&format!("add #[derive({})] to '{}'", derived, name), at, format!("#[derive({})]\n", derived));
+        }
+    }
+
     fn report_error(&mut self, message: &str, span: Span) {
         self.errors.push(
             Diagnostic::error()
@@ -392,4 +1592,18 @@ impl TypeChecker {
                 .with_labels(vec![Label::primary(self.file_id, span)]),
         );
     }
-}
\ No newline at end of file
+}
+
+/// Whether `attrs` mark their item `#[deprecated]`, and with what note.
+fn deprecation(attrs: &[ast::Attribute]) -> Option<Option<String>> {
+    let attr = attrs.iter().find(|attr| attr.name == "deprecated")?;
+    Some(match attr.args.as_slice() {
+        [ast::AttrArg::Str(note)] => Some(note.clone()),
+        _ => None,
+    })
+}
+
+fn is_c_identifier(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
@@ -12,12 +12,55 @@ fn test_file_path(relative_path: &str) -> PathBuf {
         .join(relative_path)
 }
 
+/// Compiles `source` as `main.vrv` with `args` in a fresh directory named
+/// after `name`, builds the generated C there with `cc` and `cc_args` and
+/// runs the program in it. Returns the directory, which the caller removes,
+/// and what the program did.
+#[cfg(unix)]
+fn build_and_run(name: &str, source: &str, args: &[&str], cc_args: &[&str]) -> (PathBuf, std::process::Output) {
+    let project = std::env::temp_dir().join(format!("verve-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&project).unwrap();
+    std::fs::write(project.join("main.vrv"), source).unwrap();
+    let output = Command::new(get_compiler_path())
+        .arg("main.vrv")
+        .args(args)
+        .current_dir(&project)
+        .output()
+        .expect("Failed to run compiler");
+    assert!(output.status.success(), "Compilation failed\nSTDERR:\n{}", String::from_utf8_lossy(&output.stderr));
+
+    let c_source = files_under(&project).into_iter()
+        .find(|path| path.ends_with("output.c"))
+        .expect("No generated C");
+    let program = project.join("program");
+    let build = Command::new("cc").args(cc_args).arg(&c_source).arg("-o").arg(&program).output().expect("Failed to run cc");
+    assert!(build.status.success(), "cc failed\nSTDERR:\n{}", String::from_utf8_lossy(&build.stderr));
+    let output = Command::new(&program)
+        .current_dir(&project)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .expect("Failed to run program");
+    (project, output)
+}
+
+/// Every file under `dir`, recursively.
+#[cfg(unix)]
+fn files_under(dir: &std::path::Path) -> Vec<PathBuf> {
+    let entries = std::fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().path());
+    entries.flat_map(|path| if path.is_dir() { files_under(&path) } else { vec![path] }).collect()
+}
+
 fn run_compiler(relative_path: &str) -> (bool, String, String) {
+    run_compiler_with_args(relative_path, &[])
+}
+
+fn run_compiler_with_args(relative_path: &str, args: &[&str]) -> (bool, String, String) {
     let compiler = get_compiler_path();
     let input_path = test_file_path(relative_path);
 
     let output = Command::new(compiler)
         .arg(input_path)
+        .args(args)
         .output()
         .expect("Failed to run compiler");
 
@@ -59,4 +102,838 @@ fn test_invalid_program() {
         "Missing type error message\nSTDERR:\n{}",
         stderr
     );
-}
\ No newline at end of file
+}
+#[test]
+fn test_recursive_types() {
+    let (success, stdout, stderr) = run_compiler("valid/recursive_types.vrv");
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_infinite_size_type() {
+    let (success, stdout, stderr) = run_compiler("invalid/infinite_type.vrv");
+
+    assert!(
+        !success,
+        "Invalid program compiled successfully\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+
+    assert!(
+        stderr.contains("infinite size"),
+        "Missing cycle diagnostic\nSTDERR:\n{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_unions() {
+    let (success, stdout, stderr) = run_compiler("valid/unions.vrv");
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+
+    let (success, stdout, stderr) = run_compiler("invalid/union_outside_safe.vrv");
+
+    assert!(
+        !success && stderr.contains("requires safe context"),
+        "Union access outside safe block was accepted\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_static_locals() {
+    let (success, stdout, stderr) = run_compiler("valid/static_locals.vrv");
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_enum_conversions() {
+    let (success, stdout, stderr) = run_compiler("valid/enum_conversions.vrv");
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+
+    let (success, stdout, stderr) = run_compiler("invalid/payload_enum_from.vrv");
+
+    assert!(
+        !success && stderr.contains("cannot be built from an integer"),
+        "Conversion into a payload enum was accepted\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_struct_update() {
+    let (success, stdout, stderr) = run_compiler("valid/struct_update.vrv");
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+
+    let (success, stdout, stderr) = run_compiler("invalid/struct_update_call_base.vrv");
+
+    assert!(
+        !success && stderr.contains("Update base must be a variable or field"),
+        "Struct update from a call was accepted\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_destructuring() {
+    let (success, stdout, stderr) = run_compiler("valid/destructuring.vrv");
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+
+    let (success, stdout, stderr) = run_compiler("invalid/tuple_pattern_arity.vrv");
+
+    assert!(
+        !success && stderr.contains("Pattern has 3 elements"),
+        "Tuple pattern with the wrong arity was accepted\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_match_patterns() {
+    let (success, stdout, stderr) = run_compiler("valid/match_patterns.vrv");
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+
+    let (success, stdout, stderr) = run_compiler("invalid/non_exhaustive_match.vrv");
+
+    assert!(
+        !success && stderr.contains("does not handle every value"),
+        "Non-exhaustive match was accepted\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_if_let_and_while_let() {
+    let (success, stdout, stderr) = run_compiler("valid/if_while_let.vrv");
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_stack_allocation() {
+    let (success, stdout, stderr) = run_compiler("valid/stack_allocation.vrv");
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_debug_alloc() {
+    let (success, stdout, stderr) = run_compiler_with_args("valid/debug_alloc.vrv", &["--debug-alloc"]);
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn test_debug_alloc_leak_report() {
+    let leaky = "fn make(size: i32) -> *i32 {\n    return __alloc(size) as *i32;\n}\n\n\
+                 fn main() -> i32 {\n    let kept = make(12);\n    let freed = make(4);\n    __dealloc(freed);\n    return 0;\n}\n";
+    let (project, output) = build_and_run("debug-alloc", leaky, &["--debug-alloc"], &[]);
+    std::fs::remove_dir_all(&project).unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "STDERR:\n{}", stderr);
+    // Only the allocation that is never freed is reported, at its call site.
+    assert!(stderr.contains("leak: 12 bytes allocated at main.vrv:2:12\n"), "STDERR:\n{}", stderr);
+    assert!(stderr.contains("leak report: 1 allocation(s), 12 bytes never freed"), "STDERR:\n{}", stderr);
+
+    let freed = std::fs::read_to_string(test_file_path("valid/debug_alloc.vrv")).unwrap();
+    let (project, output) = build_and_run("debug-alloc-freed", &freed, &["--debug-alloc"], &[]);
+    std::fs::remove_dir_all(&project).unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(String::from_utf8_lossy(&output.stdout).lines().next(), Some("3"));
+    assert!(!stderr.contains("leak"), "STDERR:\n{}", stderr);
+}
+
+#[test]
+fn test_sanitize_flags() {
+    let (success, stdout, stderr) =
+        run_compiler_with_args("valid/debug_alloc.vrv", &["--sanitize=address,undefined"]);
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+
+    let (success, _, stderr) = run_compiler_with_args("valid/debug_alloc.vrv", &["--sanitize=thread"]);
+    assert!(!success, "Unknown sanitizer should be rejected");
+    assert!(stderr.contains("invalid value"), "Unexpected error output:\n{}", stderr);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_sanitize_use_after_free() {
+    // The allocation does not escape, so without the sanitizer it would be
+    // moved to the stack where AddressSanitizer cannot see the free.
+    let source = "struct Cell {\n    value: i32,\n}\n\n\
+                  fn main() -> i32 {\n    let cell = __alloc(4) as *Cell;\n    cell.value = 1;\n    __dealloc(cell);\n    print(cell.value);\n    return 0;\n}\n";
+    let cc_args = verve_lang::cli::sanitizer_flags(&[verve_lang::cli::Sanitizer::Address]);
+    let cc_args = cc_args.iter().map(String::as_str).collect::<Vec<_>>();
+    let (project, output) = build_and_run("sanitize", source, &["--sanitize=address"], &cc_args);
+    std::fs::remove_dir_all(&project).unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "Use after free was not caught");
+    assert!(stderr.contains("AddressSanitizer: heap-use-after-free"), "STDERR:\n{}", stderr);
+}
+
+#[test]
+fn test_coverage_line_mapping() {
+    let (success, stdout, stderr) = run_compiler_with_args("valid/coverage.vrv", &["--coverage"]);
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn test_coverage_counters() {
+    let source = std::fs::read_to_string(test_file_path("valid/coverage.vrv")).unwrap();
+    let (project, output) = build_and_run("coverage", &source, &["--coverage"], &["--coverage"]);
+    let files = files_under(&project);
+    let c_source = files.iter().find(|path| path.ends_with("output.c")).unwrap();
+    let code = std::fs::read_to_string(c_source).unwrap();
+    let counters = files.iter().filter(|path| path.extension().is_some_and(|ext| ext == "gcda")).count();
+    std::fs::remove_dir_all(&project).unwrap();
+    assert!(output.status.success(), "STDERR:\n{}", String::from_utf8_lossy(&output.stderr));
+    // Statements carry the Verve line they came from, so gcov reports
+    // counts against main.vrv.
+    let mapped = code.split("#line 2 \"main.vrv\"\n").nth(1).and_then(|rest| rest.lines().next());
+    assert!(mapped.is_some_and(|line| line.trim_start().starts_with("if (") && line.contains("n > 10")), "{}", code);
+    assert_eq!(counters, 1, "The program wrote no coverage counters");
+}
+
+#[test]
+fn test_instrument_functions() {
+    let (success, stdout, stderr) =
+        run_compiler_with_args("valid/instrument_functions.vrv", &["--instrument-functions"]);
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn test_instrument_functions_profile() {
+    let source = std::fs::read_to_string(test_file_path("valid/instrument_functions.vrv")).unwrap();
+    let (project, output) = build_and_run("instrument", &source, &["--instrument-functions"], &[]);
+    let profile = std::fs::read_to_string(project.join("verve.prof"));
+    std::fs::remove_dir_all(&project).unwrap();
+    assert!(output.status.success(), "STDERR:\n{}", String::from_utf8_lossy(&output.stderr));
+    let profile = profile.expect("The program wrote no profile");
+    let calls = profile.lines()
+        .skip(1)
+        .map(|line| line.split('\t').take(2).collect::<Vec<_>>().join(" "))
+        .collect::<Vec<_>>();
+    // fib(24) makes 150049 calls, and work runs it twice.
+    assert_eq!(calls, ["fib 300098", "work 2", "main 1"], "{}", profile);
+}
+
+#[test]
+fn test_backend_selection() {
+    let (success, stdout, stderr) = run_compiler_with_args("valid/arithmetic.vrv", &["--backend", "c"]);
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+
+    let (success, _, stderr) = run_compiler_with_args("valid/arithmetic.vrv", &["--backend", "gpu"]);
+    assert!(!success, "Unknown backend should be rejected");
+    assert!(stderr.contains("Unknown backend 'gpu' (available: c)"), "Unexpected error output:\n{}", stderr);
+}
+
+#[test]
+fn test_c_keyword_names() {
+    let (success, stdout, stderr) = run_compiler("valid/c_names.vrv");
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_defer_runs_on_return() {
+    let (success, stdout, stderr) = run_compiler_with_args("valid/defer_return.vrv", &["--debug-alloc"]);
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_block_scopes() {
+    let (success, stdout, stderr) = run_compiler("valid/block_scopes.vrv");
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_branch_hints() {
+    for args in [&[][..], &["--gnu-extensions"][..]] {
+        let (success, stdout, stderr) = run_compiler_with_args("valid/branch_hints.vrv", args);
+
+        assert!(
+            success,
+            "Compilation failed with {:?}\nSTDOUT:\n{}\nSTDERR:\n{}",
+            args, stdout, stderr
+        );
+    }
+}
+
+#[test]
+fn test_tail_calls() {
+    let (success, stdout, stderr) = run_compiler("valid/tail_calls.vrv");
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_loop_optimizations() {
+    let (success, stdout, stderr) = run_compiler_with_args("valid/loop_opts.vrv", &["-O2"]);
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_c_optimization_flags() {
+    let (success, stdout, stderr) =
+        run_compiler_with_args("valid/arithmetic.vrv", &["-O2", "--fast-math", "--verbose"]);
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+    assert!(stdout.contains("C optimization flags: -O3 -flto -ffast-math"), "Unexpected output:\n{}", stdout);
+
+    let (success, _, stderr) = run_compiler_with_args("valid/arithmetic.vrv", &["-O3"]);
+    assert!(!success, "Unsupported optimization level should be rejected");
+    assert!(stderr.contains("invalid value"), "Unexpected error output:\n{}", stderr);
+}
+
+#[test]
+fn test_stack_guard() {
+    for args in [&["--stack-guard"][..], &["--stack-guard=100"][..]] {
+        let (success, stdout, stderr) = run_compiler_with_args("valid/stack_guard.vrv", args);
+
+        assert!(
+            success,
+            "Compilation failed with {:?}\nSTDOUT:\n{}\nSTDERR:\n{}",
+            args, stdout, stderr
+        );
+    }
+}
+
+#[test]
+fn test_signal_handlers() {
+    let (success, stdout, stderr) = run_compiler("valid/signals.vrv");
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_sockets() {
+    for triple in ["x86_64-pc-windows-msvc", "x86_64-unknown-linux-gnu"] {
+        let (success, stdout, stderr) = run_compiler_with_args("valid/net.vrv", &["--target-triple", triple]);
+
+        assert!(
+            success,
+            "Compilation failed for {}\nSTDOUT:\n{}\nSTDERR:\n{}",
+            triple, stdout, stderr
+        );
+    }
+}
+
+#[test]
+fn test_json() {
+    let (success, stdout, stderr) = run_compiler("valid/json.vrv");
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_utf8_strings() {
+    let (success, stdout, stderr) = run_compiler("valid/utf8.vrv");
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_unicode_identifiers() {
+    let (success, stdout, stderr) = run_compiler("valid/unicode_idents.vrv");
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_optional_semicolons() {
+    let (success, stdout, stderr) = run_compiler("valid/optional_semicolons.vrv");
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_block_comments() {
+    let (success, stdout, stderr) = run_compiler("valid/comments.vrv");
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_print_checked_types() {
+    let (success, stdout, stderr) = run_compiler("valid/print_types.vrv");
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_block_expressions() {
+    for args in [&[][..], &["--gnu-extensions"]] {
+        let (success, stdout, stderr) = run_compiler_with_args("valid/block_expressions.vrv", args);
+
+        assert!(
+            success,
+            "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+            stdout, stderr
+        );
+    }
+}
+
+#[test]
+fn test_exit_status() {
+    let (success, stdout, stderr) = run_compiler("valid/exit_status.vrv");
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_global_cycle() {
+    let (success, stdout, stderr) = run_compiler("invalid/global_cycle.vrv");
+
+    assert!(
+        !success,
+        "Invalid program compiled successfully\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+
+    assert!(
+        stderr.contains("Global initializers form a cycle: width -> height -> area -> width"),
+        "Missing cycle diagnostic\nSTDERR:\n{}",
+        stderr
+    );
+    assert!(!stderr.contains("Undefined variable"), "Cycle reported twice\nSTDERR:\n{}", stderr);
+}
+
+#[test]
+fn test_multiple_input_files() {
+    let shapes = test_file_path("valid/multi_file/shapes.vrv");
+    let (success, stdout, stderr) = run_compiler_with_args("valid/multi_file/main.vrv", &[shapes.to_str().unwrap()]);
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+
+    let (success, _, _) = run_compiler("valid/multi_file/main.vrv");
+    assert!(!success, "main.vrv compiled without the file defining Rect");
+}
+
+#[test]
+fn test_prelude_program() {
+    let (success, stdout, stderr) = run_compiler("valid/prelude.vrv");
+
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_standard_library() {
+    let (success, stdout, stderr) = run_compiler("valid/std_modules.vrv");
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+
+    let output = Command::new(get_compiler_path())
+        .arg(test_file_path("valid/std_modules.vrv"))
+        .env("VERVE_STD", test_file_path("no_such_std"))
+        .output()
+        .expect("Failed to run compiler");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "Compiled with a missing VERVE_STD");
+    assert!(stderr.contains("VERVE_STD:"), "STDERR:\n{}", stderr);
+}
+
+#[test]
+fn test_wasi_target() {
+    let (success, stdout, stderr) = run_compiler("valid/cli_tool.vrv");
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+
+    let output = Command::new(get_compiler_path())
+        .arg(test_file_path("valid/cli_tool.vrv"))
+        .args(["--target", "wasm32-wasi"])
+        .env("WASI_SDK_PATH", test_file_path("no_such_sdk"))
+        .output()
+        .expect("Failed to run compiler");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "Built without a wasi-sdk");
+    assert!(stderr.contains("set WASI_SDK_PATH"), "STDERR:\n{}", stderr);
+}
+
+#[test]
+fn test_interface_files() {
+    let interface = std::env::temp_dir().join(format!("geometry-{}.vervei", std::process::id()));
+    let (success, stdout, stderr) =
+        run_compiler_with_args("valid/interface/geometry.vrv", &["--emit-interface", interface.to_str().unwrap()]);
+    assert!(
+        success,
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+
+    let (success, stdout, stderr) = run_compiler_with_args("valid/interface/app.vrv", &[interface.to_str().unwrap()]);
+    std::fs::remove_file(&interface).unwrap();
+    assert!(
+        success,
+        "Compilation against the interface failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        stdout, stderr
+    );
+}
+
+#[test]
+fn test_source_from_stdin() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = Command::new(get_compiler_path())
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run compiler");
+    child.stdin.take().unwrap().write_all(b"fn main() -> i32 {\n    print(42)\n    return 0\n}\n").unwrap();
+    let output = child.wait_with_output().unwrap();
+
+    assert!(
+        output.status.success(),
+        "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn test_build_hooks() {
+    let project = std::env::temp_dir().join(format!("verve-hooks-{}", std::process::id()));
+    std::fs::create_dir_all(&project).unwrap();
+    std::fs::write(
+        project.join("verve.toml"),
+        "[build]\npre = \"cp template.vrv main.vrv\"\npost = \"cp $VERVE_C_SOURCE copy.c && echo $VERVE_PROFILE > profile.txt\"\n",
+    ).unwrap();
+    std::fs::write(project.join("template.vrv"), "fn main() -> i32 {\n    print(1)\n    return 0\n}\n").unwrap();
+
+    // main.vrv does not exist until the pre hook writes it.
+    let output = Command::new(get_compiler_path())
+        .arg(project.join("main.vrv"))
+        .arg("-O0")
+        .current_dir(&project)
+        .output()
+        .expect("Failed to run compiler");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}", stdout, stderr);
+    assert!(std::fs::read_to_string(project.join("copy.c")).unwrap().contains("int main"));
+    assert_eq!(std::fs::read_to_string(project.join("profile.txt")).unwrap(), "debug\n");
+
+    std::fs::write(project.join("verve.toml"), "[build]\npre = \"exit 3\"\n").unwrap();
+    let output = Command::new(get_compiler_path())
+        .arg(project.join("main.vrv"))
+        .current_dir(&project)
+        .output()
+        .expect("Failed to run compiler");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    std::fs::remove_dir_all(&project).unwrap();
+    assert!(!output.status.success(), "Built after a failing pre hook");
+    assert!(stderr.contains("build.pre hook `exit 3` failed"), "STDERR:\n{}", stderr);
+}
+
+#[test]
+fn test_fix_renames_unused_parameters() {
+    let project = std::env::temp_dir().join(format!("verve-fix-{}", std::process::id()));
+    std::fs::create_dir_all(&project).unwrap();
+    let source = "fn add(a: i32, b: i32) -> i32 {\n    return a\n}\n\nfn main() -> i32 {\n    return add(1, 2)\n}\n";
+    std::fs::write(project.join("main.vrv"), source).unwrap();
+    let output = Command::new(get_compiler_path())
+        .args(["fix", "main.vrv"])
+        .current_dir(&project)
+        .output()
+        .expect("Failed to run compiler");
+    let fixed = std::fs::read_to_string(project.join("main.vrv")).unwrap();
+    std::fs::remove_dir_all(&project).unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "STDOUT:\n{}\nSTDERR:\n{}", stdout, String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("main.vrv: rename it '_b' if that is intended"), "STDOUT:\n{}", stdout);
+    assert_eq!(fixed, source.replace("b: i32", "_b: i32"));
+}
+
+#[test]
+fn test_clean_only_removes_target_dirs_it_built() {
+    let project = std::env::temp_dir().join(format!("verve-clean-{}", std::process::id()));
+    std::fs::create_dir_all(project.join("target")).unwrap();
+    std::fs::write(project.join("target/notes.txt"), "mine").unwrap();
+    let clean = || Command::new(get_compiler_path())
+        .arg("clean")
+        .current_dir(&project)
+        .output()
+        .expect("Failed to run compiler");
+
+    let output = clean();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "Removed a target directory verve did not create");
+    assert!(stderr.contains("it has no .verve-target file"), "STDERR:\n{}", stderr);
+    assert!(project.join("target/notes.txt").exists());
+
+    std::fs::remove_dir_all(project.join("target")).unwrap();
+    std::fs::write(project.join("main.vrv"), "fn main() -> i32 {\n    return 0\n}\n").unwrap();
+    let output = Command::new(get_compiler_path())
+        .arg("main.vrv")
+        .current_dir(&project)
+        .output()
+        .expect("Failed to run compiler");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "Compilation failed\nSTDERR:\n{}", stderr);
+    let output = clean();
+    let removed = !project.join("target").exists();
+    std::fs::remove_dir_all(&project).unwrap();
+    assert!(output.status.success(), "STDERR:\n{}", String::from_utf8_lossy(&output.stderr));
+    assert!(removed, "verve clean left its own target directory");
+}
+
+#[test]
+#[cfg(unix)]
+fn test_linked_runtime() {
+    let project = std::env::temp_dir().join(format!("verve-linked-runtime-{}", std::process::id()));
+    std::fs::create_dir_all(&project).unwrap();
+    std::fs::write(project.join("main.vrv"), "fn main() -> i32 {\n    assert(1 == 1, \"math\")\n    print(7)\n    return 0\n}\n").unwrap();
+    let compile = || Command::new(get_compiler_path())
+        .args(["main.vrv", "-O0", "--runtime", "linked", "--target-triple", "x86_64-unknown-linux-gnu"])
+        .current_dir(&project)
+        .output()
+        .expect("Failed to run compiler");
+
+    let output = compile();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "Compilation failed\nSTDOUT:\n{}\nSTDERR:\n{}", stdout, stderr);
+    let runtime = project.join("target/x86_64-unknown-linux-gnu/runtime");
+    let library = runtime.join("libverve_rt.a");
+    let built = std::fs::metadata(&library).unwrap().modified().unwrap();
+
+    // The program links against the library rather than carrying the runtime.
+    let program = project.join("program");
+    let build = |include: &std::path::Path| Command::new("cc")
+        .arg("-w")
+        .arg(format!("-I{}", include.display()))
+        .arg(project.join("target/x86_64-unknown-linux-gnu/debug/c/output.c"))
+        .arg(&library)
+        .arg("-o")
+        .arg(&program)
+        .output()
+        .expect("Failed to run cc");
+    assert!(build(&runtime).status.success());
+    let run = Command::new(&program).output().expect("Failed to run program");
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "7\n");
+
+    // A runtime of another ABI version stops the C compiler.
+    let stale = project.join("stale");
+    std::fs::create_dir_all(&stale).unwrap();
+    let header = std::fs::read_to_string(runtime.join("verve_rt.h")).unwrap();
+    std::fs::write(stale.join("verve_rt.h"), header.replace("#define VERVE_RT_ABI_VERSION ", "#define VERVE_RT_ABI_VERSION 1000")).unwrap();
+    let output = build(&stale);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("is not version"), "{}", String::from_utf8_lossy(&output.stderr));
+
+    // A second build reuses the library.
+    assert!(compile().status.success());
+    let reused = std::fs::metadata(&library).unwrap().modified().unwrap();
+    std::fs::remove_dir_all(&project).unwrap();
+    assert_eq!(built, reused);
+}
+
+#[test]
+fn test_print_targets() {
+    let output = Command::new(get_compiler_path()).args(["--print", "target-list"]).output().expect("Failed to run compiler");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).lines().any(|line| line == "wasm32-wasi"));
+
+    let output = Command::new(get_compiler_path())
+        .args(["--print", "target-info", "--target", "aarch64-apple-darwin"])
+        .output()
+        .expect("Failed to run compiler");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("target_os = macos\n") && stdout.contains("pointer_width = 64\n"), "STDOUT:\n{}", stdout);
+
+    // A bad triple fails before anything is read or written.
+    let output = Command::new(get_compiler_path())
+        .args(["missing.vrv", "--target", "x86_64-pc-windows-msvcc"])
+        .output()
+        .expect("Failed to run compiler");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("did you mean 'x86_64-pc-windows-msvc'?"), "STDERR:\n{}", stderr);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_run_timeout() {
+    let project = std::env::temp_dir().join(format!("verve-run-timeout-{}", std::process::id()));
+    std::fs::create_dir_all(&project).unwrap();
+    std::fs::write(project.join("spin.vrv"), "fn main() -> i32 {\n    while true { }\n    return 0\n}\n").unwrap();
+    std::fs::write(project.join("exit.vrv"), "fn main() -> i32 {\n    return 3\n}\n").unwrap();
+    let run = |file: &str| Command::new(get_compiler_path())
+        .args(["run", file, "-O0", "--timeout", "1", "--target", "x86_64-unknown-linux-gnu"])
+        .current_dir(&project)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .expect("Failed to run compiler");
+
+    let output = run("spin.vrv");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("program.exe timed out after 1s"), "STDERR:\n{}", stderr);
+
+    let output = run("exit.vrv");
+    std::fs::remove_dir_all(&project).unwrap();
+    assert_eq!(output.status.code(), Some(3), "STDERR:\n{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+#[cfg(unix)]
+fn test_run_coverage() {
+    let project = std::env::temp_dir().join(format!("verve-run-coverage-{}", std::process::id()));
+    std::fs::create_dir_all(&project).unwrap();
+    let output = Command::new(get_compiler_path())
+        .arg("run")
+        .arg(test_file_path("valid/coverage.vrv"))
+        .args(["-O0", "--coverage", "--target", "x86_64-unknown-linux-gnu"])
+        .current_dir(&project)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .expect("Failed to run compiler");
+    let counters = files_under(&project).iter().filter(|path| path.extension().is_some_and(|ext| ext == "gcda")).count();
+    std::fs::remove_dir_all(&project).unwrap();
+    assert!(output.status.success(), "STDERR:\n{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(counters, 1, "The program verve run built wrote no coverage counters");
+}
@@ -0,0 +1,21 @@
+use std::path::PathBuf;
+use verve_lang::testing::{self, RunPass};
+
+#[test]
+fn run_pass() {
+    let tests = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let dir = tests.join("run-pass");
+    let harness = RunPass::from_env().expect("Failed to set up the run-pass harness");
+    let mut programs = testing::programs(&dir).expect("Failed to list tests/run-pass");
+    assert!(!programs.is_empty(), "No programs in {}", dir.display());
+    // The integration tests' valid programs run here too when their
+    // output is stored next to them.
+    let valid = testing::programs(&tests.join("integration").join("valid")).expect("Failed to list tests/integration/valid");
+    programs.extend(valid.into_iter().filter(|program| program.with_extension("stdout").exists()));
+
+    let failures: Vec<String> = programs.iter()
+        .filter_map(|program| harness.check(program).err())
+        .collect();
+    let _ = std::fs::remove_dir_all(&harness.work_dir);
+    assert!(failures.is_empty(), "{} of {} run-pass programs failed:\n\n{}", failures.len(), programs.len(), failures.join("\n\n"));
+}
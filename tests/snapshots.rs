@@ -0,0 +1,15 @@
+use std::path::PathBuf;
+use verve_lang::testing::{self, Snapshots};
+
+#[test]
+fn snapshots() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("snapshots");
+    let harness = Snapshots::from_env().expect("Failed to set up the snapshot harness");
+    let programs = testing::programs(&dir).expect("Failed to list tests/snapshots");
+    assert!(!programs.is_empty(), "No programs in {}", dir.display());
+
+    let failures: Vec<String> = programs.iter()
+        .filter_map(|program| harness.check(program).err())
+        .collect();
+    assert!(failures.is_empty(), "{} of {} snapshots changed:\n\n{}", failures.len(), programs.len(), failures.join("\n\n"));
+}
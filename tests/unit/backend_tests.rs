@@ -0,0 +1,136 @@
+use codespan::Files;
+use std::cell::RefCell;
+use std::rc::Rc;
+use verve_lang::ast::Program;
+use verve_lang::host::NativeFs;
+use verve_lang::codegen::{Backend, BackendRegistry, BuildInfo, CodegenConfig, CompileError, SourceFile};
+
+struct CountingBackend {
+    functions: Rc<RefCell<Vec<String>>>,
+}
+
+impl Backend for CountingBackend {
+    fn compile(&mut self, program: &Program) -> Result<String, CompileError> {
+        self.functions.borrow_mut().extend(program.functions.iter().map(|f| f.name.clone()));
+        Ok(String::new())
+    }
+}
+
+fn config() -> CodegenConfig {
+    CodegenConfig {
+        target_triple: "test".to_string(),
+        portable_bitfields: false,
+        debug_alloc: false,
+        line_directives: false,
+        instrument_functions: false,
+        msvc: false,
+        gnu_extensions: false,
+        opt_level: 1,
+        whole_program: false,
+        max_call_depth: None,
+        indent_width: 4,
+        sources: vec![SourceFile { name: "test".to_string(), text: String::new(), start: 0 }],
+        build: BuildInfo::default(),
+        linked_runtime: false,
+        file_system: Rc::new(NativeFs),
+    }
+}
+
+#[test]
+fn test_backend_registration() {
+    let mut files = Files::new();
+    let file_id = files.add("test", String::from("fn f() -> i32 { return 1; } fn g() -> i32 { return 2; }"));
+    let lexer = verve_lang::lexer::Lexer::new(&files, file_id);
+    let program = verve_lang::parser::Parser::new(lexer).parse().unwrap();
+
+    let functions = Rc::new(RefCell::new(Vec::new()));
+    let mut registry = BackendRegistry::default();
+    let seen = functions.clone();
+    registry.register("counting", move |_, _| Box::new(CountingBackend { functions: seen.clone() }));
+
+    assert_eq!(registry.names().collect::<Vec<_>>(), vec!["c", "counting"]);
+    assert!(registry.create("lua", config(), file_id).is_none());
+
+    let mut backend = registry.create("counting", config(), file_id).unwrap();
+    backend.compile(&program).unwrap();
+    assert_eq!(*functions.borrow(), vec!["f", "g"]);
+}
+
+#[test]
+fn test_unlowered_statements_are_reported() {
+    let sources = [
+        "fn f() { let (a, b) = (1, 2); }",
+        "fn f() { if let Some(n) = Some(1) { print(n); } }",
+        "fn f() { while let Some(n) = Some(1) { print(n); } }",
+        "fn f() { match 1 { case 1: print(1); case _: print(2); } }",
+    ];
+    for source in sources {
+        let mut files = Files::new();
+        let file_id = files.add("test", source.to_string());
+        let lexer = verve_lang::lexer::Lexer::new(&files, file_id);
+        let program = verve_lang::parser::Parser::new(lexer).parse().unwrap();
+        let stmt_span = program.functions[0].body[0].span();
+
+        let mut backend = BackendRegistry::default().create("c", config(), file_id).unwrap();
+        match backend.compile(&program) {
+            Err(CompileError::CodegenError { span, .. }) => assert_eq!(span, Some(stmt_span), "{}", source),
+            other => panic!("{}: expected a codegen error, got {:?}", source, other.map(|_| ())),
+        }
+    }
+}
+
+/// The names of the C temporaries and labels declared in each function of
+/// `code`, in order.
+fn generated_names(code: &str) -> Vec<Vec<String>> {
+    code.split("\n}\n")
+        .map(|function| {
+            function
+                .lines()
+                .filter_map(|line| {
+                    let line = line.trim();
+                    if let Some(label) = line.strip_suffix(":;") {
+                        return Some(label.to_string());
+                    }
+                    // `type name = value;` or `type name;`
+                    let mut words = line.split_whitespace();
+                    let ty = words.next()?;
+                    let name = words.next()?.trim_end_matches(';');
+                    let is_type = ty.chars().all(|c| c.is_alphanumeric() || c == '_') && !["if", "return"].contains(&ty);
+                    (is_type && name.starts_with("__tmp_")).then(|| name.to_string())
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn test_nested_temporaries_are_unique_and_deterministic() {
+    let source = "fn pick(n: i32) -> i32 {
+        let a = if n > 0 { let t = if n > 5 { let u = n * 2; u } else { n }; t + 1 } else { let v = { let w = n; w - 1 }; v };
+        let b = if n > 1 { let t = if n > 2 { let u = 3; u } else { 4 }; t } else { 0 };
+        while { let c = if a > b { let d = a; d } else { b }; c > 100 } { a = a - 1; }
+        safe { defer note(a); safe { defer note(b); if a > 3 { return a; } } }
+        return a + b;
+    }
+    fn note(n: i32) { print(n); }
+    fn main() -> i32 { let x = if true { let y = pick(2); y } else { 0 }; return x; }";
+    let compile = || {
+        let mut compiler = verve_lang::compiler::Compiler::new();
+        let file_id = compiler.add_source("main.vrv", source.to_string());
+        compiler.compile(file_id, &mut Vec::new()).unwrap()
+    };
+
+    let code = compile();
+    assert_eq!(compile(), code);
+    let functions = generated_names(&code);
+    let pick = functions.iter().find(|names| names.len() > 8).unwrap_or_else(|| panic!("{}", code));
+    for names in &functions {
+        let mut unique = names.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), names.len(), "{:?}\n{}", names, code);
+    }
+    // Numbering restarts in every function.
+    assert!(pick.contains(&"__tmp_if0".to_string()), "{:?}", pick);
+    assert!(functions.iter().filter(|names| names.contains(&"__tmp_if0".to_string())).count() >= 2, "{}", code);
+}
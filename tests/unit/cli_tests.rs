@@ -0,0 +1,22 @@
+use std::path::Path;
+use verve_lang::cli::{default_output, optimization_flags, wasi_clang_command, Sanitizer};
+
+#[test]
+fn test_optimization_flags() {
+    assert_eq!(optimization_flags(0, &[], false), ["-O0"]);
+    assert_eq!(optimization_flags(1, &[], false), ["-O2"]);
+    assert_eq!(optimization_flags(2, &[], false), ["-O3", "-flto"]);
+    assert_eq!(optimization_flags(2, &[Sanitizer::Address], false), ["-O1"]);
+    assert_eq!(optimization_flags(0, &[Sanitizer::Undefined], true), ["-O0", "-ffast-math"]);
+    assert_eq!(optimization_flags(1, &[], true), ["-O2", "-ffast-math"]);
+}
+
+#[test]
+fn test_wasi_preset() {
+    assert_eq!(default_output("wasm32-wasi"), Path::new("program.wasm"));
+    assert_eq!(default_output("x86_64-pc-windows-msvc"), Path::new("program.exe"));
+
+    let (clang, args) = wasi_clang_command(Path::new("/sdk"), "wasm32-wasi", &["-O2".to_string()], Path::new("target/c/output.c"), Path::new("tool.wasm"));
+    assert_eq!(clang, Path::new("/sdk/bin/clang"));
+    assert_eq!(args, ["--target=wasm32-wasi", "--sysroot=/sdk/share/wasi-sysroot", "-O2", "target/c/output.c", "-o", "tool.wasm"]);
+}
@@ -0,0 +1,941 @@
+use verve_lang::compiler::{CompileFailed, Compiler, Stage};
+use verve_lang::interface::Interface;
+use verve_lang::host::MemoryFs;
+use verve_lang::lexer::Lexer;
+use codespan_reporting::diagnostic::Severity;
+
+#[test]
+fn test_compile_from_memory() {
+    let mut compiler = Compiler::new().optimize(false);
+    let file_id = compiler.add_source("memory.vrv", String::from("fn main() -> i32 { print(1 + 2); return 0; }"));
+
+    let mut diagnostics = Vec::new();
+    let code = compiler.compile(file_id, &mut diagnostics).unwrap();
+    assert!(diagnostics.is_empty());
+    assert!(code.contains("int main()"), "Unexpected output:\n{}", code);
+}
+
+#[test]
+fn test_diagnostics_go_to_handler() {
+    let mut compiler = Compiler::new();
+    let broken = compiler.add_source("broken.vrv", String::from("fn main() -> i32 { return true + 1; }"));
+    let unparsable = compiler.add_source("unparsable.vrv", String::from("fn main( {"));
+
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(broken, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    assert!(!diagnostics.is_empty());
+    assert!(diagnostics.iter().all(|(stage, _)| *stage == Stage::Typecheck));
+
+    diagnostics.clear();
+    assert_eq!(compiler.compile(unparsable, &mut diagnostics), Err(CompileFailed(Stage::Parse)));
+    assert_eq!(diagnostics.len(), 1);
+
+    let unlexable = compiler.add_source("unlexable.vrv", String::from("fn main() -> i32 { let a = 1 @ 2; return $; }"));
+    diagnostics.clear();
+    assert_eq!(compiler.compile(unlexable, &mut diagnostics), Err(CompileFailed(Stage::Parse)));
+    assert_eq!(diagnostics.len(), 2);
+}
+
+#[test]
+fn test_unknown_backend() {
+    let mut compiler = Compiler::new().backend("lua");
+    let file_id = compiler.add_source("main.vrv", String::from("fn main() -> i32 { return 0; }"));
+
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(file_id, &mut diagnostics), Err(CompileFailed(Stage::Codegen)));
+    assert_eq!(diagnostics[0].1.message, "Unknown backend 'lua' (available: c)");
+}
+
+#[test]
+fn test_output_is_deterministic() {
+    let source = "fn main() -> i32 { let p = __alloc(4) as *i32; let flag = true; \
+                  let o = Some(1); print(flag); print(p); print(__is_some(o)); return 0; }";
+    let compile = || {
+        let mut compiler = Compiler::new().instrument_functions(true);
+        let file_id = compiler.add_source("main.vrv", String::from(source));
+        compiler.compile(file_id, &mut Vec::new()).unwrap()
+    };
+
+    let first = compile();
+    assert!(first.contains("<stdbool.h>") && first.contains("<time.h>"), "{}", first);
+    for _ in 0..8 {
+        assert_eq!(compile(), first);
+    }
+}
+
+#[test]
+fn test_block_scopes_do_not_leak() {
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source(
+        "main.vrv",
+        String::from("fn main() -> i32 { let x = 1; if x == 1 { let x = true; print(x); } print(x); return 0; }"),
+    );
+
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("printf(\"%s\\n\", (x ? \"true\" : \"false\"));"), "{}", code);
+    assert!(code.contains("printf(\"%d\\n\", x);"), "{}", code);
+}
+
+#[test]
+fn test_function_linkage() {
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source(
+        "main.vrv",
+        String::from(
+            "let counter = 0; fn helper() -> i32 { return 1; } #[export] fn api() -> i32 { return helper(); } \
+             fn main() -> i32 { return api(); }",
+        ),
+    );
+
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("static int counter = 0;"), "{}", code);
+    assert!(code.contains("static int helper() {"), "{}", code);
+    assert!(code.contains("\nint api() {"), "{}", code);
+    assert!(code.contains("\nint main() {"), "{}", code);
+
+    let bad = compiler.add_source("bad.vrv", String::from("#[export(all)] fn f() -> i32 { return 1; }"));
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(bad, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    assert_eq!(diagnostics[0].1.message, "#[export] takes no arguments");
+}
+
+#[test]
+fn test_msvc_output() {
+    let source = "#[align(16)] struct Vec4 { x: i32 } fn main() -> i32 { let v = Vec4 { x: 1 }; return v.x; }";
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("_Alignas(16)"), "{}", code);
+    assert!(code.contains("getchar();"), "{}", code);
+
+    let mut compiler = Compiler::new().msvc(true);
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("__declspec(align(16))"), "{}", code);
+    assert!(code.contains("system(\"pause\");"), "{}", code);
+    assert!(!code.contains("_Alignas") && !code.contains("getchar"), "{}", code);
+}
+
+#[test]
+fn test_gnu_extensions() {
+    let source = "fn main() -> i32 { let x: Option<i32> = Some(3); return __unwrap(x); }";
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("if (!o.is_some)"), "{}", code);
+    assert!(!code.contains("__builtin"), "{}", code);
+
+    let mut compiler = Compiler::new().gnu_extensions(true);
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("if (__builtin_expect(!!(!o.is_some), 0))"), "{}", code);
+}
+
+#[test]
+fn test_branch_hints() {
+    let source = "fn main() -> i32 { let x = 1; if unlikely(x == 2) { return 1; } return 0; }";
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("if ((x == 2))"), "{}", code);
+
+    let mut compiler = Compiler::new().gnu_extensions(true);
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("__builtin_expect(!!((x == 2)), 0)"), "{}", code);
+
+    let bad = compiler.add_source("bad.vrv", String::from("fn main() -> i32 { if likely(Some(1)) { return 1; } return 0; }"));
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(bad, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+}
+
+#[test]
+fn test_minimal_parentheses() {
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source(
+        "main.vrv",
+        String::from(
+            "fn f(a: i32, b: i32) -> i32 { return a * b + a / b; } \
+             fn main() -> i32 { let x = (1 + 2) * 3 - (4 - 5) + 6 - 7; x = x * (f(x + 1, 2) / 3); \
+             if x - 1 < 3 == (x > 1) { return 1; } return 0; }",
+        ),
+    );
+
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("return a * b + a / b;"), "{}", code);
+    assert!(code.contains("int x = (1 + 2) * 3 - (4 - 5) + 6 - 7;"), "{}", code);
+    assert!(code.contains("x = x * (f(x + 1, 2) / 3);"), "{}", code);
+    assert!(code.contains("if ((x - 1 < 3) == (x > 1)) {"), "{}", code);
+}
+
+#[test]
+fn test_inline_functions() {
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source(
+        "main.vrv",
+        String::from(
+            "fn twice(x: i32) -> i32 { return square(x) + square(x); } \
+             #[inline] fn square(x: i32) -> i32 { return x * x; } \
+             fn main() -> i32 { return twice(3); }",
+        ),
+    );
+
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("static inline int square(int x);"), "{}", code);
+    let square = code.find("static inline int square(int x) {").expect(&code);
+    let twice = code.find("static int twice(int x) {").expect(&code);
+    assert!(square < twice, "{}", code);
+
+    let bad = compiler.add_source("bad.vrv", String::from("#[inline] #[export] fn f() -> i32 { return 1; }"));
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(bad, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    assert_eq!(diagnostics[0].1.message, "Exported functions cannot be #[inline]");
+}
+
+#[test]
+fn test_whole_program_internalizes_exports() {
+    let source = "#[export] fn api() -> i32 { return 1; } fn main() -> i32 { return api(); }";
+    let mut compiler = Compiler::new().whole_program(true);
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("static int api() {"), "{}", code);
+    assert!(code.contains("\nint main() {"), "{}", code);
+}
+
+#[test]
+fn test_call_depth_guard() {
+    let source = "fn f(n: i32) -> i32 { if n == 0 { return 0; } return 1 + f(n - 1); } fn main() -> i32 { return f(3); }";
+    let mut compiler = Compiler::new().max_call_depth(Some(64));
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("#define VERVE_MAX_CALL_DEPTH 64"), "{}", code);
+    assert!(code.contains("if (++verve_call_depth > VERVE_MAX_CALL_DEPTH) verve_stack_overflow(\"f\");"), "{}", code);
+    assert!(code.contains("{ int __tmp_ret0 = 0; verve_call_depth--; return __tmp_ret0; }"), "{}", code);
+
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    assert!(!compiler.compile(file_id, &mut Vec::new()).unwrap().contains("verve_call_depth"));
+}
+
+#[test]
+fn test_signal_handlers() {
+    let source = "fn stop() { static let stopped = false; stopped = true; } \
+                  fn main() -> i32 { signal.on(SIGINT, stop); signal.on(SIGTERM, stop); return 0; }";
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("#include <signal.h>"), "{}", code);
+    assert!(code.contains("signal(SIGINT, verve_signal_stop);"), "{}", code);
+    assert!(code.contains("signal(SIGTERM, verve_signal_stop);"), "{}", code);
+    assert_eq!(code.matches("static void verve_signal_stop(int signo) {").count(), 1, "{}", code);
+    assert!(code.contains("static volatile bool stopped = false;"), "{}", code);
+
+    let mut compiler = Compiler::new().msvc(true);
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("SetConsoleCtrlHandler(verve_console_stop, 1);"), "{}", code);
+    assert!(code.contains("signal(SIGTERM, verve_signal_stop);"), "{}", code);
+
+    let bad = compiler.add_source("bad.vrv", String::from("fn f(x: i32) {} fn main() -> i32 { signal.on(SIGINT, f); return 0; }"));
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(bad, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    assert_eq!(diagnostics[0].1.message, "Signal handler 'f' must take no arguments and return nothing");
+
+    let bad = compiler.add_source("off.vrv", String::from("fn f() {} fn main() -> i32 { signal.off(SIGINT, f); return 0; }"));
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(bad, &mut diagnostics), Err(CompileFailed(Stage::Parse)));
+    assert_eq!(diagnostics[0].1.message, "Unknown signal function 'off': expected on");
+}
+
+#[test]
+fn test_sockets() {
+    let source = "fn main() -> i32 { let s = __tcp_connect(\"localhost\", 80); __net_write(s, \"GET\", 3); __net_close(s); return 0; }";
+    let mut compiler = Compiler::new().target_triple("x86_64-unknown-linux-gnu");
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("#include <sys/socket.h>"), "{}", code);
+    assert!(code.contains("int s = verve_tcp_connect(\"localhost\", 80);"), "{}", code);
+    assert!(code.contains("verve_net_write(s, \"GET\", 3);"), "{}", code);
+    assert!(!code.contains("winsock"), "{}", code);
+
+    let mut compiler = Compiler::new().target_triple("x86_64-pc-windows-msvc");
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("#include <winsock2.h>"), "{}", code);
+    assert!(code.contains("WSAStartup"), "{}", code);
+    assert!(!code.contains("<sys/socket.h>"), "{}", code);
+
+    let bad = compiler.add_source("bad.vrv", String::from("fn main() -> i32 { return __tcp_listen(true); }"));
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(bad, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    assert_eq!(diagnostics[0].1.message, "__tcp_listen expects i32, got bool");
+}
+
+#[test]
+fn test_json() {
+    let source = "fn main() -> i32 { let v = json.parse(\"[1]\"); if __is_some(v) { print(json.stringify(__unwrap(v))); } \
+                  print(json.stringify(JsonValue::Number(1))); return 0; }";
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("typedef enum JsonValue_Tag { JsonValue_Null, JsonValue_Bool, JsonValue_Number"), "{}", code);
+    assert!(code.contains("static Option_JsonValue verve_json_parse(const char *text) {"), "{}", code);
+    assert!(code.contains("Option_JsonValue v = verve_json_parse(\"[1]\");"), "{}", code);
+
+    let plain = compiler.add_source("plain.vrv", String::from("fn main() -> i32 { return 0; }"));
+    assert!(!compiler.compile(plain, &mut Vec::new()).unwrap().contains("JsonValue"));
+
+    let clash = compiler.add_source("clash.vrv", String::from("enum JsonValue { Null } fn main() -> i32 { return 0; }"));
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(clash, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    assert_eq!(diagnostics[0].1.message, "Type 'JsonValue' is defined more than once");
+
+    let bad = compiler.add_source("bad.vrv", String::from("fn main() -> i32 { print(json.dump(json.array())); return 0; }"));
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(bad, &mut diagnostics), Err(CompileFailed(Stage::Parse)));
+    assert_eq!(
+        diagnostics[0].1.message,
+        "Unknown JSON function 'dump': expected parse, stringify, len, at, key, get, array, object, push, set"
+    );
+}
+
+#[test]
+fn test_utf8_strings() {
+    let source = "fn main() -> i32 { let s = \"héllo\"; print(char_len(s)); for c in chars(s) { print(c); } return 0; }";
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("printf(\"%d\\n\", char_len(s));"), "{}", code);
+    assert!(code.contains("return verve_char_len(text);"), "{}", code);
+    assert!(!code.contains("str_slice(const char* text"), "{}", code);
+    assert!(code.contains("while (verve_utf8_decode(__chars0, __offset0) > 0) {"), "{}", code);
+    assert!(code.contains("static Option_string verve_str_slice(const char *s, int start, int end) {"), "{}", code);
+
+    let bad = compiler.add_source("bad.vrv", String::from("fn main() -> i32 { let c = __chars(\"x\"); return 0; }"));
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(bad, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    assert_eq!(diagnostics[0].1.message, "__chars(s) can only be the range of a for loop");
+}
+
+#[test]
+fn test_enumerate_is_only_a_loop_range() {
+    let messages = |source: &str| {
+        let mut compiler = Compiler::new();
+        let file_id = compiler.add_source("enumerate.vrv", String::from(source));
+        let mut diagnostics = Vec::new();
+        assert_eq!(compiler.compile(file_id, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+        diagnostics.into_iter().map(|(_, d)| d.message).collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        messages("fn main() -> i32 { for x in __enumerate(list_new()) { print(x); } return 0; }"),
+        vec!["__enumerate(list) can only be the range of a 'for (index, item)' loop"]
+    );
+    assert_eq!(
+        messages("fn main() -> i32 { for (i, x) in __enumerate(7) { print(i); } return 0; }"),
+        vec!["Cannot convert i32 to List"]
+    );
+}
+
+#[test]
+fn test_string_patterns() {
+    let mut compiler = Compiler::new();
+    let source = "fn f(s: string) -> i32 { match s { case \"go\": return 1; case _: return 0; } } fn main() -> i32 { return f(\"go\"); }";
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("if (verve_str_eq(__match0, \"go\")) {"), "{}", code);
+
+    let bad = compiler.add_source("bad.vrv", String::from("fn main() -> i32 { match 1 { case \"one\": return 1; case _: return 0; } }"));
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(bad, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    assert_eq!(diagnostics[0].1.message, "String pattern cannot match i32");
+}
+
+#[test]
+fn test_enum_matches_switch_on_the_tag() {
+    let source = "enum S { A, B(i32) } enum L { X, Y, Z }
+        fn f(s: S) -> i32 { match s { case A: return 0; case B(n): return n; } }
+        fn g(l: L) -> i32 { match l { case L::Y: return 1; case _: return 0; } }
+        fn main() -> i32 { return f(S::B(1)) + g(L::Z); }";
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+
+    let f = &code[code.find("static int f(S s) {").unwrap()..code.find("static int g(L l) {").unwrap()];
+    assert!(f.contains("switch (__match0.tag) {"), "{}", f);
+    assert!(f.contains("case S_A: {") && f.contains("case S_B: {"), "{}", f);
+    assert!(!f.contains("default:") && f.contains("abort();"), "{}", f);
+    let g = &code[code.find("static int g(L l) {").unwrap()..];
+    assert!(g.contains("switch (__match1) {") && g.contains("default: {"), "{}", g);
+}
+
+#[test]
+fn test_intrinsic_misuse_is_a_type_error() {
+    let messages = |source: &str| {
+        let mut compiler = Compiler::new();
+        let file_id = compiler.add_source("intrinsics.vrv", String::from(source));
+        let mut diagnostics = Vec::new();
+        assert_eq!(compiler.compile(file_id, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+        diagnostics.into_iter().map(|(_, d)| d.message).collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        messages("fn main() -> i32 { let p = __alloc(); __dealloc(p, p); __signal_on(SIGINT); return 0; }"),
+        vec![
+            "__alloc expects 1 argument, got 0",
+            "__dealloc expects 1 argument, got 2",
+            "__signal_on expects 2 arguments, got 1",
+        ]
+    );
+    assert_eq!(
+        messages("fn main() -> i32 { let p = __alloc(true); __dealloc(7 == 7); let v = __unwrap(p); return 0; }"),
+        vec![
+            "__alloc expects i32, got bool",
+            "__dealloc expects rawptr, got bool",
+            "__unwrap expects an Option, got rawptr",
+        ]
+    );
+}
+
+#[test]
+fn test_source_location_intrinsics() {
+    let source = "fn main() -> i32 {\n    print(__file());\n    print(__line());\n    print(__function());\n    return 0;\n}";
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("log.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("printf(\"%s\\n\", \"log.vrv\");"), "{}", code);
+    assert!(code.contains("printf(\"%d\\n\", 3);"), "{}", code);
+    assert!(code.contains("printf(\"%s\\n\", \"main\");"), "{}", code);
+}
+
+#[test]
+fn test_assert_eq_shows_both_values() {
+    let source = "#[derive(Eq)]\nstruct Point { x: i32, y: i32 }\nfn main() -> i32 {\n    assert_eq(Point { x: 1, y: 2 }, Point { x: 1, y: 2 });\n    assert_ne(1, 2);\n    return 0;\n}";
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("static void verve_assert_Point(Point left, Point right, bool equal, const char *site) {"), "{}", code);
+    assert!(code.contains("verve_debug_Point(out, left);"), "{}", code);
+    assert!(code.contains("true, \"main.vrv:4:5\");"), "{}", code);
+    assert!(code.contains("verve_assert_i32(1, 2, false, \"main.vrv:5:5\");"), "{}", code);
+
+    let mut messages = |source: &str| {
+        let file_id = compiler.add_source("bad.vrv", String::from(source));
+        let mut diagnostics = Vec::new();
+        assert_eq!(compiler.compile(file_id, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+        diagnostics.into_iter().map(|(_, d)| d.message).collect::<Vec<_>>()
+    };
+    assert_eq!(messages("fn main() -> i32 { assert_eq(1, \"1\"); return 0; }"), [
+        "assert_eq expects two values of the same type, got i32 and string",
+    ]);
+    assert_eq!(messages("struct P { x: i32 } fn main() -> i32 { assert_ne(P { x: 1 }, P { x: 2 }); return 0; }"), [
+        "Cannot compare P and P without #[derive(Eq)]",
+    ]);
+    assert_eq!(messages("fn main() -> i32 { assert_eq(Some(1), None); return 0; }"), [
+        "assert_eq cannot compare values of type Option<i32>",
+    ]);
+}
+
+#[test]
+fn test_build_info_builtins() {
+    let source = "fn main() -> i32 {\n    print(build.version());\n    print(build.target());\n    let at = build.timestamp();\n    print(build.version());\n    return 0;\n}";
+    let mut compiler = Compiler::new()
+        .target_triple("x86_64-unknown-linux-gnu")
+        .version("1.4.2")
+        .build_timestamp(1_700_000_000);
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert_eq!(code.matches("static const char verve_build_version[] = \"1.4.2\";").count(), 1, "{}", code);
+    assert!(code.contains("static const char verve_build_target[] = \"x86_64-unknown-linux-gnu\";"), "{}", code);
+    assert!(code.contains("static const char verve_build_timestamp[] = \"2023-11-14T22:13:20Z\";"), "{}", code);
+    assert!(code.contains("printf(\"%s\\n\", verve_build_version);"), "{}", code);
+
+    let file_id = compiler.add_source("bad.vrv", String::from("fn main() -> i32 { print(build.commit()); return 0; }"));
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(file_id, &mut diagnostics), Err(CompileFailed(Stage::Parse)));
+    assert_eq!(diagnostics[0].1.message, "Unknown build info 'commit': expected version, target, timestamp");
+}
+
+#[test]
+fn test_cfg_removes_branches_for_other_targets() {
+    let source = "fn main() -> i32 {\n    if cfg(target_os = \"linux\") { print(\"linux\"); } else { print(\"other\"); }\n    if cfg(target_family = \"windows\") == false { print(\"not windows\"); }\n    return 0;\n}";
+    let mut compiler = Compiler::new().target_triple("x86_64-unknown-linux-gnu");
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("\"linux\"") && code.contains("\"not windows\""), "{}", code);
+    assert!(!code.contains("\"other\"") && !code.contains("if ("), "{}", code);
+
+    let mut compiler = Compiler::new().target_triple("x86_64-pc-windows-msvc");
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("\"other\""), "{}", code);
+    assert!(!code.contains("\"linux\"") && !code.contains("\"not windows\""), "{}", code);
+
+    let bad = compiler.add_source("bad.vrv", String::from("fn main() -> i32 { if cfg(os = \"linux\") { return 1; } return 0; }"));
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(bad, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    assert_eq!(diagnostics[0].1.message, "Unknown cfg key 'os': expected target_os, target_arch, target_family, feature");
+}
+
+#[test]
+fn test_cfg_attributes_remove_items() {
+    let source = "#[cfg(feature = \"fast\")]\nfn speed() -> i32 { return __fast_path(); }\n#[cfg(target_os = \"linux\")]\nstruct Linux { fd: i32 }\nfn main() -> i32 {\n    if cfg(feature = \"fast\") { return speed(); }\n    return 0;\n}";
+    let mut compiler = Compiler::new().target_triple("x86_64-pc-windows-msvc");
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(!code.contains("speed") && !code.contains("Linux"), "{}", code);
+
+    let mut compiler = Compiler::new().features(["fast".to_string()]);
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(file_id, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    assert_eq!(diagnostics[0].1.message, "Undefined intrinsic '__fast_path'");
+
+    let bad = compiler.add_source("bad.vrv", String::from("#[cfg(fast)]\nfn f() {}\nfn main() -> i32 { return 0; }"));
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(bad, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    assert_eq!(diagnostics[0].1.message, "#[cfg] takes one key = \"value\", with key one of target_os, target_arch, target_family, feature");
+}
+
+#[test]
+fn test_deprecated_items_warn_at_uses() {
+    let source = "#[deprecated(\"use area2\")]\nfn area(w: i32) -> i32 { return area(w); }\nfn area2(w: i32) -> i32 { return w; }\n#[deprecated]\nstruct Old { x: i32 }\nfn main() -> i32 {\n    let o = Old { x: area(2) };\n    return area2(o.x);\n}";
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let mut diagnostics = Vec::new();
+    assert!(compiler.compile(file_id, &mut diagnostics).is_ok());
+    let messages: Vec<_> = diagnostics.iter().map(|(_, d)| (d.severity, d.message.as_str())).collect();
+    assert_eq!(messages, [
+        (Severity::Warning, "Type 'Old' is deprecated"),
+        (Severity::Warning, "Function 'area' is deprecated: use area2"),
+    ]);
+
+    let bad = compiler.add_source("bad.vrv", String::from("#[deprecated(2)]\nfn f() {}\nfn main() -> i32 { return 0; }"));
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(bad, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    assert_eq!(diagnostics[0].1.message, "#[deprecated] takes at most a note, as in #[deprecated(\"use foo2\")]");
+}
+
+#[test]
+fn test_symbol_attributes_fix_c_names() {
+    let source = "#[export_name(\"plugin_init\")]\nfn init() -> i32 { return 1; }\n#[no_mangle]\nfn on_event(code: i32) {}\nfn main() -> i32 { on_event(init()); return 0; }";
+    let mut compiler = Compiler::new().whole_program(true);
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("int plugin_init() {"), "{}", code);
+    assert!(code.contains("void on_event(int code) {"), "{}", code);
+    assert!(code.contains("on_event(plugin_init());"), "{}", code);
+    assert!(!code.contains("static int plugin_init"), "{}", code);
+
+    let mut messages = |source: &str| {
+        let file_id = compiler.add_source("bad.vrv", String::from(source));
+        let mut diagnostics = Vec::new();
+        assert_eq!(compiler.compile(file_id, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+        diagnostics.into_iter().map(|(_, d)| d.message).collect::<Vec<_>>()
+    };
+    assert_eq!(messages("#[no_mangle] fn printf() {} fn main() -> i32 { return 0; }"), [
+        "'printf' is reserved in the generated C and cannot be a symbol",
+    ]);
+    assert_eq!(messages("#[export_name(\"a b\")] fn f() {} fn main() -> i32 { return 0; }"), [
+        "#[export_name] takes the C symbol name, as in #[export_name(\"on_load\")]",
+    ]);
+    assert_eq!(messages("fn g() {} #[export_name(\"g\")] fn f() {} fn main() -> i32 { return 0; }"), [
+        "Functions 'g' and 'f' are both emitted as the C symbol 'g'",
+    ]);
+}
+
+#[test]
+fn test_calling_conventions() {
+    let source = "extern \"stdcall\" fn twice(x: i32) -> i32 { return x * 2; }\n\
+                  fn apply(f: extern \"stdcall\" fn(i32) -> i32, x: i32) -> i32 { return f(x); }\n\
+                  fn main() -> i32 { return apply(twice, 4); }";
+    let mut compiler = Compiler::new().target_triple("i686-pc-windows-msvc").msvc(true);
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("typedef int (__stdcall *Fn1_i32_i32_stdcall)(int);"), "{}", code);
+    assert!(code.contains("static int __stdcall twice(int x) {"), "{}", code);
+    assert!(code.contains("return f(x);"), "{}", code);
+    assert!(code.contains("apply(twice, 4)"), "{}", code);
+
+    let mut compiler = Compiler::new().target_triple("i686-pc-windows-gnu");
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("static int __attribute__((stdcall)) twice(int x) {"), "{}", code);
+
+    // Only 32-bit x86 has conventions to choose between.
+    let mut compiler = Compiler::new().target_triple("x86_64-unknown-linux-gnu");
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("static int twice(int x) {"), "{}", code);
+
+    let mut messages = |source: &str| {
+        let file_id = compiler.add_source("bad.vrv", String::from(source));
+        let mut diagnostics = Vec::new();
+        assert!(compiler.compile(file_id, &mut diagnostics).is_err());
+        diagnostics.into_iter().map(|(_, d)| d.message).collect::<Vec<_>>()
+    };
+    assert_eq!(messages("fn f(x: i32) -> i32 { return x; } fn g(h: extern \"stdcall\" fn(i32) -> i32) {} fn main() -> i32 { g(f); return 0; }"), [
+        "Argument 1: expected extern \"stdcall\" fn(i32) -> i32, got fn(i32) -> i32",
+    ]);
+    assert_eq!(messages("extern \"fastcall\" fn f() {} fn main() -> i32 { return 0; }"), [
+        "Unknown calling convention 'fastcall'; expected \"C\" or \"stdcall\"",
+    ]);
+}
+
+#[test]
+fn test_print_uses_checked_types() {
+    let source = "fn main() -> i32 { let p = __alloc(4) as *bool; *p = true; print(*p); let t = (1, (true, 3)); print(t.1.0); return 0; }";
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("printf(\"%s\\n\", ((*p) ? \"true\" : \"false\"));"), "{}", code);
+    assert!(code.contains("printf(\"%s\\n\", (t._1._0 ? \"true\" : \"false\"));"), "{}", code);
+}
+
+#[test]
+fn test_block_expressions() {
+    let source = "fn main() -> i32 { let n = 0; while { n = n + 1; n < 3 } { print(n); } return { let t = n * n; t + 1 }; }";
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("    while (1) {\n        bool __tmp_block0;\n        {\n            n = n + 1;\n            __tmp_block0 = n < 3;\n        }\n        if (!(__tmp_block0)) break;\n"), "{}", code);
+    assert!(code.contains("    int __tmp_block1;\n    {\n        int t = n * n;\n        __tmp_block1 = t + 1;\n    }\n    return __tmp_block1;"), "{}", code);
+
+    let mut compiler = Compiler::new().gnu_extensions(true);
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("    return ({\n        int t = n * n;\n        t + 1;\n    });"), "{}", code);
+
+    let bad = compiler.add_source("bad.vrv", String::from("fn g() {} fn main() -> i32 { let x = { g() }; return 0; }"));
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(bad, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    assert_eq!(diagnostics[0].1.message, "Block expression must end with a value");
+}
+
+#[test]
+fn test_if_expressions() {
+    let source = "fn main() -> i32 { let n = 3; let a = if n > 2 { 1 } else { 2 }; let b = if n == 3 { let t = n * 2; t } else { 0 }; print(a + b); return 0; }";
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("    int a = n > 2 ? 1 : 2;\n"), "{}", code);
+    assert!(code.contains("    int __tmp_if0;\n    if (n == 3) {\n        int t = n * 2;\n        __tmp_if0 = t;\n    } else {\n        __tmp_if0 = 0;\n    }\n    int b = __tmp_if0;\n"), "{}", code);
+
+    let bad = compiler.add_source("bad.vrv", String::from("fn main() -> i32 { let x = if true { 1 } else { \"one\" }; return 0; }"));
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(bad, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    assert_eq!(diagnostics[0].1.message, "if and else have different types: i32 and string");
+}
+
+#[test]
+fn test_break_and_continue() {
+    let source = "enum Step { Skip, Stop, Go(i32) } \
+        fn step(i: i32) -> Step { if i == 1 { return Step::Skip; } if i == 2 { return Step::Stop; } return Step::Go(i); } \
+        fn main() -> i32 { for i in 0..3 { match step(i) { case Step::Skip: continue; case Step::Stop: break; case Step::Go(n): print(n); } } \
+        while true { break; } return 0; }";
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("case Step_Skip: {\n                    continue;\n                }\n"), "{}", code);
+    // Inside the switch, `break` would only leave the switch.
+    assert!(code.contains("case Step_Stop: {\n                    goto __label_break0;\n                }\n"), "{}", code);
+    assert!(code.contains("    }\n    __label_break0:;\n"), "{}", code);
+    assert!(code.contains("    while (true) {\n        break;\n    }\n    return 0;\n"), "{}", code);
+
+    let messages = |source: &str| {
+        let mut compiler = Compiler::new();
+        let file_id = compiler.add_source("bad.vrv", String::from(source));
+        let mut diagnostics = Vec::new();
+        assert_eq!(compiler.compile(file_id, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+        diagnostics.into_iter().map(|(_, d)| d.message).collect::<Vec<_>>()
+    };
+    assert_eq!(messages("fn main() -> i32 { break; if true { continue; } return 0; }"), [
+        "break outside of a loop body",
+        "continue outside of a loop body",
+    ]);
+    assert_eq!(messages("fn main() -> i32 { while true { while { break; true } { } } return 0; }"), [
+        "break outside of a loop body",
+    ]);
+
+    // Leaving a safe block runs its deferred statements first.
+    let source = "fn done() {} fn main() -> i32 { while true { safe { defer done(); break; } } return 0; }";
+    let file_id = compiler.add_source("defer.vrv", String::from(source));
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("{ __tmp_jump3 = 1; goto __label_cleanup0; }\n"), "{}", code);
+    assert!(code.contains("__label_cleanup0:;\n            done();\n            if (__tmp_returning1) "), "{}", code);
+    assert!(code.contains("if (__tmp_jump3 == 1) break;\n"), "{}", code);
+}
+
+#[test]
+fn test_main_exit_status() {
+    let mut compiler = Compiler::new();
+    let returns = compiler.add_source("returns.vrv", String::from("fn main() -> i32 { let x = 1; if x == 1 { return 2; } else { return 3; } }"));
+    let code = compiler.compile(returns, &mut Vec::new()).unwrap();
+    assert!(!code.contains("return 0;"), "{}", code);
+
+    let falls_through = compiler.add_source("falls.vrv", String::from("fn main() -> i32 { let x = 1; if x == 1 { return 2; } }"));
+    let code = compiler.compile(falls_through, &mut Vec::new()).unwrap();
+    assert!(code.contains("        return 2;\n    }\n    getchar();\n    return 0;\n}"), "{}", code);
+
+    let bad = compiler.add_source("bad.vrv", String::from("fn main() -> bool { return true; }"));
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(bad, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    assert_eq!(diagnostics[0].1.message, "main must return i32 or nothing, not bool");
+}
+
+#[test]
+fn test_compile_files() {
+    let mut compiler = Compiler::new();
+    let main = compiler.add_source("main.vrv", String::from("fn main() -> i32 { return twice(2); }"));
+    let lib = compiler.add_source("lib.vrv", String::from("fn twice(n: i32) -> i32 { return n * 2; }"));
+    let code = compiler.compile_files(&[main, lib], &mut Vec::new()).unwrap();
+    assert!(code.contains("return twice(2);"), "{}", code);
+
+    let broken = compiler.add_source("broken.vrv", String::from("fn half(n: i32) -> i32 {\n    return true;\n}"));
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile_files(&[main, lib, broken], &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    let label = &diagnostics[0].1.labels[0];
+    assert_eq!(label.file_id, broken);
+    assert_eq!(&compiler.files().source(broken)[label.range.clone()], "true");
+
+    let unparsable = compiler.add_source("unparsable.vrv", String::from("fn f( {"));
+    diagnostics.clear();
+    assert_eq!(compiler.compile_files(&[main, lib, unparsable], &mut diagnostics), Err(CompileFailed(Stage::Parse)));
+    assert_eq!(diagnostics[0].1.labels[0].file_id, unparsable);
+    assert_eq!(diagnostics[0].1.labels[0].range, 6..7);
+
+    diagnostics.clear();
+    assert_eq!(compiler.compile_files(&[main, lib, lib], &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    assert_eq!(diagnostics[0].1.message, "Function 'twice' is defined more than once");
+    assert_eq!(diagnostics[0].1.labels[0].file_id, lib);
+}
+
+#[test]
+fn test_in_memory_source() {
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("<playground>", "fn main() -> i32 { print(7); return 0; }");
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("printf(\"%d\\n\", 7);"), "{}", code);
+    assert_eq!(compiler.files().name(file_id), "<playground>");
+}
+
+#[test]
+fn test_indentation() {
+    let source = "fn main() -> i32 { let x = 1; if x == 1 { print(\"{\"); } return 0; }";
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("main.vrv", source);
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("int main() {\n    int x = 1;\n    if (x == 1) {\n        printf(\"%s\\n\", \"{\");\n    }\n    return 0;\n"), "{}", code);
+
+    let mut compiler = Compiler::new().indent_width(2);
+    let file_id = compiler.add_source("main.vrv", source);
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("  if (x == 1) {\n    printf("), "{}", code);
+
+    let mut compiler = Compiler::new().indent_width(0);
+    let file_id = compiler.add_source("main.vrv", source);
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("int main() {\nint x = 1;\nif (x == 1) {\nprintf("), "{}", code);
+}
+
+#[test]
+fn test_compile_against_interface() {
+    let mut library = Compiler::new();
+    let lib_id = library.add_source(
+        "lib.vrv",
+        "struct Pair { a: i32, b: i32 } #[export] fn sum(p: Pair) -> i32 { return p.a + p.b; }",
+    );
+    let lib_code = library.compile(lib_id, &mut Vec::new()).unwrap();
+    assert!(!lib_code.contains("int main()"), "{}", lib_code);
+    let parsed = verve_lang::parser::Parser::new(Lexer::new(library.files(), lib_id)).parse().unwrap();
+    let interface = Interface::from_bytes(&Interface::of(&parsed).to_bytes()).unwrap();
+
+    let mut compiler = Compiler::new().whole_program(true);
+    compiler.add_interface(interface);
+    let app = compiler.add_source("app.vrv", "fn main() -> i32 { return sum(Pair { a: 1, b: 2 }); }");
+    let code = compiler.compile(app, &mut Vec::new()).unwrap();
+    assert!(code.contains("\nint sum(Pair p);"), "{}", code);
+    assert!(!code.contains("int sum(Pair p) {"), "{}", code);
+
+    let bad = compiler.add_source("bad.vrv", "#[extern] fn f() -> i32 { return 1; }");
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(bad, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    assert_eq!(diagnostics[0].1.message, "#[extern] functions have no body");
+}
+
+#[test]
+fn test_prelude() {
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("main.vrv", "fn main() -> i32 { let l = list_new(); list_set(l, 0, 1); return 0; }");
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    // list_set needs assert, which needs the panic runtime.
+    assert!(code.contains("static void list_set(List list, int verve_u_index, int value) {"), "{}", code);
+    assert!(code.contains("static void assert(bool condition, const char* message) {"), "{}", code);
+    assert!(code.contains("static void verve_panic("), "{}", code);
+    assert!(!code.contains("static void list_free("), "{}", code);
+    assert!(!code.contains("Result"), "{}", code);
+
+    // The program's own definitions take precedence.
+    let own = compiler.add_source("own.vrv", "fn assert(ok: bool) { print(ok); } fn main() -> i32 { assert(true); return 0; }");
+    let code = compiler.compile(own, &mut Vec::new()).unwrap();
+    assert!(code.contains("static void assert(bool ok) {"), "{}", code);
+    assert!(!code.contains("verve_panic"), "{}", code);
+
+    let plain = compiler.add_source("plain.vrv", "fn main() -> i32 { print(1); return 0; }");
+    let code = compiler.compile(plain, &mut Vec::new()).unwrap();
+    assert!(!code.contains("assert") && !code.contains("List"), "{}", code);
+
+    let bad = compiler.add_source("bad.vrv", "fn main() -> i32 { __nope(1); return 0; }");
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(bad, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    assert_eq!(diagnostics[0].1.message, "Undefined intrinsic '__nope'");
+}
+
+#[test]
+fn test_wasi_target() {
+    let source = "fn main() -> i32 { print(__arg(__arg_count() - 1)); return 0; }";
+    let mut compiler = Compiler::new().target_triple("wasm32-wasi");
+    let file_id = compiler.add_source("main.vrv", source);
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("int main(int argc, char **argv) {\n    verve_argc = argc;"), "{}", code);
+    assert!(!code.contains("getchar"), "{}", code);
+
+    let sockets = compiler.add_source("net.vrv", "fn main() -> i32 { return __tcp_listen(80); }");
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(sockets, &mut diagnostics), Err(CompileFailed(Stage::Codegen)));
+    assert_eq!(diagnostics[0].1.message, "__tcp_listen is not available on wasm32-wasi");
+
+    // Programs that do not read their arguments keep a parameterless main.
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("main.vrv", "fn main() -> i32 { return 0; }");
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("int main() {"), "{}", code);
+}
+
+#[test]
+fn test_errors_in_conditions_are_reported() {
+    let mut compiler = Compiler::new();
+    let bad = compiler.add_source("bad.vrv", String::from("fn main() -> i32 { if 1 + \"\" { } return 0; }"));
+
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(bad, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    assert_eq!(diagnostics[0].1.message, "Cannot apply Add to i32 and string");
+}
+
+#[test]
+fn test_unions_cannot_be_printed() {
+    let mut compiler = Compiler::new();
+    let source = "union U { a: i32, b: bool } struct S { u: U } fn main() -> i32 { print(S { u: U { a: 1 } }); return 0; }";
+    let file_id = compiler.add_source("union.vrv", String::from(source));
+
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(file_id, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    assert_eq!(diagnostics[0].1.message, "Cannot print value of type S");
+}
+
+#[test]
+fn test_only_plain_enums_convert_to_strings() {
+    let mut compiler = Compiler::new();
+    let source = "enum E { A, B(i32) } fn main() -> i32 { print(E::to_string(E::A)); return 0; }";
+    let file_id = compiler.add_source("names.vrv", String::from(source));
+
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(file_id, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    assert_eq!(diagnostics[0].1.message, "Enum 'E' has variants with values and cannot be converted to a string");
+}
+
+#[test]
+fn test_derived_comparisons_are_checked() {
+    let messages = |source: &str| {
+        let mut compiler = Compiler::new();
+        let file_id = compiler.add_source("derive.vrv", String::from(source));
+        let mut diagnostics = Vec::new();
+        assert_eq!(compiler.compile(file_id, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+        diagnostics.into_iter().map(|(_, d)| d.message).collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        messages("struct P { x: i32 } fn main() -> i32 { let same = P { x: 1 } == P { x: 1 }; return 0; }"),
+        vec!["Cannot compare P and P without #[derive(Eq)]"]
+    );
+    assert_eq!(
+        messages("#[derive(Eq)] struct P { x: i32 } fn main() -> i32 { if (P { x: 1 }) < (P { x: 2 }) { } return 0; }"),
+        vec!["Cannot compare P and P without #[derive(Ord)]"]
+    );
+    assert_eq!(
+        messages("struct P { x: i32 } #[derive(Eq, Ord)] struct Q { p: P, q: *P } fn main() -> i32 { return 0; }"),
+        vec![
+            "Cannot derive Eq for 'Q': field 'p' has type P, which does not support it",
+            "Cannot derive Ord for 'Q': field 'p' has type P, which does not support it",
+            "Cannot derive Ord for 'Q': field 'q' has type *P, which does not support it",
+        ]
+    );
+    assert_eq!(
+        messages("#[derive(Debug)] enum E { A } #[derive(Eq)] union U { a: i32 } fn main() -> i32 { return 0; }"),
+        vec!["Union 'U' cannot use #[derive]", "#[derive] takes Eq, Ord, Hash, Serialize and Deserialize"]
+    );
+    assert_eq!(
+        messages("struct P { x: i32 } #[derive(Hash)] struct Q { o: Option<i32> } fn main() -> i32 { return __hash(P { x: 1 }); }"),
+        vec![
+            "Cannot derive Hash for 'Q': field 'o' has type Option<i32>, which does not support it",
+            "Cannot hash P without #[derive(Hash)]",
+        ]
+    );
+    assert_eq!(
+        messages("#[derive(Serialize)] enum E { A(i32) } struct P { x: i32 } #[derive(Deserialize)] struct Q { p: P, o: Option<Option<i32>>, j: JsonValue } fn main() -> i32 { return 0; }"),
+        vec![
+            "Cannot derive Deserialize for 'Q': field 'p' has type P, which does not support it",
+            "Cannot derive Deserialize for 'Q': field 'o' has type Option<Option<i32>>, which does not support it",
+            "Cannot derive Serialize for 'E': only structs and enums without values convert to JSON",
+        ]
+    );
+}
+
+#[test]
+fn test_include_assets() {
+    let dir = std::env::temp_dir().join(format!("verve-assets-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("data")).unwrap();
+    std::fs::write(dir.join("data/raw.bin"), [0u8, 255, 7]).unwrap();
+    let main = dir.join("main.vrv");
+    let source = "fn main() -> i32 {\n    let a = include_bytes(\"data/raw.bin\");\n    let b = include_bytes(\"data/raw.bin\");\n    print(list_len(a) + list_len(b));\n    return 0;\n}";
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source(&main.to_string_lossy(), source);
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert_eq!(code.matches("static const unsigned char verve_asset_0[4] = {\n    0, 255, 7, 0,\n};").count(), 1, "{}", code);
+    assert!(!code.contains("verve_asset_1"), "{}", code);
+    assert_eq!(code.matches("verve_list_from_bytes(verve_asset_0, 3)").count(), 2, "{}", code);
+
+    let text = compiler.add_source(&main.to_string_lossy(), "fn main() -> i32 { print(include_str(\"data/raw.bin\")); return 0; }");
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(text, &mut diagnostics), Err(CompileFailed(Stage::Codegen)));
+    assert_eq!(diagnostics[0].1.message, "include_str needs a UTF-8 text file without NUL bytes; use include_bytes");
+
+    let missing = compiler.add_source(&main.to_string_lossy(), "fn main() -> i32 { print(include_str(\"nope.txt\")); return 0; }");
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(missing, &mut diagnostics), Err(CompileFailed(Stage::Codegen)));
+    assert!(diagnostics[0].1.message.starts_with("Cannot read '"), "{:?}", diagnostics);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let computed = compiler.add_source("main.vrv", "fn main() -> i32 { let p = \"a.txt\"; print(include_str(p)); return 0; }");
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(computed, &mut diagnostics), Err(CompileFailed(Stage::Typecheck)));
+    assert_eq!(diagnostics[0].1.message, "include_str expects a string literal path");
+}
+
+#[test]
+fn test_include_reads_through_file_system() {
+    let mut files = MemoryFs::default();
+    files.insert("assets/greeting.txt", "hello");
+    let mut compiler = Compiler::new().file_system(files);
+    let file_id = compiler.add_source("assets/../main.vrv", "fn main() -> i32 { print(include_str(\"greeting.txt\")); return 0; }");
+    let mut diagnostics = Vec::new();
+    assert_eq!(compiler.compile(file_id, &mut diagnostics), Err(CompileFailed(Stage::Codegen)));
+    assert!(diagnostics[0].1.message.starts_with("Cannot read 'assets/../greeting.txt'"), "{:?}", diagnostics);
+
+    let file_id = compiler.add_source("assets/main.vrv", "fn main() -> i32 { print(include_str(\"greeting.txt\")); return 0; }");
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    assert!(code.contains("104, 101, 108, 108, 111, 0,"), "{}", code);
+}
@@ -0,0 +1,44 @@
+use codespan::Files;
+use verve_lang::ast::Stmt;
+use verve_lang::deps::{global_order, topo_sort};
+use verve_lang::lexer::Lexer;
+
+fn parse_globals(source: &str) -> Vec<Stmt> {
+    let mut files = Files::new();
+    let file_id = files.add("test", String::from(source));
+    let mut parser = verve_lang::parser::Parser::new(Lexer::new(&files, file_id));
+    parser.parse().unwrap().stmts
+}
+
+#[test]
+fn test_topo_sort_orders_dependencies_first() {
+    // 0 depends on 2, 2 depends on 1
+    let deps = [vec![2], vec![], vec![1]];
+    let order = topo_sort(deps.len(), |i| deps[i].clone()).unwrap();
+
+    assert_eq!(order, vec![1, 2, 0]);
+}
+
+#[test]
+fn test_topo_sort_reports_cycle() {
+    let deps = [vec![1], vec![2], vec![0]];
+    let cycle = topo_sort(deps.len(), |i| deps[i].clone()).unwrap_err();
+
+    assert_eq!(cycle, vec![0, 1, 2, 0]);
+}
+
+#[test]
+fn test_global_order_puts_initializers_after_their_reads() {
+    let stmts = parse_globals("let area = width * height; print(area); let width = height + 2; let height = 3;");
+    let order = global_order(&stmts).unwrap();
+
+    assert_eq!(order, vec![3, 2, 0]);
+}
+
+#[test]
+fn test_global_order_reports_cycle() {
+    let stmts = parse_globals("let a = b + 1; let b = c; let c = a * 2;");
+    let cycle = global_order(&stmts).unwrap_err();
+
+    assert_eq!(cycle, vec![0, 1, 2, 0]);
+}
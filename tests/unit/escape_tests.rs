@@ -0,0 +1,34 @@
+use codespan::Files;
+use verve_lang::ast::{Expr, Stmt};
+use verve_lang::escape::stack_allocate;
+use verve_lang::lexer::Lexer;
+
+fn allocation_intrinsic(stmt: &Stmt) -> Option<&str> {
+    let Stmt::Let(_, _, Expr::Cast(inner, _, _, _), _) = stmt else { return None };
+    match &**inner {
+        Expr::IntrinsicCall(name, _, _, _) => Some(name),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_non_escaping_allocation_moves_to_stack() {
+    let mut files = Files::new();
+    let source = String::from(
+        "fn f() -> i32 { let p = __alloc(4) as *i32; *p = 1; let q = __alloc(4) as *i32; g(q); \
+         safe { __dealloc(p as rawptr); } __dealloc(p as rawptr); return *p; }",
+    );
+
+    let file_id = files.add("test", source);
+
+    let lexer = Lexer::new(&files, file_id);
+    let mut parser = verve_lang::parser::Parser::new(lexer);
+    let mut program = parser.parse().unwrap();
+    stack_allocate(&mut program);
+
+    let body = &program.functions[0].body;
+    assert_eq!(allocation_intrinsic(&body[0]), Some("__stack_alloc"));
+    assert_eq!(allocation_intrinsic(&body[2]), Some("__alloc"));
+    assert!(matches!(&body[4], Stmt::Expr(Expr::SafeBlock(stmts, _, _), _) if stmts.is_empty()));
+    assert!(matches!(&body[5], Stmt::Return(..)));
+}
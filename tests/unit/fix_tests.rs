@@ -0,0 +1,72 @@
+use codespan::{Files, Span};
+use verve_lang::compiler::{Compiler, DiagnosticHandler, Stage};
+use verve_lang::fix::{apply, closest_name, diff, Suggestion};
+
+#[derive(Default)]
+struct Suggestions(Vec<Suggestion>);
+
+impl DiagnosticHandler for Suggestions {
+    fn report(&mut self, _: Stage, _: &codespan_reporting::diagnostic::Diagnostic<codespan::FileId>, _: &Files<String>) {}
+
+    fn suggest(&mut self, suggestion: &Suggestion) {
+        self.0.push(suggestion.clone());
+    }
+}
+
+fn fixed(source: &str) -> String {
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("main.vrv", String::from(source));
+    let mut suggestions = Suggestions::default();
+    assert!(compiler.compile(file_id, &mut suggestions).is_err());
+    assert!(suggestions.0.iter().all(|suggestion| suggestion.file_id == file_id));
+    apply(source, &suggestions.0.iter().collect::<Vec<_>>())
+}
+
+#[test]
+fn test_suggested_fixes() {
+    assert_eq!(
+        fixed("fn total(n: i32) -> i32 { return n; }\nfn main() -> i32 { let count = 1; return totl(cont); }"),
+        "fn total(n: i32) -> i32 { return n; }\nfn main() -> i32 { let count = 1; return total(count); }",
+    );
+    // Both comparisons ask for the same derive.
+    assert_eq!(
+        fixed("struct P { x: i32 }\nfn main() -> i32 { let p = P { x: 1 }; print(p == p); print(p == p); return 0; }"),
+        "#[derive(Eq)]\nstruct P { x: i32 }\nfn main() -> i32 { let p = P { x: 1 }; print(p == p); print(p == p); return 0; }",
+    );
+    // Nothing in scope is close enough to guess.
+    let source = "fn main() -> i32 { return frobnicate(1); }";
+    assert_eq!(fixed(source), source);
+}
+
+#[test]
+fn test_apply_skips_overlapping_fixes() {
+    let file_id = Files::new().add("a", String::new());
+    let edit = |start: u32, end: u32, replacement: &str| Suggestion {
+        message: String::new(),
+        file_id,
+        span: Span::new(start, end),
+        replacement: replacement.to_string(),
+    };
+    let (a, b, c) = (edit(4, 9, "x"), edit(6, 8, "y"), edit(0, 0, ">"));
+    assert_eq!(apply("let value = 1;", &[&a, &b, &c]), ">let x = 1;");
+}
+
+#[test]
+fn test_diff() {
+    let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\n";
+    let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\nL\n";
+    assert_eq!(
+        diff("x.vrv", old, new),
+        "--- x.vrv\n+++ x.vrv\n@@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n@@ -9,4 +9,4 @@\n i\n j\n k\n-l\n+L\n",
+    );
+    assert_eq!(diff("x.vrv", "a\n", "#\na\n"), "--- x.vrv\n+++ x.vrv\n@@ -1,1 +1,2 @@\n+#\n a\n");
+}
+
+#[test]
+fn test_closest_name() {
+    let names = ["total", "count", "print_all"];
+    assert_eq!(closest_name("totl", names), Some("total"));
+    assert_eq!(closest_name("print_al", names), Some("print_all"));
+    assert_eq!(closest_name("cnt", names), None);
+    assert_eq!(closest_name("total", names), None);
+}
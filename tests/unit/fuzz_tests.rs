@@ -0,0 +1,89 @@
+use verve_lang::compiler::Compiler;
+use verve_lang::lint::{Level, Lint};
+use verve_lang::fuzz::{panic_message, program, reduce, token_stream, Target};
+
+/// Pseudo-random inputs of every length up to `count`.
+fn inputs(count: usize) -> Vec<Vec<u8>> {
+    let mut state: u32 = 0x2545_f491;
+    (0..count)
+        .map(|len| {
+            (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    state as u8
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn test_token_stream() {
+    assert_eq!(token_stream(&[]), "");
+    assert_eq!(token_stream(&[0, 1, 2]), "fn let if");
+}
+
+#[test]
+fn test_empty_input_makes_a_valid_program() {
+    // Generated programs need not use all they declare.
+    let mut compiler = Compiler::new()
+        .lint_level(Lint::DeadFields, Level::Allow)
+        .lint_level(Lint::UnusedParameters, Level::Allow);
+    let file_id = compiler.add_source("fuzz.vrv", program(&[]));
+    let mut diagnostics = Vec::new();
+    compiler.compile(file_id, &mut diagnostics).unwrap();
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+}
+
+#[test]
+fn test_generated_programs_mostly_type_check() {
+    let inputs = inputs(200);
+    let compiled = inputs.iter()
+        .filter(|data| {
+            let mut compiler = Compiler::new();
+            let file_id = compiler.add_source("fuzz.vrv", program(data));
+            compiler.compile(file_id, &mut Vec::new()).is_ok()
+        })
+        .count();
+    assert!(compiled > inputs.len() / 5, "only {} of {} compiled", compiled, inputs.len());
+}
+
+#[test]
+fn test_targets() {
+    for target in Target::ALL {
+        assert_eq!(Target::from_name(target.name()), Some(target));
+    }
+    assert_eq!(Target::Source.input(b"fn main"), Some(("fn main".to_string(), 0)));
+    assert_eq!(Target::Source.input(&[0xff]), None);
+    assert_eq!(Target::Tokens.input(&[5, 0]), Some(("fn".to_string(), 2)));
+    assert_eq!(Target::Programs.input(&[]), None);
+}
+
+#[test]
+fn test_no_panics() {
+    for target in Target::ALL {
+        for data in inputs(150) {
+            if let Some((source, opt_level)) = target.input(&data) {
+                let message = panic_message(&source, opt_level);
+                assert!(message.is_none(), "{} panicked at -O{}: {:?}\n{}", target.name(), opt_level, message, source);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_reduce() {
+    let source = "fn main() -> i32 { let a = (1 + 2); /* note */ print(a); if a { print(zz); } return 0; }";
+    assert_eq!(reduce(source, |candidate| candidate.contains("print ( zz )")), "print ( zz )");
+
+    let undefined = |candidate: &str| {
+        let mut compiler = Compiler::new();
+        let file_id = compiler.add_source("reduce.vrv", candidate);
+        let mut diagnostics = Vec::new();
+        compiler.compile(file_id, &mut diagnostics).is_err()
+            && diagnostics.iter().any(|(_, diagnostic)| diagnostic.message == "Undefined variable 'zz'")
+    };
+    assert_eq!(reduce(source, undefined), "zz");
+}
@@ -0,0 +1,41 @@
+use verve_lang::ice;
+
+#[test]
+fn test_catch_records_phase_and_message() {
+    let ice = ice::catch(|| {
+        ice::enter("type checking");
+        panic!("unexpected type {}", 42);
+    }).unwrap_err();
+    assert_eq!(ice.phase, "type checking");
+    assert_eq!(ice.message, "unexpected type 42");
+    assert!(ice.location.contains("ice_tests.rs"));
+    let report = ice.report(None);
+    assert!(report.starts_with("internal compiler error during type checking: unexpected type 42"));
+}
+
+#[test]
+fn test_catch_passes_through_result() {
+    assert_eq!(ice::catch(|| 7).unwrap(), 7);
+}
+
+#[test]
+fn test_write_bundle_keeps_sources_and_config() {
+    let ice = ice::catch(|| {
+        ice::enter("code generation");
+        panic!("boom");
+    }).unwrap_err();
+    let parent = std::env::temp_dir().join(format!("verve-ice-test-{}", std::process::id()));
+    let sources = vec![
+        ("a/main.vrv".to_string(), "fn main() {}".to_string()),
+        ("b/main.vrv".to_string(), "let x = 1;".to_string()),
+    ];
+    let dir = ice.write_bundle(&parent, &sources, "target: wasm32-wasi").unwrap();
+    assert_eq!(std::fs::read_to_string(dir.join("sources/main.vrv")).unwrap(), "fn main() {}");
+    assert_eq!(std::fs::read_to_string(dir.join("sources/1-main.vrv")).unwrap(), "let x = 1;");
+    let report = std::fs::read_to_string(dir.join("report.txt")).unwrap();
+    assert!(report.contains("phase: code generation"));
+    assert!(report.contains("panic: boom"));
+    assert!(report.contains("target: wasm32-wasi"));
+    assert!(ice.report(Some(&dir)).contains(&dir.display().to_string()));
+    std::fs::remove_dir_all(parent).unwrap();
+}
@@ -0,0 +1,65 @@
+use codespan::Files;
+use verve_lang::ast::{CallConv, Type};
+use verve_lang::interface::{Interface, Signature};
+use verve_lang::lexer::Lexer;
+
+fn interface_of(source: &str) -> Interface {
+    let mut files = Files::new();
+    let file_id = files.add("test", String::from(source));
+    let mut parser = verve_lang::parser::Parser::new(Lexer::new(&files, file_id));
+    Interface::of(&parser.parse().unwrap())
+}
+
+#[test]
+fn test_interface_holds_exported_signatures_and_types() {
+    let interface = interface_of(
+        "#[packed] struct Flags { a: i32 : 3, b: bool } enum Shape { Dot, Circle(i32) } \
+         fn helper() -> i32 { return 1; } #[export] fn make(r: i32) -> Shape { return Shape::Circle(r); }",
+    );
+
+    assert_eq!(
+        interface.functions,
+        vec![Signature {
+            name: "make".to_string(),
+            params: vec![("r".to_string(), Type::I32)],
+            return_type: Type::Named("Shape".to_string()),
+            symbol: None,
+            call_conv: CallConv::C,
+        }]
+    );
+    assert_eq!(interface.structs[0].name, "Flags");
+    assert_eq!(interface.enums[0].variants[1].fields, vec![Type::I32]);
+}
+
+#[test]
+fn test_interface_roundtrip() {
+    let interface = interface_of(
+        "#[packed] struct Flags { a: i32 : 3, b: bool } #[derive(Eq)] enum Shape { Dot, Circle(i32) } \
+         #[export] fn make(r: i32, p: *Flags) -> (i32, Option<Shape>) { return (r, None); } \
+         #[export_name(\"plugin_init\")] fn init() {}",
+    );
+
+    let loaded = Interface::from_bytes(&interface.to_bytes()).unwrap();
+    assert_eq!(loaded.functions, interface.functions);
+    assert_eq!(loaded.functions[1].symbol.as_deref(), Some("plugin_init"));
+    let flags = &loaded.structs[0];
+    assert_eq!((flags.name.as_str(), flags.attrs[0].name.as_str()), ("Flags", "packed"));
+    assert_eq!(flags.fields.iter().map(|f| f.bits).collect::<Vec<_>>(), vec![Some(3), None]);
+    assert_eq!(loaded.enums[0].variants[1].name, "Circle");
+    assert_eq!(loaded.enums[0].attrs[0].name, "derive");
+    assert_eq!(loaded.to_bytes(), interface.to_bytes());
+}
+
+#[test]
+fn test_interface_rejects_malformed_input() {
+    let bytes = interface_of("#[export] fn f(x: i32) -> i32 { return x; }").to_bytes();
+
+    assert_eq!(Interface::from_bytes(b"ELF\x7f").unwrap_err(), "Not a Verve interface file");
+    assert_eq!(Interface::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(), "Truncated interface file");
+    let mut newer = bytes.clone();
+    newer[4] = 9;
+    assert_eq!(Interface::from_bytes(&newer).unwrap_err(), "Unsupported interface version 9 (expected 4)");
+    let mut trailing = bytes;
+    trailing.push(0);
+    assert_eq!(Interface::from_bytes(&trailing).unwrap_err(), "Trailing bytes after the interface");
+}
@@ -20,4 +20,49 @@ fn test_keyword_recognition() {
             &Token::KwIf
         ]
     );
-}
\ No newline at end of file
+}
+#[test]
+fn test_unicode_identifiers() {
+    let mut files = Files::new();
+    let file_id = files.add("test", String::from("größe _名前2"));
+
+    let lexer = Lexer::new(&files, file_id);
+    let tokens: Vec<_> = lexer.tokens().into_iter().map(|(t, _)| t).collect();
+
+    assert_eq!(tokens, vec![Token::Ident("größe".to_string()), Token::Ident("_名前2".to_string())]);
+    assert!(lexer.errors().is_empty());
+}
+
+#[test]
+fn test_invalid_characters() {
+    let mut files = Files::new();
+    let file_id = files.add("test", String::from("let a = 1 @ 2; let b = $$;"));
+
+    let lexer = Lexer::new(&files, file_id);
+    let errors = lexer.errors();
+
+    let messages: Vec<_> = errors.iter().map(|e| e.message.as_str()).collect();
+    assert_eq!(messages, vec!["Invalid character '@'", "Invalid characters '$$'"]);
+    assert_eq!(errors[1].labels[0].range, 23..25);
+    assert_eq!(lexer.tokens().len(), 10);
+}
+
+#[test]
+fn test_block_comments() {
+    let mut files = Files::new();
+    let file_id = files.add("test", String::from("fn /* outer /* inner */ still outer */ let /**/ if"));
+
+    let lexer = Lexer::new(&files, file_id);
+    let tokens: Vec<_> = lexer.tokens().into_iter().map(|(t, _)| t).collect();
+
+    assert_eq!(tokens, vec![Token::KwFn, Token::KwLet, Token::KwIf]);
+    let comments: Vec<_> = lexer.comments().iter().map(|span| (span.start().to_usize(), span.end().to_usize())).collect();
+    assert_eq!(comments, vec![(3, 38), (43, 47)]);
+    assert!(lexer.errors().is_empty());
+
+    let file_id = files.add("unterminated", String::from("fn /* a /* b */"));
+    let errors = Lexer::new(&files, file_id).errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message, "Unterminated block comment");
+    assert_eq!(errors[0].labels[0].range, 3..5);
+}
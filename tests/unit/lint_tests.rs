@@ -0,0 +1,86 @@
+use codespan_reporting::diagnostic::Severity;
+use verve_lang::compiler::{CompileFailed, Compiler, Stage};
+use verve_lang::lint::{Level, Lint};
+
+/// A lint diagnostic: code, severity and message.
+type Found = (String, Severity, String);
+
+/// The result of compiling `source`, and the lint diagnostics.
+fn lints(compiler: Compiler, source: &str) -> (Result<String, CompileFailed>, Vec<Found>) {
+    let mut compiler = compiler;
+    let file_id = compiler.add_source("lints.vrv", String::from(source));
+    let mut diagnostics = Vec::new();
+    let result = compiler.compile(file_id, &mut diagnostics);
+    let found = diagnostics.into_iter()
+        .filter_map(|(_, d)| Some((d.code?, d.severity, d.message)))
+        .collect();
+    (result, found)
+}
+
+#[test]
+fn test_dead_fields() {
+    let source = "struct Point { x: i32, y: i32, _pad: i32 }\n\
+        struct Shown { a: i32 }\n\
+        #[derive(Eq)]\nstruct Key { k: i32 }\n\
+        struct Shared { s: i32 }\n\
+        #[export]\nfn share(p: *Shared) -> i32 { return 0; }\n\
+        fn main() -> i32 {\n\
+            let p = Point { x: 1, y: 2, _pad: 0 };\n\
+            p.y = 3;\n\
+            print(Shown { a: 1 });\n\
+            let k = Key { k: 1 };\n\
+            return p.x;\n\
+        }";
+    let (result, found) = lints(Compiler::new(), source);
+    assert!(result.is_ok());
+    assert_eq!(found, [("dead_fields".to_string(), Severity::Warning, "Field 'y' of 'Point' is never read".to_string())]);
+}
+
+#[test]
+fn test_unused_parameters() {
+    let source = "fn add(a: i32, b: i32, _c: i32) -> i32 { return a; }\n\
+        #[export]\nfn callback(x: i32) -> i32 { return 0; }\n\
+        fn main() -> i32 { return add(1, 2, 3); }";
+    let (result, found) = lints(Compiler::new(), source);
+    assert!(result.is_ok());
+    assert_eq!(found, [("unused_parameters".to_string(), Severity::Warning, "Parameter 'b' of 'add' is never used".to_string())]);
+
+    // The label is on the parameter, and the note names the fix.
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("lints.vrv", String::from(source));
+    let mut diagnostics = Vec::new();
+    compiler.compile(file_id, &mut diagnostics).unwrap();
+    let (_, warning) = &diagnostics[0];
+    assert_eq!(&source[warning.labels[0].range.clone()], "b");
+    assert_eq!(warning.notes, ["help: rename it '_b' if that is intended"]);
+}
+
+#[test]
+fn test_lint_levels() {
+    let source = "struct P { x: i32 }\nfn f(n: i32) -> i32 { return 0; }\nfn main() -> i32 { let p = P { x: 1 }; return f(1); }";
+
+    let (result, found) = lints(Compiler::new().lint_level(Lint::DeadFields, Level::Allow), source);
+    assert!(result.is_ok());
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].0, "unused_parameters");
+
+    let (result, found) = lints(Compiler::new().lint_level(Lint::UnusedParameters, Level::Deny), source);
+    assert_eq!(result, Err(CompileFailed(Stage::Typecheck)));
+    let severities: Vec<_> = found.iter().map(|(code, severity, _)| (code.as_str(), *severity)).collect();
+    assert_eq!(severities, [("unused_parameters", Severity::Error), ("dead_fields", Severity::Warning)]);
+
+    assert_eq!(Lint::select("warnings"), Some(Lint::ALL));
+    assert_eq!(Lint::select("dead_fields"), Some(&[Lint::DeadFields][..]));
+    assert_eq!(Lint::select("dead-fields"), None);
+}
+
+#[test]
+fn test_deprecated_is_a_lint() {
+    let source = "#[deprecated]\nfn old() -> i32 { return 1; }\nfn main() -> i32 { return old(); }";
+    let (result, found) = lints(Compiler::new(), source);
+    assert!(result.is_ok());
+    assert_eq!(found, [("deprecated".to_string(), Severity::Warning, "Function 'old' is deprecated".to_string())]);
+
+    let (result, _) = lints(Compiler::new().lint_level(Lint::Deprecated, Level::Deny), source);
+    assert_eq!(result, Err(CompileFailed(Stage::Typecheck)));
+}
@@ -0,0 +1,63 @@
+use verve_lang::compiler::Compiler;
+use verve_lang::log::{self, Filter, Level};
+
+#[test]
+fn test_filter() {
+    let filter = Filter::parse("warn, codegen=debug,typeck=trace,codegen=info").unwrap();
+    assert!(filter.enabled("lexer", Level::Warn));
+    assert!(!filter.enabled("lexer", Level::Info));
+    assert!(filter.enabled("codegen", Level::Info));
+    assert!(!filter.enabled("codegen", Level::Debug));
+    assert!(filter.enabled("typeck", Level::Trace));
+
+    let off = Filter::default();
+    assert!(!off.enabled("driver", Level::Error));
+    assert_eq!(Filter::parse("").unwrap(), off);
+    assert_eq!(Filter::parse("debug").unwrap(), Filter::at(Level::Debug));
+
+    assert!(Filter::parse("codgen=debug").unwrap_err().starts_with("unknown log module 'codgen'"));
+    assert!(Filter::parse("verbose").unwrap_err().starts_with("unknown log level 'verbose'"));
+}
+
+#[test]
+fn test_compile_logs() {
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("logged.vrv", String::from("fn twice(n: i32) -> i32 { return n * 2; }\nfn main() -> i32 { return twice(2); }"));
+    let (result, lines) = log::capture(Filter::parse("error,codegen=debug").unwrap(), || compiler.compile(file_id, &mut Vec::new()));
+    assert!(result.is_ok());
+    assert!(lines.iter().any(|line| line.contains("debug codegen] emitting function 'twice'")), "{:#?}", lines);
+    assert!(lines.iter().any(|line| line.contains("info  codegen] generated")), "{:#?}", lines);
+    assert!(lines.iter().all(|line| line.contains(" codegen] ")), "{:#?}", lines);
+
+    // Outside `capture` the filter is back to logging nothing.
+    assert!(!log::enabled("codegen", Level::Error));
+}
+
+#[test]
+fn test_pass_decisions_are_logged() {
+    let source = "struct P { x: i32 }\n\
+        fn count(n: i32) -> i32 { if n == 0 { return 0; } return count(n - 1); }\n\
+        fn main() -> i32 {\n\
+            let p = __alloc(4) as *P;\n\
+            p.x = 1;\n\
+            let total = 0;\n\
+            for i in 0..3 { total = total + i; }\n\
+            let (a, b) = (1, 2);\n\
+            let l = list_new();\n\
+            return count(p.x) + total + a + b + list_len(l);\n\
+        }";
+    let mut compiler = Compiler::new().opt_level(2);
+    let file_id = compiler.add_source("passes.vrv", String::from(source));
+    let filter = Filter::parse("error,opt=debug,lower=debug,resolve=debug").unwrap();
+    let (result, lines) = log::capture(filter, || compiler.compile(file_id, &mut Vec::new()));
+    assert!(result.is_ok());
+    for expected in [
+        "debug opt] moved allocation 'p' in 'main' to the stack",
+        "debug opt] unrolled loop over 'i' in 'main' into 3 copies",
+        "debug opt] rewrote tail call of 'count' to a jump",
+        "debug lower] split a destructuring let in 'main' into plain lets",
+        "debug resolve] 'list_new' resolves to the library's",
+    ] {
+        assert!(lines.iter().any(|line| line.contains(expected)), "no {:?} in {:#?}", expected, lines);
+    }
+}
@@ -0,0 +1,59 @@
+use codespan::Files;
+use verve_lang::ast::{BinOp, Expr, Program, Stmt};
+use verve_lang::lexer::Lexer;
+use verve_lang::loops::{reduce_strength, unroll_loops};
+
+fn parse(source: &str) -> Program {
+    let mut files = Files::new();
+    let file_id = files.add("test", String::from(source));
+    let lexer = Lexer::new(&files, file_id);
+    verve_lang::parser::Parser::new(lexer).parse().unwrap()
+}
+
+#[test]
+fn test_small_constant_loops_are_unrolled() {
+    let mut program = parse(
+        "fn f() -> i32 { let t = 0; for i in 2..5 { t = t + i; } for j in 0..9 { t = t + j; } \
+         for k in 0..2 { k = 1; } for n in 0..2 { static let c = 0; } \
+         for m in 0..2 { if t > 3 { break; } } for q in 0..2 { if q == 0 { continue; } t = t + q; } return t; }",
+    );
+    unroll_loops(&mut program);
+
+    let body = &program.functions[0].body;
+    let Stmt::Block(iterations, _) = &body[1] else { panic!("expected unrolled loop, got {:?}", body[1]) };
+    assert_eq!(iterations.len(), 3);
+    for (i, iteration) in iterations.iter().enumerate() {
+        let Stmt::Block(stmts, _) = iteration else { panic!("expected iteration block") };
+        assert!(matches!(&stmts[0], Stmt::Let(name, _, Expr::Int(n, _, _), _) if name == "i" && *n == i as i64));
+        assert!(matches!(&stmts[1], Stmt::Block(..)));
+    }
+    assert!(matches!(&body[2], Stmt::For(..)));
+    assert!(matches!(&body[3], Stmt::For(..)));
+    assert!(matches!(&body[4], Stmt::For(..)));
+    assert!(matches!(&body[5], Stmt::For(..)));
+    assert!(matches!(&body[6], Stmt::For(..)));
+}
+
+#[test]
+fn test_induction_products_become_running_sums() {
+    let mut program = parse(
+        "fn f(n: i32) -> i32 { let t = 0; for i in 0..n { t = t + i * 4 + 4 * i + i * 2; } \
+         for j in 0..n { j = j + 1; t = t + j * 4; } \
+         for k in 0..n { if k == 2 { continue; } t = t + k * 4; } return t; }",
+    );
+    reduce_strength(&mut program);
+
+    let body = &program.functions[0].body;
+    let Stmt::Block(stmts, _) = &body[1] else { panic!("expected reduced loop, got {:?}", body[1]) };
+    assert!(matches!(&stmts[0], Stmt::Let(name, _, Expr::Int(0, _, _), _) if name == "__stride0"));
+    assert!(matches!(&stmts[1], Stmt::Let(name, _, Expr::Int(0, _, _), _) if name == "__stride1"));
+    let Stmt::For(_, _, loop_body, _) = &stmts[2] else { panic!("expected loop") };
+    assert_eq!(loop_body.len(), 3);
+    assert!(matches!(
+        &loop_body[1],
+        Stmt::Expr(Expr::Assign(_, sum, _, _), _)
+            if matches!(&**sum, Expr::BinOp(_, BinOp::Add, step, _, _) if matches!(**step, Expr::Int(4, _, _)))
+    ));
+    assert!(matches!(&body[2], Stmt::For(..)));
+    assert!(matches!(&body[3], Stmt::For(..)));
+}
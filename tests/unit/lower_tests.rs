@@ -0,0 +1,122 @@
+use codespan::Files;
+use verve_lang::ast::{Stmt, Type};
+use verve_lang::lexer::Lexer;
+use verve_lang::lower::lower_program;
+
+#[test]
+fn test_destructuring_let_lowering() {
+    let mut files = Files::new();
+    let source = String::from("fn f() -> i32 { let (a, Point { x, y: b }) = g(); return a; }");
+
+    let file_id = files.add("test", source);
+
+    let lexer = Lexer::new(&files, file_id);
+    let mut parser = verve_lang::parser::Parser::new(lexer);
+    let mut program = parser.parse().unwrap();
+    lower_program(&mut program);
+
+    let lets: Vec<(&str, Option<&Type>)> = program.functions[0].body.iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Let(name, ty, _, _) => Some((name.as_str(), ty.as_ref())),
+            _ => None,
+        })
+        .collect();
+    let holes = Type::Tuple(vec![Type::Unknown, Type::Unknown]);
+    let point = Type::Named("Point".to_string());
+    assert_eq!(lets, vec![
+        ("__destructure0", Some(&holes)),
+        ("a", None),
+        ("__destructure1", Some(&point)),
+        ("x", None),
+        ("b", None),
+    ]);
+}
+
+#[test]
+fn test_while_let_lowering() {
+    let mut files = Files::new();
+    let source = String::from("fn f() { while let Some(x) = next() { print(x); } }");
+
+    let file_id = files.add("test", source);
+
+    let lexer = Lexer::new(&files, file_id);
+    let mut parser = verve_lang::parser::Parser::new(lexer);
+    let mut program = parser.parse().unwrap();
+    lower_program(&mut program);
+
+    let body = &program.functions[0].body;
+    assert!(matches!(&body[0], Stmt::Let(name, Some(Type::Bool), _, _) if name == "__loop0"));
+    let Stmt::While(_, loop_body, _) = &body[1] else {
+        panic!("expected a while loop");
+    };
+    assert!(matches!(&loop_body[0], Stmt::Match(_, arms, _, _) if arms.len() == 2));
+}
+
+#[test]
+fn test_for_chars_lowering() {
+    let mut files = Files::new();
+    let source = String::from("fn f(s: string) { for c in __chars(s) { print(c); } }");
+
+    let file_id = files.add("test", source);
+
+    let lexer = Lexer::new(&files, file_id);
+    let mut parser = verve_lang::parser::Parser::new(lexer);
+    let mut program = parser.parse().unwrap();
+    lower_program(&mut program);
+
+    let Stmt::Block(block, _) = &program.functions[0].body[0] else {
+        panic!("expected a block");
+    };
+    assert!(matches!(&block[0], Stmt::Let(name, Some(Type::String), _, _) if name == "__chars0"));
+    assert!(matches!(&block[1], Stmt::Let(name, Some(Type::I32), _, _) if name == "__offset0"));
+    let Stmt::While(_, loop_body, _) = &block[2] else {
+        panic!("expected a while loop");
+    };
+    assert!(matches!(&loop_body[0], Stmt::Let(name, Some(Type::I32), _, _) if name == "c"));
+}
+
+#[test]
+fn test_for_chars_call_lowering() {
+    let mut files = Files::new();
+    let source = String::from("fn f(s: string) { for c in chars(s) { print(c); } }");
+    let own = String::from("fn chars(s: string) -> i32 { return 3; } fn f(s: string) { for c in chars(s) { print(c); } }");
+
+    let mut lowered = Vec::new();
+    for source in [source, own] {
+        let file_id = files.add("test", source);
+        let lexer = Lexer::new(&files, file_id);
+        let mut parser = verve_lang::parser::Parser::new(lexer);
+        let mut program = parser.parse().unwrap();
+        lower_program(&mut program);
+        lowered.push(program.functions.last().unwrap().body[0].clone());
+    }
+
+    assert!(matches!(&lowered[0], Stmt::Block(block, _) if matches!(&block[0], Stmt::Let(name, _, _, _) if name == "__chars0")));
+    // A program's own `chars` is called like any other function.
+    assert!(matches!(&lowered[1], Stmt::For(name, _, _, _) if name == "c"));
+}
+
+#[test]
+fn test_for_enumerate_lowering() {
+    let mut files = Files::new();
+    let source = String::from("fn f(xs: List) { for (i, x) in __enumerate(xs) { print(i + x); } }");
+
+    let file_id = files.add("test", source);
+
+    let lexer = Lexer::new(&files, file_id);
+    let mut parser = verve_lang::parser::Parser::new(lexer);
+    let mut program = parser.parse().unwrap();
+    lower_program(&mut program);
+
+    let Stmt::Block(block, _) = &program.functions[0].body[0] else {
+        panic!("expected a block");
+    };
+    let list = Type::Named("List".to_string());
+    assert!(matches!(&block[0], Stmt::Let(name, Some(ty), _, _) if name == "__list0" && *ty == list));
+    assert!(matches!(&block[1], Stmt::Let(name, Some(Type::I32), _, _) if name == "__index0"));
+    let Stmt::While(_, loop_body, _) = &block[2] else {
+        panic!("expected a while loop");
+    };
+    assert!(matches!(&loop_body[0], Stmt::Let(name, None, _, _) if name == "i"));
+    assert!(matches!(&loop_body[1], Stmt::Let(name, None, _, _) if name == "x"));
+}
@@ -0,0 +1,36 @@
+use verve_lang::codegen::{demangle, mangle, mangle_variant};
+
+#[test]
+fn test_mangle_reserved_names() {
+    assert_eq!(mangle("total"), "total");
+    assert_eq!(mangle("main"), "main");
+    assert_eq!(mangle("int"), "verve_u_int");
+    assert_eq!(mangle("printf"), "verve_u_printf");
+    assert_eq!(mangle("verve_debug_alloc"), "verve_u_verve_debug_alloc");
+    assert_eq!(mangle("verve_u_int"), "verve_u_verve_u_int");
+}
+
+#[test]
+fn test_mangle_header_names() {
+    assert_eq!(mangle("puts"), "verve_u_puts");
+    assert_eq!(mangle("remove"), "verve_u_remove");
+    assert_eq!(mangle("time_t"), "verve_u_time_t");
+    assert_eq!(mangle("tm"), "verve_u_tm");
+    assert_eq!(mangle("SIGINT"), "verve_u_SIGINT");
+    assert_eq!(mangle("strlen2"), "verve_u_strlen2");
+    assert_eq!(mangle("_Point"), "verve_u__Point");
+    assert_eq!(mangle("__match0"), "__match0");
+    assert_eq!(mangle("Shape"), "Shape");
+    assert_eq!(mangle("Event"), "Event");
+    assert_eq!(mangle_variant("SIG", "INT"), "verve_u_SIG_INT");
+    assert_eq!(mangle_variant("Shape", "Circle"), "Shape_Circle");
+}
+
+#[test]
+fn test_demangle_compiler_output() {
+    assert_eq!(
+        demangle("error: 'verve_u_register' undeclared; did you mean 'verve_u_verve_u_int'?"),
+        "error: 'register' undeclared; did you mean 'verve_u_int'?"
+    );
+    assert_eq!(demangle("my_verve_u_x + 1"), "my_verve_u_x + 1");
+}
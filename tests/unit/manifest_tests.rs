@@ -0,0 +1,45 @@
+use std::collections::BTreeSet;
+use verve_lang::manifest::Manifest;
+
+fn set(names: &[&str]) -> BTreeSet<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+#[test]
+fn test_manifest_features() {
+    let text = "[package]\nname = \"demo\"  # ignored\nversion = \"0.2.0\"\n\n[features]\ndefault = [\"std\"]\nstd = []\njson = [\n    \"std\",  # json needs std\n]\nfast = []\n";
+    let manifest = Manifest::parse(text).unwrap();
+    assert_eq!(manifest.version.as_deref(), Some("0.2.0"));
+    assert_eq!(manifest.features.len(), 4);
+    assert_eq!(manifest.features["json"], ["std"]);
+
+    assert_eq!(manifest.resolve(&[], true).unwrap(), set(&["std"]));
+    assert_eq!(manifest.resolve(&[], false).unwrap(), set(&[]));
+    assert_eq!(manifest.resolve(&["json".to_string()], false).unwrap(), set(&["json", "std"]));
+    assert_eq!(manifest.resolve(&["fast".to_string()], true).unwrap(), set(&["fast", "std"]));
+    let err = manifest.resolve(&["gpu".to_string()], true).unwrap_err();
+    assert_eq!(err, "Unknown feature 'gpu' (not in [features] of verve.toml)");
+}
+
+#[test]
+fn test_malformed_manifests() {
+    let err = Manifest::parse("[features]\njson = \"std\"\n").unwrap_err();
+    assert_eq!(err, "line 2: feature 'json' must be a list of feature names");
+    let err = Manifest::parse("[features]\njson = [\"std\"]\n").unwrap_err();
+    assert_eq!(err, "feature 'json' enables unknown feature 'std'");
+    assert!(Manifest::parse("[features]\njson\n").unwrap_err().starts_with("line 2:"));
+    let err = Manifest::parse("[package]\nversion = 2\n").unwrap_err();
+    assert_eq!(err, "line 2: version must be a string");
+    assert_eq!(Manifest::parse("").unwrap(), Manifest::default());
+}
+
+#[test]
+fn test_build_hooks() {
+    let text = "[build]\npre = \"python3 gen.py > generated.vrv\"  # sources\npost = 'echo done'\n";
+    assert_eq!(Manifest::parse(text).unwrap_err(), "line 3: build hook 'post' must be a string");
+    let manifest = Manifest::parse("[build]\npre = \"python3 gen.py > generated.vrv\"\n").unwrap();
+    assert_eq!(manifest.build.pre.as_deref(), Some("python3 gen.py > generated.vrv"));
+    assert_eq!(manifest.build.post, None);
+    let err = Manifest::parse("[build]\nscript = \"build.vrv\"\n").unwrap_err();
+    assert_eq!(err, "line 2: unknown build hook 'script' (expected pre or post)");
+}
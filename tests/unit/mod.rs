@@ -1,2 +1,28 @@
 pub mod lexer_tests;
-pub mod parser_tests;
\ No newline at end of file
+pub mod parser_tests;
+pub mod deps_tests;
+pub mod lower_tests;
+pub mod escape_tests;
+pub mod tailcall_tests;
+pub mod loops_tests;
+pub mod profile_tests;
+pub mod manifest_tests;
+pub mod backend_tests;
+pub mod compiler_tests;
+pub mod mangle_tests;
+pub mod cli_tests;
+pub mod interface_tests;
+pub mod stdlib_tests;
+pub mod testing_tests;
+pub mod fuzz_tests;
+pub mod lint_tests;
+pub mod log_tests;
+pub mod pretty_tests;
+pub mod sandbox_tests;
+pub mod fix_tests;
+pub mod ice_tests;
+pub mod target_dir_tests;
+pub mod target_tests;
+pub mod model_tests;
+pub mod runtime_tests;
+pub mod playground_tests;
@@ -0,0 +1,46 @@
+use verve_lang::compiler::Compiler;
+use verve_lang::model::{Attribute, Location, TypeKind, TypeRef};
+
+#[test]
+fn test_model_of_program() {
+    let source = "#[align(8)]\nstruct User { id: i32, name: string, role: Role, manager: Option<*User> }\nenum Role { Admin, Member(i32) }\nlet limit = 10;\n#[export]\nfn find(id: i32) -> Option<User> { return None; }\nfn main() -> i32 { return 0; }";
+    let mut compiler = Compiler::new();
+    let file_id = compiler.add_source("app.vrv", source);
+    let model = compiler.model(&[file_id], &mut Vec::new()).unwrap();
+
+    let user = model.type_named("User").unwrap();
+    let role = model.type_named("Role").unwrap();
+    assert!(role.id < user.id, "ids follow name order");
+    assert_eq!(model.type_decl(user.id).name, "User");
+    assert_eq!(user.location, Some(Location { file: "app.vrv".to_string(), line: 2 }));
+    assert_eq!(user.attributes, [Attribute { name: "align".to_string(), args: vec!["8".to_string()] }]);
+    let TypeKind::Struct(fields) = &user.kind else { panic!("User is a struct") };
+    assert_eq!(fields[2].ty, TypeRef::Named(role.id));
+    assert_eq!(fields[3].ty, TypeRef::Option(Box::new(TypeRef::Pointer(Box::new(TypeRef::Named(user.id))))));
+    let TypeKind::Enum(variants) = &role.kind else { panic!("Role is an enum") };
+    assert_eq!(variants[1].fields, [TypeRef::I32]);
+
+    let find = model.function_named("find").unwrap();
+    assert!(find.exported && !find.is_extern);
+    assert_eq!(find.params, [("id".to_string(), TypeRef::I32)]);
+    assert_eq!(find.result, TypeRef::Option(Box::new(TypeRef::Named(user.id))));
+    assert!(!model.function_named("main").unwrap().attributes.iter().any(|attr| attr.name == "export"));
+
+    assert_eq!(model.globals.len(), 1);
+    assert_eq!(model.globals[0].ty, TypeRef::I32);
+    assert_eq!(model.global(model.globals[0].id).name, "limit");
+}
+
+#[test]
+fn test_model_ids_ignore_declaration_order() {
+    let mut compiler = Compiler::new();
+    let first = compiler.add_source("a.vrv", "struct B { x: i32 }\nstruct A { b: B }\nfn main() -> i32 { return 0; }");
+    let second = compiler.add_source("b.vrv", "struct A { b: B }\nstruct B { x: i32 }\nfn main() -> i32 { return 0; }");
+    let first = compiler.model(&[first], &mut Vec::new()).unwrap();
+    let second = compiler.model(&[second], &mut Vec::new()).unwrap();
+    assert_eq!(first.types.iter().map(|decl| (decl.id, &decl.kind)).collect::<Vec<_>>(),
+               second.types.iter().map(|decl| (decl.id, &decl.kind)).collect::<Vec<_>>());
+
+    let broken = compiler.add_source("broken.vrv", "fn main() -> i32 { return true; }");
+    assert!(compiler.model(&[broken], &mut Vec::new()).is_err());
+}
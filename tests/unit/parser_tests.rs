@@ -1,4 +1,5 @@
 use codespan::Files;
+use verve_lang::ast::{AttrArg, Expr, Pattern, Stmt, Type};
 use verve_lang::lexer::Lexer;
 
 #[test]
@@ -15,3 +16,193 @@ fn test_function_parsing() {
     assert_eq!(program.functions.len(), 1);
     assert_eq!(program.functions[0].name, "add");
 }
+
+#[test]
+fn test_recursive_struct_parsing() {
+    let mut files = Files::new();
+    let source = String::from("struct Node { value: i32, next: *Node } enum List { Nil, Cons(i32, *List) }");
+
+    let file_id = files.add("test", source);
+
+    let lexer = Lexer::new(&files, file_id);
+    let mut parser = verve_lang::parser::Parser::new(lexer);
+    let program = parser.parse().unwrap();
+
+    assert_eq!(program.structs.len(), 1);
+    assert_eq!(program.structs[0].fields[1].ty, Type::Pointer(Box::new(Type::Named("Node".to_string()))));
+    assert_eq!(program.enums.len(), 1);
+    assert_eq!(program.enums[0].variants[1].fields.len(), 2);
+}
+
+#[test]
+fn test_bitfield_parsing() {
+    let mut files = Files::new();
+    let source = String::from("struct Reg { mode: i32 : 3, enabled: bool : 1, id: i32 }");
+
+    let file_id = files.add("test", source);
+
+    let lexer = Lexer::new(&files, file_id);
+    let mut parser = verve_lang::parser::Parser::new(lexer);
+    let program = parser.parse().unwrap();
+
+    let bits: Vec<_> = program.structs[0].fields.iter().map(|f| f.bits).collect();
+    assert_eq!(bits, vec![Some(3), Some(1), None]);
+}
+
+#[test]
+fn test_struct_attribute_parsing() {
+    let mut files = Files::new();
+    let source = String::from("#[repr(C)] #[align(16)] struct Vec4 { x: i32 }");
+
+    let file_id = files.add("test", source);
+
+    let lexer = Lexer::new(&files, file_id);
+    let mut parser = verve_lang::parser::Parser::new(lexer);
+    let program = parser.parse().unwrap();
+
+    let def = &program.structs[0];
+    assert_eq!(def.attr("repr").unwrap().args, vec![AttrArg::Ident("C".to_string())]);
+    assert_eq!(def.attr("align").unwrap().args, vec![AttrArg::Int(16)]);
+    assert!(def.attr("packed").is_none());
+}
+
+#[test]
+fn test_match_parsing() {
+    let mut files = Files::new();
+    let source = String::from("fn f(s: Shape) { match s { case Circle(r) if r > 10: print(r); case Shape::Rect(_, h): print(h); print(0); case _: } }");
+
+    let file_id = files.add("test", source);
+
+    let lexer = Lexer::new(&files, file_id);
+    let mut parser = verve_lang::parser::Parser::new(lexer);
+    let program = parser.parse().unwrap();
+
+    let Stmt::Match(_, arms, _, _) = &program.functions[0].body[0] else {
+        panic!("expected a match statement");
+    };
+    assert_eq!(arms.len(), 3);
+    assert!(arms[0].guard.is_some());
+    assert!(matches!(&arms[1].pattern, Pattern::Variant(Some(e), v, args, _) if e == "Shape" && v == "Rect" && args.len() == 2));
+    assert_eq!(arms[1].body.len(), 2);
+    assert!(matches!(arms[2].pattern, Pattern::Wildcard(_)));
+    assert!(arms[2].body.is_empty());
+}
+
+#[test]
+fn test_optional_semicolons() {
+    let mut files = Files::new();
+    let source = String::from("fn f(a: i32, b: i32,) -> i32 {\n    let x = f(a,\n        b,)\n    return x }");
+
+    let file_id = files.add("test", source);
+
+    let lexer = Lexer::new(&files, file_id);
+    let mut parser = verve_lang::parser::Parser::new(lexer);
+    let program = parser.parse().unwrap();
+
+    let function = &program.functions[0];
+    assert_eq!(function.params.len(), 2);
+    assert!(matches!(&function.body[0], Stmt::Let(name, ..) if name == "x"));
+    assert!(matches!(&function.body[1], Stmt::Return(..)));
+
+    let file_id = files.add("paren_line", String::from("fn f(x: i32) -> i32 {\n    let y = x\n    (y)\n    return g\n    (y, 1) }"));
+    let mut parser = verve_lang::parser::Parser::new(Lexer::new(&files, file_id));
+    let body = parser.parse().unwrap().functions.remove(0).body;
+    assert!(matches!(&body[0], Stmt::Let(_, _, Expr::Var(name, ..), _) if name == "x"), "{:?}", body[0]);
+    assert!(matches!(&body[1], Stmt::Expr(Expr::Var(name, ..), _) if name == "y"), "{:?}", body[1]);
+    assert!(matches!(&body[2], Stmt::Return(Expr::Var(name, ..), _) if name == "g"), "{:?}", body[2]);
+    assert!(matches!(&body[3], Stmt::Expr(Expr::Tuple(..), _)), "{:?}", body[3]);
+
+    let file_id = files.add("same_line", String::from("fn f() -> i32 { let x = 1 return x; }"));
+    let mut parser = verve_lang::parser::Parser::new(Lexer::new(&files, file_id));
+    assert_eq!(parser.parse().unwrap_err().message, "Expected 'Semi'");
+}
+
+#[test]
+fn test_block_expressions() {
+    let mut files = Files::new();
+    let source = String::from("fn f(a: i32) -> i32 {\n    let b = { let t = a * a\n        t + 1 }\n    { print(b) }\n    return b }");
+
+    let file_id = files.add("test", source);
+
+    let lexer = Lexer::new(&files, file_id);
+    let mut parser = verve_lang::parser::Parser::new(lexer);
+    let program = parser.parse().unwrap();
+
+    let body = &program.functions[0].body;
+    let Stmt::Let(_, _, Expr::Block(stmts, value, _, _), _) = &body[0] else { panic!("{:?}", body[0]) };
+    assert!(matches!(stmts.as_slice(), [Stmt::Let(name, ..)] if name == "t"));
+    assert!(matches!(**value, Expr::BinOp(..)));
+    assert!(matches!(&body[1], Stmt::Block(stmts, _) if stmts.len() == 1));
+
+    let file_id = files.add("no_value", String::from("fn f() -> i32 { return { let t = 1; }; }"));
+    let mut parser = verve_lang::parser::Parser::new(Lexer::new(&files, file_id));
+    assert_eq!(parser.parse().unwrap_err().message, "Expected a value at the end of the block");
+}
+
+#[test]
+fn test_if_expressions() {
+    let mut files = Files::new();
+    let source = String::from("fn f(a: i32) -> i32 { let b = if a > 0 { a } else if a == 0 { 1 } else { let t = 0 - a; t }; return b }");
+    let file_id = files.add("test", source);
+    let mut parser = verve_lang::parser::Parser::new(Lexer::new(&files, file_id));
+    let program = parser.parse().unwrap();
+
+    let body = &program.functions[0].body;
+    let Stmt::Let(_, _, Expr::If(cond, then_value, else_value, _, _), _) = &body[0] else { panic!("{:?}", body[0]) };
+    assert!(matches!(**cond, Expr::BinOp(..)));
+    assert!(matches!(&**then_value, Expr::Block(stmts, _, _, _) if stmts.is_empty()));
+    let Expr::If(_, _, last, _, _) = &**else_value else { panic!("{:?}", else_value) };
+    assert!(matches!(&**last, Expr::Block(stmts, _, _, _) if stmts.len() == 1));
+
+    let file_id = files.add("no_else", String::from("fn f() -> i32 { return if true { 1 }; }"));
+    let mut parser = verve_lang::parser::Parser::new(Lexer::new(&files, file_id));
+    assert_eq!(parser.parse().unwrap_err().message, "An if expression needs an else branch");
+}
+
+#[test]
+fn test_input_ending_inside_parentheses() {
+    for source in ["if (", "let x = ((", "f(1, ("] {
+        let mut files = Files::new();
+        let file_id = files.add("unclosed", String::from(source));
+        let mut parser = verve_lang::parser::Parser::new(Lexer::new(&files, file_id));
+        assert_eq!(parser.parse().unwrap_err().message, "Expected primary expression", "{}", source);
+    }
+}
+
+#[test]
+fn test_else_if_parsing() {
+    let mut files = Files::new();
+    let source = String::from("fn sign(n: i32) -> i32 { if n < 0 { return 0 - 1; } else if n > 0 { return 1; } else { return 0; } }");
+    let file_id = files.add("test", source);
+    let mut parser = verve_lang::parser::Parser::new(Lexer::new(&files, file_id));
+    let program = parser.parse().unwrap();
+
+    let Stmt::If(_, _, Some(else_branch), span) = &program.functions[0].body[0] else {
+        panic!("{:?}", program.functions[0].body[0])
+    };
+    let [Stmt::If(Expr::BinOp(..), then_branch, Some(last), nested)] = else_branch.as_slice() else {
+        panic!("{:?}", else_branch)
+    };
+    assert_eq!((then_branch.len(), last.len()), (1, 1));
+    assert_eq!(span.end(), nested.end());
+}
+
+#[test]
+fn test_break_and_continue_parsing() {
+    let mut files = Files::new();
+    let source = String::from("fn f() { while true {\n    if done() { break }\n    continue\n} let n = { break; 1 }; }");
+    let file_id = files.add("test", source);
+    let mut parser = verve_lang::parser::Parser::new(Lexer::new(&files, file_id));
+    let program = parser.parse().unwrap();
+
+    let body = &program.functions[0].body;
+    let Stmt::While(_, loop_body, _) = &body[0] else { panic!("{:?}", body[0]) };
+    assert!(matches!(&loop_body[0], Stmt::If(_, then_branch, None, _) if matches!(then_branch.as_slice(), [Stmt::Break(_)])));
+    assert!(matches!(&loop_body[1], Stmt::Continue(_)));
+    let Stmt::Let(_, _, Expr::Block(stmts, _, _, _), _) = &body[1] else { panic!("{:?}", body[1]) };
+    assert!(matches!(stmts.as_slice(), [Stmt::Break(_)]));
+
+    let file_id = files.add("same_line", String::from("fn f() { while true { break print(1); } }"));
+    let mut parser = verve_lang::parser::Parser::new(Lexer::new(&files, file_id));
+    assert_eq!(parser.parse().unwrap_err().message, "Expected 'Semi'");
+}
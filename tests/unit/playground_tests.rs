@@ -0,0 +1,40 @@
+use codespan_reporting::diagnostic::Severity;
+use verve_lang::compiler::Stage;
+use verve_lang::playground::{compile, to_json, Diagnostic};
+
+#[test]
+fn test_compile_returns_c() {
+    let code = compile("fn main() -> i32 { print(6 * 7); return 0; }").unwrap();
+    assert!(code.starts_with("// Generated by Verve Compiler (target: x86_64-unknown-linux-gnu)"), "{}", code);
+    assert!(code.contains("int main("), "{}", code);
+}
+
+#[test]
+fn test_compile_locates_diagnostics() {
+    let diagnostics = compile("fn main() -> i32 {\n    return nope;\n}").unwrap_err();
+    assert_eq!(diagnostics[0], Diagnostic {
+        stage: Stage::Typecheck,
+        severity: Severity::Error,
+        message: "Undefined variable 'nope'".to_string(),
+        location: Some((2, 12)),
+    });
+}
+
+#[test]
+fn test_compile_reads_no_files() {
+    let diagnostics = compile("fn main() -> i32 { print(include_str(\"Cargo.toml\")); return 0; }").unwrap_err();
+    assert!(diagnostics[0].message.starts_with("Cannot read 'Cargo.toml'"), "{:?}", diagnostics);
+}
+
+#[test]
+fn test_to_json() {
+    assert_eq!(to_json(&Ok("int x = \"\\\n\";".to_string())), r#"{"ok":true,"code":"int x = \"\\\n\";"}"#);
+    let diagnostics = vec![
+        Diagnostic { stage: Stage::Parse, severity: Severity::Error, message: "Unexpected '\t'".to_string(), location: Some((1, 3)) },
+        Diagnostic { stage: Stage::Typecheck, severity: Severity::Warning, message: "unused".to_string(), location: None },
+    ];
+    assert_eq!(
+        to_json(&Err(diagnostics)),
+        r#"{"ok":false,"diagnostics":[{"severity":"error","message":"Unexpected '\t'","line":1,"column":3},{"severity":"warning","message":"unused"}]}"#,
+    );
+}
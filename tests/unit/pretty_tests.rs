@@ -0,0 +1,136 @@
+use codespan::Files;
+use std::path::{Path, PathBuf};
+use verve_lang::ast::Program;
+use verve_lang::lexer::Lexer;
+use verve_lang::{fuzz, pretty};
+
+fn parse(source: &str) -> Option<Program> {
+    let mut files = Files::new();
+    let file_id = files.add("test", source.to_string());
+    let lexer = Lexer::new(&files, file_id);
+    let mut parser = verve_lang::parser::Parser::new(lexer);
+    parser.parse().ok()
+}
+
+/// The tree printed with its spans left out, so trees parsed from
+/// differently laid out source compare equal.
+fn shape(program: &Program) -> String {
+    let debug = format!("{:?}", program);
+    let mut out = String::new();
+    let mut rest = debug.as_str();
+    while let Some(start) = rest.find("Span {") {
+        out.push_str(&rest[..start]);
+        let mut depth = 0;
+        let mut end = start;
+        for (i, c) in rest[start..].char_indices() {
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = start + i + 1;
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Printing `source` and parsing the result gives the same tree, and
+/// printing that tree gives the same text again.
+fn assert_roundtrips(source: &str) {
+    let Some(ast) = parse(source) else { return };
+    let printed = pretty::program(&ast);
+    let reparsed = parse(&printed)
+        .unwrap_or_else(|| panic!("printed program does not parse\nsource:\n{}\nprinted:\n{}", source, printed));
+    assert_eq!(shape(&reparsed), shape(&ast), "tree changed\nsource:\n{}\nprinted:\n{}", source, printed);
+    assert_eq!(pretty::program(&reparsed), printed, "printing is not a fixed point\nsource:\n{}", source);
+}
+
+fn sources(dir: &Path, found: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else { return };
+    for entry in entries {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            sources(&path, found);
+        } else if path.extension().is_some_and(|ext| ext == "vrv") {
+            found.push(path);
+        }
+    }
+}
+
+/// Pseudo-random inputs of every length up to `count`.
+fn inputs(count: usize) -> Vec<Vec<u8>> {
+    let mut state: u32 = 0x9e37_79b9;
+    (0..count)
+        .map(|len| {
+            (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    state as u8
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn test_sample_programs_roundtrip() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut found = vec![root.join("src/prelude.vrv")];
+    for dir in ["tests", "std", "examples"] {
+        sources(&root.join(dir), &mut found);
+    }
+    assert!(found.len() > 20, "only found {:?}", found);
+    for path in found {
+        assert_roundtrips(&std::fs::read_to_string(&path).unwrap());
+    }
+}
+
+#[test]
+fn test_generated_programs_roundtrip() {
+    for data in inputs(400) {
+        assert_roundtrips(&fuzz::program(&data));
+    }
+}
+
+#[test]
+fn test_grammar_corners_roundtrip() {
+    let sources = [
+        "let a = *p.x; let b = (*p).x; let c = *p as i32; let d = (a + b) as i32;",
+        "for i in 0..(n + 1) { print(i); } for j in a.b..c.d { print(j); }",
+        "let r = (0..n).start; let t = (1,); let u = ((1, 2), (3,)).0.1;",
+        "x = y = z; let w = (x = 1) == 2;",
+        "while (P { x: 1 }).x == f(q) { break; }",
+        "match (S { a: 1 }) { case S { a: (b, 1) } if b < 2: print(b); case E::V(_, 3): case _: print(0); }",
+        "if let Some(n) = (P { x: 1 }).x { print(n); } else { if a < b { print(b); } else { print(c); } }",
+        "let v = { let t = 1; t + 1 }; let w = ({ 1 }).x;",
+        "let v = if a { 1 } else if b { let t = 2; t } else { 3 }; let w = (if a { p } else { q }).x + (if c { 1 } else { 2 });",
+        "if a < b { print(a); } else if let Some(n) = o { print(n); } else if b { print(b); }",
+        "let m = Line { b: Point { z: 9, ..l.a }, ..l }; let (a, b) = (1, 2); let P { x, y } = p;",
+        "safe { defer __dealloc(p as rawptr); print(*p); }",
+        "fn f(a: *i32, b: (i32, bool)) -> *i32 { return a; } enum E { A, B(i32, *E) } struct S { x: i32 : 3 }",
+        "#[export] fn g() { /* a comment */ } union U { a: i32, b: f32 }",
+        "#[derive(Eq, Ord)] enum Level { Low, High } #[derive(Eq)] #[packed] struct P { l: Level }",
+    ];
+    for source in sources {
+        assert!(parse(source).is_some(), "sample does not parse: {}", source);
+        assert_roundtrips(source);
+    }
+}
+
+#[test]
+fn test_parentheses() {
+    let printed = pretty::program(&parse("let x = (a + b) * (c - (d - e)); let y = a - b - c;").unwrap());
+    assert_eq!(printed, "let x = (a + b) * (c - (d - e));\nlet y = a - b - c;\n");
+
+    let printed = pretty::program(&parse("if (P { x: 1 }).x == 1 { print(1); }").unwrap());
+    assert!(printed.starts_with("if (P { x: 1 }).x == 1 {"), "{}", printed);
+}
@@ -0,0 +1,34 @@
+use verve_lang::profile::{aggregate, parse, report, FunctionProfile};
+
+fn entry(name: &str, calls: u64, total_ns: u64, self_ns: u64) -> FunctionProfile {
+    FunctionProfile { name: name.to_string(), calls, total_ns, self_ns }
+}
+
+#[test]
+fn test_profile_parsing() {
+    let text = "# verve profile v1\nfib\t10\t5000\t4000\nmain\t1\t6000\t1000\n";
+    assert_eq!(
+        parse(text).unwrap(),
+        vec![entry("fib", 10, 5000, 4000), entry("main", 1, 6000, 1000)]
+    );
+
+    assert!(parse("fib\t1\t2\t3\n").is_err());
+    let err = parse("# verve profile v1\nfib\t1\tlots\t3\n").unwrap_err();
+    assert!(err.contains("line 2"), "Unexpected error: {}", err);
+}
+
+#[test]
+fn test_profile_aggregation_and_report() {
+    let runs = vec![
+        vec![entry("main", 1, 6000, 1000), entry("fib", 10, 5000, 4000)],
+        vec![entry("fib", 5, 3000, 2000), entry("main", 1, 4000, 1000)],
+    ];
+    let profiles = aggregate(&runs);
+    assert_eq!(profiles, vec![entry("fib", 15, 8000, 6000), entry("main", 2, 10000, 2000)]);
+
+    let table = report(&profiles);
+    let rows = table.lines().collect::<Vec<_>>();
+    assert_eq!(rows.len(), 3);
+    assert!(rows[1].contains("75.00%") && rows[1].ends_with("fib"), "{}", table);
+    assert!(rows[2].contains("25.00%") && rows[2].ends_with("main"), "{}", table);
+}
@@ -0,0 +1,50 @@
+use verve_lang::codegen::runtime;
+use verve_lang::compiler::Compiler;
+
+#[test]
+fn test_prototypes() {
+    let code = "#pragma comment(lib, \"ws2_32.lib\")\nstatic int verve_add(int a, int b) {\n    return a + b;\n}\n";
+    assert_eq!(runtime::prototypes(code), "#pragma comment(lib, \"ws2_32.lib\")\nint verve_add(int a, int b);\n\n");
+}
+
+#[test]
+fn test_library_source() {
+    let unix = runtime::library_source("x86_64-unknown-linux-gnu");
+    assert!(unix.contains("void verve_panic(const char *message) {"), "Unexpected source:\n{}", unix);
+    assert!(!unix.lines().any(|line| line.starts_with("static ")), "Unexpected source:\n{}", unix);
+
+    let windows = runtime::header("x86_64-pc-windows-msvc");
+    assert!(windows.contains("#include <winsock2.h>"));
+    assert!(!windows.contains("sys/socket.h"));
+}
+
+#[test]
+fn test_linked_runtime() {
+    let source = "fn main() -> i32 { assert(1 == 1, \"math\"); return 0; }";
+    let compile = |compiler: Compiler| {
+        let mut compiler = compiler.target_triple("x86_64-unknown-linux-gnu");
+        let file_id = compiler.add_source("linked.vrv", String::from(source));
+        compiler.compile(file_id, &mut Vec::new()).unwrap()
+    };
+
+    let bundled = compile(Compiler::new());
+    assert!(bundled.contains("static void verve_panic(const char *message) {"), "Unexpected output:\n{}", bundled);
+
+    assert!(!bundled.contains(runtime::HEADER_NAME), "Unexpected output:\n{}", bundled);
+
+    let linked = compile(Compiler::new().linked_runtime(true));
+    assert!(linked.contains(&runtime::abi_check()), "Unexpected output:\n{}", linked);
+    assert!(!linked.contains("verve_panic(const char *message) {"), "Unexpected output:\n{}", linked);
+}
+
+#[test]
+fn test_header() {
+    let header = runtime::header("x86_64-unknown-linux-gnu");
+    assert!(header.contains(&format!("#define VERVE_RT_ABI_VERSION {}\n", runtime::ABI_VERSION)), "Unexpected header:\n{}", header);
+    assert!(header.contains("void verve_panic(const char *message);"), "Unexpected header:\n{}", header);
+    assert!(header.contains("void *verve_list_new(void);"), "Unexpected header:\n{}", header);
+    assert!(header.contains("int verve_tcp_listen(int port);"), "Unexpected header:\n{}", header);
+    assert!(header.contains("#include <sys/socket.h>"), "Unexpected header:\n{}", header);
+    assert!(header.ends_with("#endif\n"), "Unexpected header:\n{}", header);
+    assert!(runtime::library_source("x86_64-unknown-linux-gnu").contains(&runtime::abi_check()));
+}
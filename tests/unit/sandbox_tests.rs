@@ -0,0 +1,50 @@
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use verve_lang::sandbox::{self, parse_size, Limits, Outcome};
+
+#[test]
+fn test_parse_size() {
+    assert_eq!(parse_size("4096"), Ok(4096));
+    assert_eq!(parse_size("64k"), Ok(64 << 10));
+    assert_eq!(parse_size("512M"), Ok(512 << 20));
+    assert_eq!(parse_size("2G"), Ok(2 << 30));
+    assert!(parse_size("12T").unwrap_err().contains("unknown size unit 'T'"));
+    assert!(parse_size("M").is_err());
+    assert!(parse_size("99999999999G").is_err());
+}
+
+#[test]
+#[cfg(unix)]
+fn test_run_collects_output() {
+    let limits = Limits { timeout: Some(Duration::from_secs(10)), memory: None };
+    let mut command = Command::new("sh");
+    command.args(["-c", "echo out; echo err >&2; exit 4"]).stdout(Stdio::piped()).stderr(Stdio::piped());
+    let Outcome::Exited { status, stdout, stderr } = sandbox::run(&mut command, &limits).unwrap() else {
+        panic!("sh timed out");
+    };
+    assert_eq!(status.code(), Some(4));
+    assert_eq!(stdout, b"out\n");
+    assert_eq!(stderr, b"err\n");
+}
+
+#[test]
+#[cfg(unix)]
+fn test_run_kills_at_timeout() {
+    let limits = Limits { timeout: Some(Duration::from_millis(100)), memory: None };
+    let start = Instant::now();
+    let outcome = sandbox::run(Command::new("sleep").arg("10"), &limits).unwrap();
+    assert!(matches!(outcome, Outcome::TimedOut(timeout) if timeout == Duration::from_millis(100)), "{:?}", outcome);
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_memory_limit() {
+    let limits = Limits { timeout: None, memory: Some(16 << 20) };
+    let mut command = sandbox::command(std::path::Path::new("sh"), &limits);
+    command.args(["-c", "ulimit -v"]).stdout(Stdio::piped());
+    let Outcome::Exited { stdout, .. } = sandbox::run(&mut command, &limits).unwrap() else {
+        panic!("sh timed out");
+    };
+    assert_eq!(String::from_utf8_lossy(&stdout), "16384\n");
+}
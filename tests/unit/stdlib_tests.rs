@@ -0,0 +1,40 @@
+use std::path::{Path, PathBuf};
+use verve_lang::compiler::Compiler;
+use verve_lang::stdlib::{modules, search_path};
+
+#[test]
+fn test_search_path() {
+    let dirs = search_path(None, Some(Path::new("/opt/verve/bin/verve-lang")));
+    assert_eq!(dirs[0], Path::new("/opt/verve/bin/std"));
+    assert_eq!(dirs[1], Path::new("/opt/verve/lib/verve/std"));
+    assert_eq!(dirs[2], Path::new(env!("CARGO_MANIFEST_DIR")).join("std"));
+
+    let dirs = search_path(Some(PathBuf::from("/home/me/std")), Some(Path::new("/opt/verve/bin/verve-lang")));
+    assert_eq!(dirs, vec![PathBuf::from("/home/me/std")]);
+}
+
+#[test]
+fn test_shipped_modules_compile() {
+    let std_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("std");
+    let mut compiler = Compiler::new();
+    let shipped = modules(&std_dir).unwrap();
+    assert!(shipped.iter().any(|(path, _)| path.ends_with("math.vrv")), "{:?}", shipped);
+    assert!(shipped.iter().any(|(path, _)| path.ends_with("net.vrv")), "{:?}", shipped);
+    for (path, text) in shipped {
+        compiler.add_std_module(&path.to_string_lossy(), text);
+    }
+
+    let file_id = compiler.add_source(
+        "main.vrv",
+        "fn max(a: i32, b: i32) -> i32 { return a; } \
+         fn main() -> i32 { print(clamp(12, 0, 10)); print(list_sum(list_range(1, 4))); print(max(1, 2)); return 0; }",
+    );
+    let code = compiler.compile(file_id, &mut Vec::new()).unwrap();
+    // clamp pulls in min and max, but the program's own max wins.
+    assert!(code.contains("static int clamp(int n, int low, int high) {"), "{}", code);
+    assert!(code.contains("static int min(int a, int b) {"), "{}", code);
+    assert!(code.contains("static int max(int a, int b) {\n    return a;"), "{}", code);
+    // list_range needs the prelude's List.
+    assert!(code.contains("static List list_new() {"), "{}", code);
+    assert!(!code.contains("gcd"), "{}", code);
+}
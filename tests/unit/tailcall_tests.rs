@@ -0,0 +1,41 @@
+use codespan::Files;
+use verve_lang::ast::{Expr, Program, Stmt};
+use verve_lang::lexer::Lexer;
+use verve_lang::tailcall::{eliminate_tail_calls, has_tail_call};
+
+fn lower(source: &str) -> Program {
+    let mut files = Files::new();
+    let file_id = files.add("test", String::from(source));
+    let lexer = Lexer::new(&files, file_id);
+    let mut program = verve_lang::parser::Parser::new(lexer).parse().unwrap();
+    eliminate_tail_calls(&mut program);
+    program
+}
+
+#[test]
+fn test_self_tail_calls_are_rewritten() {
+    let program = lower(
+        "fn f(n: i32) -> i32 { if n == 0 { return g(n); } while n > 1 { return f(n - 2); } return f(n - 1); } \
+         fn g(n: i32) -> i32 { return f(n) + 1; }",
+    );
+
+    let body = &program.functions[0].body;
+    assert!(matches!(&body[0], Stmt::If(_, then_branch, _, _) if matches!(then_branch[0], Stmt::Return(Expr::Call(..), _))));
+    assert!(matches!(&body[1], Stmt::While(_, loop_body, _) if matches!(&loop_body[0], Stmt::TailCall(args, _) if args.len() == 1)));
+    assert!(matches!(&body[2], Stmt::TailCall(..)));
+    assert!(has_tail_call(body));
+    assert!(!has_tail_call(&program.functions[1].body));
+}
+
+#[test]
+fn test_tail_calls_needing_cleanup_or_shadowing_are_kept() {
+    let program = lower(
+        "fn f(n: i32) -> i32 { let p = __alloc(4); safe { defer __dealloc(p); return f(n - 1); } return 0; } \
+         fn g(n: i32) -> i32 { if n == 0 { let n = 1; return n; } return g(n - 1); }",
+    );
+
+    assert!(!has_tail_call(&program.functions[0].body));
+    let Stmt::Expr(Expr::SafeBlock(stmts, _, _), _) = &program.functions[0].body[1] else { panic!("expected safe block") };
+    assert!(matches!(&stmts[1], Stmt::Return(..)));
+    assert!(!has_tail_call(&program.functions[1].body));
+}
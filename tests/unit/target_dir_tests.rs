@@ -0,0 +1,41 @@
+use std::path::Path;
+use verve_lang::target_dir::{self, TargetDir};
+
+#[test]
+fn test_build_layout() {
+    let target = TargetDir::for_project(Some(Path::new("project/verve.toml")));
+    assert_eq!(target.root(), Path::new("project/target"));
+    let debug = target.build("wasm32-wasi", 0);
+    assert_eq!(debug.c_source(), Path::new("project/target/wasm32-wasi/debug/c/output.c"));
+    assert_eq!(debug.binary("program.wasm"), Path::new("project/target/wasm32-wasi/debug/bin/program.wasm"));
+    let release = target.build("wasm32-wasi", 1);
+    assert_eq!(release.path(), Path::new("project/target/wasm32-wasi/release"));
+    let release_o2 = target.build("wasm32-wasi", 2);
+    assert_eq!(release_o2.path(), Path::new("project/target/wasm32-wasi/release-O2"));
+    assert_eq!(target_dir::profile(0), "debug");
+    assert_eq!(target.runtime("wasm32-wasi"), Path::new("project/target/wasm32-wasi/runtime"));
+
+    assert_eq!(TargetDir::for_project(None).root(), Path::new("target"));
+}
+
+#[test]
+fn test_clean() {
+    let project = std::env::temp_dir().join(format!("verve-clean-test-{}", std::process::id()));
+    let target = TargetDir::new(project.join("target"));
+    let build = target.build("x86_64-pc-windows-msvc", 1);
+    build.create().unwrap();
+    std::fs::write(build.c_source(), "int main(void) { return 0; }").unwrap();
+
+    assert!(target.root().join(target_dir::MARKER).is_file());
+    assert!(target.clean().unwrap());
+    assert!(!target.root().exists());
+    assert!(!target.clean().unwrap());
+
+    // A target/ the compiler did not create is left alone.
+    std::fs::create_dir_all(target.root()).unwrap();
+    std::fs::write(target.root().join("notes.txt"), "mine").unwrap();
+    let error = target.clean().unwrap_err();
+    assert_eq!(error.to_string(), "it has no .verve-target file, so verve did not create it");
+    assert!(target.root().join("notes.txt").exists());
+    std::fs::remove_dir_all(project).unwrap();
+}
@@ -0,0 +1,51 @@
+use std::path::Path;
+use verve_lang::target::{Target, TARGETS};
+
+#[test]
+fn test_known_targets_parse() {
+    for triple in TARGETS {
+        assert!(Target::parse(triple).is_ok(), "{} does not parse", triple);
+    }
+    let mut sorted = TARGETS.to_vec();
+    sorted.sort_unstable();
+    assert_eq!(sorted, TARGETS);
+}
+
+#[test]
+fn test_target_properties() {
+    let target = Target::parse("i686-unknown-linux-gnu").unwrap();
+    assert_eq!((target.arch, target.os, target.family, target.pointer_width), ("x86", "linux", "unix", 32));
+    assert_eq!(target.to_string(), "triple = i686-unknown-linux-gnu\ntarget_arch = x86\ntarget_os = linux\ntarget_family = unix\npointer_width = 32");
+
+    let wasi = Target::parse("wasm32-wasi").unwrap();
+    assert_eq!((wasi.arch, wasi.os, wasi.family), ("wasm32", "wasi", "wasm"));
+    assert_eq!(wasi.c_compiler(Path::new("/sdk")).as_deref(), Some(Path::new("/sdk/bin/clang")));
+}
+
+#[test]
+fn test_native_triple() {
+    let native = verve_lang::host::native_triple();
+    assert!(TARGETS.contains(&native));
+    let target = Target::parse(native).unwrap();
+    assert_eq!((target.arch, target.os), (std::env::consts::ARCH, std::env::consts::OS));
+}
+
+#[test]
+fn test_invalid_targets() {
+    assert_eq!(
+        Target::parse("x86-64-pc-windows-msvc").unwrap_err(),
+        "invalid target triple 'x86-64-pc-windows-msvc': unknown architecture 'x86'; did you mean 'x86_64-pc-windows-msvc'?",
+    );
+    assert_eq!(
+        Target::parse("sparc-sun-solaris").unwrap_err(),
+        "invalid target triple 'sparc-sun-solaris': unknown architecture 'sparc'; --print target-list shows the known targets",
+    );
+    assert_eq!(
+        Target::parse("x86_64-pc-windows-msvcc").unwrap_err(),
+        "invalid target triple 'x86_64-pc-windows-msvcc': not a known target; did you mean 'x86_64-pc-windows-msvc'?",
+    );
+    assert!(Target::parse("x86_64-unknown-netbsd-musl").is_err());
+    assert!(Target::parse("powerpc64-unknown-linux-gnu").is_err());
+    assert!(Target::parse("x86_64").unwrap_err().contains("at least an architecture and an operating system"));
+    assert!(Target::parse("x86_64-unknown-none").unwrap_err().contains("unknown operating system"));
+}
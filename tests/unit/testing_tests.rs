@@ -0,0 +1,76 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use verve_lang::sandbox::Limits;
+use verve_lang::testing::{normalize, programs, RunPass};
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("verve-testing-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn harness(work_dir: PathBuf, bless: bool) -> RunPass {
+    RunPass { cc: std::env::var("CC").unwrap_or_else(|_| "cc".to_string()), work_dir, std_modules: Vec::new(), bless, limits: Limits::default() }
+}
+
+#[test]
+fn test_programs_lists_sources_in_order() {
+    let dir = scratch("programs");
+    for name in ["b.vrv", "a.vrv", "a.stdout", "notes.txt"] {
+        std::fs::write(dir.join(name), "").unwrap();
+    }
+    let found = programs(&dir).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(found, vec![dir.join("a.vrv"), dir.join("b.vrv")]);
+}
+
+#[test]
+fn test_check_blesses_and_compares() {
+    let dir = scratch("check");
+    let program = dir.join("hello.vrv");
+    std::fs::write(&program, "fn main() -> i32 { print(6 * 7); return 0; }").unwrap();
+
+    harness(dir.clone(), true).check(&program).unwrap();
+    assert_eq!(std::fs::read_to_string(dir.join("hello.stdout")).unwrap(), "42\n");
+    harness(dir.clone(), false).check(&program).unwrap();
+
+    std::fs::write(dir.join("hello.stdout"), "41\n").unwrap();
+    let error = harness(dir.clone(), false).check(&program).unwrap_err();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(error.contains("at -O0 printed\n42\n"), "{}", error);
+}
+
+#[test]
+fn test_run_reports_compile_errors() {
+    let dir = scratch("errors");
+    let program = dir.join("broken.vrv");
+    std::fs::write(&program, "fn main() -> i32 { return undefined_name; }").unwrap();
+    let error = harness(dir.clone(), false).run(&program, 0).unwrap_err();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(error.starts_with("Type check failed"), "{}", error);
+    assert!(error.contains("undefined_name"), "{}", error);
+}
+
+#[test]
+fn test_run_times_out() {
+    let dir = scratch("timeout");
+    let program = dir.join("spin.vrv");
+    std::fs::write(&program, "fn main() -> i32 { while true { } return 0; }").unwrap();
+    let mut harness = harness(dir.clone(), false);
+    harness.limits.timeout = Some(Duration::from_millis(200));
+    let error = harness.run(&program, 0).unwrap_err();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(error, format!("{} at -O0 timed out after 200ms", program.display()));
+}
+
+#[test]
+fn test_normalize_strips_build_details() {
+    let code = "// Generated by Verve Compiler (target: x86_64-unknown-linux-gnu)\nstatic const char verve_build_target[] = \"x86_64-unknown-linux-gnu\";\nint main(void) {}\n";
+    assert_eq!(normalize(code), "// Generated by Verve Compiler\nstatic const char verve_build_target[] = \"<build>\";\nint main(void) {}\n");
+}
+
+#[test]
+fn test_normalize_renumbers_temporaries() {
+    let code = "int __tmp_block7 = __match3;\ngoto __label_end9;\n__tmp_x2 = __tmp_block7 + __match5;\n__list_push(__index2);\n";
+    assert_eq!(normalize(code), "int __tmp_block0 = __match0;\ngoto __label_end0;\n__tmp_x1 = __tmp_block0 + __match1;\n__list_push(__index0);\n");
+}